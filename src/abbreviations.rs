@@ -0,0 +1,120 @@
+//! Shorthand that definitions use, like "sg." and "intr.", spelled out for readers who don't know
+//! it yet. See `Formatters::expand_abbreviations`.
+
+use std::collections::HashMap;
+
+// In every language's definitions.
+const COMMON: &[(&str, &str)] = &[
+    ("abbr.", "abbreviation"),
+    ("acc.", "accusative"),
+    ("colloq.", "colloquial"),
+    ("dat.", "dative"),
+    ("dim.", "diminutive"),
+    ("esp.", "especially"),
+    ("fig.", "figuratively"),
+    ("fut.", "future"),
+    ("gen.", "genitive"),
+    ("impf.", "imperfect"),
+    ("ind.", "indicative"),
+    ("inf.", "infinitive"),
+    ("intr.", "intransitive"),
+    ("lit.", "literally"),
+    ("nom.", "nominative"),
+    ("obs.", "obsolete"),
+    ("perf.", "perfect"),
+    ("pers.", "person"),
+    ("pl.", "plural"),
+    ("pres.", "present"),
+    ("sg.", "singular"),
+    ("subj.", "subjunctive"),
+    ("tr.", "transitive"),
+    ("usu.", "usually"),
+];
+
+// Only in the language's own definitions, where they can't mean anything else.
+const BY_LANGUAGE: &[(&str, &[(&str, &str)])] = &[
+    (
+        "Latin",
+        &[
+            ("abl.", "ablative"),
+            ("sup.", "supine"),
+            ("voc.", "vocative"),
+        ],
+    ),
+    ("German", &[("jmdm.", "jemandem"), ("jmdn.", "jemanden")]),
+];
+
+/// Abbreviations and what they stand for, by the language of the definitions they're in.
+pub struct Abbreviations {
+    common: Vec<(String, String)>,
+    by_language: HashMap<String, Vec<(String, String)>>,
+}
+
+impl Default for Abbreviations {
+    /// The built-in table.
+    fn default() -> Self {
+        let pairs = |pairs: &[(&str, &str)]| -> Vec<(String, String)> {
+            pairs
+                .iter()
+                .map(|&(short, long)| (short.to_owned(), long.to_owned()))
+                .collect()
+        };
+        Abbreviations {
+            common: pairs(COMMON),
+            by_language: BY_LANGUAGE
+                .iter()
+                .map(|&(language, abbreviations)| (language.to_owned(), pairs(abbreviations)))
+                .collect(),
+        }
+    }
+}
+
+impl Abbreviations {
+    pub fn empty() -> Abbreviations {
+        Abbreviations {
+            common: Vec::new(),
+            by_language: HashMap::new(),
+        }
+    }
+
+    /// Adds an abbreviation for `language`'s definitions, or for every language's if it's None.
+    pub fn add(&mut self, language: Option<&str>, short: &str, long: &str) {
+        let abbreviations = match language {
+            None => &mut self.common,
+            Some(language) => self.by_language.entry(language.to_owned()).or_default(),
+        };
+        abbreviations.push((short.to_owned(), long.to_owned()));
+    }
+
+    /// `text` with the abbreviations for `language` spelled out. They only match as whole words,
+    /// so "pl." doesn't match in "appl.".
+    pub fn expand(&self, language: &str, text: &str) -> String {
+        let abbreviations: Vec<&(String, String)> = self
+            .by_language
+            .get(language)
+            .into_iter()
+            .flatten()
+            .chain(&self.common)
+            .collect();
+        let mut expanded = String::with_capacity(text.len());
+        let mut rest = text;
+        'text: while let Some(c) = rest.chars().next() {
+            let at_word_start = expanded
+                .chars()
+                .next_back()
+                .is_none_or(|c| !c.is_alphanumeric());
+            if at_word_start {
+                for (short, long) in &abbreviations {
+                    if rest.starts_with(short.as_str()) {
+                        expanded.push_str(long);
+                        rest = &rest[short.len()..];
+                        continue 'text;
+                    }
+                }
+            }
+            expanded.push(c);
+            rest = &rest[c.len_utf8()..];
+        }
+        expanded
+    }
+}
@@ -0,0 +1,467 @@
+//! Anki packages (.apkg) of flashcards, one note per word with the word on the front and its
+//! definitions on the back.
+//!
+//! A package is a zip file holding an Anki collection, a SQLite database in the layout Anki has
+//! read since 2.0 (schema version 11), and a list of the media files in it, which is empty here.
+//! Notes get GUIDs from their deck and word, so importing a new package of the same deck updates
+//! the cards that are already there instead of adding them again.
+
+use std::fs;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use rusqlite::{params, Connection};
+use serde_json::json;
+
+use checksum::entry_checksum;
+use render::escape_html;
+
+/// A flashcard.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Note {
+    /// The front: the word, as text. Anki sorts notes and finds duplicates by it.
+    pub word: String,
+    /// The back, as HTML.
+    pub back: String,
+}
+
+const SCHEMA: &str = "
+CREATE TABLE col (
+    id integer primary key, crt integer not null, mod integer not null, scm integer not null,
+    ver integer not null, dty integer not null, usn integer not null, ls integer not null,
+    conf text not null, models text not null, decks text not null, dconf text not null,
+    tags text not null
+);
+CREATE TABLE notes (
+    id integer primary key, guid text not null, mid integer not null, mod integer not null,
+    usn integer not null, tags text not null, flds text not null, sfld integer not null,
+    csum integer not null, flags integer not null, data text not null
+);
+CREATE TABLE cards (
+    id integer primary key, nid integer not null, did integer not null, ord integer not null,
+    mod integer not null, usn integer not null, type integer not null, queue integer not null,
+    due integer not null, ivl integer not null, factor integer not null, reps integer not null,
+    lapses integer not null, left integer not null, odue integer not null, odid integer not null,
+    flags integer not null, data text not null
+);
+CREATE TABLE revlog (
+    id integer primary key, cid integer not null, usn integer not null, ease integer not null,
+    ivl integer not null, lastIvl integer not null, factor integer not null, time integer not null,
+    type integer not null
+);
+CREATE TABLE graves (usn integer not null, oid integer not null, type integer not null);
+CREATE INDEX ix_notes_usn ON notes (usn);
+CREATE INDEX ix_cards_usn ON cards (usn);
+CREATE INDEX ix_revlog_usn ON revlog (usn);
+CREATE INDEX ix_cards_nid ON cards (nid);
+CREATE INDEX ix_cards_sched ON cards (did, queue, due);
+CREATE INDEX ix_revlog_cid ON revlog (cid);
+CREATE INDEX ix_notes_csum ON notes (csum);
+";
+
+const STYLE: &str = ".card{font:18px/1.4 sans-serif;color:#222;background:#fff;text-align:left}\
+.front{font-size:1.6em;text-align:center}\
+.reading,.ipa{color:#666;margin:0 0 .3em}\
+h3{font-size:1em;color:#2a7a2a;margin:.5em 0 .2em}\
+h4{font-size:.9em;font-style:italic;font-weight:normal;margin:.3em 0 .1em}\
+ol{margin:0;padding-left:1.6em}";
+
+// An id for something named `name`, the same every time, so the deck and note type of a package
+// match the ones imported from an earlier one. Anki's ids are milliseconds, and JavaScript has
+// to be able to hold them, so they're kept under 2^53.
+fn stable_id(name: &str) -> i64 {
+    let hash = u64::from_str_radix(&entry_checksum(name, "anki", &[]), 16).unwrap();
+    (hash & ((1 << 52) - 1)) as i64 + 1
+}
+
+// Anki's checksum of a note's first field, for finding duplicates: the first 32 bits of its SHA-1.
+fn field_checksum(field: &str) -> i64 {
+    let digest = sha1(field.as_bytes());
+    i64::from(u32::from_be_bytes([
+        digest[0], digest[1], digest[2], digest[3],
+    ]))
+}
+
+fn collection(path: &Path, deck: &str, notes: &[Note]) -> rusqlite::Result<()> {
+    let conn = Connection::open(path)?;
+    conn.execute_batch(SCHEMA)?;
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let (seconds, millis) = (now.as_secs() as i64, now.as_millis() as i64);
+    let deck_id = stable_id(&format!("deck {}", deck));
+    let model_id = stable_id("define3 word");
+
+    let fields: Vec<_> = ["Word", "Back"]
+        .iter()
+        .enumerate()
+        .map(|(i, name)| {
+            json!({
+                "name": name,
+                "ord": i,
+                "sticky": false,
+                "rtl": false,
+                "font": "Arial",
+                "size": 20,
+                "media": [],
+            })
+        })
+        .collect();
+    let model = json!({
+        "id": model_id,
+        "name": "define3 word",
+        "type": 0,
+        "mod": seconds,
+        "usn": -1,
+        "sortf": 0,
+        "did": deck_id,
+        "tmpls": [{
+            "name": "Card 1",
+            "ord": 0,
+            "qfmt": "<div class=front>{{Word}}</div>",
+            "afmt": "{{FrontSide}}<hr id=answer>{{Back}}",
+            "bqfmt": "",
+            "bafmt": "",
+            "did": null,
+        }],
+        "flds": fields,
+        "css": STYLE,
+        "latexPre": "\\documentclass[12pt]{article}\n\\special{papersize=3in,5in}\n\
+                     \\usepackage[utf8]{inputenc}\n\\usepackage{amssymb,amsmath}\n\
+                     \\pagestyle{empty}\n\\setlength{\\parindent}{0in}\n\\begin{document}\n",
+        "latexPost": "\\end{document}",
+        // The card needs a word.
+        "req": [[0, "all", [0]]],
+        "tags": [],
+        "vers": [],
+    });
+    let deck_json = |id: i64, name: &str| {
+        json!({
+            "id": id,
+            "name": name,
+            "desc": "",
+            "mod": seconds,
+            "usn": -1,
+            "conf": 1,
+            "dyn": 0,
+            "collapsed": false,
+            "extendNew": 10,
+            "extendRev": 50,
+            "newToday": [0, 0],
+            "revToday": [0, 0],
+            "lrnToday": [0, 0],
+            "timeToday": [0, 0],
+        })
+    };
+    let decks = json!({
+        "1": deck_json(1, "Default"),
+        deck_id.to_string(): deck_json(deck_id, deck),
+    });
+    let dconf = json!({"1": {
+        "id": 1,
+        "name": "Default",
+        "mod": 0,
+        "usn": 0,
+        "maxTaken": 60,
+        "autoplay": true,
+        "replayq": true,
+        "timer": 0,
+        "new": {"bury": true, "delays": [1, 10], "initialFactor": 2500, "ints": [1, 4, 7],
+                "order": 1, "perDay": 20, "separate": true},
+        "rev": {"bury": true, "ease4": 1.3, "fuzz": 0.05, "ivlFct": 1, "maxIvl": 36500,
+                "minSpace": 1, "perDay": 100},
+        "lapse": {"delays": [10], "leechAction": 0, "leechFails": 8, "minInt": 1, "mult": 0},
+    }});
+    let conf = json!({
+        "activeDecks": [1],
+        "curDeck": deck_id,
+        "curModel": model_id.to_string(),
+        "nextPos": notes.len() + 1,
+        "newSpread": 0,
+        "collapseTime": 1200,
+        "timeLim": 0,
+        "estTimes": true,
+        "dueCounts": true,
+        "newBury": true,
+        "sortType": "noteFld",
+        "sortBackwards": false,
+        "addToCur": true,
+    });
+    let models = json!({ model_id.to_string(): model });
+    conn.execute(
+        "INSERT INTO col VALUES (1, ?1, ?2, ?2, 11, 0, 0, 0, ?3, ?4, ?5, ?6, '{}')",
+        params![
+            seconds / 86400 * 86400,
+            millis,
+            conf.to_string(),
+            models.to_string(),
+            decks.to_string(),
+            dconf.to_string()
+        ],
+    )?;
+
+    let mut insert_note =
+        conn.prepare("INSERT INTO notes VALUES (?1, ?2, ?3, ?4, -1, '', ?5, ?6, ?7, 0, '')")?;
+    let mut insert_card = conn.prepare(
+        "INSERT INTO cards VALUES (?1, ?2, ?3, 0, ?4, -1, 0, 0, ?5, 0, 0, 0, 0, 0, 0, 0, 0, '')",
+    )?;
+    for (i, note) in notes.iter().enumerate() {
+        let id = millis + i as i64;
+        let guid = entry_checksum(&note.word, deck, &["anki"]);
+        let fields = format!("{}\x1f{}", escape_html(&note.word), note.back);
+        insert_note.execute(params![
+            id,
+            guid,
+            model_id,
+            seconds,
+            fields,
+            note.word,
+            field_checksum(&note.word)
+        ])?;
+        insert_card.execute(params![id, id, deck_id, seconds, i as i64 + 1])?;
+    }
+    Ok(())
+}
+
+/// Writes an Anki package of `notes` in a deck called `deck` to `path`.
+pub fn write_package(path: &Path, deck: &str, notes: &[Note]) -> Result<(), String> {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".collection");
+    let collection_path = path.with_file_name(name);
+    let _ = fs::remove_file(&collection_path);
+    let database = collection(&collection_path, deck, notes)
+        .map_err(|e| e.to_string())
+        .and_then(|_| fs::read(&collection_path).map_err(|e| e.to_string()));
+    let _ = fs::remove_file(&collection_path);
+    let database = database.map_err(|e| format!("Could not make the collection: {}", e))?;
+    let zip = zip(&[("collection.anki2", &database), ("media", b"{}")]);
+    fs::write(path, zip).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+// A zip file of `files`, stored without compression, which every unzipper reads.
+fn zip(files: &[(&str, &[u8])]) -> Vec<u8> {
+    let mut zip = Vec::new();
+    let mut directory = Vec::new();
+    for &(name, data) in files {
+        let offset = zip.len() as u32;
+        let crc = crc32(data);
+        // Version needed, flags, method (stored), time and date (1980-01-01).
+        let common = |header: &mut Vec<u8>| {
+            header.extend_from_slice(&20u16.to_le_bytes());
+            header.extend_from_slice(&0u16.to_le_bytes());
+            header.extend_from_slice(&0u16.to_le_bytes());
+            header.extend_from_slice(&0u16.to_le_bytes());
+            header.extend_from_slice(&0x21u16.to_le_bytes());
+            header.extend_from_slice(&crc.to_le_bytes());
+            header.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            header.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            header.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            header.extend_from_slice(&0u16.to_le_bytes());
+        };
+        zip.extend_from_slice(&0x04034b50u32.to_le_bytes());
+        common(&mut zip);
+        zip.extend_from_slice(name.as_bytes());
+        zip.extend_from_slice(data);
+
+        directory.extend_from_slice(&0x02014b50u32.to_le_bytes());
+        directory.extend_from_slice(&20u16.to_le_bytes());
+        common(&mut directory);
+        // Comment length, disk, internal and external attributes, and where the file is.
+        directory.extend_from_slice(&0u16.to_le_bytes());
+        directory.extend_from_slice(&0u16.to_le_bytes());
+        directory.extend_from_slice(&0u16.to_le_bytes());
+        directory.extend_from_slice(&0u32.to_le_bytes());
+        directory.extend_from_slice(&offset.to_le_bytes());
+        directory.extend_from_slice(name.as_bytes());
+    }
+    let directory_offset = zip.len() as u32;
+    zip.extend_from_slice(&directory);
+    zip.extend_from_slice(&0x06054b50u32.to_le_bytes());
+    zip.extend_from_slice(&0u16.to_le_bytes());
+    zip.extend_from_slice(&0u16.to_le_bytes());
+    zip.extend_from_slice(&(files.len() as u16).to_le_bytes());
+    zip.extend_from_slice(&(files.len() as u16).to_le_bytes());
+    zip.extend_from_slice(&(directory.len() as u32).to_le_bytes());
+    zip.extend_from_slice(&directory_offset.to_le_bytes());
+    zip.extend_from_slice(&0u16.to_le_bytes());
+    zip
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xedb88320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut h: [u32; 5] = [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476, 0xc3d2e1f0];
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&(data.len() as u64 * 8).to_be_bytes());
+    for block in message.chunks(64) {
+        let mut w = [0u32; 80];
+        for (i, word) in block.chunks(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e] = h;
+        for (i, &word) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5a827999),
+                20..=39 => (b ^ c ^ d, 0x6ed9eba1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8f1bbcdc),
+                _ => (b ^ c ^ d, 0xca62c1d6),
+            };
+            let t = a
+                .rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = t;
+        }
+        for (h, x) in h.iter_mut().zip([a, b, c, d, e]) {
+            *h = h.wrapping_add(x);
+        }
+    }
+    let mut digest = [0u8; 20];
+    for (i, h) in h.iter().enumerate() {
+        digest[i * 4..i * 4 + 4].copy_from_slice(&h.to_be_bytes());
+    }
+    digest
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::process;
+
+    use super::*;
+
+    // The files in a zip `zip` wrote, from their local headers.
+    fn unzip(mut zip: &[u8]) -> Vec<(String, Vec<u8>)> {
+        let number = |bytes: &[u8], at: usize, width: usize| {
+            bytes[at..at + width]
+                .iter()
+                .rev()
+                .fold(0, |n, &b| n << 8 | b as usize)
+        };
+        let mut files = Vec::new();
+        while number(zip, 0, 4) == 0x04034b50 {
+            let (size, name_length) = (number(zip, 22, 4), number(zip, 26, 2));
+            let name = String::from_utf8(zip[30..30 + name_length].to_vec()).unwrap();
+            let data = zip[30 + name_length..30 + name_length + size].to_vec();
+            assert_eq!(crc32(&data) as usize, number(zip, 14, 4), "{}", name);
+            files.push((name, data));
+            zip = &zip[30 + name_length + size..];
+        }
+        assert_eq!(number(zip, 0, 4), 0x02014b50);
+        files
+    }
+
+    #[test]
+    fn checksums() {
+        assert_eq!(crc32(b"123456789"), 0xcbf43926);
+        assert_eq!(crc32(b""), 0);
+        let hex = |digest: [u8; 20]| {
+            digest
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect::<String>()
+        };
+        assert_eq!(
+            hex(sha1(b"abc")),
+            "a9993e364706816aba3e25717850c26c9cd0d89d"
+        );
+        assert_eq!(hex(sha1(b"")), "da39a3ee5e6b4b0d3255bfef95601890afd80709");
+        assert_eq!(
+            hex(sha1(&[b'a'; 1000])),
+            "291e9a6c66994949b57ba5e650361e98fc36b1ba"
+        );
+        assert_eq!(field_checksum("abc"), 0xa9993e36);
+    }
+
+    #[test]
+    fn ids_are_stable_and_fit_in_javascript() {
+        assert_eq!(stable_id("deck French"), stable_id("deck French"));
+        assert_ne!(stable_id("deck French"), stable_id("deck German"));
+        for name in &["deck French", "deck German", "define3 word"] {
+            assert!(stable_id(name) > 0 && stable_id(name) < 1 << 53, "{}", name);
+        }
+    }
+
+    #[test]
+    fn package() {
+        let path = env::temp_dir().join(format!("define3-test-{}.apkg", process::id()));
+        let notes = [
+            Note {
+                word: "chat".to_owned(),
+                back: "<ol><li>cat</li></ol>".to_owned(),
+            },
+            Note {
+                word: "<b>".to_owned(),
+                back: "<ol><li>bold</li></ol>".to_owned(),
+            },
+        ];
+        write_package(&path, "French", &notes).unwrap();
+        let files = unzip(&fs::read(&path).unwrap());
+        let _ = fs::remove_file(&path);
+        let names: Vec<_> = files.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["collection.anki2", "media"]);
+        assert_eq!(files[1].1, b"{}");
+
+        fs::write(&path, &files[0].1).unwrap();
+        let conn = Connection::open(&path).unwrap();
+        let mut statement = conn
+            .prepare("SELECT guid, flds, sfld, csum FROM notes ORDER BY id")
+            .unwrap();
+        let rows: Vec<(String, String, String, i64)> = statement
+            .query_map([], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+            })
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].0, entry_checksum("chat", "French", &["anki"]));
+        assert_eq!(rows[0].1, "chat\x1f<ol><li>cat</li></ol>");
+        assert_eq!(rows[1].1, "&lt;b&gt;\x1f<ol><li>bold</li></ol>");
+        assert_eq!(
+            (rows[1].2.as_str(), rows[1].3),
+            ("<b>", field_checksum("<b>"))
+        );
+        let cards: i64 = conn
+            .query_row(
+                "SELECT count(*) FROM cards JOIN notes ON notes.id = cards.nid WHERE did = ?1",
+                [stable_id("deck French")],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(cards, 2);
+        let decks: String = conn
+            .query_row("SELECT decks FROM col", [], |row| row.get(0))
+            .unwrap();
+        assert!(decks.contains("\"name\":\"French\""), "{}", decks);
+        drop(statement);
+        drop(conn);
+        let _ = fs::remove_file(&path);
+    }
+}
@@ -1,229 +1,1177 @@
+extern crate crossbeam_channel;
 extern crate define3;
 extern crate getopts;
 extern crate regex;
 extern crate rusqlite;
 
-use define3::parse_wikitext::parse_wikitext;
+use define3::cache::CACHE_TABLE;
+use define3::checksum::entry_checksum;
+use define3::compression::{train_dictionary, Compressor, DICTIONARY_TABLE};
+use define3::config::Config;
+use define3::expand::{expand, TemplatePolicies};
+use define3::headword::grammar;
+use define3::import_report::ImportReport;
+use define3::memory::{detect_low_memory, limit_memory, low_memory, set_low_memory};
+use define3::normalize::{fold, fold_diacritics, folds_to_string, parse_folds, DEFAULT_FOLDS};
+use define3::parse_wikitext::{
+    parse_wikitext_with, HeaderCorrector, MarkupCleaner, ParsedWikitext, IMPORTED_LANGUAGES,
+    IMPORTED_PARTS_OF_SPEECH,
+};
+use define3::parse_xml::{for_pages_with_progress, Progress};
+use define3::reverse::build_search_index;
+use define3::wiktextract::for_entries;
 use define3::PageContent;
-use define3::{Module, Template, Word};
+use define3::{Headword, Module, Page, Template, Word};
 
 use getopts::Options;
 use regex::Regex;
-use rusqlite::{Connection, Transaction};
-use std::collections::{HashMap, HashSet};
+use rusqlite::{Connection, OpenFlags, Transaction};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs;
 use std::fs::File;
-use std::io::Write;
-use std::path::Path;
+use std::io::{IsTerminal, Write};
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
 
-fn main() {
-    // TODO: figure out list of languages automatically
-    let languages: HashSet<&str> = [
-        "Alemannic German",
-        "Chinese",
-        "English",
-        "Esperanto",
-        "French",
-        "German",
-        "Japanese",
-        "Korean",
-        "Lojban",
-    ]
-    .iter()
-    .cloned()
-    .collect();
-
-    // TODO: figure out POS list automatically
-    let parts_of_speech: HashSet<&str> = [
-        "Adjective",
-        "Adverb",
-        "Brivla",
-        "Cmavo",
-        "Conjunction",
-        "Definitions",
-        "Gismu",
-        "Hanja",
-        "Hanzi",
-        "Infix",
-        "Initialism",
-        "Interjection",
-        "Kanji",
-        "Noun",
-        "Phrase",
-        "Proper noun",
-        "Rafsi",
-        "Romanization",
-        "Verb",
-    ]
-    .iter()
-    .cloned()
-    .collect();
+// How many pages or parsed words can be waiting between two stages of the import.
+const CHANNEL_CAPACITY: usize = 1024;
+// With --low-memory. Pages can be large, so even this many can add up to a few megabytes.
+const LOW_MEMORY_CHANNEL_CAPACITY: usize = 32;
 
-    let args: Vec<String> = std::env::args().collect();
-    let mut opts = Options::new();
-    opts.optflag("h", "help", "print this help text");
-    let matches = opts.parse(&args[1..]).unwrap();
-    if matches.opt_present("h") || matches.free.len() != 1 {
-        let brief = format!(
-            "Usage: {} PATH_TO_enwiktionary-YYYYMMDD-pages-meta-current.xml [options]",
-            args[0]
+// How many definitions the compression dictionary is trained on.
+const DICTIONARY_SAMPLES: i64 = 100_000;
+const LOW_MEMORY_DICTIONARY_SAMPLES: i64 = 10_000;
+
+// How many rows are compressed per query.
+const COMPRESSION_BATCH: i64 = 10_000;
+
+fn get_metadata(conn: &Connection, key: &str) -> Option<String> {
+    conn.query_row("select value from metadata where key = ?1", [key], |row| {
+        row.get(0)
+    })
+    .ok()
+}
+
+fn set_metadata(conn: &Connection, key: &str, value: &str) {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS metadata (
+             key            text primary key,
+             value          text not null
+         )",
+    )
+    .unwrap();
+    conn.execute(
+        "insert or replace into metadata (key, value) values (?1, ?2)",
+        [key, value],
+    )
+    .unwrap();
+}
+
+// Width of the progress bar, in characters.
+const PROGRESS_BAR_WIDTH: u64 = 30;
+
+// Draws a progress bar on stderr as a pass reads through the dump, redrawing only when it moves a
+// percent. Off when stderr isn't a terminal, so logs don't fill up with it.
+fn progress_bar(label: &'static str) -> impl FnMut(&Progress) {
+    let show = std::io::stderr().is_terminal();
+    let mut last_percent = None;
+    move |progress: &Progress| {
+        if !show || progress.total_bytes == 0 {
+            return;
+        }
+        let percent = (progress.bytes_read * 100 / progress.total_bytes).min(100);
+        if last_percent == Some(percent) {
+            return;
+        }
+        last_percent = Some(percent);
+        let filled = percent * PROGRESS_BAR_WIDTH / 100;
+        eprint!(
+            "\r{} [{}{}] {:>3}% {} pages",
+            label,
+            "#".repeat(filled as usize),
+            " ".repeat((PROGRESS_BAR_WIDTH - filled) as usize),
+            percent,
+            progress.pages
         );
-        print!("{}", opts.usage(&brief));
-        return;
+        if percent == 100 {
+            eprintln!();
+        }
     }
-    let xml_path = matches.free[0].clone();
+}
+
+// Identifies the dump a partial database came from, so we only ever resume an import of the same
+// file.
+fn source_fingerprint(xml_path: &str) -> String {
+    let metadata = fs::metadata(xml_path).unwrap();
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |duration| duration.as_secs());
+    format!(
+        "{}:{}:{}",
+        fs::canonicalize(xml_path).unwrap().display(),
+        metadata.len(),
+        modified
+    )
+}
 
-    let mut sqlite_path = dirs::data_dir().unwrap();
-    sqlite_path.push("define3");
-    std::fs::create_dir_all(&sqlite_path).unwrap();
-    sqlite_path.push("define3.sqlite3");
+// Dumps are named after their edition, e.g. "frwiktionary-20240101-pages-meta-current.xml".
+fn guess_edition(xml_path: &str) -> Option<String> {
+    let file_name = Path::new(xml_path).file_name()?.to_str()?;
+    let end = file_name.find("wiktionary")?;
+    if end == 0 {
+        None
+    } else {
+        Some(file_name[..end].to_owned())
+    }
+}
 
-    let mut conn = Connection::open(&sqlite_path).unwrap();
-    let tx = Transaction::new(&mut conn, rusqlite::TransactionBehavior::Exclusive).unwrap();
+// Dumps are also named after their date, e.g. "enwiktionary-20240101-pages-meta-current.xml".
+// Returns it as YYYY-MM-DD.
+fn guess_dump_date(xml_path: &str) -> Option<String> {
+    let file_name = Path::new(xml_path).file_name()?.to_str()?;
+    let date = file_name
+        .split(['-', '.'])
+        .find(|part| part.len() == 8 && part.bytes().all(|b| b.is_ascii_digit()))?;
+    Some(format!("{}-{}-{}", &date[..4], &date[4..6], &date[6..]))
+}
 
-    println!("Saving data to {:?}", sqlite_path);
+// When each entry first appeared in an imported dump, for `define new-words`.
+fn create_first_seen(conn: &Connection) {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS first_seen (
+             name           text not null,
+             language       text not null,
+             edition        text not null,
+             dump_date      text not null,
+             primary key (name, language, edition)
+         )",
+    )
+    .unwrap();
+}
+
+// Copies when entries were first seen from the database this import replaces, so that the dates
+// survive rebuilds and not just merges.
+fn carry_over_first_seen(conn: &Connection, sqlite_path: &Path) {
+    create_first_seen(conn);
+    if !sqlite_path.exists() {
+        return;
+    }
+    conn.execute(
+        "attach database ?1 as previous",
+        [sqlite_path.to_str().unwrap()],
+    )
+    .unwrap();
+    let has_first_seen: bool = conn
+        .query_row(
+            "select count(*) > 0 from previous.sqlite_master where name = 'first_seen'",
+            [],
+            |row| row.get(0),
+        )
+        .unwrap();
+    if has_first_seen {
+        conn.execute(
+            "insert or ignore into first_seen select * from previous.first_seen",
+            [],
+        )
+        .unwrap();
+    }
+    conn.execute("detach database previous", []).unwrap();
+}
 
-    let mut count: u64 = 0;
+// Databases built before a column existed get it with the value old rows would have had.
+fn add_column_if_missing(tx: &Transaction, table: &str, column: &str, definition: &str) {
+    let exists: bool = tx
+        .query_row(
+            &format!(
+                "select count(*) > 0 from pragma_table_info('{}') where name = ?1",
+                table
+            ),
+            [column],
+            |row| row.get(0),
+        )
+        .unwrap();
+    if !exists {
+        tx.execute(
+            &format!("alter table {} add column {} {}", table, column, definition),
+            [],
+        )
+        .unwrap();
+    }
+}
 
-    let mut templates: HashMap<String, String> = HashMap::new();
-    let mut modules: HashMap<String, String> = HashMap::new();
+// Definitions per language of this edition in the database that the import will replace, to
+// compare the new counts against.
+fn previous_counts(sqlite_path: &Path, edition: &str) -> Option<BTreeMap<String, u64>> {
+    if !sqlite_path.exists() {
+        return None;
+    }
+    let conn = Connection::open_with_flags(sqlite_path, OpenFlags::SQLITE_OPEN_READ_ONLY).ok()?;
+    // Databases from before editions were tracked only have en.
+    let mut stmt = conn
+        .prepare("select language, count(*) from words where edition = ?1 group by language")
+        .or_else(|_| {
+            conn.prepare("select language, count(*) from words where ?1 = 'en' group by language")
+        })
+        .ok()?;
+    let counts = stmt
+        .query_map([edition], |row| {
+            Ok((row.get(0)?, row.get::<_, i64>(1)? as u64))
+        })
+        .ok()?;
+    counts.collect::<rusqlite::Result<_>>().ok()
+}
 
-    println!("Pass 1: Collecting templates and modules");
+fn stage_done(conn: &Connection, stage: &str) -> bool {
+    let done: i64 = conn
+        .query_row(
+            "select count(*) from import_stages where stage = ?1",
+            [stage],
+            |row| row.get(0),
+        )
+        .unwrap();
+    done > 0
+}
 
-    tx.execute("DROP TABLE IF EXISTS templates", []).unwrap();
+// Each stage commits on its own together with its checkpoint, so an interrupted import loses at
+// most the stage it was in.
+fn finish_stage(tx: Transaction, stage: &str, source: &str) {
     tx.execute(
-        "CREATE TABLE templates (
-             name           text not null,
-             content        text not null
-         )",
-        [],
+        "insert into import_stages (stage, source) values (?1, ?2)",
+        [stage, source],
     )
     .unwrap();
+    tx.commit().unwrap();
+}
+
+// Compresses one text column of a table in place; NULLs stay NULL.
+fn compress_column(tx: &Transaction, compressor: &mut Compressor, table: &str, column: &str) {
+    let mut select = tx
+        .prepare(&format!(
+            "select rowid, {0} from {1} where rowid > ?1 and typeof({0}) = 'text'
+             order by rowid limit ?2",
+            column, table
+        ))
+        .unwrap();
+    let mut update = tx
+        .prepare(&format!(
+            "update {} set {} = ?1 where rowid = ?2",
+            table, column
+        ))
+        .unwrap();
+    let mut last_rowid: i64 = 0;
+    loop {
+        let rows: Vec<(i64, Option<String>)> = select
+            .query_map([last_rowid, COMPRESSION_BATCH], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+        if rows.is_empty() {
+            break;
+        }
+        for (rowid, text) in &rows {
+            let data = text.as_ref().map(|text| compressor.compress(text).unwrap());
+            update.execute(rusqlite::params![data, rowid]).unwrap();
+        }
+        last_rowid = rows.last().unwrap().0;
+    }
+}
 
-    tx.execute("DROP TABLE IF EXISTS modules", []).unwrap();
+// Trains a zstd dictionary on a spread-out sample of definitions, stores it, and compresses
+// definitions and templates with it. When merging into a database that is already compressed,
+// its dictionary is kept, since the rows already there need it to be read.
+fn compress_database(tx: &Transaction) {
+    let existing: Option<Vec<u8>> = tx
+        .query_row(
+            &format!("select content from {}", DICTIONARY_TABLE),
+            [],
+            |row| row.get(0),
+        )
+        .ok();
+    if let Some(dictionary) = existing {
+        let mut compressor = Compressor::new(&dictionary).unwrap();
+        compress_column(tx, &mut compressor, "words", "definition");
+        compress_column(tx, &mut compressor, "words", "expanded");
+        compress_column(tx, &mut compressor, "templates", "content");
+        return;
+    }
+
+    let count: i64 = tx
+        .query_row("select count(*) from words", [], |row| row.get(0))
+        .unwrap();
+    let samples = if low_memory() {
+        LOW_MEMORY_DICTIONARY_SAMPLES
+    } else {
+        DICTIONARY_SAMPLES
+    };
+    let step = (count / samples).max(1);
+    let samples: Vec<String> = tx
+        .prepare("select definition from words where rowid % ?1 = 0")
+        .unwrap()
+        .query_map([step], |row| row.get(0))
+        .unwrap()
+        .collect::<rusqlite::Result<_>>()
+        .unwrap();
+    let dictionary = train_dictionary(&samples).unwrap_or_else(|e| {
+        // zstd refuses to train on too little text, which only happens with tiny dumps.
+        println!(
+            "Could not train a dictionary ({}), compressing without one",
+            e
+        );
+        Vec::new()
+    });
+
+    tx.execute(&format!("DROP TABLE IF EXISTS {}", DICTIONARY_TABLE), [])
+        .unwrap();
     tx.execute(
-        "CREATE TABLE modules (
-             name           text not null,
-             content        text not null
-         )",
+        &format!("CREATE TABLE {} (content blob not null)", DICTIONARY_TABLE),
         [],
     )
     .unwrap();
+    tx.execute(
+        &format!("insert into {} (content) values (?1)", DICTIONARY_TABLE),
+        [&dictionary],
+    )
+    .unwrap();
+
+    let mut compressor = Compressor::new(&dictionary).unwrap();
+    compress_column(tx, &mut compressor, "words", "definition");
+    compress_column(tx, &mut compressor, "words", "expanded");
+    compress_column(tx, &mut compressor, "templates", "content");
+}
+
+// Bad options get an error and the usage rather than a panic.
+fn parse_options(opts: &Options, args: &[String], brief: &str) -> getopts::Matches {
+    match opts.parse(args) {
+        Ok(matches) => matches,
+        Err(e) => {
+            eprintln!("{}\n", e);
+            eprint!("{}", opts.usage(brief));
+            std::process::exit(1);
+        }
+    }
+}
+
+fn main() {
+    let languages: HashSet<&str> = IMPORTED_LANGUAGES.iter().cloned().collect();
+    let parts_of_speech: HashSet<&str> = IMPORTED_PARTS_OF_SPEECH.iter().cloned().collect();
+
+    let args: Vec<String> = std::env::args().collect();
+    let mut opts = Options::new();
+    opts.optflag("h", "help", "print this help text");
+    opts.optflag("V", "version", "print the version");
+    opts.optopt(
+        "j",
+        "threads",
+        "number of threads parsing wikitext (default: one per CPU)",
+        "N",
+    );
+    opts.optopt(
+        "",
+        "edition",
+        "the Wiktionary edition the dump is from, e.g. fr (default: guessed from the file name, \
+         or en)",
+        "CODE",
+    );
+    opts.optflag(
+        "",
+        "merge",
+        "keep the other editions already in the database, replacing only this one",
+    );
+    opts.optflag(
+        "",
+        "jsonl",
+        "read wiktextract's JSONL (e.g. from kaikki.org) instead of a dump (the default for .jsonl \
+         and .jsonl.bz2 files)",
+    );
+    opts.optflag(
+        "",
+        "pre-expand",
+        "also store every definition with its templates expanded, so lookups don't have to expand \
+         them (makes the database bigger)",
+    );
+    opts.optflag(
+        "",
+        "compress",
+        "compress definitions and templates with zstd, for a much smaller database",
+    );
+    opts.optopt(
+        "",
+        "normalize",
+        "what headwords are folded with for lookups that don't match exactly: case, length-marks \
+         and/or diacritics, comma-separated, or none (default length-marks)",
+        "FOLDS",
+    );
+    opts.optopt(
+        "",
+        "dump-date",
+        "when the dump was made, as YYYY-MM-DD, for `define new-words` (default: from the file \
+         name, or when it was last modified)",
+        "DATE",
+    );
+    opts.optopt(
+        "",
+        "report",
+        "where to write the import report (default: import-report.json next to the database)",
+        "PATH",
+    );
+    opts.optflag(
+        "",
+        "fix-headers",
+        "take section headers that are a typo away from a known one, like \"Adjectiv\", to be \
+         that one (the import report lists every correction)",
+    );
+    opts.optopt(
+        "",
+        "header-whitelist",
+        "with --fix-headers, a file of headers to leave alone, one per line",
+        "PATH",
+    );
+    opts.optflag(
+        "",
+        "restart",
+        "throw away progress left by an interrupted import",
+    );
+    opts.optflag(
+        "",
+        "low-memory",
+        "for machines with 1GB or less, like a Raspberry Pi: one thread, short queues and small \
+         caches (the default on such machines)",
+    );
+    let brief = format!(
+        "Usage: {} PATH_TO_enwiktionary-YYYYMMDD-pages-meta-current.xml[.bz2] [options]\n       \
+         {} PATH_TO_wiktextract.jsonl[.bz2] [options]",
+        args[0], args[0]
+    );
+    let matches = parse_options(&opts, &args[1..], &brief);
+    if matches.opt_present("V") {
+        println!("build_definitions_db {}", env!("CARGO_PKG_VERSION"));
+        return;
+    }
+    if matches.opt_present("h") || matches.free.len() != 1 {
+        print!("{}", opts.usage(&brief));
+        return;
+    }
+    let xml_path = matches.free[0].clone();
+    let jsonl =
+        matches.opt_present("jsonl") || xml_path.trim_end_matches(".bz2").ends_with(".jsonl");
+    set_low_memory(matches.opt_present("low-memory") || detect_low_memory());
+    let threads = match matches.opt_str("j") {
+        // Each thread holds a page or two and its parse.
+        None if low_memory() => 1,
+        None => std::thread::available_parallelism().map_or(1, |n| n.get()),
+        Some(n) => n
+            .parse::<usize>()
+            .expect("--threads must be a number")
+            .max(1),
+    };
+
+    // Wherever define will look for it.
+    let config = Config::load_default().unwrap_or_else(|e| {
+        eprintln!("Bad config file {}", e);
+        std::process::exit(1);
+    });
+    let sqlite_path = match config.database {
+        Some(path) => path,
+        None => {
+            let mut path = dirs::data_dir().unwrap();
+            path.push("define3");
+            path.push("define3.sqlite3");
+            path
+        }
+    };
+    if let Some(dir) = sqlite_path.parent() {
+        std::fs::create_dir_all(dir).unwrap();
+    }
+
+    // Everything goes into a partial database next to the real one, which is only renamed into
+    // place once every stage has finished. Readers never see a half-built dictionary.
+    let partial_path = sqlite_path.with_extension("sqlite3.partial");
+    if matches.opt_present("restart") && partial_path.exists() {
+        fs::remove_file(&partial_path).unwrap();
+    }
+    let edition = matches
+        .opt_str("edition")
+        .or_else(|| guess_edition(&xml_path))
+        .unwrap_or_else(|| "en".to_owned());
+    let merge = matches.opt_present("merge");
+    let pre_expand = matches.opt_present("pre-expand");
+    let compress = matches.opt_present("compress");
+    let folds = match matches.opt_str("normalize").as_deref() {
+        None => DEFAULT_FOLDS.to_vec(),
+        Some("none") => Vec::new(),
+        Some(folds) => parse_folds(folds).unwrap_or_else(|e| {
+            eprintln!("--normalize: {}", e);
+            std::process::exit(1);
+        }),
+    };
+    let fix_headers = matches.opt_present("fix-headers");
+    let header_whitelist: HashSet<String> = match matches.opt_str("header-whitelist") {
+        None => HashSet::new(),
+        Some(path) => fs::read_to_string(&path)
+            .unwrap_or_else(|e| {
+                eprintln!("Could not read {}: {}", path, e);
+                std::process::exit(1);
+            })
+            .lines()
+            .map(str::trim)
+            .filter(|header| !header.is_empty())
+            .map(str::to_owned)
+            .collect(),
+    };
+    let dump_date = matches
+        .opt_str("dump-date")
+        .or_else(|| guess_dump_date(&xml_path));
+    // Stages done with different options can't be reused either.
+    let source = format!(
+        "{} --edition {}{}{}{}{} --normalize {}{}",
+        source_fingerprint(&xml_path),
+        edition,
+        if merge { " --merge" } else { "" },
+        if pre_expand { " --pre-expand" } else { "" },
+        if compress { " --compress" } else { "" },
+        if fix_headers { " --fix-headers" } else { "" },
+        folds_to_string(&folds),
+        dump_date
+            .as_ref()
+            .map_or(String::new(), |date| format!(" --dump-date {}", date))
+    );
+
+    let open_partial = || {
+        let conn = Connection::open(&partial_path).unwrap();
+        limit_memory(&conn).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS import_stages (
+                 stage          text primary key,
+                 source         text not null
+             )",
+        )
+        .unwrap();
+        conn
+    };
+    let mut conn = open_partial();
+    // Progress made on a different dump is no use to us.
+    conn.execute("delete from import_stages where source != ?1", [&source])
+        .unwrap();
+    let resuming: bool = conn
+        .query_row("select count(*) > 0 from import_stages", [], |row| {
+            row.get(0)
+        })
+        .unwrap();
+    if !resuming {
+        // Start over from nothing, or from a copy of the current database when merging.
+        drop(conn);
+        fs::remove_file(&partial_path).unwrap();
+        if merge && sqlite_path.exists() {
+            fs::copy(&sqlite_path, &partial_path).unwrap();
+        }
+        conn = open_partial();
+        conn.execute("delete from import_stages", []).unwrap();
+    }
+
+    println!("Saving data to {:?}", sqlite_path);
 
     let re_noinclude = Regex::new(r"<noinclude>(?P<text>(?s:.)*?)</noinclude>").unwrap();
     let re_includeonly = Regex::new(r"<includeonly>(?P<text>(?s:.)*?)</includeonly>").unwrap();
-    let re_html_comment = Regex::new(r"<!--(?s:.)*?-->").unwrap();
-    // TODO: combine link REs into one
-    let re_display_link = Regex::new(r"\[\[[^\]]*?\|(?P<text>.*?)\]\]").unwrap();
-    let re_link = Regex::new(r"\[\[(?P<text>.*?)\]\]").unwrap();
-    // This technically doesn't work if some jerk decided to format a single quote.
-    let re_bold = Regex::new(r"'''(?P<text>[^']*?)'''").unwrap();
-    let re_italic = Regex::new(r"''(?P<text>[^']*?)''").unwrap();
-
-    define3::parse_xml::for_pages(&xml_path, |page| {
-        if page.title.starts_with("Template:") {
-            let content = page.content;
-            let content = re_noinclude.replace_all(&content, "");
-            let content = re_html_comment.replace_all(&content, "");
-            let content = content.into_owned();
-            let content = match re_includeonly.captures(&content) {
-                None => content.clone(),
-                Some(captures) => captures.name("text").unwrap().as_str().to_owned(),
-            };
-            let title = &page.title[9..];
+    let cleaner = MarkupCleaner::new();
+
+    if stage_done(&conn, "templates") {
+        println!("Pass 1: Already done, skipping");
+    } else {
+        println!("Pass 1: Collecting templates and modules");
+        let tx = Transaction::new(&mut conn, rusqlite::TransactionBehavior::Exclusive).unwrap();
+        let mut templates: HashMap<String, String> = HashMap::new();
+        let mut modules: HashMap<String, String> = HashMap::new();
+
+        tx.execute(
+            "CREATE TABLE IF NOT EXISTS templates (
+                 name           text not null,
+                 content        text not null,
+                 edition        text not null
+             )",
+            [],
+        )
+        .unwrap();
+        add_column_if_missing(&tx, "templates", "edition", "text not null default 'en'");
+        tx.execute("delete from templates where edition = ?1", [&edition])
+            .unwrap();
+
+        tx.execute(
+            "CREATE TABLE IF NOT EXISTS modules (
+                 name           text not null,
+                 content        text not null,
+                 edition        text not null
+             )",
+            [],
+        )
+        .unwrap();
+        add_column_if_missing(&tx, "modules", "edition", "text not null default 'en'");
+        tx.execute("delete from modules where edition = ?1", [&edition])
+            .unwrap();
+
+        // wiktextract has already expanded the templates, and doesn't pass them on.
+        if !jsonl {
+            for_pages_with_progress(
+                &xml_path,
+                |page| {
+                    if page.title.starts_with("Template:") {
+                        let content = page.content;
+                        let content = re_noinclude.replace_all(&content, "");
+                        let content = cleaner.strip_comments(&content);
+                        let content = content.into_owned();
+                        let content = match re_includeonly.captures(&content) {
+                            None => content.clone(),
+                            Some(captures) => captures.name("text").unwrap().as_str().to_owned(),
+                        };
+                        let title = &page.title[9..];
+                        tx.execute(
+                            "insert into templates (name, content, edition) values (?1, ?2, ?3)",
+                            [&title, &content.as_str(), &edition.as_str()],
+                        )
+                        .unwrap();
+                        templates.insert(title.to_owned(), content);
+                    } else if page.title.starts_with("Module:") {
+                        let title = &page.title[7..];
+                        tx.execute(
+                            "insert into modules (name, content, edition) values (?1, ?2, ?3)",
+                            [&title, &page.content.as_str(), &edition.as_str()],
+                        )
+                        .unwrap();
+
+                        println!("Saved module: {}", page.title);
+                        let path = format!("modules/{}.lua", page.title);
+                        let path = Path::new(&path);
+                        fs::create_dir_all(path.parent().unwrap()).unwrap();
+                        let mut file = File::create(path).unwrap();
+                        file.write_all(page.content.as_bytes()).unwrap();
+                        modules.insert(title.to_owned(), page.content);
+                    }
+                },
+                progress_bar("Pass 1"),
+            );
+        }
+        finish_stage(tx, "templates", &source);
+    }
+
+    // Only an import that ran pass 2 itself has anything to report.
+    let mut report: Option<ImportReport> = None;
+    if stage_done(&conn, "words") {
+        println!("Pass 2: Already done, skipping");
+    } else {
+        println!("Pass 2: Collecting words");
+        // A merge already copied them along with everything else.
+        if !merge {
+            carry_over_first_seen(&conn, &sqlite_path);
+        }
+        let tx = Transaction::new(&mut conn, rusqlite::TransactionBehavior::Exclusive).unwrap();
+        let mut count: u64 = 0;
+        let mut import_report = ImportReport {
+            previous_definitions_by_language: previous_counts(&sqlite_path, &edition),
+            ..ImportReport::default()
+        };
+
+        tx.execute(
+            "CREATE TABLE IF NOT EXISTS words (
+                 name           text not null,
+                 language       text not null,
+                 part_of_speech text not null,
+                 definition     text not null,
+                 expanded       text,
+                 edition        text not null,
+                 normalized_name text,
+                 unaccented_name text
+             )",
+            [],
+        )
+        .unwrap();
+        add_column_if_missing(&tx, "words", "expanded", "text");
+        add_column_if_missing(&tx, "words", "edition", "text not null default 'en'");
+        add_column_if_missing(&tx, "words", "normalized_name", "text");
+        add_column_if_missing(&tx, "words", "unaccented_name", "text");
+        tx.execute("delete from words where edition = ?1", [&edition])
+            .unwrap();
+        // Cached expansions are by rowid, which no longer mean the same rows.
+        tx.execute(&format!("DROP TABLE IF EXISTS {}", CACHE_TABLE), [])
+            .unwrap();
+        // Other editions merged in were folded the same way unless they were built before the
+        // column existed, or with other folds.
+        let previous_folds = get_metadata(&tx, "normalized_name_folds")
+            .unwrap_or_else(|| folds_to_string(DEFAULT_FOLDS));
+        let unnormalized = if previous_folds == folds_to_string(&folds) {
+            "select rowid, name from words where normalized_name is null"
+        } else {
+            "select rowid, name from words"
+        };
+        let unnormalized: Vec<(i64, String)> = tx
+            .prepare(unnormalized)
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+        for (rowid, name) in unnormalized {
             tx.execute(
-                "insert into templates (name, content) values (?1, ?2)",
-                &[&title, &content.as_str()],
+                "update words set normalized_name = ?1 where rowid = ?2",
+                rusqlite::params![fold(&name, &folds), rowid],
             )
             .unwrap();
-            templates.insert(title.to_owned(), content);
-        } else if page.title.starts_with("Module:") {
-            let title = &page.title[7..];
+        }
+        set_metadata(&tx, "normalized_name_folds", &folds_to_string(&folds));
+        // Editions merged in from before the column existed.
+        let unaccented: Vec<(i64, String)> = tx
+            .prepare("select rowid, name from words where unaccented_name is null")
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+        for (rowid, name) in unaccented {
             tx.execute(
-                "insert into modules (name, content) values (?1, ?2)",
-                [&title, &page.content.as_str()],
+                "update words set unaccented_name = ?1 where rowid = ?2",
+                rusqlite::params![fold_diacritics(&name), rowid],
             )
             .unwrap();
-
-            println!("Saved module: {}", page.title);
-            let path = format!("modules/{}.lua", page.title);
-            let path = Path::new(&path);
-            fs::create_dir_all(path.parent().unwrap()).unwrap();
-            let mut file = File::create(path).unwrap();
-            file.write_all(page.content.as_bytes()).unwrap();
-            modules.insert(title.to_owned(), page.content);
         }
-    });
 
-    println!("Pass 2: Collecting words");
+        tx.execute(
+            "CREATE TABLE IF NOT EXISTS relations (
+                 name           text not null,
+                 language       text not null,
+                 part_of_speech text,
+                 relation       text not null,
+                 target         text not null,
+                 edition        text not null,
+                 sense          text
+             )",
+            [],
+        )
+        .unwrap();
+        add_column_if_missing(&tx, "relations", "edition", "text not null default 'en'");
+        add_column_if_missing(&tx, "relations", "sense", "text");
+        tx.execute("delete from relations where edition = ?1", [&edition])
+            .unwrap();
+        // An etymology's parts of speech are separated by tabs.
+        tx.execute(
+            "CREATE TABLE IF NOT EXISTS etymologies (
+                 name            text not null,
+                 language        text not null,
+                 number          integer not null,
+                 text            text not null,
+                 parts_of_speech text not null,
+                 edition         text not null
+             )",
+            [],
+        )
+        .unwrap();
+        tx.execute("delete from etymologies where edition = ?1", [&edition])
+            .unwrap();
+        // A row for each transcription, recording or hyphenation; `kind` is "ipa", "audio" or
+        // "hyphenation".
+        tx.execute(
+            "CREATE TABLE IF NOT EXISTS pronunciations (
+                 name     text not null,
+                 language text not null,
+                 kind     text not null,
+                 value    text not null,
+                 edition  text not null
+             )",
+            [],
+        )
+        .unwrap();
+        tx.execute("delete from pronunciations where edition = ?1", [&edition])
+            .unwrap();
+        // An example belongs to the definition in `words` with the same name, language, part of
+        // speech and definition.
+        tx.execute(
+            "CREATE TABLE IF NOT EXISTS examples (
+                 name           text not null,
+                 language       text not null,
+                 part_of_speech text not null,
+                 definition     text not null,
+                 text           text not null,
+                 translation    text,
+                 edition        text not null
+             )",
+            [],
+        )
+        .unwrap();
+        tx.execute("delete from examples where edition = ?1", [&edition])
+            .unwrap();
+        // A row for each of a part of speech's grammatical forms, e.g. its plural, with `value`
+        // empty for labels that stand alone, like "uncountable".
+        tx.execute(
+            "CREATE TABLE IF NOT EXISTS headwords (
+                 name           text not null,
+                 language       text not null,
+                 part_of_speech text not null,
+                 label          text not null,
+                 value          text not null,
+                 edition        text not null
+             )",
+            [],
+        )
+        .unwrap();
+        tx.execute("delete from headwords where edition = ?1", [&edition])
+            .unwrap();
 
-    tx.execute("DROP TABLE IF EXISTS words", []).unwrap();
-    tx.execute(
-        "CREATE TABLE words (
-             name           text not null,
-             language       text not null,
-             part_of_speech text not null,
-             definition     text not null
-         )",
-        [],
-    )
-    .unwrap();
+        // Pass 2 is a pipeline: one thread reads pages out of the XML, `threads` workers parse
+        // them, and this thread writes the results. The channels are bounded so that a slow stage
+        // makes the ones before it wait instead of piling pages up in memory.
+        let capacity = if low_memory() {
+            LOW_MEMORY_CHANNEL_CAPACITY
+        } else {
+            CHANNEL_CAPACITY
+        };
+        let corrector = if fix_headers {
+            Some(HeaderCorrector::new(&parts_of_speech, header_whitelist))
+        } else {
+            None
+        };
+        let (page_sender, page_receiver) = crossbeam_channel::bounded::<Page>(capacity);
+        let (word_sender, word_receiver) =
+            crossbeam_channel::bounded::<(Word, ParsedWikitext)>(capacity);
+        std::thread::scope(|scope| {
+            let xml_path = &xml_path;
+            let entry_sender = word_sender.clone();
+            let (languages, parts_of_speech) = (&languages, &parts_of_speech);
+            let corrector = corrector.as_ref();
+            scope.spawn(move || {
+                if jsonl {
+                    // Entries are already parsed, so they skip the workers.
+                    drop(page_sender);
+                    for_entries(
+                        xml_path,
+                        languages,
+                        parts_of_speech,
+                        |entry| match entry {
+                            Ok(entry) => entry_sender.send(entry).unwrap(),
+                            Err(e) => eprintln!("\n{}: {}", xml_path, e),
+                        },
+                        progress_bar("Pass 2"),
+                    );
+                } else {
+                    drop(entry_sender);
+                    for_pages_with_progress(
+                        xml_path,
+                        |page| page_sender.send(page).unwrap(),
+                        progress_bar("Pass 2"),
+                    );
+                }
+            });
 
-    define3::parse_xml::for_pages(&xml_path, |page| {
-        let page_content = match page.title.split(':').next() {
-            Some("Template") => Box::new(PageContent::Template(Template {
-                name: page.title,
-                content: page.content,
-            })),
-            Some("Module") => Box::new(PageContent::Module(Module {
-                name: page.title,
-                src: page.content,
-            })),
-            _ => {
-                let meanings = parse_wikitext(page.content, &languages, &parts_of_speech);
-                Box::new(PageContent::Word(Word {
-                    name: page.title,
-                    meanings: meanings,
-                }))
+            for _ in 0..threads {
+                let page_receiver = page_receiver.clone();
+                let word_sender = word_sender.clone();
+                let cleaner = &cleaner;
+                scope.spawn(move || {
+                    for page in page_receiver {
+                        let mut parsed = ParsedWikitext::default();
+                        let page_content = match page.title.split(':').next() {
+                            Some("Template") => Box::new(PageContent::Template(Template {
+                                name: page.title,
+                                content: page.content,
+                            })),
+                            Some("Module") => Box::new(PageContent::Module(Module {
+                                name: page.title,
+                                src: page.content,
+                            })),
+                            _ => {
+                                parsed = parse_wikitext_with(
+                                    page.content,
+                                    languages,
+                                    parts_of_speech,
+                                    corrector,
+                                );
+                                let title = &page.title;
+                                let headwords = parsed
+                                    .headword_templates
+                                    .iter()
+                                    .map(|(language, part_of_speech, template)| Headword {
+                                        language: language.clone(),
+                                        part_of_speech: part_of_speech.clone(),
+                                        forms: grammar(template, title),
+                                    })
+                                    .filter(|headword| !headword.forms.is_empty())
+                                    .collect();
+                                Box::new(PageContent::Word(Word {
+                                    name: page.title,
+                                    meanings: mem::take(&mut parsed.meanings),
+                                    relations: mem::take(&mut parsed.relations),
+                                    etymologies: mem::take(&mut parsed.etymologies),
+                                    pronunciations: mem::take(&mut parsed.pronunciations),
+                                    examples: mem::take(&mut parsed.examples),
+                                    headwords,
+                                }))
+                            }
+                        };
+                        if let PageContent::Word(mut word) = *page_content {
+                            for etymology in &mut word.etymologies {
+                                etymology.text = cleaner.clean(&etymology.text);
+                            }
+                            for example in &mut word.examples {
+                                example.definition = cleaner.clean(&example.definition);
+                                example.text = cleaner.clean(&example.text);
+                                example.translation =
+                                    example.translation.as_deref().map(|s| cleaner.clean(s));
+                            }
+                            for relation in &mut word.relations {
+                                relation.sense =
+                                    relation.sense.as_deref().map(|s| cleaner.clean(s));
+                            }
+                            for meaning in &mut word.meanings {
+                                meaning.definition = cleaner.clean(&meaning.definition);
+                                if pre_expand {
+                                    let (expanded, _) =
+                                        expand(&meaning.definition, &TemplatePolicies::default());
+                                    meaning.expanded = Some(expanded);
+                                }
+                            }
+                            // What's left in `parsed` is for the import report.
+                            word_sender.send((word, parsed)).unwrap();
+                        }
+                    }
+                });
             }
-        };
-        match *page_content {
-            PageContent::Word(word) => {
+            // The workers have their own copies; the loop below ends once they've all hung up.
+            drop(page_receiver);
+            drop(word_sender);
+
+            let mut insert_word = tx
+                .prepare(
+                    "insert into words
+                     (name, language, part_of_speech, definition, expanded, edition,
+                      normalized_name, unaccented_name)
+                     values (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                )
+                .unwrap();
+            let mut insert_relation = tx
+                .prepare(
+                    "insert into relations
+                     (name, language, part_of_speech, relation, target, edition, sense)
+                     values (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                )
+                .unwrap();
+            let mut insert_etymology = tx
+                .prepare(
+                    "insert into etymologies
+                     (name, language, number, text, parts_of_speech, edition)
+                     values (?1, ?2, ?3, ?4, ?5, ?6)",
+                )
+                .unwrap();
+            let mut insert_pronunciation = tx
+                .prepare(
+                    "insert into pronunciations (name, language, kind, value, edition)
+                     values (?1, ?2, ?3, ?4, ?5)",
+                )
+                .unwrap();
+            let mut insert_example = tx
+                .prepare(
+                    "insert into examples
+                     (name, language, part_of_speech, definition, text, translation, edition)
+                     values (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                )
+                .unwrap();
+            let mut insert_headword = tx
+                .prepare(
+                    "insert into headwords
+                     (name, language, part_of_speech, label, value, edition)
+                     values (?1, ?2, ?3, ?4, ?5, ?6)",
+                )
+                .unwrap();
+            for (word, parsed) in word_receiver {
+                import_report.add_page(&word, &parsed);
                 count += 1;
-                if count % 1000000 == 0 {
+                if count.is_multiple_of(1000000) {
                     println!("{}: {}", count, word.name);
                 }
+                let normalized_name = fold(&word.name, &folds);
+                let unaccented_name = fold_diacritics(&word.name);
                 for meaning in &word.meanings {
-                    let defn = &meaning.definition;
-                    //let defn = re_link.replace_all(&defn, "\x1b[0;36m$x\x1b[0m");
-                    let defn = re_display_link.replace_all(&defn, "$text");
-                    let defn = re_link.replace_all(&defn, "$text");
-                    let defn = re_html_comment.replace_all(&defn, "");
-                    let defn = re_bold.replace_all(&defn, "$text");
-                    let defn = re_italic.replace_all(&defn, "$text");
-                    tx.execute(
-                        "insert into words (name, language, part_of_speech, definition)
-                 values (?1, ?2, ?3, ?4)",
-                        &[
+                    insert_word
+                        .execute(rusqlite::params![
                             &word.name,
                             &meaning.language,
                             &meaning.part_of_speech,
-                            &defn.into_owned(),
-                        ],
-                    )
-                    .unwrap();
+                            &meaning.definition,
+                            &meaning.expanded,
+                            &edition,
+                            &normalized_name,
+                            &unaccented_name,
+                        ])
+                        .unwrap();
+                }
+                for relation in &word.relations {
+                    insert_relation
+                        .execute(rusqlite::params![
+                            &word.name,
+                            &relation.language,
+                            &relation.part_of_speech,
+                            &relation.kind,
+                            &relation.target,
+                            &edition,
+                            &relation.sense,
+                        ])
+                        .unwrap();
+                }
+                for etymology in &word.etymologies {
+                    insert_etymology
+                        .execute(rusqlite::params![
+                            &word.name,
+                            &etymology.language,
+                            &etymology.number,
+                            &etymology.text,
+                            &etymology.parts_of_speech.join("\t"),
+                            &edition,
+                        ])
+                        .unwrap();
+                }
+                for headword in &word.headwords {
+                    for (label, value) in &headword.forms {
+                        insert_headword
+                            .execute(rusqlite::params![
+                                &word.name,
+                                &headword.language,
+                                &headword.part_of_speech,
+                                label,
+                                value,
+                                &edition,
+                            ])
+                            .unwrap();
+                    }
+                }
+                for example in &word.examples {
+                    insert_example
+                        .execute(rusqlite::params![
+                            &word.name,
+                            &example.language,
+                            &example.part_of_speech,
+                            &example.definition,
+                            &example.text,
+                            &example.translation,
+                            &edition,
+                        ])
+                        .unwrap();
+                }
+                for pronunciation in &word.pronunciations {
+                    let kinds = [
+                        ("ipa", &pronunciation.ipa),
+                        ("audio", &pronunciation.audio),
+                        ("hyphenation", &pronunciation.hyphenation),
+                    ];
+                    for (kind, values) in kinds {
+                        for value in values {
+                            insert_pronunciation
+                                .execute(rusqlite::params![
+                                    &word.name,
+                                    &pronunciation.language,
+                                    kind,
+                                    value,
+                                    &edition,
+                                ])
+                                .unwrap();
+                        }
+                    }
                 }
             }
-            _ => (),
-        }
-    });
+        });
+        let dump_date = dump_date.clone().unwrap_or_else(|| {
+            let modified = fs::metadata(&xml_path)
+                .and_then(|metadata| metadata.modified())
+                .ok()
+                .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                .map_or(0, |duration| duration.as_secs() as i64);
+            tx.query_row("select date(?1, 'unixepoch')", [modified], |row| row.get(0))
+                .unwrap()
+        });
+        create_first_seen(&tx);
+        tx.execute(
+            "insert or ignore into first_seen (name, language, edition, dump_date)
+             select distinct name, language, edition, ?1 from words where edition = ?2",
+            [&dump_date, &edition],
+        )
+        .unwrap();
+        finish_stage(tx, "words", &source);
+        report = Some(import_report);
+    }
 
-    tx.execute_batch(
-        "create index words_name_idx on words(name);
-         create index words_language_idx on words(language);
-         create index words_part_of_speech_idx on words(part_of_speech);",
-    )
-    .unwrap();
+    if !stage_done(&conn, "search_index") {
+        println!("Indexing definitions for reverse lookups");
+        let tx = Transaction::new(&mut conn, rusqlite::TransactionBehavior::Exclusive).unwrap();
+        build_search_index(&tx).unwrap();
+        finish_stage(tx, "search_index", &source);
+    }
 
-    tx.commit().unwrap();
+    if compress && !stage_done(&conn, "compress") {
+        println!("Compressing definitions and templates");
+        let tx = Transaction::new(&mut conn, rusqlite::TransactionBehavior::Exclusive).unwrap();
+        compress_database(&tx);
+        finish_stage(tx, "compress", &source);
+    }
+
+    if !stage_done(&conn, "indexes") {
+        println!("Creating indexes");
+        let tx = Transaction::new(&mut conn, rusqlite::TransactionBehavior::Exclusive).unwrap();
+        tx.execute_batch(
+            "drop index if exists words_name_idx;
+             drop index if exists words_language_idx;
+             drop index if exists words_part_of_speech_idx;
+             drop index if exists relations_name_idx;
+             drop index if exists relations_target_idx;
+             drop index if exists etymologies_name_idx;
+             drop index if exists pronunciations_name_idx;
+             drop index if exists examples_name_idx;
+             drop index if exists headwords_name_idx;
+             drop index if exists words_edition_idx;
+             drop index if exists words_normalized_name_idx;
+             drop index if exists words_name_nocase_idx;
+             drop index if exists words_unaccented_name_idx;
+             create index words_name_idx on words(name);
+             create index words_language_idx on words(language);
+             create index words_part_of_speech_idx on words(part_of_speech);
+             create index relations_name_idx on relations(name);
+             create index relations_target_idx on relations(target);
+             create index etymologies_name_idx on etymologies(name);
+             create index pronunciations_name_idx on pronunciations(name);
+             create index examples_name_idx on examples(name);
+             create index headwords_name_idx on headwords(name);
+             create index words_edition_idx on words(edition);
+             create index words_normalized_name_idx on words(normalized_name);
+             create index words_name_nocase_idx on words(name collate nocase);
+             create index words_unaccented_name_idx on words(unaccented_name);",
+        )
+        .unwrap();
+        finish_stage(tx, "indexes", &source);
+    }
+
+    // Every import gets a new build id, even one that's resumed or merged into an existing
+    // database, since any of them can change what an entry looks like.
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+    let build_id = entry_checksum(&source, &now.as_nanos().to_string(), &[]);
+    set_metadata(&conn, "build_id", &build_id);
+
+    if compress {
+        // Compressing in place leaves the old text's pages free but still in the file.
+        println!("Reclaiming space");
+        conn.execute_batch("VACUUM").unwrap();
+    }
+
+    // Close the connection first so nothing is left in the journal, then swap the finished
+    // database in. A rename within one directory is atomic.
+    drop(conn);
+    fs::rename(&partial_path, &sqlite_path).unwrap();
+
+    if let Some(report) = report {
+        let report_path = matches
+            .opt_str("report")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| sqlite_path.with_file_name("import-report.json"));
+        fs::write(&report_path, report.to_json()).unwrap();
+        println!();
+        print!("{}", report.summary());
+        println!("Full report saved to {:?}", report_path);
+    }
 }
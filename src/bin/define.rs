@@ -1,166 +1,3881 @@
 extern crate colored;
 extern crate define3;
 extern crate getopts;
+#[cfg(unix)]
+extern crate libc;
 extern crate nom;
 extern crate regex;
 extern crate rusqlite;
+extern crate serde_json;
 extern crate textwrap;
 
-use define3::Meaning;
+use define3::abbreviations::Abbreviations;
+use define3::anki::{write_package, Note};
+use define3::checksum::entry_checksum;
+use define3::config::{config_path, is_alias_name, set_alias, ColorChoice, Config};
+use define3::coverage::template_coverage;
+use define3::dict_server::{self, DEFAULT_PORT as DICT_PORT};
+use define3::dictionary::{inverse_relation, Dictionary};
+use define3::digest::{digest_entries, digest_html, digest_text};
+use define3::dot;
+use define3::dump::{dump, DumpFormat};
+use define3::embed::meanings_html;
+use define3::entry_diff::{diff_meanings, Change};
+use define3::error::Error;
+use define3::etymology::{origins, Origin};
+use define3::expand::{expand_with, ExpansionIssue, NoTemplates, TemplatePolicies};
+use define3::export::{export, ExportFormat};
+use define3::fetch::{installed_version, record_version, Release};
+use define3::history::History;
+use define3::http_server::{self, DEFAULT_ADDRESS as HTTP_ADDRESS};
+use define3::json::entry_json;
+use define3::languages::{language_by_name, normalize_language};
+use define3::levels::{flashcards, list_language, parse_list, write_cards, CardFormat};
+use define3::locale::Locale;
+use define3::lookup::{group_by_language, Grouped, LookupResult, SearchOptions};
+use define3::memory::{detect_low_memory, set_low_memory};
+use define3::normalize::Normalizers;
+use define3::pagination::Cursor;
+use define3::parse_wikitext::{
+    parse_wikitext, script_pointer, MarkupCleaner, IMPORTED_LANGUAGES, IMPORTED_PARTS_OF_SPEECH,
+};
+use define3::permalink::wiktionary_url;
+use define3::query::{
+    contains_pattern, CancelToken, MeaningFilter, PartialSearchLimits, Query, QueryRequest,
+    QueryResponse,
+};
+use define3::ranking::sort_common_first;
+use define3::references::form_of;
+use define3::render::{escape_html, Formatters, Truncator, Typography};
+use define3::reverse::SEARCH_TABLE;
+use define3::romanization::kanji_spellings;
+use define3::scrape::{write_dump, Scraper, DEFAULT_DELAY};
+use define3::scripts::{group_by_script, Script};
+use define3::template_corpus::{first_difference, parse_corpus, run_corpus};
+use define3::templates::{parse_templates, Node};
+#[cfg(unix)]
+use define3::tui;
+use define3::{Etymology, Example, Headword, Meaning, Pronunciation, Relation};
 
 use colored::*;
 use getopts::Options;
-use regex::{Captures, Regex};
-use rusqlite::Connection;
-use std::collections::BTreeMap;
+use regex::Regex;
+use std::collections::HashSet;
 use std::env;
-use std::path::Path;
+use std::io::{BufRead, IsTerminal, Read, Write};
+use std::net::TcpListener;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Mutex, Once, OnceLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-fn get_defns_by_lang(
-    conn: &Connection,
+// Default for --max-bytes. Some entries (single CJK characters, "a") are large enough that
+// expanding all of their templates takes seconds.
+const DEFAULT_MAX_BYTES: usize = 1 << 16;
+
+struct PrintOptions {
+    /// Once this many bytes of definitions have been printed, the rest of the entry is skipped.
+    max_bytes: Option<usize>,
+    /// Link each language to its section on Wiktionary.
+    permalinks: bool,
+    /// Say what each line is in words rather than with color and indentation alone, and put
+    /// each sentence on its own line instead of wrapping, for screen readers.
+    accessible: bool,
+    /// Never break a word when wrapping, even one longer than the line.
+    plain: bool,
+    sense_marker: SenseMarker,
+    /// How quotes and dashes are written in definitions, examples and etymologies.
+    typography: Typography,
+    /// Definitions are wrapped to this many columns.
+    width: usize,
+    /// Limits on the whole output, for status bars, notifications and bots; see `Truncator`.
+    max_chars: Option<usize>,
+    max_lines: Option<usize>,
+}
+
+/// What each definition starts with.
+#[derive(Clone, Copy, PartialEq)]
+enum SenseMarker {
+    None,
+    /// "1.", "2.", ... within each part of speech.
+    Number,
+    Dash,
+    Star,
+}
+
+impl FromStr for SenseMarker {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<SenseMarker, String> {
+        match s {
+            "none" => Ok(SenseMarker::None),
+            "number" => Ok(SenseMarker::Number),
+            "dash" => Ok(SenseMarker::Dash),
+            "star" => Ok(SenseMarker::Star),
+            _ => Err(format!(
+                "unknown sense marker {:?} (expected none, number, dash or star)",
+                s
+            )),
+        }
+    }
+}
+
+impl SenseMarker {
+    fn marker(self, i: usize) -> String {
+        match self {
+            SenseMarker::None => String::new(),
+            SenseMarker::Number => format!("{}. ", i + 1),
+            SenseMarker::Dash => "- ".to_owned(),
+            SenseMarker::Star => "* ".to_owned(),
+        }
+    }
+}
+
+fn sentence_end_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"[.!?]\s+\p{Lu}").unwrap())
+}
+
+// Splits text after full stops (and ! and ?) that are followed by a capital letter, which
+// leaves abbreviations like "e.g." alone.
+fn sentences(s: &str) -> Vec<&str> {
+    let mut sentences = Vec::new();
+    let mut start = 0;
+    for m in sentence_end_regex().find_iter(s) {
+        sentences.push(s[start..m.start() + 1].trim());
+        start = m.start() + 1;
+    }
+    sentences.push(s[start..].trim());
+    sentences.retain(|sentence| !sentence.is_empty());
+    sentences
+}
+
+/// What's printed with each language besides its definitions: its headwords' grammar, and the
+/// rest when asked for. It's printed as it is, so anything with templates in it should be
+/// expanded first.
+#[derive(Default)]
+struct Extras {
+    headwords: Vec<Headword>,
+    pronunciations: Vec<Pronunciation>,
+    etymologies: Vec<Etymology>,
+    /// Synonyms and antonyms.
+    thesaurus: Vec<Relation>,
+    /// Printed under the definitions they're examples of.
+    examples: Vec<Example>,
+    /// The word's spellings in other scripts, under its language.
+    other_scripts: Vec<Relation>,
+}
+
+// A headword's grammar as it goes after its part of speech, e.g. "m, plural chats": genders on
+// their own, then the labeled forms.
+fn grammar_summary(headword: &Headword) -> String {
+    let (genders, forms): (Vec<_>, Vec<_>) = headword
+        .forms
+        .iter()
+        .partition(|(label, _)| label == "gender");
+    let mut parts: Vec<String> = genders.into_iter().map(|(_, g)| g.clone()).collect();
+    parts.extend(forms.into_iter().map(|(label, form)| {
+        if form.is_empty() {
+            label.clone()
+        } else {
+            format!("{} {}", label, form)
+        }
+    }));
+    parts.join(", ")
+}
+
+// (relation, heading) for what the thesaurus lists.
+const THESAURUS: &[(&str, &str)] = &[("synonym", "Synonyms"), ("antonym", "Antonyms")];
+
+/// Prints definitions, expanding each one only right before it is printed, with each language's
+/// IPA under its name, each part of speech's grammar after it, examples under their definitions,
+/// and its thesaurus and etymologies after its definitions.
+fn print_words<F>(
     word: &str,
-) -> Box<BTreeMap<String, BTreeMap<String, Vec<String>>>> {
-    let mut stmt = conn
-        .prepare("SELECT language, part_of_speech, definition FROM words WHERE name = ?1")
-        .unwrap();
-    let word_iter = stmt
-        .query_map(&[&word], |row| {
-            Ok(Meaning {
-                language: row.get(0).unwrap(),
-                part_of_speech: row.get(1).unwrap(),
-                definition: row.get(2).unwrap(),
-            })
-        })
-        .unwrap();
-
-    let mut langs: BTreeMap<String, BTreeMap<String, Vec<String>>> = BTreeMap::new();
-
-    for meaning in word_iter {
-        let meaning = meaning.unwrap();
-        langs
-            .entry(meaning.language)
-            .or_insert(BTreeMap::new())
-            .entry(meaning.part_of_speech)
-            .or_insert(Vec::new())
-            .push(meaning.definition);
-    }
-    Box::new(langs)
-}
-
-// TODO: Actually expand templates. This is very hard because Wikitext templates have a bunch of
-// functions and often call out into Lua code.
-// https://www.mediawiki.org/wiki/Help:Extension:ParserFunctions
-// https://www.mediawiki.org/wiki/Extension:Scribunto
-#[allow(dead_code)]
-fn expand_template(conn: &Connection, args: &[&str]) -> String {
-    fn get_template_content(conn: &Connection, name: &str) -> String {
-        let result = conn.query_row(
-            "SELECT content FROM templates WHERE name = ?1",
-            &[&name],
-            |row| row.get(0),
-        );
-        println!("{}", name);
-        result.unwrap()
-    }
-    get_template_content(conn, args[0])
-}
-#[warn(dead_code)]
-
-// For now, we just hardcode a couple common templates.
-fn replace_template(_conn: &Connection, caps: &Captures) -> String {
-    let s = caps.get(1).unwrap().as_str();
-    let elems: Vec<&str> = s.split('|').collect();
-    //match elems[0] {
-    //    _ => expand_template(conn, &elems)
-    //}
-    match elems[0] {
-        "," => ",".to_owned(),
-        "ngd" | "unsupported" | "non-gloss definition" => elems[1].to_owned(),
-        "alternative form of" => format!("Alternative form of {}", elems[1]),
-        "ja-romanization of" => format!("Rōmaji transcription of {}", elems[1]),
-        "sumti" => format!("x{}", elems[1]),
-        "ja-def" => format!("{}:", elems[1]),
-        "qualifier" => format!("({})", elems[1]),
-        "lb" => format!("({})", elems[2]),
-        "m" | "l" => elems[2].to_owned(),
-        _ => caps.get(0).unwrap().as_str().to_owned(),
-    }
-}
-
-fn print_words<F>(langs: &BTreeMap<String, BTreeMap<String, Vec<String>>>, mut format: F)
-where
-    F: FnMut(&str) -> String,
+    langs: &Grouped,
+    extras: &Extras,
+    formatters: &Formatters,
+    options: &PrintOptions,
+    mut format: F,
+) where
+    F: FnMut(&Meaning) -> String,
 {
-    let textwrap_opts = textwrap::Options::new(80)
-        .initial_indent("    ")
-        .subsequent_indent("      ");
+    let max_bytes = options.max_bytes;
 
-    for (lang, poses) in langs {
-        println!("{}", lang.green().bold());
+    let total: usize = langs
+        .iter()
+        .flat_map(|(_, poses)| poses.values())
+        .map(Vec::len)
+        .sum();
+    let mut printed = 0;
+    let mut bytes = 0;
+    let over_limit = |bytes: usize| max_bytes.is_some_and(|max| bytes >= max);
+    let mut out = Truncator::new(options.max_chars, options.max_lines);
+    // Prints a block of output, a sense if the first argument is true, if it fits.
+    macro_rules! emit {
+        ($sense:expr, $($arg:tt)*) => {
+            if let Some(block) = out.push(format!($($arg)*), $sense) {
+                println!("{}", block);
+            }
+        };
+    }
+
+    'langs: for (lang, poses) in langs {
+        if over_limit(bytes) || out.is_full() {
+            break;
+        }
+        if options.accessible {
+            emit!(false, "Language: {}", lang);
+        } else {
+            emit!(false, "{}", lang.green().bold());
+        }
+        for pronunciation in extras.pronunciations.iter().filter(|p| &p.language == lang) {
+            if !pronunciation.ipa.is_empty() {
+                let ipa = pronunciation.ipa.join(", ");
+                if options.accessible {
+                    emit!(false, "  IPA: {}", ipa);
+                } else {
+                    emit!(false, "  {} {}", "IPA".white(), ipa);
+                }
+            }
+        }
+        let spellings: Vec<String> = extras
+            .other_scripts
+            .iter()
+            .filter(|spelling| &spelling.language == lang)
+            .map(|spelling| {
+                format!(
+                    "{} ({})",
+                    spelling.target,
+                    Script::of(&spelling.target).name()
+                )
+            })
+            .collect();
+        if !spellings.is_empty() {
+            if options.accessible {
+                emit!(false, "  Also written: {}", spellings.join(", "));
+            } else {
+                emit!(
+                    false,
+                    "  {}",
+                    format!("Also written {}", spellings.join(", ")).dimmed()
+                );
+            }
+        }
+        let meanings: Vec<&Meaning> = poses.values().flatten().collect();
+        for line in formatters.headword_lines(lang, word, &meanings) {
+            emit!(false, "  {}", line.dimmed());
+        }
+        if options.permalinks {
+            // Databases from before editions were tracked only have en.
+            let mut editions: Vec<&str> = meanings
+                .iter()
+                .map(|meaning| meaning.edition.as_deref().unwrap_or("en"))
+                .collect();
+            editions.sort_unstable();
+            editions.dedup();
+            for edition in editions {
+                emit!(false, "  {}", wiktionary_url(edition, word, lang));
+            }
+        }
         for (pos, defns) in poses {
-            println!("  {}", pos.white());
-            for defn in defns {
-                let defn = format(defn);
-                let defn = textwrap::fill(&defn, &textwrap_opts);
-                println!("{}", defn);
+            if over_limit(bytes) || out.is_full() {
+                break 'langs;
+            }
+            let grammar = extras
+                .headwords
+                .iter()
+                .find(|headword| &headword.language == lang && &headword.part_of_speech == pos)
+                .map(grammar_summary)
+                .filter(|grammar| !grammar.is_empty());
+            match (options.accessible, grammar) {
+                (true, Some(grammar)) => emit!(false, "  Part of speech: {}; {}", pos, grammar),
+                (true, None) => emit!(false, "  Part of speech: {}", pos),
+                (false, Some(grammar)) => {
+                    let textwrap_opts = textwrap::Options::new(options.width)
+                        .initial_indent("  ")
+                        .subsequent_indent("      ")
+                        .break_words(!options.plain);
+                    let grammar = format!("({})", grammar).dimmed();
+                    let line = format!("{} {}", pos.white(), grammar);
+                    emit!(false, "{}", textwrap::fill(&line, &textwrap_opts))
+                }
+                (false, None) => emit!(false, "  {}", pos.white()),
+            }
+            for (i, meaning) in defns.iter().enumerate() {
+                if over_limit(bytes) || out.is_full() {
+                    break 'langs;
+                }
+                let defn = options.typography.apply(&format(meaning));
+                bytes += defn.len();
+                printed += 1;
+                if options.accessible {
+                    let mut label = format!("Definition {}: ", i + 1);
+                    let mut lines = Vec::new();
+                    for sentence in defn.lines().flat_map(sentences) {
+                        lines.push(format!("    {}{}", label, sentence));
+                        label = String::new();
+                    }
+                    emit!(true, "{}", lines.join("\n"));
+                } else {
+                    let marker = format!("    {}", options.sense_marker.marker(i));
+                    // Continuation lines are indented past the marker, or a little without one.
+                    let indent = " ".repeat(marker.len().max(6));
+                    let textwrap_opts = textwrap::Options::new(options.width)
+                        .initial_indent(&marker)
+                        .subsequent_indent(&indent)
+                        .break_words(!options.plain);
+                    emit!(true, "{}", textwrap::fill(&defn, &textwrap_opts));
+                }
+                let examples = extras.examples.iter().filter(|example| {
+                    example.language == meaning.language
+                        && example.part_of_speech == meaning.part_of_speech
+                        && example.definition == meaning.definition
+                });
+                for example in examples {
+                    let text = options.typography.apply(&example.text);
+                    let translation = example
+                        .translation
+                        .as_ref()
+                        .map(|translation| options.typography.apply(translation));
+                    if options.accessible {
+                        emit!(false, "      Example: {}", text);
+                        if let Some(ref translation) = translation {
+                            emit!(false, "      Translation: {}", translation);
+                        }
+                        continue;
+                    }
+                    let textwrap_opts = textwrap::Options::new(options.width)
+                        .initial_indent("        ")
+                        .subsequent_indent("        ")
+                        .break_words(!options.plain);
+                    let mut lines = vec![textwrap::fill(&text, &textwrap_opts)];
+                    if let Some(ref translation) = translation {
+                        let textwrap_opts = textwrap_opts
+                            .initial_indent("          ")
+                            .subsequent_indent("          ");
+                        lines.push(textwrap::fill(translation, &textwrap_opts));
+                    }
+                    emit!(false, "{}", lines.join("\n").dimmed());
+                }
+            }
+        }
+        let thesaurus: Vec<&Relation> = extras
+            .thesaurus
+            .iter()
+            .filter(|relation| &relation.language == lang)
+            .collect();
+        if !thesaurus.is_empty() {
+            if options.accessible {
+                emit!(false, "  Thesaurus");
+            } else {
+                emit!(false, "  {}", "Thesaurus".white());
+            }
+            // In the order they're first listed in, with the ones not for any one sense last.
+            let mut senses: Vec<Option<&str>> = Vec::new();
+            for relation in &thesaurus {
+                if !senses.contains(&relation.sense.as_deref()) {
+                    senses.push(relation.sense.as_deref());
+                }
+            }
+            senses.sort_by_key(Option::is_none);
+            for &sense in &senses {
+                match sense {
+                    Some(sense) => emit!(false, "    {}", sense),
+                    None if senses.len() > 1 => emit!(false, "    Other senses"),
+                    None => (),
+                }
+                for &(kind, heading) in THESAURUS {
+                    let mut targets: Vec<&str> = Vec::new();
+                    for relation in &thesaurus {
+                        if relation.kind == kind
+                            && relation.sense.as_deref() == sense
+                            && !targets.contains(&relation.target.as_str())
+                        {
+                            targets.push(&relation.target);
+                        }
+                    }
+                    if !targets.is_empty() {
+                        emit!(false, "      {}: {}", heading, targets.join(", "));
+                    }
+                }
+            }
+        }
+        for etymology in extras.etymologies.iter().filter(|e| &e.language == lang) {
+            let mut title = match etymology.number {
+                0 => "Etymology".to_owned(),
+                n => format!("Etymology {}", n),
+            };
+            if !etymology.parts_of_speech.is_empty() {
+                title = format!("{} ({})", title, etymology.parts_of_speech.join(", "));
+            }
+            let text = options.typography.apply(&etymology.text);
+            if options.accessible {
+                let mut label = format!("{}: ", title);
+                for sentence in text.lines().flat_map(sentences) {
+                    emit!(false, "  {}{}", label, sentence);
+                    label = String::new();
+                }
+            } else {
+                emit!(false, "  {}", title.white());
+                let textwrap_opts = textwrap::Options::new(options.width)
+                    .initial_indent("    ")
+                    .subsequent_indent("    ")
+                    .break_words(!options.plain);
+                for paragraph in text.lines() {
+                    emit!(false, "{}", textwrap::fill(paragraph, &textwrap_opts));
+                }
             }
         }
     }
 
-    if langs.len() == 0usize {
-        println!("No results found.");
+    if langs.is_empty() {
+        emit!(false, "No results found.");
+    }
+    let truncated = out.is_full();
+    if let Some(last) = out.finish() {
+        println!("{}", last);
+    }
+    if printed < total && !truncated {
+        println!(
+            "{}",
+            format!(
+                "({} more definitions not shown; pass --max-bytes 0 to show everything)",
+                total - printed
+            )
+            .dimmed()
+        );
     }
 }
 
-fn main() {
-    let args: Vec<String> = env::args().collect();
+// Bad options get an error and the usage rather than a panic. The config file's colors apply
+// from here on, except to --help and --version, which never read it.
+fn parse_options(opts: &Options, args: &[String], brief: &str) -> getopts::Matches {
+    let matches = match opts.parse(args) {
+        Ok(matches) => matches,
+        Err(e) => {
+            eprintln!("{}\n", e);
+            eprint!("{}", opts.usage(brief));
+            std::process::exit(FAILED);
+        }
+    };
+    let asked = |name: &str| matches.opt_defined(name) && matches.opt_present(name);
+    if !asked("h") && !asked("V") {
+        match config().color {
+            Some(ColorChoice::Always) => colored::control::set_override(true),
+            Some(ColorChoice::Never) => colored::control::set_override(false),
+            Some(ColorChoice::Auto) | None => {}
+        }
+    }
+    matches
+}
+
+// Where define3 keeps `file`.
+fn data_path(file: &str) -> PathBuf {
+    let mut path = match dirs::data_dir() {
+        Some(path) => path,
+        None => {
+            eprintln!("Could not find a data directory to look for the database in.");
+            std::process::exit(FAILED);
+        }
+    };
+    path.push("define3");
+    path.push(file);
+    path
+}
+
+// The defaults from the config file, read the first time they're needed.
+fn config() -> &'static Config {
+    static CONFIG: OnceLock<Config> = OnceLock::new();
+    CONFIG.get_or_init(|| {
+        Config::load_default().unwrap_or_else(|e| {
+            eprintln!("Bad config file {}", e);
+            std::process::exit(FAILED);
+        })
+    })
+}
+
+// The language given with -l, or else the config file's.
+fn language_option(matches: &getopts::Matches) -> Option<String> {
+    matches
+        .opt_str("l")
+        // export also takes it as --lang.
+        .or_else(|| {
+            matches
+                .opt_defined("lang")
+                .then(|| matches.opt_str("lang"))
+                .flatten()
+        })
+        .or_else(|| config().language.clone())
+        .map(|lang| normalize_language(&lang))
+}
+
+// The database given with -d, which goes with any subcommand.
+static DATABASE: OnceLock<PathBuf> = OnceLock::new();
+
+// Only called once a subcommand actually needs the database, so that --help and bad options
+// work without one.
+fn database_path() -> PathBuf {
+    DATABASE
+        .get()
+        .or(config().database.as_ref())
+        .cloned()
+        .unwrap_or_else(|| data_path("define3.sqlite3"))
+}
+
+// What `define` exits with, for scripts: 0 when it printed a definition, NOT_FOUND when there was
+// none to print, and FAILED or more when something went wrong, up to the database errors'
+// `Error::exit_code`.
+const NOT_FOUND: i32 = 1;
+const FAILED: i32 = 2;
+
+// Ends `define` with the error's message and exit code, rather than a panic.
+trait OrExit<T> {
+    fn or_exit(self) -> T;
+}
+
+impl<T, E: Into<Error>> OrExit<T> for Result<T, E> {
+    fn or_exit(self) -> T {
+        self.unwrap_or_else(|e| {
+            let e = e.into();
+            show_held_output();
+            eprintln!("{}", e);
+            std::process::exit(e.exit_code())
+        })
+    }
+}
+
+fn open_dictionary() -> Dictionary {
+    // Small machines get the low-memory profile without asking, but /proc/meminfo is only read
+    // once there's a database to open.
+    static DETECTED: Once = Once::new();
+    DETECTED.call_once(|| {
+        if detect_low_memory() {
+            set_low_memory(true);
+        }
+    });
+    Dictionary::open(database_path()).or_exit()
+}
+
+fn open_history() -> History {
+    let path = data_path("history.sqlite3");
+    if let Some(dir) = path.parent() {
+        let _ = std::fs::create_dir_all(dir);
+    }
+    History::open(&path).unwrap_or_else(|e| {
+        eprintln!(
+            "Could not open the lookup history at {}: {}",
+            path.display(),
+            e
+        );
+        std::process::exit(FAILED);
+    })
+}
+
+// How many words study-stats lists by default.
+const DEFAULT_TOP_WORDS: usize = 20;
+
+fn study_stats_main(args: &[String]) {
     let mut opts = Options::new();
     opts.optflag("h", "help", "print this help text");
-    opts.optflag("r", "raw", "don't expand wiki templates");
-    opts.optopt("l", "language", "only print this language", "lang");
-    let matches = opts.parse(&args[1..]).unwrap();
-    if matches.opt_present("h") || matches.free.len() != 1 {
-        let brief = format!("Usage: {} [options] WORD", args[0]);
+    opts.optopt("", "format", "json (default) or csv", "FORMAT");
+    opts.optopt(
+        "",
+        "top",
+        &format!(
+            "how many of the most looked-up words to list (default {})",
+            DEFAULT_TOP_WORDS
+        ),
+        "N",
+    );
+    let brief = format!(
+        "Usage: {} study-stats [options]\n\nSummarizes the lookup history: lookups per day, the \
+         most looked-up words and the languages they were in.",
+        args[0]
+    );
+    let matches = parse_options(&opts, &args[2..], &brief);
+    if matches.opt_present("h") || !matches.free.is_empty() {
         print!("{}", opts.usage(&brief));
         return;
     }
+    let top = match matches.opt_str("top").map(|n| n.parse()) {
+        None => DEFAULT_TOP_WORDS,
+        Some(Ok(n)) => n,
+        Some(Err(_)) => {
+            eprintln!("--top must be a number");
+            std::process::exit(FAILED);
+        }
+    };
+    let format = matches
+        .opt_str("format")
+        .unwrap_or_else(|| "json".to_owned());
+    if format != "json" && format != "csv" {
+        eprintln!("--format must be json or csv, got {:?}", format);
+        std::process::exit(FAILED);
+    }
+
+    let stats = open_history().stats(top).or_exit();
+    if format == "csv" {
+        print!("{}", stats.to_csv());
+    } else {
+        println!("{}", stats.to_json());
+    }
+}
+
+// `define import` is build_definitions_db under another name, so it has only one set of options to
+// keep up to date. The two are installed side by side.
+fn import_main(args: &[String]) {
+    run_builder(&args[2..]);
+}
+
+fn run_builder(args: &[String]) -> ! {
+    let builder = env::current_exe()
+        .ok()
+        .and_then(|exe| Some(exe.parent()?.join("build_definitions_db")))
+        .filter(|builder| builder.exists());
+    let builder = match builder {
+        Some(builder) => builder,
+        None => {
+            eprintln!("Could not find build_definitions_db next to this program.");
+            std::process::exit(FAILED);
+        }
+    };
+    let status = std::process::Command::new(&builder)
+        .args(args)
+        .status()
+        .unwrap_or_else(|e| {
+            eprintln!("Could not run {}: {}", builder.display(), e);
+            std::process::exit(FAILED);
+        });
+    std::process::exit(status.code().unwrap_or(FAILED));
+}
+
+fn scrape_main(args: &[String]) {
+    let mut opts = Options::new();
+    opts.optflag("h", "help", "print this help text");
+    opts.optopt("", "from", "the words to fetch, one per line", "FILE");
+    opts.optopt(
+        "",
+        "edition",
+        "the Wiktionary edition to fetch from, e.g. fr (default en)",
+        "EDITION",
+    );
+    opts.optopt(
+        "",
+        "delay",
+        &format!(
+            "how long to wait between requests, in seconds (default {})",
+            DEFAULT_DELAY.as_secs()
+        ),
+        "SECONDS",
+    );
+    opts.optflag(
+        "",
+        "refresh",
+        "fetch every word again, even the cached ones",
+    );
+    let brief = format!(
+        "Usage: {0} scrape --from FILE [options] [-- IMPORT_OPTIONS]\n\nFetches the entries for \
+         the words in FILE from the Wiktionary API, 50 at a time, and builds the database from \
+         them, for when a few thousand words are all that's needed. Pages are cached in {1}, so \
+         running it again only fetches new words. Templates aren't fetched, so only the built-in \
+         ones expand. Options after -- go to `{0} import`, e.g. -- --merge.",
+        args[0],
+        data_path("scrape").display()
+    );
+    let matches = parse_options(&opts, &args[2..], &brief);
+    let path = match matches.opt_str("from") {
+        Some(path) if !matches.opt_present("h") => path,
+        _ => {
+            print!("{}", opts.usage(&brief));
+            return;
+        }
+    };
+    let edition = matches
+        .opt_str("edition")
+        .unwrap_or_else(|| "en".to_owned());
+    let delay = match matches.opt_str("delay").map(|delay| delay.parse::<f64>()) {
+        None => DEFAULT_DELAY,
+        Some(Ok(seconds)) if seconds >= 0.0 && seconds.is_finite() => {
+            Duration::from_secs_f64(seconds)
+        }
+        Some(_) => {
+            eprintln!("--delay must be a number of seconds");
+            std::process::exit(FAILED);
+        }
+    };
+    let text = std::fs::read_to_string(&path).unwrap_or_else(|e| {
+        eprintln!("Could not read {}: {}", path, e);
+        std::process::exit(FAILED);
+    });
+    let titles: Vec<String> = text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_owned)
+        .collect();
 
-    // TODO: We currently support nested templates in a very bad way. We expand templates in
-    // layers, most deeply nested first, and we do this by excluding curly braces in the regex.
-    // Should eventually use a more legit parser (nom maybe?)
-    let re_template = Regex::new(r"\{\{(?P<text>(?s:[^\{])*?)\}\}").unwrap();
+    let mut scraper = Scraper::new(&edition, &data_path("scrape"), delay);
+    let pages = scraper
+        .fetch(&titles, matches.opt_present("refresh"), |done, total| {
+            eprint!("\rFetched {} of {} words", done, total);
+        })
+        .unwrap_or_else(|e| {
+            eprintln!("\n{}", e);
+            std::process::exit(FAILED);
+        });
+    eprintln!(
+        "\r{} of {} words have entries on {}.wiktionary.org",
+        pages.len(),
+        titles.len(),
+        edition
+    );
 
-    let mut sqlite_path = dirs::data_dir().unwrap();
-    sqlite_path.push("define3");
-    sqlite_path.push("define3.sqlite3");
-    let conn = Connection::open(Path::new(&sqlite_path)).unwrap();
+    // Named like a dump, so the import can tell which edition it's from.
+    let dump = data_path("scrape").join(format!("{}wiktionary-scraped.xml", edition));
+    if let Err(e) = std::fs::create_dir_all(data_path("scrape"))
+        .and_then(|_| std::fs::File::create(&dump))
+        .map(std::io::BufWriter::new)
+        .and_then(|mut out| write_dump(&pages, &mut out).and_then(|_| out.flush()))
+    {
+        eprintln!("Could not write {}: {}", dump.display(), e);
+        std::process::exit(FAILED);
+    }
+    let mut import_args = vec![dump.display().to_string()];
+    import_args.extend(matches.free.iter().cloned());
+    run_builder(&import_args);
+}
 
-    let all_langs = *get_defns_by_lang(&conn, &matches.free[0]);
-    let langs = match matches.opt_str("l") {
-        None => all_langs,
-        Some(lang) => {
-            let mut result = BTreeMap::new();
-            for &result_for_lang in all_langs.get(&lang).iter() {
-                result.insert(lang.clone(), result_for_lang.clone());
+// `define alias`. Running an alias gives back the command line to run in its place; everything
+// else is done here.
+fn alias_main(args: &[String]) -> Option<Vec<String>> {
+    let usage = || {
+        println!(
+            "Usage: {0} alias save NAME -- ARGS...\n       {0} alias run NAME [MORE_ARGS...]\n       \
+             {0} alias list\n       {0} alias remove NAME\n\nSaves a command line under a name \
+             in the config file, so `{0} alias run NAME` runs `{0} ARGS...` again, with any \
+             MORE_ARGS after them.",
+            args[0]
+        );
+        None
+    };
+    let name = args.get(3).map(String::as_str);
+    if let Some(name) = name.filter(|name| !is_alias_name(name)) {
+        eprintln!("Alias names are letters, digits, - and _, not {:?}", name);
+        std::process::exit(FAILED);
+    }
+    let path = config_path().unwrap_or_else(|| {
+        eprintln!("Could not find a config directory to keep aliases in.");
+        std::process::exit(FAILED);
+    });
+    let saved = |name: &str| {
+        config().aliases.get(name).unwrap_or_else(|| {
+            eprintln!(
+                "There's no alias called {}; see `{} alias list`.",
+                name, args[0]
+            );
+            std::process::exit(FAILED);
+        })
+    };
+    match (args.get(2).map(String::as_str), name) {
+        (Some("save"), Some(name)) if args.get(4).map(String::as_str) == Some("--") => {
+            let alias = &args[5..];
+            if alias.is_empty() {
+                return usage();
             }
-            result
+            if alias[0] == "alias" {
+                eprintln!("An alias can't run another alias.");
+                std::process::exit(FAILED);
+            }
+            let verb = if config().aliases.contains_key(name) {
+                "Replaced"
+            } else {
+                "Saved"
+            };
+            if let Err(e) = set_alias(&path, name, Some(alias)) {
+                eprintln!("Could not save the alias: {}", e);
+                std::process::exit(FAILED);
+            }
+            println!("{} {} in {}", verb, name, path.display());
+            None
+        }
+        (Some("run"), Some(name)) => {
+            let mut command = vec![args[0].clone()];
+            command.extend(saved(name).iter().cloned());
+            command.extend(args[4..].iter().cloned());
+            Some(command)
+        }
+        (Some("remove"), Some(name)) if args.len() == 4 => {
+            saved(name);
+            if let Err(e) = set_alias(&path, name, None) {
+                eprintln!("Could not remove the alias: {}", e);
+                std::process::exit(FAILED);
+            }
+            println!("Removed {} from {}", name, path.display());
+            None
+        }
+        (Some("list"), None) => {
+            for (name, alias) in &config().aliases {
+                println!("{}\t{}", name, alias.join(" "));
+            }
+            None
+        }
+        _ => usage(),
+    }
+}
+
+fn fetch_main(args: &[String]) {
+    let mut opts = Options::new();
+    opts.optflag("h", "help", "print this help text");
+    opts.optopt(
+        "",
+        "manifest",
+        "where the release to fetch is described (required, since no releases are published \
+         yet)",
+        "URL",
+    );
+    opts.optflag(
+        "",
+        "check",
+        "only say whether there's a newer database, without downloading it",
+    );
+    opts.optflag(
+        "",
+        "force",
+        "download the database even if it's the latest one",
+    );
+    let brief = format!(
+        "Usage: {0} fetch --manifest URL [options]\n\nDownloads the prebuilt database that the \
+         manifest at URL describes to {1}, or where the config file's `database` says, so \
+         there's no need to build one. No releases are published yet, so there's no default \
+         manifest. The download is checked against its SHA-256 before the database is \
+         replaced, and an interrupted one carries on where it stopped when {0} fetch is run \
+         again.",
+        args[0],
+        data_path("define3.sqlite3").display()
+    );
+    let matches = parse_options(&opts, &args[2..], &brief);
+    if matches.opt_present("h") || !matches.free.is_empty() {
+        print!("{}", opts.usage(&brief));
+        return;
+    }
+    let manifest = matches.opt_str("manifest").unwrap_or_else(|| {
+        eprintln!(
+            "{} fetch needs --manifest URL: no prebuilt databases are published yet, so there's \
+             no default to fetch from",
+            args[0]
+        );
+        std::process::exit(FAILED);
+    });
+    let database = database_path();
+    let release = Release::fetch(&manifest).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(FAILED);
+    });
+    let installed = installed_version(&database);
+    if installed.as_ref() == Some(&release.version) && !matches.opt_present("force") {
+        println!("The database is up to date ({}).", release.version);
+        return;
+    }
+    if matches.opt_present("check") {
+        match installed {
+            Some(installed) => println!(
+                "{} is available; the database is {}.",
+                release.version, installed
+            ),
+            None if database.exists() => println!(
+                "{} is available; the database at {} wasn't fetched.",
+                release.version,
+                database.display()
+            ),
+            None => println!("{} is available.", release.version),
+        }
+        return;
+    }
+
+    if let Some(dir) = database.parent() {
+        if let Err(e) = std::fs::create_dir_all(dir) {
+            eprintln!("Could not create {}: {}", dir.display(), e);
+            std::process::exit(FAILED);
+        }
+    }
+    let partial = release.partial_path(&database);
+    eprintln!("Downloading {} from {}", release.version, release.url);
+    if let Err(e) = release
+        .download(&partial)
+        .and_then(|_| release.install(&partial, &database))
+    {
+        eprintln!("{}", e);
+        std::process::exit(FAILED);
+    }
+    if let Err(e) = record_version(&database, &release) {
+        eprintln!("Could not record the database's version: {}", e);
+    }
+    println!("Installed {} at {}", release.version, database.display());
+}
+
+fn export_main(args: &[String]) {
+    let mut opts = Options::new();
+    opts.optflag("h", "help", "print this help text");
+    opts.optopt("", "format", "stardict or dictd", "FORMAT");
+    opts.optopt("l", "language", "the language to export", "LANG");
+    opts.optopt("", "lang", "the same as --language", "LANG");
+    opts.optopt(
+        "",
+        "name",
+        "what to call the files (default define3- and the language)",
+        "NAME",
+    );
+    let brief = format!(
+        "Usage: {} export --format stardict|dictd -l LANG [options] DIR\n\nWrites the \
+         definitions in one language to DIR as a StarDict dictionary (.ifo, .idx and .dict), \
+         for GoldenDict, KOReader and e-readers, or a dictd one (.index and .dict.dz, or .dict \
+         if dictzip isn't installed). Templates are expanded.",
+        args[0]
+    );
+    let matches = parse_options(&opts, &args[2..], &brief);
+    let (format, dir) = match (matches.opt_str("format"), &matches.free[..]) {
+        (Some(format), [dir]) if !matches.opt_present("h") => (format, dir),
+        _ => {
+            print!("{}", opts.usage(&brief));
+            return;
         }
     };
-    print_words(&langs, |s| {
-        let replace_template = |caps: &Captures| -> String { replace_template(&conn, caps) };
-        let mut result = s.to_owned();
-        if !matches.opt_present("r") {
-            loop {
-                let result_ = re_template
-                    .replace_all(&result, &replace_template)
-                    .to_string();
-                //println!("{}", result_);
-                if result == result_ {
-                    break;
-                }
-                result = result_;
+    let format: ExportFormat = format.parse().unwrap_or_else(|e| {
+        eprintln!("--format: {}", e);
+        std::process::exit(FAILED);
+    });
+    let language = language_option(&matches).unwrap_or_else(|| {
+        eprintln!("Say which language to export with -l.");
+        std::process::exit(FAILED);
+    });
+    let name = matches
+        .opt_str("name")
+        .unwrap_or_else(|| format!("define3-{}", language.to_lowercase().replace(' ', "-")));
+
+    let dictionary = open_dictionary();
+    if !dictionary
+        .languages()
+        .or_exit()
+        .iter()
+        .any(|(other, _)| *other == language)
+    {
+        eprintln!("There are no definitions in {}.", language);
+        std::process::exit(FAILED);
+    }
+    let exported =
+        export(&dictionary, &language, format, Path::new(dir), &name).unwrap_or_else(|e| {
+            eprintln!("Could not export {}: {}", language, e);
+            std::process::exit(FAILED);
+        });
+    let locale = Locale::current();
+    println!(
+        "Exported {} words in {}:",
+        locale.number(exported.words as i64),
+        language
+    );
+    for file in &exported.files {
+        println!("  {}", file.display());
+    }
+    if exported.skipped > 0 {
+        eprintln!(
+            "Left out {} words that the format can't hold.",
+            locale.number(exported.skipped as i64)
+        );
+    }
+}
+
+fn dump_main(args: &[String]) {
+    let mut opts = Options::new();
+    opts.optflag("h", "help", "print this help text");
+    opts.optopt(
+        "",
+        "format",
+        "jsonl, jsonl.gz or jsonl.zst (default the one the file's name ends in)",
+        "FORMAT",
+    );
+    opts.optopt("l", "language", "only dump this language", "LANG");
+    let brief = format!(
+        "Usage: {} dump [options] FILE\n\nWrites every entry to FILE (- for stdout) as JSON \
+         Lines, one word per line, with its definitions expanded and raw, the way --json prints \
+         it. jsonl.gz needs gzip installed.",
+        args[0]
+    );
+    let matches = parse_options(&opts, &args[2..], &brief);
+    let path = match &matches.free[..] {
+        [path] if !matches.opt_present("h") => Path::new(path),
+        _ => {
+            print!("{}", opts.usage(&brief));
+            return;
+        }
+    };
+    let format = match matches.opt_str("format") {
+        Some(format) => format.parse().unwrap_or_else(|e| {
+            eprintln!("--format: {}", e);
+            std::process::exit(FAILED);
+        }),
+        None => DumpFormat::from_path(path).unwrap_or(DumpFormat::Jsonl),
+    };
+    let language = language_option(&matches);
+
+    let dictionary = open_dictionary();
+    if let Some(ref language) = language {
+        if !dictionary
+            .languages()
+            .or_exit()
+            .iter()
+            .any(|(other, _)| other == language)
+        {
+            eprintln!("There are no definitions in {}.", language);
+            std::process::exit(FAILED);
+        }
+    }
+    let dumped = dump(&dictionary, language.as_deref(), format, path).unwrap_or_else(|e| {
+        eprintln!("Could not dump the dictionary: {}", e);
+        std::process::exit(FAILED);
+    });
+    if path != Path::new("-") {
+        let locale = Locale::current();
+        println!(
+            "Dumped {} entries ({} definitions) to {}",
+            locale.number(dumped.entries as i64),
+            locale.number(dumped.definitions as i64),
+            path.display()
+        );
+    }
+}
+
+fn export_anki_main(args: &[String]) {
+    let mut opts = Options::new();
+    opts.optflag("h", "help", "print this help text");
+    opts.optopt("l", "language", "only definitions in this language", "LANG");
+    opts.optopt(
+        "",
+        "deck",
+        "what to call the deck (default the word list's file name)",
+        "NAME",
+    );
+    opts.optflag("", "ipa", "put the pronunciation on the back too");
+    let brief = format!(
+        "Usage: {} export-anki [options] WORDS DECK.apkg\n\nMakes an Anki deck of the words in \
+         WORDS, one per line (optionally followed by a tab or comma and its reading), with the \
+         word on the front of each card and its definitions on the back. Importing a newer deck \
+         of the same name updates the cards instead of adding them again.",
+        args[0]
+    );
+    let matches = parse_options(&opts, &args[2..], &brief);
+    let (list, output) = match &matches.free[..] {
+        [list, output] if !matches.opt_present("h") => (list, output),
+        _ => {
+            print!("{}", opts.usage(&brief));
+            return;
+        }
+    };
+    let text = std::fs::read_to_string(list).unwrap_or_else(|e| {
+        eprintln!("Could not read {}: {}", list, e);
+        std::process::exit(FAILED);
+    });
+    let entries = parse_list(&text);
+    let deck = matches.opt_str("deck").unwrap_or_else(|| {
+        Path::new(list).file_stem().map_or_else(
+            || "define3".to_owned(),
+            |stem| stem.to_string_lossy().into_owned(),
+        )
+    });
+    let language = language_option(&matches);
+
+    let dictionary = open_dictionary();
+    let words: Vec<&str> = entries.iter().map(|entry| entry.word.as_str()).collect();
+    let found = dictionary.lookup_many(&words).or_exit();
+    let policies = TemplatePolicies::default();
+    let formatters = Formatters::default();
+    let mut notes = Vec::new();
+    let mut missing = Vec::new();
+    for (entry, mut word) in entries.iter().zip(found) {
+        if let Some(ref language) = language {
+            word.meanings
+                .retain(|meaning| meaning.language == *language);
+        }
+        if word.meanings.is_empty() {
+            missing.push(entry.word.as_str());
+            continue;
+        }
+        let mut back = String::new();
+        if let Some(ref reading) = entry.reading {
+            back.push_str(&format!("<p class=reading>{}</p>", escape_html(reading)));
+        }
+        if matches.opt_present("ipa") {
+            let pronunciations = dictionary
+                .pronunciations(&entry.word, language.as_deref())
+                .or_exit();
+            let ipa: Vec<&str> = pronunciations
+                .iter()
+                .flat_map(|pronunciation| pronunciation.ipa.first())
+                .map(String::as_str)
+                .collect();
+            if !ipa.is_empty() {
+                back.push_str(&format!(
+                    "<p class=ipa>{}</p>",
+                    escape_html(&ipa.join(", "))
+                ));
             }
         }
-        result
+        back.push_str(&meanings_html(
+            &entry.word,
+            &word.meanings,
+            &policies,
+            &formatters,
+            false,
+        ));
+        notes.push(Note {
+            word: entry.word.clone(),
+            back,
+        });
+    }
+    if let Err(e) = write_package(Path::new(output), &deck, &notes) {
+        eprintln!("Could not write the deck: {}", e);
+        std::process::exit(FAILED);
+    }
+    println!(
+        "Wrote {} cards to {} as the deck {:?}",
+        notes.len(),
+        output,
+        deck
+    );
+    if !missing.is_empty() {
+        eprintln!(
+            "{} of {} words have no definitions{}: {}",
+            missing.len(),
+            entries.len(),
+            language.map_or(String::new(), |language| format!(" in {}", language)),
+            missing.join(", ")
+        );
+    }
+}
+
+fn check_main(args: &[String]) {
+    let mut opts = Options::new();
+    opts.optflag("h", "help", "print this help text");
+    opts.optflag(
+        "",
+        "against-live",
+        "compare the entry with the page on Wiktionary as it is now",
+    );
+    opts.optopt(
+        "",
+        "edition",
+        "the Wiktionary edition to compare with, e.g. fr (default en)",
+        "EDITION",
+    );
+    opts.optopt("l", "language", "only compare this language", "lang");
+    let brief = format!(
+        "Usage: {0} check --against-live [options] WORD\n\nFetches WORD's page from the \
+         Wiktionary API, imports it the way the database was imported, and shows how its \
+         definitions differ from the database's, for telling how out of date the database is.",
+        args[0]
+    );
+    let matches = parse_options(&opts, &args[2..], &brief);
+    if matches.opt_present("h") || !matches.opt_present("against-live") || matches.free.len() != 1 {
+        print!("{}", opts.usage(&brief));
+        return;
+    }
+    let word = &matches.free[0];
+    let edition = matches
+        .opt_str("edition")
+        .unwrap_or_else(|| "en".to_owned());
+    let language = language_option(&matches);
+
+    let dictionary = open_dictionary();
+    let mut stored = dictionary.lookup(word).or_exit();
+    // Databases from before editions were tracked only have en.
+    stored.retain(|meaning| meaning.edition.as_deref().unwrap_or("en") == edition);
+
+    let mut scraper = Scraper::new(&edition, &data_path("scrape"), DEFAULT_DELAY);
+    let content = scraper.live(word).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(FAILED);
     });
+    let languages: HashSet<&str> = IMPORTED_LANGUAGES.iter().cloned().collect();
+    let parts_of_speech: HashSet<&str> = IMPORTED_PARTS_OF_SPEECH.iter().cloned().collect();
+    let mut live = match content {
+        Some(content) => parse_wikitext(content, &languages, &parts_of_speech).meanings,
+        None => Vec::new(),
+    };
+    let cleaner = MarkupCleaner::new();
+    for meaning in &mut live {
+        meaning.definition = cleaner.clean(&meaning.definition);
+    }
+    if let Some(ref language) = language {
+        stored.retain(|meaning| &meaning.language == language);
+        live.retain(|meaning| &meaning.language == language);
+    }
+
+    let changes = diff_meanings(&stored, &live);
+    let mut heading: Option<(&str, &str)> = None;
+    for &change in &changes {
+        let meaning = change.meaning();
+        if heading.map(|(language, _)| language) != Some(&meaning.language) {
+            println!("{}", meaning.language.green().bold());
+        }
+        if heading != Some((&meaning.language, &meaning.part_of_speech)) {
+            println!("  {}", meaning.part_of_speech.white());
+        }
+        heading = Some((&meaning.language, &meaning.part_of_speech));
+        match change {
+            Change::Same(_) => println!("{}", format!("    {}", meaning.definition).dimmed()),
+            Change::Removed(_) => println!("{}", format!("  - {}", meaning.definition).red()),
+            Change::Added(_) => println!("{}", format!("  + {}", meaning.definition).green()),
+        }
+    }
+    let count = |f: fn(&Change) -> bool| changes.iter().filter(|change| f(change)).count();
+    let added = count(|change| matches!(change, Change::Added(_)));
+    let removed = count(|change| matches!(change, Change::Removed(_)));
+    let since = match dictionary.dump_date(&edition).or_exit() {
+        Some(date) => format!("the {} dump", Locale::current().date(&date)),
+        None => "the database was built".to_owned(),
+    };
+    if changes.is_empty() {
+        println!(
+            "{} has no definitions here or on {}.wiktionary.org.",
+            word, edition
+        );
+    } else if added == 0 && removed == 0 {
+        println!("No changes since {}.", since);
+    } else {
+        println!(
+            "{} definitions added and {} removed since {}.",
+            added, removed, since
+        );
+    }
+}
+
+// A length of time like 7d, as seconds. Also takes hours and weeks.
+fn parse_duration(s: &str) -> Option<i64> {
+    let unit = match s.chars().last()? {
+        'h' => 3600,
+        'd' => 86_400,
+        'w' => 7 * 86_400,
+        _ => return None,
+    };
+    let n: i64 = s[..s.len() - 1].parse().ok()?;
+    Some(n * unit)
+}
+
+fn digest_main(args: &[String]) {
+    let mut opts = Options::new();
+    opts.optflag("h", "help", "print this help text");
+    opts.optopt(
+        "",
+        "words-from",
+        "where the words come from; only history for now (default)",
+        "SOURCE",
+    );
+    opts.optopt(
+        "",
+        "last",
+        "how far back to go, e.g. 12h, 7d or 2w (default 7d)",
+        "TIME",
+    );
+    opts.optopt("", "format", "text or html (default text)", "FORMAT");
+    opts.optflag(
+        "",
+        "email",
+        "start with Subject and Content-Type headers, for piping into sendmail",
+    );
+    let brief = format!(
+        "Usage: {0} digest [options]\n\nLists the words looked up lately with their definitions, \
+         for reviewing them, e.g. weekly from cron:\n\n    {0} digest --format html --email | \
+         sendmail you@example.com",
+        args[0]
+    );
+    let matches = parse_options(&opts, &args[2..], &brief);
+    if matches.opt_present("h") || !matches.free.is_empty() {
+        print!("{}", opts.usage(&brief));
+        return;
+    }
+    let source = matches
+        .opt_str("words-from")
+        .unwrap_or_else(|| "history".to_owned());
+    if source != "history" {
+        eprintln!("--words-from must be history, got {:?}", source);
+        std::process::exit(FAILED);
+    }
+    let last = matches.opt_str("last").unwrap_or_else(|| "7d".to_owned());
+    let seconds = match parse_duration(&last) {
+        Some(seconds) => seconds,
+        None => {
+            eprintln!("--last must be a number of hours, days or weeks, like 7d");
+            std::process::exit(FAILED);
+        }
+    };
+    let format = matches
+        .opt_str("format")
+        .unwrap_or_else(|| "text".to_owned());
+    if format != "text" && format != "html" {
+        eprintln!("--format must be text or html, got {:?}", format);
+        std::process::exit(FAILED);
+    }
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs() as i64);
+    let words = open_history().words_since(now - seconds).or_exit();
+    let dictionary = open_dictionary();
+    let entries = digest_entries(&dictionary, &words).or_exit();
+    let title = format!(
+        "define3 digest: {} {} looked up in the last {}",
+        entries.len(),
+        if entries.len() == 1 { "word" } else { "words" },
+        last
+    );
+    if matches.opt_present("email") {
+        let content_type = if format == "html" { "html" } else { "plain" };
+        print!(
+            "Subject: {}\r\nMIME-Version: 1.0\r\nContent-Type: text/{}; charset=utf-8\r\n\r\n",
+            title, content_type
+        );
+    }
+    if format == "html" {
+        print!("{}", digest_html(&entries, &title));
+    } else {
+        print!("{}", digest_text(&dictionary, &entries, &title));
+    }
+}
+
+// How many words `define warm` caches unless told otherwise.
+const DEFAULT_WARM_WORDS: usize = 10_000;
+
+fn warm_main(args: &[String]) {
+    let mut opts = Options::new();
+    opts.optflag("h", "help", "print this help text");
+    opts.optopt(
+        "",
+        "top",
+        &format!(
+            "how many of the most looked-up words to cache (default {})",
+            DEFAULT_WARM_WORDS
+        ),
+        "N",
+    );
+    opts.optopt(
+        "",
+        "words-from",
+        "where the words come from: history (default), or a file of words one per line, most \
+         common first, like a frequency list",
+        "SOURCE",
+    );
+    opts.optflag("", "clear", "empty the cache instead");
+    let brief = format!(
+        "Usage: {} warm [options]\n\nExpands the definitions of the most looked-up words ahead \
+         of time, so that looking them up is quick even on slow storage. Run it again after \
+         importing, which empties the cache.",
+        args[0]
+    );
+    let matches = parse_options(&opts, &args[2..], &brief);
+    if matches.opt_present("h") || !matches.free.is_empty() {
+        print!("{}", opts.usage(&brief));
+        return;
+    }
+    let mut dictionary = open_dictionary();
+    if matches.opt_present("clear") {
+        dictionary.clear_cache().or_exit();
+        return;
+    }
+    let top = match matches.opt_str("top").map(|n| n.parse()) {
+        None => DEFAULT_WARM_WORDS,
+        Some(Ok(n)) => n,
+        Some(Err(_)) => {
+            eprintln!("--top must be a number");
+            std::process::exit(FAILED);
+        }
+    };
+    let words: Vec<String> = match matches.opt_str("words-from").as_deref() {
+        None | Some("history") => open_history()
+            .words_since(0)
+            .or_exit()
+            .into_iter()
+            .map(|(word, _)| word)
+            .take(top)
+            .collect(),
+        Some(path) => {
+            let list = std::fs::read_to_string(path).unwrap_or_else(|e| {
+                eprintln!("Could not read {}: {}", path, e);
+                std::process::exit(FAILED);
+            });
+            list.lines()
+                .map(str::trim)
+                .filter(|word| !word.is_empty())
+                .map(str::to_owned)
+                .take(top)
+                .collect()
+        }
+    };
+    let words: Vec<&str> = words.iter().map(String::as_str).collect();
+    let cached = dictionary
+        .warm_cache(&words, |done| {
+            if done % 100 == 0 {
+                eprint!("\r{}/{} words", done, words.len());
+            }
+        })
+        .or_exit();
+    eprint!("\r");
+    println!("Cached {} definitions of {} words", cached, words.len());
+}
+
+// How many unhandled templates `template-coverage` lists unless asked for more.
+const DEFAULT_COVERAGE_TEMPLATES: usize = 25;
+
+fn test_templates_main(args: &[String]) {
+    let mut opts = Options::new();
+    opts.optflag("h", "help", "print this help text");
+    opts.optflag(
+        "",
+        "database-templates",
+        "expand templates that aren't built in from their bodies in the database, as lookups do",
+    );
+    let brief = format!(
+        "Usage: {} test-templates [options] CORPUS.toml\n\nExpands the wikitext of each case in \
+         CORPUS.toml, like template-corpus.toml, and lists the ones that didn't come out as \
+         expected. Cases are expanded in the C locale, so that they pass anywhere. Exits with 1 \
+         if any case fails.",
+        args[0]
+    );
+    let matches = parse_options(&opts, &args[2..], &brief);
+    if matches.opt_present("h") || matches.free.len() != 1 {
+        print!("{}", opts.usage(&brief));
+        return;
+    }
+    // Before anything reads it.
+    env::set_var("LC_ALL", "C");
+    let path = &matches.free[0];
+    let cases = std::fs::read_to_string(path)
+        .map_err(|e| e.to_string())
+        .and_then(|text| parse_corpus(&text))
+        .unwrap_or_else(|e| {
+            eprintln!("{}: {}", path, e);
+            std::process::exit(FAILED);
+        });
+    let failures = if matches.opt_present("database-templates") {
+        run_corpus(&cases, &open_dictionary())
+    } else {
+        run_corpus(&cases, &NoTemplates)
+    };
+    for failure in &failures {
+        let case = &failure.case;
+        println!(
+            "{} {} {}",
+            "FAIL".red().bold(),
+            case.label(),
+            format!("(line {})", case.line).dimmed()
+        );
+        println!("  wikitext: {}", case.wikitext);
+        println!("  expected: {}", case.expected);
+        println!("  actual:   {}", failure.actual);
+        let column = first_difference(&case.expected, &failure.actual);
+        println!("            {}{}", " ".repeat(column), "^".yellow());
+    }
+    println!(
+        "{} of {} cases passed.",
+        cases.len() - failures.len(),
+        cases.len()
+    );
+    if !failures.is_empty() {
+        // Like other test runners.
+        std::process::exit(1);
+    }
+}
+
+fn template_coverage_main(args: &[String]) {
+    let mut opts = Options::new();
+    opts.optflag("h", "help", "print this help text");
+    opts.optopt(
+        "l",
+        "language",
+        "only scan definitions in this language",
+        "LANG",
+    );
+    opts.optopt(
+        "",
+        "sample",
+        "scan only this many definitions, picked at random, instead of all of them",
+        "N",
+    );
+    opts.optopt(
+        "",
+        "top",
+        &format!(
+            "how many templates to list (default {})",
+            DEFAULT_COVERAGE_TEMPLATES
+        ),
+        "N",
+    );
+    let brief = format!(
+        "Usage: {} template-coverage [options]\n\nExpands the definitions in the database and \
+         lists the templates that didn't expand, the ones that broke the most definitions \
+         first, with a few of the entries they're in.",
+        args[0]
+    );
+    let matches = parse_options(&opts, &args[2..], &brief);
+    if matches.opt_present("h") || !matches.free.is_empty() {
+        print!("{}", opts.usage(&brief));
+        return;
+    }
+    let number = |name: &str| match matches.opt_str(name).map(|n| n.parse::<usize>()) {
+        None => None,
+        Some(Ok(n)) => Some(n),
+        Some(Err(_)) => {
+            eprintln!("--{} must be a number", name);
+            std::process::exit(FAILED);
+        }
+    };
+    let sample = number("sample");
+    let top = number("top").unwrap_or(DEFAULT_COVERAGE_TEMPLATES);
+    let language = matches.opt_str("l").map(|lang| normalize_language(&lang));
+    let dictionary = open_dictionary();
+    let coverage = template_coverage(&dictionary, language.as_deref(), sample).or_exit();
+    let locale = Locale::current();
+    let percent = |n: u64| {
+        let percent = if coverage.definitions == 0 {
+            0.0
+        } else {
+            n as f64 * 100.0 / coverage.definitions as f64
+        };
+        format!("{}%", locale.decimal(percent, 1))
+    };
+    println!(
+        "Scanned {} definitions; {} ({}) have templates that didn't expand.",
+        locale.number(coverage.definitions as i64),
+        locale.number(coverage.unhandled as i64),
+        percent(coverage.unhandled)
+    );
+    if coverage.templates.is_empty() {
+        return;
+    }
+    let width = coverage
+        .templates
+        .iter()
+        .take(top)
+        .map(|usage| usage.name.chars().count())
+        .max()
+        .unwrap_or(0);
+    println!();
+    for usage in coverage.templates.iter().take(top) {
+        println!(
+            "{:>8} {:>6}  {:<width$}  {}",
+            locale.number(usage.unhandled as i64),
+            percent(usage.unhandled),
+            usage.name,
+            format!(
+                "({} {}; e.g. {})",
+                locale.number(usage.uses as i64),
+                if usage.uses == 1 { "use" } else { "uses" },
+                usage.examples.join(", ")
+            )
+            .dimmed(),
+            width = width
+        );
+    }
+    if coverage.templates.len() > top {
+        println!(
+            "{}",
+            format!(
+                "({} more; pass --top {} to list them all)",
+                coverage.templates.len() - top,
+                coverage.templates.len()
+            )
+            .dimmed()
+        );
+    }
+}
+
+fn export_level_main(args: &[String]) {
+    let mut opts = Options::new();
+    opts.optflag("h", "help", "print this help text");
+    opts.optopt(
+        "",
+        "list",
+        "the word list: the name of one saved with --import, like jlpt-n4, or a file",
+        "NAME",
+    );
+    opts.optopt(
+        "",
+        "import",
+        "save FILE (a word per line, optionally followed by a tab or comma and its reading) as \
+         the list named by --list",
+        "FILE",
+    );
+    opts.optopt("", "format", "csv (default) or tsv", "FORMAT");
+    opts.optopt(
+        "l",
+        "language",
+        "only definitions in this language (default: the list's, for JLPT, HSK and TOPIK lists)",
+        "lang",
+    );
+    let brief = format!(
+        "Usage: {0} export-level --list NAME [options]\n       {0} export-level --list NAME \
+         --import FILE\n\nPrints flashcards for the words on a level list, with their readings \
+         and definitions. Lists are kept in {1}.",
+        args[0],
+        data_path("lists").display()
+    );
+    let matches = parse_options(&opts, &args[2..], &brief);
+    let name = match matches.opt_str("list") {
+        Some(name) if !matches.opt_present("h") && matches.free.is_empty() => name,
+        _ => {
+            print!("{}", opts.usage(&brief));
+            return;
+        }
+    };
+    let saved = data_path("lists").join(format!("{}.txt", name));
+
+    if let Some(path) = matches.opt_str("import") {
+        let text = std::fs::read_to_string(&path).unwrap_or_else(|e| {
+            eprintln!("Could not read {}: {}", path, e);
+            std::process::exit(FAILED);
+        });
+        let words = parse_list(&text).len();
+        if let Err(e) = saved
+            .parent()
+            .map_or(Ok(()), std::fs::create_dir_all)
+            .and_then(|_| std::fs::write(&saved, &text))
+        {
+            eprintln!("Could not save {}: {}", saved.display(), e);
+            std::process::exit(FAILED);
+        }
+        println!("Saved {} words as {}", words, name);
+        return;
+    }
+
+    let format: CardFormat = match matches.opt_str("format").map(|format| format.parse()) {
+        None => CardFormat::Csv,
+        Some(Ok(format)) => format,
+        Some(Err(e)) => {
+            eprintln!("--format: {}", e);
+            std::process::exit(FAILED);
+        }
+    };
+    let path = if saved.exists() {
+        saved
+    } else {
+        PathBuf::from(&name)
+    };
+    let text = std::fs::read_to_string(&path).unwrap_or_else(|e| {
+        eprintln!("No list {:?} ({}); save one with --import first.", name, e);
+        std::process::exit(FAILED);
+    });
+    let language = matches
+        .opt_str("l")
+        .map(|lang| normalize_language(&lang))
+        .or_else(|| {
+            let stem = path.file_stem()?.to_str()?;
+            list_language(stem).map(str::to_owned)
+        });
+
+    let dictionary = open_dictionary();
+    let cards = flashcards(&dictionary, &parse_list(&text), language.as_deref()).or_exit();
+    let missing = cards
+        .iter()
+        .filter(|card| card.definition.is_empty())
+        .count();
+    print!("{}", write_cards(&cards, format));
+    if missing > 0 {
+        eprintln!(
+            "{} of {} words have no definitions{}",
+            missing,
+            cards.len(),
+            language.map_or(String::new(), |language| format!(" in {}", language))
+        );
+    }
+}
+
+// How many definitions `define search` lists unless told otherwise.
+const DEFAULT_SEARCH_RESULTS: usize = 50;
+
+fn search_main(args: &[String]) {
+    let mut opts = Options::new();
+    opts.optflag("h", "help", "print this help text");
+    opts.optopt(
+        "",
+        "name-prefix",
+        "headwords starting with PREFIX",
+        "PREFIX",
+    );
+    opts.optopt(
+        "",
+        "name",
+        "headwords matching PATTERN, with * and ? wildcards",
+        "PATTERN",
+    );
+    opts.optopt(
+        "",
+        "defn-contains",
+        "definitions containing TEXT (in their wikitext, ignoring ASCII case)",
+        "TEXT",
+    );
+    opts.optopt("", "pos", "only this part of speech, e.g. Adjective", "POS");
+    opts.optopt(
+        "l",
+        "language",
+        "only this language (a name or an ISO 639 code)",
+        "lang",
+    );
+    opts.optopt(
+        "",
+        "edition",
+        "only this Wiktionary edition, e.g. fr",
+        "CODE",
+    );
+    opts.optopt(
+        "",
+        "limit",
+        &format!(
+            "list at most N definitions (default {})",
+            DEFAULT_SEARCH_RESULTS
+        ),
+        "N",
+    );
+    opts.optopt(
+        "",
+        "after",
+        "continue from where a previous search stopped",
+        "TOKEN",
+    );
+    opts.optflag("r", "raw", "don't expand wiki templates");
+    let brief = format!(
+        "Usage: {} search [options]\n\nLists definitions passing every filter given, e.g. \
+         --name-prefix un --defn-contains not --pos Adjective -l English.",
+        args[0]
+    );
+    let matches = parse_options(&opts, &args[2..], &brief);
+    if matches.opt_present("h") || !matches.free.is_empty() {
+        print!("{}", opts.usage(&brief));
+        return;
+    }
+    let filter = MeaningFilter {
+        name_prefix: matches.opt_str("name-prefix"),
+        name_pattern: matches.opt_str("name"),
+        definition_contains: matches.opt_str("defn-contains"),
+        part_of_speech: matches.opt_str("pos"),
+    };
+    let limit = match matches.opt_str("limit").map(|n| n.parse()) {
+        None => DEFAULT_SEARCH_RESULTS,
+        Some(Ok(n)) if n > 0 => n,
+        _ => {
+            eprintln!("--limit must be a positive number");
+            std::process::exit(FAILED);
+        }
+    };
+    let after = matches.opt_str("after").map(|token| {
+        Cursor::decode(&token).unwrap_or_else(|| {
+            eprintln!("--after must be a token printed by an earlier search");
+            std::process::exit(FAILED);
+        })
+    });
+
+    let dictionary = open_dictionary();
+    let mut request = QueryRequest::new(Query::Matching {
+        filter,
+        after,
+        limit,
+    });
+    request.language = language_option(&matches);
+    request.edition = matches.opt_str("edition");
+    let page = match dictionary.run(&request, &CancelToken::new()).or_exit() {
+        QueryResponse::Entries(page) => page,
+        response => unreachable!("{:?}", response),
+    };
+    for (word, meaning) in &page.items {
+        println!(
+            "{} {}",
+            word.bold(),
+            format!("({}, {})", meaning.language, meaning.part_of_speech).dimmed()
+        );
+        if matches.opt_present("r") {
+            println!("  {}", meaning.definition);
+        } else {
+            println!("  {}", dictionary.expand(&meaning.definition));
+        }
+    }
+    if page.items.is_empty() {
+        println!("No results found.");
+    } else if let Some(next) = page.next {
+        println!(
+            "{}",
+            format!("(more with --after {})", next.encode()).dimmed()
+        );
+    }
+}
+
+fn serve_main(args: &[String]) {
+    let mut opts = Options::new();
+    opts.optflag("h", "help", "print this help text");
+    opts.optflag(
+        "",
+        "dict",
+        "speak the DICT protocol (RFC 2229), for dict, GNOME Dictionary and other DICT clients",
+    );
+    opts.optopt(
+        "",
+        "bind",
+        "the address for DICT to listen on (default 127.0.0.1)",
+        "ADDRESS",
+    );
+    opts.optopt(
+        "",
+        "port",
+        &format!("the port for DICT to listen on (default {})", DICT_PORT),
+        "PORT",
+    );
+    opts.optopt(
+        "",
+        "http",
+        &format!(
+            "serve a JSON and HTML API over HTTP on ADDR, like {}",
+            HTTP_ADDRESS
+        ),
+        "ADDR",
+    );
+    opts.optopt(
+        "",
+        "base-url",
+        "where clients reach the HTTP server, for links in its responses (default http://ADDR)",
+        "URL",
+    );
+    let brief = format!(
+        "Usage: {0} serve --dict [options]\n       {0} serve --http ADDR [options]\n\nServes the \
+         database to other programs. Give both --dict and --http to do both at once.",
+        args[0]
+    );
+    let matches = parse_options(&opts, &args[2..], &brief);
+    let http = matches.opt_str("http");
+    let dict = matches.opt_present("dict");
+    if matches.opt_present("h") || !matches.free.is_empty() || !(dict || http.is_some()) {
+        print!("{}", opts.usage(&brief));
+        return;
+    }
+    let port = match matches.opt_str("port").map(|port| port.parse::<u16>()) {
+        None => DICT_PORT,
+        Some(Ok(port)) => port,
+        Some(Err(_)) => {
+            eprintln!("--port must be a number from 0 to 65535");
+            std::process::exit(FAILED);
+        }
+    };
+    let bind = matches
+        .opt_str("bind")
+        .unwrap_or_else(|| "127.0.0.1".to_owned());
+
+    // Fail now rather than on the first connection.
+    drop(open_dictionary());
+    let path = data_path("define3.sqlite3");
+    let listen = |address: &str| {
+        TcpListener::bind(address).unwrap_or_else(|e| {
+            eprintln!("Could not listen on {}: {}", address, e);
+            std::process::exit(FAILED);
+        })
+    };
+    let dict_listener = if dict {
+        let listener = listen(&format!("{}:{}", bind, port));
+        println!("Serving DICT on {}", listener.local_addr().unwrap());
+        Some(listener)
+    } else {
+        None
+    };
+    let http = http.map(|address| {
+        let listener = listen(&address);
+        let base_url = matches
+            .opt_str("base-url")
+            .unwrap_or_else(|| format!("http://{}", listener.local_addr().unwrap()));
+        println!("Serving HTTP on {}", base_url);
+        (listener, base_url)
+    });
+
+    let result = match (dict_listener, http) {
+        (Some(dict_listener), Some((listener, base_url))) => {
+            let dict_path = path.clone();
+            let dict = std::thread::spawn(move || dict_server::serve(dict_listener, &dict_path));
+            http_server::serve(listener, &path, &base_url)
+                .and_then(|()| dict.join().expect("DICT server panicked"))
+        }
+        (Some(dict_listener), None) => dict_server::serve(dict_listener, &path),
+        (None, Some((listener, base_url))) => http_server::serve(listener, &path, &base_url),
+        (None, None) => unreachable!(),
+    };
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        std::process::exit(FAILED);
+    }
+}
+
+fn new_words_main(args: &[String]) {
+    let mut opts = Options::new();
+    opts.optflag("h", "help", "print this help text");
+    opts.optopt(
+        "",
+        "since",
+        "only entries first seen in dumps from this date on: YYYY, YYYY-MM or YYYY-MM-DD",
+        "DATE",
+    );
+    opts.optopt(
+        "l",
+        "language",
+        "only this language (a name or an ISO 639 code)",
+        "lang",
+    );
+    let brief = format!(
+        "Usage: {} new-words --since DATE [options]\n\nLists entries that weren't in the first \
+         dump imported, with the date of the dump they first appeared in.",
+        args[0]
+    );
+    let matches = parse_options(&opts, &args[2..], &brief);
+    let since = match matches.opt_str("since") {
+        Some(since) if !matches.opt_present("h") && matches.free.is_empty() => since,
+        _ => {
+            print!("{}", opts.usage(&brief));
+            return;
+        }
+    };
+    if since.is_empty() || !since.chars().all(|c| c.is_ascii_digit() || c == '-') {
+        eprintln!("--since must be a date like 2024, 2024-01 or 2024-01-31");
+        std::process::exit(FAILED);
+    }
+    let language = language_option(&matches);
+
+    let dictionary = open_dictionary();
+    if !dictionary.has_table("first_seen").or_exit() {
+        eprintln!(
+            "This database doesn't know when entries were added; rebuild it with \
+                   build_definitions_db."
+        );
+        std::process::exit(FAILED);
+    }
+    let words = dictionary.new_words(&since, language.as_deref()).or_exit();
+    let mut current_language: Option<&str> = None;
+    for word in &words {
+        if current_language != Some(word.language.as_str()) {
+            println!("{}", word.language.green().bold());
+            current_language = Some(&word.language);
+        }
+        let first_seen = Locale::current().date(&word.first_seen);
+        println!("  {}  {}", first_seen.dimmed(), word.name);
+    }
+    if words.is_empty() {
+        println!("No new words since {}.", since);
+    }
+}
+
+fn languages_main(args: &[String]) {
+    let mut opts = Options::new();
+    opts.optflag("h", "help", "print this help text");
+    opts.optflag(
+        "",
+        "accessible",
+        "one sentence per language instead of columns, for screen readers",
+    );
+    let brief = format!(
+        "Usage: {} languages\n\nLists the languages in the database with their ISO 639 codes \
+         and number of words.",
+        args[0]
+    );
+    let matches = parse_options(&opts, &args[2..], &brief);
+    if matches.opt_present("h") || !matches.free.is_empty() {
+        print!("{}", opts.usage(&brief));
+        return;
+    }
+
+    let dictionary = open_dictionary();
+    for (name, count) in dictionary.languages().or_exit() {
+        let words = if count == 1 { "word" } else { "words" };
+        let codes = match language_by_name(&name) {
+            None => String::new(),
+            Some(language) => match language.iso639_1 {
+                None => language.iso639_3.to_owned(),
+                Some(iso639_1) => format!("{} {}", iso639_1, language.iso639_3),
+            },
+        };
+        let count = Locale::current().number(count);
+        if !matches.opt_present("accessible") {
+            println!("{:<30} {:<7} {:>9}", name, codes, count);
+        } else if codes.is_empty() {
+            println!("{}: {} {}.", name, count, words);
+        } else {
+            println!("{} ({}): {} {}.", name, codes, count, words);
+        }
+    }
+}
+
+// (kind, targets) pairs.
+type RelatedWords<'a> = Vec<(&'a str, Vec<&'a str>)>;
+
+fn related_main(args: &[String]) {
+    let mut opts = Options::new();
+    opts.optflag("h", "help", "print this help text");
+    opts.optopt(
+        "l",
+        "language",
+        "only follow relations in this language (a name or an ISO 639 code)",
+        "lang",
+    );
+    opts.optopt(
+        "",
+        "depth",
+        "how many steps away from WORD to go (default 1)",
+        "N",
+    );
+    opts.optmulti(
+        "",
+        "type",
+        "only follow this kind of relation: synonym, antonym, hypernym, hyponym, meronym, \
+         holonym, troponym or \"coordinate term\" (default all)",
+        "KIND",
+    );
+    opts.optopt(
+        "",
+        "format",
+        "grouped (default) or dot, for rendering with Graphviz",
+        "FORMAT",
+    );
+    let brief = format!(
+        "Usage: {} related [options] WORD\n\nWalks the graph of synonyms, antonyms, hypernyms \
+         etc. around WORD.",
+        args[0]
+    );
+    let matches = parse_options(&opts, &args[2..], &brief);
+    if matches.opt_present("h") || matches.free.len() != 1 {
+        print!("{}", opts.usage(&brief));
+        return;
+    }
+    let depth = match matches.opt_str("depth").map(|n| n.parse()) {
+        None => 1,
+        Some(Ok(n)) => n,
+        Some(Err(_)) => {
+            eprintln!("--depth must be a number");
+            std::process::exit(FAILED);
+        }
+    };
+    let language = language_option(&matches);
+    let format = matches
+        .opt_str("format")
+        .unwrap_or_else(|| "grouped".to_owned());
+    if format != "grouped" && format != "dot" {
+        eprintln!("--format must be grouped or dot, got {:?}", format);
+        std::process::exit(FAILED);
+    }
+
+    let dictionary = open_dictionary();
+    if !dictionary.has_table("relations").or_exit() {
+        eprintln!("This database has no relations; rebuild it with build_definitions_db.");
+        std::process::exit(FAILED);
+    }
+    let mut request = QueryRequest::new(Query::Related {
+        word: matches.free[0].clone(),
+        kinds: matches.opt_strs("type"),
+        depth,
+    });
+    request.language = language;
+    let edges = match dictionary.run(&request, &CancelToken::new()).or_exit() {
+        QueryResponse::Related(edges) => edges,
+        response => unreachable!("{:?}", response),
+    };
+
+    if format == "dot" {
+        let edges: Vec<dot::Edge> = edges
+            .iter()
+            .map(|(from, relation)| dot::Edge {
+                from,
+                to: &relation.target,
+                label: &relation.kind,
+                directed: inverse_relation(&relation.kind) != Some(relation.kind.as_str()),
+            })
+            .collect();
+        print!("{}", dot::to_dot(&matches.free[0], &edges));
+        return;
+    }
+
+    // Group edges by the word they start from, then by kind, keeping the order they were found.
+    let mut groups: Vec<(&str, RelatedWords)> = Vec::new();
+    for (from, relation) in &edges {
+        if groups.last().is_none_or(|(word, _)| word != from) {
+            groups.push((from, Vec::new()));
+        }
+        let kinds = &mut groups.last_mut().unwrap().1;
+        match kinds.iter_mut().find(|(kind, _)| *kind == relation.kind) {
+            Some((_, targets)) => targets.push(&relation.target),
+            None => kinds.push((&relation.kind, vec![&relation.target])),
+        }
+    }
+    for (word, kinds) in &groups {
+        println!("{}", word.green().bold());
+        for (kind, targets) in kinds {
+            println!("  {}: {}", kind.white(), targets.join(", "));
+        }
+    }
+    if groups.is_empty() {
+        println!("No related words found.");
+    }
+}
+
+fn etymology_tree_main(args: &[String]) {
+    let mut opts = Options::new();
+    opts.optflag("h", "help", "print this help text");
+    opts.optopt(
+        "l",
+        "language",
+        "only the etymologies in this language (a name or an ISO 639 code)",
+        "lang",
+    );
+    opts.optopt(
+        "",
+        "format",
+        "tree (default) or dot, for rendering with Graphviz",
+        "FORMAT",
+    );
+    let brief = format!(
+        "Usage: {} etymology-tree [options] WORD\n\nShows the words WORD comes from, as its \
+         etymologies' inherited, derived and borrowed templates name them, and the parts it's \
+         made of.",
+        args[0]
+    );
+    let matches = parse_options(&opts, &args[2..], &brief);
+    if matches.opt_present("h") || matches.free.len() != 1 {
+        print!("{}", opts.usage(&brief));
+        return;
+    }
+    let format = matches
+        .opt_str("format")
+        .unwrap_or_else(|| "tree".to_owned());
+    if format != "tree" && format != "dot" {
+        eprintln!("--format must be tree or dot, got {:?}", format);
+        std::process::exit(FAILED);
+    }
+    let word = &matches.free[0];
+    let language = language_option(&matches);
+
+    let dictionary = open_dictionary();
+    if !dictionary.has_table("etymologies").or_exit() {
+        eprintln!("This database has no etymologies; rebuild it with build_definitions_db.");
+        std::process::exit(FAILED);
+    }
+    let etymologies = dictionary.etymologies(word, language.as_deref()).or_exit();
+    let trees: Vec<(&Etymology, Vec<Origin>)> = etymologies
+        .iter()
+        .map(|etymology| (etymology, origins(&etymology.text)))
+        .filter(|(_, origins)| !origins.is_empty())
+        .collect();
+
+    if format == "dot" {
+        let label = |term: &str, language: &str| format!("{} ({})", term, language);
+        let mut nodes: Vec<(String, String, &str)> = Vec::new();
+        for (etymology, origins) in &trees {
+            let root = label(word, &etymology.language);
+            for origin in origins {
+                let from = match origin.parent {
+                    None => root.clone(),
+                    Some(i) => label(&origins[i].term, &origins[i].language),
+                };
+                nodes.push((from, label(&origin.term, &origin.language), origin.kind));
+            }
+        }
+        let edges: Vec<dot::Edge> = nodes
+            .iter()
+            .map(|(from, to, kind)| dot::Edge {
+                from,
+                to,
+                label: kind,
+                directed: true,
+            })
+            .collect();
+        print!("{}", dot::to_dot(word, &edges));
+        return;
+    }
+
+    for (etymology, origins) in &trees {
+        let title = match etymology.number {
+            0 => format!("{} ({})", word, etymology.language),
+            n => format!("{} ({}), etymology {}", word, etymology.language, n),
+        };
+        println!("{}", title.green().bold());
+        // Depth first, so each origin is under the one it's the origin of.
+        let mut stack: Vec<(Option<usize>, usize)> = vec![(None, 1)];
+        while let Some((parent, depth)) = stack.pop() {
+            if let Some(i) = parent {
+                let origin = &origins[i];
+                let how = match origin.kind {
+                    "part" => "part:".to_owned(),
+                    "calque" => "calque of".to_owned(),
+                    kind => format!("{} from", kind),
+                };
+                println!(
+                    "{}{} {} ({})",
+                    "  ".repeat(depth - 1),
+                    how.white(),
+                    origin.term,
+                    origin.language
+                );
+            }
+            let children = (0..origins.len()).filter(|&j| origins[j].parent == parent);
+            let children: Vec<usize> = children.collect();
+            for &j in children.iter().rev() {
+                stack.push((Some(j), depth + 1));
+            }
+        }
+    }
+    if trees.is_empty() {
+        println!("No etymology found.");
+    }
+}
+
+// How many words a partial search lists.
+const PARTIAL_SEARCH_RESULTS: usize = 100;
+
+fn partial_search(matches: &getopts::Matches) {
+    let s = &matches.free[0];
+    if !matches.opt_present("force") {
+        let mut limits = PartialSearchLimits::default();
+        if let Some(n) = matches.opt_str("min-length") {
+            limits.min_length = n.parse().unwrap_or_else(|_| {
+                eprintln!("--min-length must be a number, got {:?}", n);
+                std::process::exit(FAILED);
+            });
+        }
+        if let Some(path) = matches.opt_str("stopwords") {
+            let stopwords = std::fs::read_to_string(&path).unwrap_or_else(|e| {
+                eprintln!("Could not read {}: {}", path, e);
+                std::process::exit(FAILED);
+            });
+            limits.stopwords = stopwords
+                .lines()
+                .map(|word| word.trim().to_lowercase())
+                .filter(|word| !word.is_empty())
+                .collect();
+        }
+        if let Err(e) = limits.check(s) {
+            eprintln!(
+                "Not searching: {}, so it would be slow and match too much. Pass --force to \
+                 search anyway.",
+                e
+            );
+            std::process::exit(FAILED);
+        }
+    }
+
+    let dictionary = open_dictionary();
+    let request = QueryRequest::new(Query::Search {
+        pattern: contains_pattern(s),
+        after: None,
+        limit: PARTIAL_SEARCH_RESULTS,
+    });
+    let page = match dictionary.run(&request, &CancelToken::new()).or_exit() {
+        QueryResponse::Headwords(page) => page,
+        response => unreachable!("{:?}", response),
+    };
+    let empty = page.items.is_empty();
+    let groups = group_by_script(page.items);
+    // Headers only when there's more than one group to tell apart.
+    let headers = groups.len() > 1;
+    for (script, words) in &groups {
+        if headers {
+            println!("{}", script.name().green().bold());
+        }
+        for word in words {
+            println!("{}", word);
+        }
+    }
+    if empty {
+        println!("No results found.");
+        std::process::exit(NOT_FOUND);
+    } else if page.next.is_some() {
+        println!(
+            "{}",
+            format!("(only the first {} shown)", PARTIAL_SEARCH_RESULTS).dimmed()
+        );
+    }
+}
+
+// How many words a glob search lists, and how many definitions it reads at a time to find them.
+const GLOB_SEARCH_RESULTS: usize = 100;
+const GLOB_SEARCH_PAGE: usize = 500;
+
+// Lists the words matching a glob pattern with their parts of speech, one per line, for
+// crosswords and for looking at words built alike.
+fn glob_search(matches: &getopts::Matches) {
+    let dictionary = open_dictionary();
+    let filter = MeaningFilter {
+        name_pattern: Some(matches.free[0].clone()),
+        ..MeaningFilter::default()
+    };
+    // (word, its parts of speech in the order they're first seen)
+    let mut words: Vec<(String, Vec<String>)> = Vec::new();
+    let mut after = None;
+    let more = loop {
+        let mut request = QueryRequest::new(Query::Matching {
+            filter: filter.clone(),
+            after,
+            limit: GLOB_SEARCH_PAGE,
+        });
+        request.language = language_option(matches);
+        request.edition = matches.opt_str("edition");
+        let page = match dictionary.run(&request, &CancelToken::new()).or_exit() {
+            QueryResponse::Entries(page) => page,
+            response => unreachable!("{:?}", response),
+        };
+        // Whether there's a word after the last one listed.
+        let mut full = false;
+        for (word, meaning) in page.items {
+            match words.last_mut() {
+                Some((last, parts_of_speech)) if *last == word => {
+                    if !parts_of_speech.contains(&meaning.part_of_speech) {
+                        parts_of_speech.push(meaning.part_of_speech);
+                    }
+                    continue;
+                }
+                _ => (),
+            }
+            if words.len() == GLOB_SEARCH_RESULTS {
+                full = true;
+                break;
+            }
+            words.push((word, vec![meaning.part_of_speech]));
+        }
+        if full || page.next.is_none() {
+            break full;
+        }
+        after = page.next;
+    };
+    for (word, parts_of_speech) in &words {
+        println!(
+            "{} {}",
+            word,
+            format!("({})", parts_of_speech.join(", ")).dimmed()
+        );
+    }
+    if words.is_empty() {
+        println!("No results found.");
+        std::process::exit(NOT_FOUND);
+    } else if more {
+        println!(
+            "{}",
+            format!("(only the first {} shown)", GLOB_SEARCH_RESULTS).dimmed()
+        );
+    }
+}
+
+// How wide definitions are wrapped when there's no telling how wide stdout is.
+const DEFAULT_WIDTH: usize = 80;
+
+// How many similar words are suggested for a word that isn't found, unless --suggest says
+// otherwise.
+const DEFAULT_SUGGESTIONS: usize = 5;
+
+// How many definitions a reverse search lists.
+const REVERSE_SEARCH_RESULTS: usize = 20;
+
+fn reverse_search(matches: &getopts::Matches) {
+    let dictionary = open_dictionary();
+    if !dictionary.has_table(SEARCH_TABLE).or_exit() {
+        eprintln!(
+            "This database has no index for reverse lookups; rebuild it with \
+             build_definitions_db."
+        );
+        std::process::exit(FAILED);
+    }
+    let language = language_option(matches);
+    let results = dictionary
+        .reverse_search(
+            &matches.free[0],
+            language.as_deref(),
+            None,
+            REVERSE_SEARCH_RESULTS,
+        )
+        .or_exit()
+        .items;
+    for (word, meaning) in &results {
+        println!(
+            "{} {}",
+            word.bold(),
+            format!("({}, {})", meaning.language, meaning.part_of_speech).dimmed()
+        );
+        println!("  {}", dictionary.expand(&meaning.definition));
+    }
+    if results.is_empty() {
+        println!("No results found.");
+        std::process::exit(NOT_FOUND);
+    }
+}
+
+fn read_context(path: &str) -> String {
+    let context = if path == "-" {
+        let mut context = String::new();
+        std::io::stdin()
+            .read_to_string(&mut context)
+            .map(|_| context)
+    } else {
+        std::fs::read_to_string(path)
+    };
+    match context {
+        Ok(context) => context.split_whitespace().collect::<Vec<_>>().join(" "),
+        Err(e) => {
+            eprintln!("Could not read {}: {}", path, e);
+            std::process::exit(FAILED);
+        }
+    }
+}
+
+fn highlight_word(text: &str, word: &str) -> String {
+    let re = Regex::new(&format!(r"(?i)\b{}\b", regex::escape(word))).unwrap();
+    re.replace_all(text, |caps: &regex::Captures| {
+        caps[0].bold().underline().to_string()
+    })
+    .into_owned()
+}
+
+fn define_main(args: &[String]) {
+    let mut opts = Options::new();
+    opts.optflag("h", "help", "print this help text");
+    opts.optflag("V", "version", "print the version");
+    opts.optflag("r", "raw", "don't expand wiki templates");
+    opts.optflag(
+        "",
+        "low-memory",
+        "for machines with 1GB or less, like a Raspberry Pi: keep less in memory, at some cost \
+         in speed (the default on such machines; goes with any subcommand)",
+    );
+    opts.optopt(
+        "d",
+        "database",
+        "the database to use instead of the config file's or the default (goes with any \
+         subcommand)",
+        "PATH",
+    );
+    opts.optopt(
+        "",
+        "report",
+        "if a definition can't be shown, save it and what went wrong to FILE, for a bug report",
+        "FILE",
+    );
+    opts.optopt(
+        "w",
+        "width",
+        "wrap definitions to this many columns (default the terminal's width, or $COLUMNS, or 80)",
+        "N",
+    );
+    opts.optflag(
+        "",
+        "no-pager",
+        "don't page entries taller than the terminal through $PAGER (default less)",
+    );
+    opts.optflag(
+        "",
+        "tui",
+        "browse entries full-screen, with a search box and a sidebar to jump to each language \
+         and part of speech; WORD is optional",
+    );
+    opts.optflag(
+        "i",
+        "interactive",
+        "look up one word after another, read from a prompt, with the database opened once. \
+         Lines can't be edited beyond what the terminal allows; type history to list earlier \
+         words, kept between sessions, and !N to look up the Nth again",
+    );
+    opts.optflag(
+        "",
+        "stdin",
+        "look up every word of a list read from stdin, one per line, with the database opened \
+         once; also with - as WORD. With --json, prints JSON Lines",
+    );
+    opts.optflag(
+        "",
+        "json",
+        "print the entry as JSON, with raw and expanded definitions, the entries they refer to \
+         and any problems expanding them",
+    );
+    opts.optflag(
+        "",
+        "no-history",
+        "don't record this lookup in the history that study-stats summarizes",
+    );
+    opts.optopt(
+        "l",
+        "language",
+        "only print this language (a name or an ISO 639 code)",
+        "lang",
+    );
+    opts.optopt(
+        "",
+        "langs",
+        "only print these languages, in this order (comma-separated names or ISO 639 codes)",
+        "LANGS",
+    );
+    opts.optopt(
+        "",
+        "max-bytes",
+        &format!(
+            "stop printing an entry after this many bytes of definitions (default {}, 0 for no limit)",
+            DEFAULT_MAX_BYTES
+        ),
+        "N",
+    );
+    opts.optopt(
+        "",
+        "max-chars",
+        "print at most this many characters, cutting between senses and ending in \"…\", for \
+         status bars and bots",
+        "N",
+    );
+    opts.optopt(
+        "",
+        "max-lines",
+        "print at most this many lines, cutting between senses and ending in \"…\"",
+        "N",
+    );
+    opts.optmulti(
+        "",
+        "unknown-templates",
+        "what to print for templates that can't be expanded: keep, strip or placeholder \
+         (default); NAME=POLICY sets it for a single template",
+        "[NAME=]POLICY",
+    );
+    opts.optflag(
+        "",
+        "common-first",
+        "list everyday senses before obsolete, archaic and rare ones",
+    );
+    opts.optflag(
+        "",
+        "warnings",
+        "list problems found while expanding templates, and links to words that aren't in the \
+         dictionary, for bug reports",
+    );
+    opts.optopt(
+        "",
+        "edition",
+        "only print definitions from this Wiktionary edition, e.g. en or fr",
+        "CODE",
+    );
+    opts.optflag(
+        "",
+        "ignore-case",
+        "also print the words spelt like WORD but for case, like Polish for polish",
+    );
+    opts.optflag(
+        "",
+        "fold-diacritics",
+        "if nothing is called exactly WORD, also try words that are WORD with accents, like \
+         façade for facade",
+    );
+    opts.optopt(
+        "",
+        "normalize",
+        &format!(
+            "what to try when nothing is called exactly WORD, in order: comma-separated {}, or \
+             none (default all)",
+            Normalizers::names().join(", ")
+        ),
+        "STEPS",
+    );
+    opts.optflag(
+        "",
+        "accessible",
+        "for screen readers and monochrome logs: no color, say what each line is, and one \
+         sentence per line",
+    );
+    opts.optflag(
+        "",
+        "plain",
+        "no color and numbered definitions, for braille displays and for diffing output",
+    );
+    opts.optflag(
+        "",
+        "smart-quotes",
+        "print curly quotes and apostrophes, and dashes for -- and ---, for publishing",
+    );
+    opts.optflag(
+        "",
+        "ascii",
+        "print straight quotes, hyphens and plain spaces instead of curly quotes, dashes and \
+         no-break spaces, for plain-text pipelines",
+    );
+    opts.optopt(
+        "",
+        "sense-marker",
+        "what to start each definition with: none, number, dash or star (default none, or \
+         number with --plain)",
+        "MARKER",
+    );
+    opts.optflag(
+        "",
+        "permalink",
+        "print a link to each language's section on Wiktionary, for sharing",
+    );
+    opts.optopt(
+        "",
+        "context",
+        "like --context-file, with the text given inline",
+        "TEXT",
+    );
+    opts.optopt(
+        "",
+        "context-file",
+        "the text WORD was found in (- for stdin), to pick its language and the sense that fits \
+         best; the text is echoed with WORD highlighted",
+        "PATH",
+    );
+    opts.optflag(
+        "",
+        "kanji",
+        "for Japanese kana (or romaji), also list the kanji spellings with that reading",
+    );
+    opts.optflag(
+        "",
+        "etymology",
+        "also print where the word comes from, after each language's definitions",
+    );
+    opts.optflag(
+        "",
+        "ipa",
+        "also print how the word is said in IPA, under each language's name",
+    );
+    opts.optflag(
+        "",
+        "follow",
+        "for words whose definitions are all forms of another word, like geese, also print that \
+         word's definitions",
+    );
+    opts.optflag(
+        "",
+        "examples",
+        "also print example sentences, with translations, under the definitions they're for",
+    );
+    opts.optflag(
+        "",
+        "thesaurus",
+        "also print synonyms and antonyms, by sense, after each language's definitions",
+    );
+    opts.optflag(
+        "",
+        "expand-abbreviations",
+        "spell out shorthand like sg. and intr. in definitions",
+    );
+    opts.optopt(
+        "",
+        "suggest",
+        "how many similar words to suggest if WORD isn't found (default 5, or 0 with --first)",
+        "N",
+    );
+    opts.optflag(
+        "",
+        "first",
+        "only print the best-ranked definition, without reading the rest, for popups where \
+         only the top hit matters and speed does",
+    );
+    opts.optflag("p", "partial", "list words that contain WORD instead");
+    opts.optflag(
+        "",
+        "reverse",
+        "list words whose definitions match WORD instead, e.g. \"fear of spiders\"",
+    );
+    opts.optflag(
+        "g",
+        "glob",
+        "list words matching WORD as a pattern instead, with their parts of speech: ? for any \
+         letter, * for any run of them and [abc] for one of a, b or c, e.g. 'c?t*'",
+    );
+    opts.optopt(
+        "",
+        "min-length",
+        &format!(
+            "refuse partial searches shorter than this (default {})",
+            PartialSearchLimits::default().min_length
+        ),
+        "N",
+    );
+    opts.optopt(
+        "",
+        "stopwords",
+        "refuse partial searches for the words in this file, one per line, instead of the \
+         built-in list",
+        "FILE",
+    );
+    opts.optflag(
+        "",
+        "force",
+        "do a partial search even if it's too short or a stopword",
+    );
+    let brief = format!(
+        "Usage: {0} [options] WORD\n       {0} languages            list languages in the \
+         database\n       {0} related [options] WORD  walk synonyms, antonyms etc.\n       {0} \
+         etymology-tree [options] WORD  the words WORD comes from\n       {0} \
+         study-stats [options]  summarize the lookup history\n       {0} new-words --since \
+         DATE   list entries added since DATE\n       {0} digest [options]  \
+         recently looked-up words with their definitions\n       {0} serve --dict|--http ADDR  \
+         serve the database to DICT or HTTP clients\n       {0} warm [options]  \
+         expand the most looked-up words ahead of time\n       {0} search [options]  \
+         list definitions matching several filters at once\n       {0} export-level --list \
+         NAME  flashcards for a word list like jlpt-n4\n       {0} import DUMP [options]  \
+         build the database from a Wiktionary dump (.xml or .xml.bz2) or wiktextract \
+         JSONL\n       {0} scrape --from FILE  build a small database from the Wiktionary \
+         API instead\n       {0} export --format stardict|dictd DIR  a dictionary for \
+         other programs\n       {0} dump FILE.jsonl.zst  every entry as compressed JSON \
+         Lines\n       {0} export-anki WORDS DECK.apkg  Anki flashcards for a word \
+         list\n       {0} alias save NAME -- ARGS  save a command line to run \
+         again\n       {0} fetch --manifest URL  download a \
+         prebuilt database\n       {0} check --against-live WORD  compare an entry with \
+         Wiktionary now\n       {0} template-coverage  list the templates that don't \
+         expand\n       {0} test-templates CORPUS.toml  check how templates expand against \
+         expected text\n       {0} -p \
+         [options] TEXT         list words containing TEXT\n       {0} \
+         --reverse [options] TEXT  list words whose definitions match TEXT\n       {0} -g \
+         [options] PATTERN      list words matching PATTERN, like 'c?t*'\n\nUse \
+         `{0} -- WORD` to look up a word that has the same name as a subcommand, `{0} -i` \
+         to look up one word after another, and `{0} --stdin` to look up a list of them.",
+        args[0]
+    );
+    let matches = parse_options(&opts, &args[1..], &brief);
+    if matches.opt_present("V") {
+        println!("define {}", env!("CARGO_PKG_VERSION"));
+        return;
+    }
+    // Partial, reverse and glob searches take their text from the command line even with -i.
+    let interactive = matches.opt_present("i")
+        && !matches.opt_present("p")
+        && !matches.opt_present("reverse")
+        && !matches.opt_present("g");
+    let tui = matches.opt_present("tui");
+    let batch = matches.opt_present("stdin") || matches.free == ["-"];
+    let words = if interactive || matches.opt_present("stdin") {
+        0..=0
+    } else if tui {
+        0..=1
+    } else {
+        1..=1
+    };
+    if matches.opt_present("h") || !words.contains(&matches.free.len()) {
+        print!("{}", opts.usage(&brief));
+        return;
+    }
+
+    if batch && (interactive || tui) {
+        eprintln!("--stdin can't be used with -i or --tui");
+        std::process::exit(FAILED);
+    }
+
+    let accessible = matches.opt_present("accessible");
+    let plain = matches.opt_present("plain");
+    if accessible || plain {
+        colored::control::set_override(false);
+    }
+    let sense_marker = match matches.opt_str("sense-marker") {
+        None if plain => SenseMarker::Number,
+        None => SenseMarker::None,
+        Some(marker) => marker.parse().unwrap_or_else(|e| {
+            eprintln!("--sense-marker: {}", e);
+            std::process::exit(FAILED);
+        }),
+    };
+
+    let typography = match (
+        matches.opt_present("smart-quotes"),
+        matches.opt_present("ascii"),
+    ) {
+        (true, true) => {
+            eprintln!("--smart-quotes and --ascii can't be used together");
+            std::process::exit(FAILED);
+        }
+        (true, false) => Typography::Smart,
+        (false, true) => Typography::Ascii,
+        (false, false) => Typography::AsIs,
+    };
+
+    let max_bytes = match matches.opt_str("max-bytes") {
+        None => Some(DEFAULT_MAX_BYTES),
+        Some(n) => match n.parse() {
+            Ok(0) => None,
+            Ok(n) => Some(n),
+            Err(_) => {
+                eprintln!("--max-bytes must be a number, got {:?}", n);
+                std::process::exit(FAILED);
+            }
+        },
+    };
+
+    let positive = |name: &str| match matches.opt_str(name).map(|n| n.parse::<usize>()) {
+        None => None,
+        Some(Ok(n)) if n > 0 => Some(n),
+        Some(_) => {
+            eprintln!("--{} must be a positive number", name);
+            std::process::exit(FAILED);
+        }
+    };
+    let (max_chars, max_lines) = (positive("max-chars"), positive("max-lines"));
+
+    let width = match matches.opt_str("w") {
+        None => config().width.unwrap_or_else(terminal_width),
+        Some(n) => match n.parse() {
+            Ok(n) if n > 0 => n,
+            _ => {
+                eprintln!("--width must be a positive number, got {:?}", n);
+                std::process::exit(FAILED);
+            }
+        },
+    };
+
+    let template_policies = match TemplatePolicies::parse(&matches.opt_strs("unknown-templates")) {
+        Ok(policies) => policies,
+        Err(e) => {
+            eprintln!("--unknown-templates: {}", e);
+            std::process::exit(FAILED);
+        }
+    };
+
+    let normalizers = match matches.opt_str("normalize") {
+        None => Normalizers::default(),
+        Some(spec) => Normalizers::parse(&spec).unwrap_or_else(|e| {
+            eprintln!("--normalize: {}", e);
+            std::process::exit(FAILED);
+        }),
+    };
+
+    let first = matches.opt_present("first");
+    let suggest = match matches.opt_str("suggest").map(|n| n.parse()) {
+        None if first => 0,
+        None => DEFAULT_SUGGESTIONS,
+        Some(Ok(n)) => n,
+        Some(Err(_)) => {
+            eprintln!("--suggest must be a number");
+            std::process::exit(FAILED);
+        }
+    };
+
+    if matches.opt_present("p") {
+        partial_search(&matches);
+        return;
+    }
+    if matches.opt_present("reverse") {
+        reverse_search(&matches);
+        return;
+    }
+    if matches.opt_present("g") {
+        glob_search(&matches);
+        return;
+    }
+
+    let context = match matches.opt_str("context-file") {
+        Some(ref path) if path == "-" && (interactive || batch) => {
+            eprintln!(
+                "--context-file - can't be used with -i or --stdin, which read words from stdin"
+            );
+            std::process::exit(FAILED);
+        }
+        Some(path) => Some(read_context(&path)),
+        None => matches.opt_str("context"),
+    };
+    let dictionary = open_dictionary();
+    if matches.opt_present("fold-diacritics") && !dictionary.can_fold_diacritics() {
+        eprintln!(
+            "This database was built before --fold-diacritics; rebuild it with \
+             build_definitions_db to find words without their accents."
+        );
+    }
+    let mut formatters = Formatters::default();
+    if matches.opt_present("expand-abbreviations") {
+        formatters.expand_abbreviations(Abbreviations::default());
+    }
+    let languages: Vec<String> = matches
+        .opt_str("langs")
+        .map(|langs| {
+            langs
+                .split(',')
+                .map(str::trim)
+                .filter(|lang| !lang.is_empty())
+                .map(normalize_language)
+                .collect()
+        })
+        .unwrap_or_default();
+    if !languages.is_empty() && matches.opt_present("l") {
+        eprintln!("-l and --langs can't be used together");
+        std::process::exit(FAILED);
+    }
+    let options = SearchOptions {
+        // --langs replaces the config file's language too.
+        language: if languages.is_empty() {
+            language_option(&matches)
+        } else {
+            None
+        },
+        languages,
+        preferred_languages: config()
+            .languages
+            .iter()
+            .map(|lang| normalize_language(lang))
+            .collect(),
+        hidden_languages: config()
+            .hide_languages
+            .iter()
+            .map(|lang| normalize_language(lang))
+            .collect(),
+        edition: matches.opt_str("edition"),
+        normalizers,
+        // The context picks the best sense itself, from all of them.
+        first: first && context.is_none(),
+        ignore_case: matches.opt_present("ignore-case"),
+        fold_diacritics: matches.opt_present("fold-diacritics"),
+    };
+    let session = Session {
+        matches: &matches,
+        // Editions often say the same thing, so with more than one it matters which one is
+        // talking.
+        show_editions: dictionary.editions().or_exit().len() > 1,
+        dictionary,
+        history: if matches.opt_present("no-history") {
+            None
+        } else {
+            Some(open_history())
+        },
+        formatters,
+        options,
+        template_policies,
+        print_options: PrintOptions {
+            max_bytes,
+            permalinks: matches.opt_present("permalink"),
+            accessible,
+            plain,
+            sense_marker,
+            typography,
+            width,
+            max_chars,
+            max_lines,
+        },
+        context,
+        suggest,
+        first,
+        json_lines: batch,
+        // Definitions expanded by `build_definitions_db --pre-expand` were expanded with the
+        // default policies and without keeping warnings, so they're only good for the default
+        // output.
+        use_pre_expanded: matches.opt_strs("unknown-templates").is_empty()
+            && !matches.opt_present("warnings"),
+    };
+    if tui {
+        browse(&session);
+    } else if interactive {
+        repl(&session);
+    } else if batch {
+        if !define_batch(&session) {
+            std::process::exit(NOT_FOUND);
+        }
+    } else {
+        if !matches.opt_present("no-pager") {
+            hold_output();
+        }
+        let found = define_word(&session, &matches.free[0]);
+        show_held_output();
+        if !found {
+            std::process::exit(NOT_FOUND);
+        }
+    }
+}
+
+// How wide stdout is: its terminal's width if it's a terminal, or else $COLUMNS, which shells set
+// but don't always export, or else 80.
+fn terminal_width() -> usize {
+    #[cfg(unix)]
+    // Safe: TIOCGWINSZ only writes the winsize given.
+    unsafe {
+        let mut size: libc::winsize = std::mem::zeroed();
+        if libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) == 0 && size.ws_col > 0 {
+            return size.ws_col as usize;
+        }
+    }
+    env::var("COLUMNS")
+        .ok()
+        .and_then(|columns| columns.trim().parse().ok())
+        .filter(|&columns| columns > 0)
+        .unwrap_or(DEFAULT_WIDTH)
+}
+
+// How tall stdout's terminal is, or else $LINES, or else 24.
+fn terminal_height() -> usize {
+    #[cfg(unix)]
+    // Safe: TIOCGWINSZ only writes the winsize given.
+    unsafe {
+        let mut size: libc::winsize = std::mem::zeroed();
+        if libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) == 0 && size.ws_row > 0 {
+            return size.ws_row as usize;
+        }
+    }
+    env::var("LINES")
+        .ok()
+        .and_then(|lines| lines.trim().parse().ok())
+        .filter(|&lines| lines > 0)
+        .unwrap_or(24)
+}
+
+// Stdout while it's being held back to see how tall it is: where it really goes, and what's
+// reading what's written to it instead.
+#[cfg(unix)]
+struct HeldOutput {
+    stdout: std::os::unix::io::RawFd,
+    reader: std::thread::JoinHandle<Vec<u8>>,
+}
+
+#[cfg(unix)]
+static HELD_OUTPUT: Mutex<Option<HeldOutput>> = Mutex::new(None);
+
+// Holds back what's printed to stdout, if it's a terminal and there's a pager to use, so that
+// `show_held_output` can page it only if it's taller than the terminal.
+#[cfg(unix)]
+fn hold_output() {
+    use std::os::unix::io::FromRawFd;
+
+    if !std::io::stdout().is_terminal() || pager_command().is_none() {
+        return;
+    }
+    let mut fds = [0; 2];
+    // Safe: pipe only writes the two descriptors, and dup/dup2 only change which files this
+    // process's descriptors refer to.
+    let stdout = unsafe {
+        if libc::pipe(fds.as_mut_ptr()) != 0 {
+            return;
+        }
+        let stdout = libc::dup(libc::STDOUT_FILENO);
+        libc::dup2(fds[1], libc::STDOUT_FILENO);
+        libc::close(fds[1]);
+        stdout
+    };
+    // Whether to color is decided by whether stdout is a terminal, which it's about not to be.
+    if colored::control::SHOULD_COLORIZE.should_colorize() {
+        colored::control::set_override(true);
+    }
+    // Safe: the read end is only used here, and closed when the file is dropped.
+    let mut pipe = unsafe { std::fs::File::from_raw_fd(fds[0]) };
+    let reader = std::thread::spawn(move || {
+        let mut held = Vec::new();
+        let _ = pipe.read_to_end(&mut held);
+        held
+    });
+    *HELD_OUTPUT.lock().unwrap() = Some(HeldOutput { stdout, reader });
+}
+
+#[cfg(not(unix))]
+fn hold_output() {}
+
+// Prints what `hold_output` held back, through the pager if it doesn't fit on the screen. Called
+// before exiting, on errors too, so that nothing printed is lost.
+#[cfg(unix)]
+fn show_held_output() {
+    let held = match HELD_OUTPUT.lock().unwrap().take() {
+        Some(held) => held,
+        None => return,
+    };
+    let _ = std::io::stdout().flush();
+    // Safe: puts the real stdout back, which closes the pipe's last write end.
+    unsafe {
+        libc::dup2(held.stdout, libc::STDOUT_FILENO);
+        libc::close(held.stdout);
+    }
+    let output = held.reader.join().unwrap_or_default();
+    let width = terminal_width();
+    let rows: usize = String::from_utf8_lossy(&output)
+        .lines()
+        .map(|line| textwrap::core::display_width(line).max(1).div_ceil(width))
+        .sum();
+    if rows >= terminal_height() {
+        if let Some(mut pager) = pager_command().and_then(|mut command| command.spawn().ok()) {
+            if let Some(mut stdin) = pager.stdin.take() {
+                // A pager quit before the end stops reading, which is fine.
+                let _ = stdin.write_all(&output);
+            }
+            let _ = pager.wait();
+            return;
+        }
+    }
+    let _ = std::io::stdout().write_all(&output);
+    let _ = std::io::stdout().flush();
+}
+
+#[cfg(not(unix))]
+fn show_held_output() {}
+
+// $PAGER (or less), to be given the output on stdin; None if it's set to nothing or to cat. less
+// is told to keep the colors, unless $LESS says otherwise.
+#[cfg(unix)]
+fn pager_command() -> Option<std::process::Command> {
+    let pager = env::var("PAGER").unwrap_or_else(|_| "less".to_owned());
+    if pager.trim().is_empty() || pager == "cat" {
+        return None;
+    }
+    let mut command = std::process::Command::new("sh");
+    command
+        .arg("-c")
+        .arg(&pager)
+        .stdin(std::process::Stdio::piped());
+    if env::var_os("LESS").is_none() {
+        command.env("LESS", "FRX");
+    }
+    Some(command)
+}
+
+#[cfg(unix)]
+fn browse(session: &Session) {
+    let result = tui::browse(
+        &session.dictionary,
+        &session.options,
+        &session.template_policies,
+        &session.formatters,
+        session.matches.free.first().map(String::as_str),
+    );
+    if let Err(e) = result {
+        eprintln!("--tui: {}", e);
+        std::process::exit(FAILED);
+    }
+}
+
+#[cfg(not(unix))]
+fn browse(_: &Session) {
+    eprintln!("--tui isn't supported on this platform yet.");
+    std::process::exit(FAILED);
+}
+
+// How many earlier words `history` lists at the prompt.
+const PROMPT_HISTORY_SHOWN: usize = 20;
+
+// The words typed at the prompt, oldest first, kept one per line between sessions unless
+// there's no `path` to keep them in.
+struct PromptHistory {
+    path: Option<PathBuf>,
+    words: Vec<String>,
+}
+
+impl PromptHistory {
+    fn open(save: bool) -> PromptHistory {
+        let path = data_path("prompt-history.txt");
+        let words = std::fs::read_to_string(&path)
+            .map(|text| text.lines().map(str::to_owned).collect())
+            .unwrap_or_default();
+        PromptHistory {
+            path: if save { Some(path) } else { None },
+            words,
+        }
+    }
+
+    fn push(&mut self, word: &str) {
+        if self.words.last().map(String::as_str) == Some(word) {
+            return;
+        }
+        self.words.push(word.to_owned());
+        let path = match self.path {
+            Some(ref path) => path,
+            None => return,
+        };
+        if let Some(dir) = path.parent() {
+            let _ = std::fs::create_dir_all(dir);
+        }
+        let saved = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .and_then(|mut file| writeln!(file, "{}", word));
+        if let Err(e) = saved {
+            eprintln!("Could not save {} to {}: {}", word, path.display(), e);
+        }
+    }
+
+    // The last words, numbered from 1 for the whole history as `!N` counts them.
+    fn print(&self) {
+        let start = self.words.len().saturating_sub(PROMPT_HISTORY_SHOWN);
+        for (i, word) in self.words.iter().enumerate().skip(start) {
+            println!("{:>5}  {}", i + 1, word);
+        }
+    }
+}
+
+// Reads words from a prompt and looks each one up, until end of input or a line saying quit or
+// exit.
+fn repl(session: &Session) {
+    let stdin = std::io::stdin();
+    // Words piped in don't need prompting for.
+    let prompt = stdin.is_terminal();
+    // --no-history keeps this session's words out of it too.
+    let mut history = PromptHistory::open(session.history.is_some());
+    let mut line = String::new();
+    loop {
+        if prompt {
+            print!("{} ", "define>".green().bold());
+            std::io::stdout().flush().unwrap();
+        }
+        line.clear();
+        match stdin.read_line(&mut line) {
+            Ok(0) => {
+                if prompt {
+                    println!();
+                }
+                return;
+            }
+            Ok(_) => {}
+            // A line that isn't UTF-8 is skipped, not taken for the end of input.
+            Err(ref e) if e.kind() == std::io::ErrorKind::InvalidData => {
+                eprintln!("Could not read that line: {}", e);
+                continue;
+            }
+            Err(e) => {
+                eprintln!("Could not read words from stdin: {}", e);
+                std::process::exit(FAILED);
+            }
+        }
+        let word = match line.trim() {
+            "" => continue,
+            "quit" | "exit" => return,
+            "history" => {
+                history.print();
+                continue;
+            }
+            again if again.starts_with('!') => {
+                let n: Option<usize> = again[1..].parse().ok();
+                match n
+                    .and_then(|n| n.checked_sub(1))
+                    .and_then(|i| history.words.get(i))
+                {
+                    Some(word) => word.clone(),
+                    None => {
+                        eprintln!("No word {} in the history.", again);
+                        continue;
+                    }
+                }
+            }
+            word => word.to_owned(),
+        };
+        history.push(&word);
+        define_word(session, &word);
+        println!();
+    }
+}
+
+// How many words of a list on stdin are looked up together.
+const BATCH_SIZE: usize = 256;
+
+// Looks up every word of a list on stdin, one per line, each under its own heading unless it's
+// JSON Lines. Words are read BATCH_SIZE at a time and looked up with one query where they can be.
+// Returns whether any of them were found.
+fn define_batch(session: &Session) -> bool {
+    let json = session.matches.opt_present("json");
+    let mut first = true;
+    let mut found = false;
+    let mut lines = std::io::stdin().lock().lines();
+    loop {
+        let mut words: Vec<String> = Vec::with_capacity(BATCH_SIZE);
+        for line in lines.by_ref() {
+            let line = line.unwrap_or_else(|e| {
+                eprintln!("Could not read words from stdin: {}", e);
+                std::process::exit(FAILED);
+            });
+            let word = line.trim();
+            if !word.is_empty() {
+                words.push(word.to_owned());
+            }
+            if words.len() == BATCH_SIZE {
+                break;
+            }
+        }
+        if words.is_empty() {
+            return found;
+        }
+        let names: Vec<&str> = words.iter().map(String::as_str).collect();
+        let results = session
+            .dictionary
+            .lookup_many_with(&names, &session.options)
+            .or_exit();
+        for (word, result) in words.iter().zip(results) {
+            if !json {
+                if !first {
+                    println!();
+                }
+                println!("{}", word.bold().underline());
+            }
+            first = false;
+            found |= define_found(session, word, result);
+        }
+    }
+}
+
+// The message of the last panic in `catch_render`.
+static RENDER_PANIC: Mutex<Option<String>> = Mutex::new(None);
+
+// Runs `render`, turning a panic into its message, so that one definition that trips a bug
+// doesn't take the rest of the entry down with it.
+fn catch_render<T, F: FnOnce() -> T>(render: F) -> Result<T, String> {
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(|info| {
+        *RENDER_PANIC.lock().unwrap() = Some(info.to_string());
+    }));
+    let result = panic::catch_unwind(AssertUnwindSafe(render));
+    panic::set_hook(hook);
+    result.map_err(|_| {
+        RENDER_PANIC
+            .lock()
+            .unwrap()
+            .take()
+            .unwrap_or_else(|| "unknown error".to_owned())
+    })
+}
+
+// Writes the definitions that couldn't be shown, with the templates in each and what went wrong,
+// as JSON for a bug report.
+fn write_failure_report(
+    path: &str,
+    word: &str,
+    failures: &[(Meaning, String)],
+) -> std::io::Result<()> {
+    let failures: Vec<serde_json::Value> = failures
+        .iter()
+        .map(|(meaning, error)| {
+            // The parser could be what failed.
+            let templates: Vec<String> = catch_render(|| {
+                parse_templates(&meaning.definition)
+                    .iter()
+                    .filter_map(|node| match node {
+                        Node::Template(template) => Some(template.raw.to_owned()),
+                        _ => None,
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+            serde_json::json!({
+                "language": meaning.language,
+                "part_of_speech": meaning.part_of_speech,
+                "edition": meaning.edition,
+                "definition": meaning.definition,
+                "templates": templates,
+                "error": error,
+            })
+        })
+        .collect();
+    let report = serde_json::json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "word": word,
+        "failures": failures,
+    });
+    std::fs::write(path, serde_json::to_string_pretty(&report).unwrap())
+}
+
+// For each language in which every definition of `word` points to another word, as `pointer`
+// finds it in (definition, language), those words' definitions in that language, grouped by word.
+fn pointed_to<F>(
+    dictionary: &Dictionary,
+    word: &str,
+    langs: &Grouped,
+    edition: Option<&str>,
+    pointer: F,
+) -> Vec<(String, Grouped)>
+where
+    F: Fn(&str, &str) -> Option<String>,
+{
+    let mut lemmas: Vec<(String, Vec<Meaning>)> = Vec::new();
+    for (lang, poses) in langs {
+        let mut targets: Vec<String> = Vec::new();
+        for meaning in poses.values().flatten() {
+            match pointer(&meaning.definition, lang) {
+                Some(target) => {
+                    if target != word && !targets.contains(&target) {
+                        targets.push(target);
+                    }
+                }
+                _ => {
+                    targets.clear();
+                    break;
+                }
+            }
+        }
+        for target in targets {
+            let mut meanings = dictionary.lookup(&target).or_exit();
+            meanings.retain(|meaning| {
+                &meaning.language == lang
+                    && edition.is_none_or(|edition| meaning.edition.as_deref() == Some(edition))
+            });
+            match lemmas.iter_mut().find(|(lemma, _)| *lemma == target) {
+                Some((_, found)) => found.extend(meanings),
+                None => lemmas.push((target, meanings)),
+            }
+        }
+    }
+    lemmas
+        .into_iter()
+        .filter(|(_, meanings)| !meanings.is_empty())
+        .map(|(lemma, meanings)| (lemma, group_by_language(meanings)))
+        .collect()
+}
+
+// For each language in which every definition of `word` is a form of another word, like "Plural
+// of goose", those words' definitions in that language, grouped by word.
+fn form_of_lemmas(
+    dictionary: &Dictionary,
+    word: &str,
+    langs: &Grouped,
+    edition: Option<&str>,
+) -> Vec<(String, Grouped)> {
+    pointed_to(dictionary, word, langs, edition, |definition, lang| {
+        form_of(definition)
+            .filter(|reference| reference.language.as_deref().is_none_or(|l| l == lang))
+            .map(|reference| reference.target)
+    })
+}
+
+// For each language in which `word` is only defined by pointing to its spelling in another
+// script, like the Serbo-Croatian мачка's `{{sh-see|mačka}}`, that spelling's definitions.
+fn script_counterparts(
+    dictionary: &Dictionary,
+    word: &str,
+    langs: &Grouped,
+    edition: Option<&str>,
+) -> Vec<(String, Grouped)> {
+    pointed_to(dictionary, word, langs, edition, |definition, _| {
+        script_pointer(definition)
+    })
+}
+
+// Everything `define WORD` needs to look up and print a word, set up once so that `define -i`
+// can look up one word after another.
+struct Session<'a> {
+    matches: &'a getopts::Matches,
+    dictionary: Dictionary,
+    history: Option<History>,
+    formatters: Formatters,
+    options: SearchOptions,
+    template_policies: TemplatePolicies,
+    print_options: PrintOptions,
+    context: Option<String>,
+    suggest: usize,
+    first: bool,
+    show_editions: bool,
+    // Whether JSON goes on one line, for JSON Lines.
+    json_lines: bool,
+    use_pre_expanded: bool,
+}
+
+// Looks up and prints `query`, returning whether it had any definitions.
+fn define_word(session: &Session, query: &str) -> bool {
+    let found = session
+        .dictionary
+        .lookup_with(query, &session.options)
+        .or_exit();
+    define_found(session, query, found)
+}
+
+// Prints what looking up `query` found; see `define_word`.
+fn define_found(session: &Session, query: &str, mut found: LookupResult) -> bool {
+    let json = session.matches.opt_present("json");
+    if found.headword != *query && !json {
+        println!(
+            "{}",
+            format!("(showing results for {})", found.headword).dimmed()
+        );
+    }
+    let others = std::mem::take(&mut found.others);
+    let (mut any, mut entry) = show_headword(session, query, found, true);
+    // With --ignore-case, each headword spelt the same but for case is its own entry.
+    let mut also = Vec::new();
+    for other in others {
+        if !json {
+            println!();
+            println!("{}", format!("(also {})", other.headword).dimmed());
+        }
+        let (shown, other_entry) = show_headword(session, query, other, false);
+        any |= shown;
+        also.extend(other_entry);
+    }
+    if let Some(mut entry) = entry.take() {
+        if !also.is_empty() {
+            entry["also"] = serde_json::Value::Array(also);
+        }
+        if session.json_lines {
+            println!("{}", serde_json::to_string(&entry).unwrap());
+        } else {
+            println!("{}", serde_json::to_string_pretty(&entry).unwrap());
+        }
+    }
+    any
+}
+
+// Shows what was found for one headword, or with --json returns it as JSON instead. Only the
+// first headword shown for a query echoes the context. Returns whether there was anything.
+fn show_headword(
+    session: &Session,
+    query: &str,
+    found: LookupResult,
+    first_shown: bool,
+) -> (bool, Option<serde_json::Value>) {
+    let Session {
+        matches,
+        ref dictionary,
+        ref formatters,
+        ref options,
+        ref template_policies,
+        suggest,
+        first,
+        show_editions,
+        use_pre_expanded,
+        ..
+    } = *session;
+    let json = matches.opt_present("json");
+    let raw = matches.opt_present("r") || config().expand_templates == Some(false);
+    let word = found.headword;
+    let mut meanings = found.meanings;
+    // With a context, only the language it's in, and the sense that fits it best.
+    let mut chosen: Option<Meaning> = None;
+    if let Some(ref context) = session.context {
+        let ranking = dictionary.rank_senses(&word, context).or_exit();
+        if let (None, Some(language)) = (&options.language, &ranking.language) {
+            meanings.retain(|meaning| &meaning.language == language);
+        }
+        chosen = ranking
+            .best()
+            .filter(|&best| meanings.contains(best))
+            .cloned();
+        if first {
+            if let Some(ref best) = chosen {
+                meanings.retain(|meaning| meaning == best);
+            }
+            meanings.truncate(1);
+        }
+        if !json && first_shown {
+            println!("{}", highlight_word(context, &word));
+        }
+    }
+
+    let kanji = if matches.opt_present("kanji") {
+        let spellings = kanji_spellings(&meanings);
+        let spellings: Vec<&str> = spellings.iter().map(String::as_str).collect();
+        let mut words = dictionary.lookup_many(&spellings).or_exit();
+        words.retain(|word| !word.meanings.is_empty());
+        words.into_iter().map(|word| word.name).collect()
+    } else {
+        Vec::new()
+    };
+
+    if let (false, Some(history)) = (meanings.is_empty(), &session.history) {
+        let mut languages: Vec<&str> = meanings
+            .iter()
+            .map(|meaning| meaning.language.as_str())
+            .collect();
+        languages.dedup();
+        if let Err(e) = history.record(&word, &languages) {
+            eprintln!("Could not record the lookup: {}", e);
+        }
+    }
+
+    let suggestions = if meanings.is_empty() && suggest > 0 {
+        dictionary.suggestions(&word, suggest).or_exit()
+    } else {
+        Vec::new()
+    };
+
+    let mut langs = group_by_language(meanings);
+    if matches.opt_present("common-first") {
+        for (_, poses) in &mut langs {
+            for defns in poses.values_mut() {
+                sort_common_first(defns);
+            }
+        }
+    }
+    if json {
+        // Anything that changes the output changes the checksum.
+        let mut checksum_options = vec!["json".to_owned()];
+        checksum_options.extend(matches.opt_strs("unknown-templates"));
+        checksum_options.extend(options.language.iter().map(|l| format!("language={}", l)));
+        checksum_options.extend(options.edition.iter().map(|e| format!("edition={}", e)));
+        checksum_options.push(format!(
+            "common-first={}",
+            matches.opt_present("common-first")
+        ));
+        checksum_options.push(format!("suggest={}", suggest));
+        if options.ignore_case {
+            checksum_options.push("ignore-case".to_owned());
+        }
+        checksum_options.push(format!("first={}", first));
+        checksum_options.push(format!(
+            "expand-abbreviations={}",
+            matches.opt_present("expand-abbreviations")
+        ));
+        let checksum_options: Vec<&str> = checksum_options.iter().map(String::as_str).collect();
+        let checksum = dictionary
+            .build_id()
+            .or_exit()
+            .map(|build_id| entry_checksum(&word, &build_id, &checksum_options));
+        let mut entry = entry_json(
+            query,
+            &word,
+            &langs,
+            template_policies,
+            dictionary,
+            formatters,
+            checksum.as_deref(),
+        );
+        if langs.is_empty() {
+            entry["suggestions"] = serde_json::json!(suggestions);
+        }
+        return (!langs.is_empty(), Some(entry));
+    }
+
+    // (raw definition, issues found while expanding it)
+    let mut warnings: Vec<(String, Vec<ExpansionIssue>)> = Vec::new();
+    // (meaning, why it couldn't be shown)
+    let mut failures: Vec<(Meaning, String)> = Vec::new();
+    let expand_extra = |s: &str| {
+        if raw {
+            s.to_owned()
+        } else {
+            expand_with(s, template_policies, dictionary).0
+        }
+    };
+    let mut extras = Extras {
+        headwords: dictionary
+            .headwords(&word, options.language.as_deref())
+            .or_exit(),
+        ..Extras::default()
+    };
+    if matches.opt_present("etymology") {
+        extras.etymologies = dictionary
+            .etymologies(&word, options.language.as_deref())
+            .or_exit();
+        extras
+            .etymologies
+            .retain(|etymology| langs.iter().any(|(lang, _)| *lang == etymology.language));
+        for etymology in &mut extras.etymologies {
+            etymology.text = expand_extra(&etymology.text);
+        }
+    }
+    if matches.opt_present("ipa") {
+        extras.pronunciations = dictionary
+            .pronunciations(&word, options.language.as_deref())
+            .or_exit();
+    }
+    if matches.opt_present("examples") {
+        extras.examples = dictionary
+            .examples(&word, options.language.as_deref())
+            .or_exit();
+        for example in &mut extras.examples {
+            example.text = expand_extra(&example.text);
+            example.translation = example.translation.as_deref().map(expand_extra);
+        }
+    }
+    if matches.opt_present("thesaurus") {
+        extras.thesaurus = dictionary
+            .relations(&word, options.language.as_deref())
+            .or_exit();
+        extras
+            .thesaurus
+            .retain(|relation| THESAURUS.iter().any(|&(kind, _)| relation.kind == kind));
+        for relation in &mut extras.thesaurus {
+            relation.sense = relation.sense.as_deref().map(expand_extra);
+        }
+    }
+    extras.other_scripts = dictionary
+        .other_scripts(&word, options.language.as_deref())
+        .or_exit();
+    let mut render_definition = |meaning: &Meaning| {
+        let s = &meaning.definition;
+        let rendered = catch_render(|| {
+            let result = if raw {
+                s.to_owned()
+            } else if let (true, Some(expanded)) = (use_pre_expanded, &meaning.expanded) {
+                expanded.clone()
+            } else {
+                let (result, issues) = expand_with(s, template_policies, dictionary);
+                if !issues.is_empty() {
+                    warnings.push((s.to_owned(), issues));
+                }
+                result
+            };
+            formatters.definition(&meaning.language, result)
+        });
+        let result = match rendered {
+            Ok(result) => result,
+            Err(error) => {
+                failures.push((meaning.clone(), error));
+                return "(Sorry, this definition couldn't be shown.)"
+                    .red()
+                    .to_string();
+            }
+        };
+        let result = if chosen.as_ref() == Some(meaning) {
+            format!("> {}", result).bold().to_string()
+        } else {
+            result
+        };
+        match meaning.edition {
+            Some(ref edition) if show_editions => format!("[{}] {}", edition, result),
+            _ => result,
+        }
+    };
+    print_words(
+        &word,
+        &langs,
+        &extras,
+        formatters,
+        &session.print_options,
+        &mut render_definition,
+    );
+    // The entry in one script is often only a pointer to the other's, so show that one too.
+    for (spelling, spelling_langs) in
+        script_counterparts(dictionary, &word, &langs, options.edition.as_deref())
+    {
+        println!();
+        println!(
+            "{}",
+            format!(
+                "({} is written {} in {})",
+                word,
+                spelling,
+                Script::of(&spelling).name()
+            )
+            .dimmed()
+        );
+        print_words(
+            &spelling,
+            &spelling_langs,
+            &Extras::default(),
+            formatters,
+            &session.print_options,
+            &mut render_definition,
+        );
+    }
+    if matches.opt_present("follow") {
+        for (lemma, lemma_langs) in
+            form_of_lemmas(dictionary, &word, &langs, options.edition.as_deref())
+        {
+            println!();
+            println!("{}", format!("({} is a form of {})", word, lemma).dimmed());
+            print_words(
+                &lemma,
+                &lemma_langs,
+                &Extras::default(),
+                formatters,
+                &session.print_options,
+                &mut render_definition,
+            );
+        }
+    }
+
+    if !failures.is_empty() {
+        match matches.opt_str("report") {
+            Some(path) => match write_failure_report(&path, &word, &failures) {
+                Ok(()) => eprintln!(
+                    "Saved what went wrong to {}; please attach it to a bug report.",
+                    path
+                ),
+                Err(e) => eprintln!("Could not write {}: {}", path, e),
+            },
+            None => eprintln!(
+                "{} couldn't be shown; pass --report FILE to save what went wrong for a bug \
+                 report.",
+                if failures.len() == 1 {
+                    "1 definition".to_owned()
+                } else {
+                    format!("{} definitions", failures.len())
+                }
+            ),
+        }
+    }
+
+    if !suggestions.is_empty() {
+        println!("{} {}", "Did you mean:".white(), suggestions.join(", "));
+    }
+
+    // Rather than leave it looking like the word isn't there at all.
+    if langs.is_empty() && (options.language.is_some() || !options.languages.is_empty()) {
+        let mut others: Vec<String> = dictionary
+            .lookup(&word)
+            .or_exit()
+            .into_iter()
+            .map(|meaning| meaning.language)
+            .collect();
+        others.dedup();
+        if !others.is_empty() {
+            println!("{} {}", "In other languages:".white(), others.join(", "));
+        }
+    }
+
+    if !kanji.is_empty() {
+        println!("{} {}", "Kanji:".white(), kanji.join(", "));
+    }
+
+    if matches.opt_present("warnings") {
+        for (definition, issues) in &warnings {
+            for issue in issues {
+                eprintln!("{}: {}", "warning".yellow().bold(), issue);
+            }
+            eprintln!("  in: {}", definition);
+        }
+    }
+    (!langs.is_empty(), None)
+}
+
+// Takes -d PATH, --database PATH or --database=PATH out of the options before any `--`.
+fn take_database_option(args: &mut Vec<String>) -> Option<PathBuf> {
+    let words_start = args
+        .iter()
+        .position(|arg| arg == "--")
+        .unwrap_or(args.len());
+    let i = 1 + args[1..words_start]
+        .iter()
+        .position(|arg| arg == "-d" || arg == "--database" || arg.starts_with("--database="))?;
+    let option = args.remove(i);
+    if let Some(path) = option.strip_prefix("--database=") {
+        return Some(PathBuf::from(path));
+    }
+    if i + 1 >= words_start {
+        eprintln!("{} needs the path to a database", option);
+        std::process::exit(FAILED);
+    }
+    Some(PathBuf::from(args.remove(i)))
+}
+
+fn main() {
+    let mut args: Vec<String> = env::args().collect();
+    if args.get(1).map(String::as_str) == Some("alias") {
+        match alias_main(&args) {
+            Some(command) => args = command,
+            None => return,
+        }
+    }
+    // --low-memory and -d go for every subcommand, so they're taken out before they parse their
+    // options. build_definitions_db has its own.
+    if args.get(1).map(String::as_str) != Some("import") {
+        if let Some(path) = take_database_option(&mut args) {
+            let _ = DATABASE.set(path);
+        }
+    }
+    let words_start = args
+        .iter()
+        .position(|arg| arg == "--")
+        .unwrap_or(args.len());
+    let low_memory = args[1..words_start].iter().any(|arg| arg == "--low-memory");
+    if low_memory && args[1] != "import" {
+        let words = args.split_off(words_start);
+        args.retain(|arg| arg != "--low-memory");
+        args.extend(words);
+    }
+    set_low_memory(low_memory);
+    match args.get(1).map(String::as_str) {
+        Some("languages") => languages_main(&args),
+        Some("related") => related_main(&args),
+        Some("etymology-tree") => etymology_tree_main(&args),
+        Some("study-stats") => study_stats_main(&args),
+        Some("new-words") => new_words_main(&args),
+        Some("import") => import_main(&args),
+        Some("digest") => digest_main(&args),
+        Some("serve") => serve_main(&args),
+        Some("warm") => warm_main(&args),
+        Some("search") => search_main(&args),
+        Some("export-level") => export_level_main(&args),
+        Some("scrape") => scrape_main(&args),
+        Some("fetch") => fetch_main(&args),
+        Some("export") => export_main(&args),
+        Some("export-anki") => export_anki_main(&args),
+        Some("dump") => dump_main(&args),
+        Some("check") => check_main(&args),
+        Some("template-coverage") => template_coverage_main(&args),
+        Some("test-templates") => test_templates_main(&args),
+        _ => define_main(&args),
+    }
 }
@@ -0,0 +1,69 @@
+//! Expanded definitions of the most looked-up words, kept so that looking them up doesn't expand
+//! their templates again every time. That's most of a lookup's work, which matters on slow storage
+//! like a Raspberry Pi's SD card. `define warm` fills it; without `--pre-expand`, lookups read
+//! `Meaning::expanded` from here.
+
+use rusqlite::Result;
+
+use dictionary::Dictionary;
+use expand::{expand_with, TemplatePolicies};
+
+/// The cache table, by rowid of the words table. Rows are renumbered whenever an edition is
+/// reimported, so imports drop it.
+pub const CACHE_TABLE: &str = "expansion_cache";
+
+impl Dictionary {
+    /// Expands the definitions of `words` with the default policies and caches them, replacing
+    /// what was cached before. `progress` is called with how many words are done so far. Returns
+    /// how many definitions were cached.
+    pub fn warm_cache<F: FnMut(usize)>(
+        &mut self,
+        words: &[&str],
+        mut progress: F,
+    ) -> Result<usize> {
+        self.clear_cache()?;
+        self.connection().execute_batch(&format!(
+            "CREATE TABLE {} (rowid INTEGER PRIMARY KEY, expanded TEXT NOT NULL)",
+            CACHE_TABLE
+        ))?;
+        self.has_cache = true;
+        let policies = TemplatePolicies::default();
+        let mut cached = 0;
+        let tx = self.connection().unchecked_transaction()?;
+        {
+            let mut rowids = self
+                .connection()
+                .prepare("SELECT rowid FROM words WHERE name = ?1")?;
+            let mut insert = self.connection().prepare(&format!(
+                "INSERT INTO {} (rowid, expanded) VALUES (?1, ?2)",
+                CACHE_TABLE
+            ))?;
+            for (done, word) in words.iter().enumerate() {
+                let rowids: Vec<i64> = rowids
+                    .query_map([word], |row| row.get(0))?
+                    .collect::<Result<_>>()?;
+                for rowid in rowids {
+                    let (_, meaning) = self.meaning_by_rowid(rowid)?;
+                    // Already expanded by `build_definitions_db --pre-expand`.
+                    if meaning.expanded.is_some() {
+                        continue;
+                    }
+                    let (expanded, _) = expand_with(&meaning.definition, &policies, self);
+                    insert.execute(rusqlite::params![rowid, expanded])?;
+                    cached += 1;
+                }
+                progress(done + 1);
+            }
+        }
+        tx.commit()?;
+        Ok(cached)
+    }
+
+    /// Empties the cache.
+    pub fn clear_cache(&mut self) -> Result<()> {
+        self.connection()
+            .execute_batch(&format!("DROP TABLE IF EXISTS {}", CACHE_TABLE))?;
+        self.has_cache = false;
+        Ok(())
+    }
+}
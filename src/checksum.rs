@@ -0,0 +1,83 @@
+// 64-bit FNV-1a. Std's hasher may change between Rust releases, and checksums handed out to
+// clients should stay the same across rebuilds of define3.
+fn fnv1a(parts: &[&str]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for part in parts {
+        // A separator that can't appear in the parts keeps ("ab", "c") and ("a", "bc") apart.
+        for &byte in part.as_bytes().iter().chain(&[0xff]) {
+            hash ^= u64::from(byte);
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+    hash
+}
+
+/// A stable checksum of an entry as rendered: the word, the database build it came from (see
+/// `Dictionary::build_id`), and whatever options change what's rendered, in a fixed order.
+pub fn entry_checksum(word: &str, build_id: &str, options: &[&str]) -> String {
+    let mut parts = vec![word, build_id];
+    parts.extend_from_slice(options);
+    format!("{:016x}", fnv1a(&parts))
+}
+
+/// A checksum as the value of an HTTP `ETag` header.
+pub fn etag(checksum: &str) -> String {
+    format!("\"{}\"", checksum)
+}
+
+/// Whether an `If-None-Match` header already covers the entry with this checksum, i.e. whether
+/// the client can be sent a 304.
+pub fn if_none_match(header: &str, checksum: &str) -> bool {
+    header
+        .split(',')
+        .map(str::trim)
+        .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag(checksum))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Clients keep checksums across rebuilds of define3, so they mustn't change.
+    #[test]
+    fn checksums_are_stable() {
+        assert_eq!(fnv1a(&[]), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(entry_checksum("cat", "build", &[]), "73603f32dfa18ea5");
+    }
+
+    #[test]
+    fn any_change_changes_the_checksum() {
+        let checksum = entry_checksum("cat", "build-1", &["English", "expanded"]);
+        assert_eq!(checksum.len(), 16);
+        for other in [
+            entry_checksum("Cat", "build-1", &["English", "expanded"]),
+            entry_checksum("cat", "build-2", &["English", "expanded"]),
+            entry_checksum("cat", "build-1", &["English", "raw"]),
+            entry_checksum("cat", "build-1", &["expanded", "English"]),
+            entry_checksum("cat", "build-1", &["English"]),
+            // The same bytes, split differently.
+            entry_checksum("cat", "build-1", &["Englishexpanded"]),
+            entry_checksum("ca", "tbuild-1", &["English", "expanded"]),
+        ] {
+            assert_ne!(other, checksum);
+        }
+    }
+
+    #[test]
+    fn matches_etags() {
+        let checksum = entry_checksum("cat", "build", &[]);
+        assert_eq!(etag(&checksum), "\"73603f32dfa18ea5\"");
+        assert!(if_none_match("\"73603f32dfa18ea5\"", &checksum));
+        assert!(if_none_match("W/\"73603f32dfa18ea5\"", &checksum));
+        assert!(if_none_match(
+            "\"0000000000000000\", \"73603f32dfa18ea5\"",
+            &checksum
+        ));
+        assert!(if_none_match("*", &checksum));
+        // A checksum from another build, or from before the entry changed.
+        let changed = entry_checksum("cat", "build-2", &[]);
+        assert!(!if_none_match(&etag(&changed), &checksum));
+        assert!(!if_none_match("73603f32dfa18ea5", &checksum));
+        assert!(!if_none_match("", &checksum));
+    }
+}
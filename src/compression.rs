@@ -0,0 +1,126 @@
+use std::io;
+use std::io::Read;
+
+use zstd::dict::DecoderDictionary;
+use zstd::stream::raw::CParameter;
+
+/// Databases built with `--compress` have this table, holding the zstd dictionary that their
+/// definitions and templates were compressed with. Its presence is what marks a database as
+/// compressed.
+pub const DICTIONARY_TABLE: &str = "zstd_dictionary";
+
+// zstd's own default for trained dictionaries.
+const DICTIONARY_SIZE: usize = 112 * 1024;
+
+// Databases are built once and read many times, so it's worth spending time on a smaller file.
+const COMPRESSION_LEVEL: i32 = 12;
+
+/// Trains a dictionary on a sample of the text that will be compressed. Short definitions
+/// compress badly on their own; with a dictionary of the words and markup they tend to share,
+/// they shrink a lot more.
+pub fn train_dictionary(samples: &[String]) -> io::Result<Vec<u8>> {
+    zstd::dict::from_samples(samples, DICTIONARY_SIZE)
+}
+
+pub struct Compressor {
+    compressor: zstd::bulk::Compressor<'static>,
+}
+
+impl Compressor {
+    pub fn new(dictionary: &[u8]) -> io::Result<Compressor> {
+        let mut compressor =
+            zstd::bulk::Compressor::with_dictionary(COMPRESSION_LEVEL, dictionary)?;
+        // There's only ever one dictionary, and most definitions are short enough that four
+        // bytes per frame add up.
+        compressor.set_parameter(CParameter::DictIdFlag(false))?;
+        Ok(Compressor { compressor })
+    }
+
+    pub fn compress(&mut self, text: &str) -> io::Result<Vec<u8>> {
+        self.compressor.compress(text.as_bytes())
+    }
+}
+
+pub struct Decompressor {
+    dictionary: DecoderDictionary<'static>,
+}
+
+impl Decompressor {
+    pub fn new(dictionary: &[u8]) -> Decompressor {
+        Decompressor {
+            dictionary: DecoderDictionary::copy(dictionary),
+        }
+    }
+
+    pub fn decompress(&self, data: &[u8]) -> io::Result<String> {
+        let mut text = String::new();
+        zstd::stream::read::Decoder::with_prepared_dictionary(data, &self.dictionary)?
+            .read_to_string(&mut text)?;
+        Ok(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rusqlite::Connection;
+
+    use super::*;
+    use sql_functions::register_decompress;
+
+    fn samples() -> Vec<String> {
+        (0..2000)
+            .map(|i| {
+                format!(
+                    "{{{{lb|en|transitive}}}} To [[move]] something {} times, as in [[example {}]].",
+                    i % 37,
+                    i
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn round_trips_with_a_dictionary() {
+        let dictionary = train_dictionary(&samples()).unwrap();
+        let mut compressor = Compressor::new(&dictionary).unwrap();
+        let decompressor = Decompressor::new(&dictionary);
+        for text in [
+            "{{lb|en|transitive}} To [[move]] something 3 times.",
+            "",
+            "façade, 日本語 and 안녕",
+        ] {
+            let compressed = compressor.compress(text).unwrap();
+            assert_eq!(decompressor.decompress(&compressed).unwrap(), text);
+        }
+        // What the dictionary is for: short text that shares its words shrinks.
+        let text = "{{lb|en|transitive}} To [[move]] something 12 times, as in [[example 5]].";
+        assert!(compressor.compress(text).unwrap().len() < text.len() / 2);
+    }
+
+    #[test]
+    fn rejects_other_data() {
+        let dictionary = train_dictionary(&samples()).unwrap();
+        let decompressor = Decompressor::new(&dictionary);
+        assert!(decompressor.decompress(b"not zstd at all").is_err());
+    }
+
+    #[test]
+    fn decompress_in_sql() {
+        let dictionary = train_dictionary(&samples()).unwrap();
+        let mut compressor = Compressor::new(&dictionary).unwrap();
+        let conn = Connection::open_in_memory().unwrap();
+        register_decompress(&conn, &dictionary).unwrap();
+        let compressed = compressor.compress("To [[move]] something.").unwrap();
+        let decompress = |value: &dyn rusqlite::ToSql| -> rusqlite::Result<Option<String>> {
+            conn.query_row("SELECT decompress(?1)", [value], |row| row.get(0))
+        };
+        assert_eq!(
+            decompress(&compressed).unwrap().as_deref(),
+            Some("To [[move]] something.")
+        );
+        // Rows merged in uncompressed, and missing ones.
+        assert_eq!(decompress(&"plain").unwrap().as_deref(), Some("plain"));
+        assert_eq!(decompress(&None::<String>).unwrap(), None);
+        assert!(decompress(&b"garbage".to_vec()).is_err());
+    }
+}
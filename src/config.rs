@@ -0,0 +1,459 @@
+//! Defaults for define's options, from `~/.config/define3/config.toml`: which database to use,
+//! which languages to show and in what order, colors, wrap width and whether to expand
+//! templates. Options given on the command line win over it. It also keeps aliases, saved
+//! command lines that `define alias run` runs again, as `alias.NAME = ["search", "--pos", "Noun"]`.
+//!
+//! A project can have settings of its own in a `.define3.toml`, found in the current directory or
+//! the nearest one above it, like git finds its repository. They go over the ones in the config
+//! file, and a relative `database` in one is relative to the directory it's in.
+//!
+//! Only the part of TOML a flat settings file needs is understood: `key = value` lines with
+//! strings, integers, booleans and arrays of them, and comments. Tables aren't.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Where the config file is, if there's a config directory at all.
+pub fn config_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("define3").join("config.toml"))
+}
+
+/// What a project's settings file is called.
+pub const WORKSPACE_FILE: &str = ".define3.toml";
+
+/// The project settings file for `dir`: the one in it, or in the nearest directory above it.
+pub fn workspace_path(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .map(|dir| dir.join(WORKSPACE_FILE))
+        .find(|path| path.is_file())
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Config {
+    /// The database to read instead of the one in the data directory. A leading `~/` is the home
+    /// directory.
+    pub database: Option<PathBuf>,
+    /// Only print this language (a name or an ISO 639 code), like `-l`.
+    pub language: Option<String>,
+    /// Print these languages before the others, in this order.
+    pub languages: Vec<String>,
+    /// Don't print these languages unless asked for with `-l` or `--langs`.
+    pub hide_languages: Vec<String>,
+    pub color: Option<ColorChoice>,
+    /// Wrap definitions to this many columns, like `-w`.
+    pub width: Option<usize>,
+    /// Whether to expand templates; false is like passing `-r` every time.
+    pub expand_templates: Option<bool>,
+    /// Saved arguments to define, by name.
+    pub aliases: BTreeMap<String, Vec<String>>,
+}
+
+/// Whether `name` can name an alias: letters, digits, - and _, so it's a bare TOML key.
+pub fn is_alias_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+}
+
+// `s` as a TOML string.
+fn quote(s: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Saves `args` as the alias `name` in the config file at `path`, or removes the alias if `args`
+/// is None. The rest of the file is left as it is.
+pub fn set_alias(path: &Path, name: &str, args: Option<&[String]>) -> Result<(), String> {
+    let error = |e: io::Error| format!("{}: {}", path.display(), e);
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(error(e)),
+    };
+    let key = format!("alias.{}", name);
+    let mut lines: Vec<String> = text
+        .lines()
+        .filter(|line| line.split_once('=').map(|(k, _)| k.trim()) != Some(key.as_str()))
+        .map(str::to_owned)
+        .collect();
+    if let Some(args) = args {
+        let args: Vec<String> = args.iter().map(|arg| quote(arg)).collect();
+        lines.push(format!("{} = [{}]", key, args.join(", ")));
+    }
+    let mut text = lines.join("\n");
+    text.push('\n');
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(error)?;
+    }
+    fs::write(path, text).map_err(error)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ColorChoice {
+    /// Color when writing to a terminal.
+    Auto,
+    Always,
+    Never,
+}
+
+impl FromStr for ColorChoice {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<ColorChoice, String> {
+        match s {
+            "auto" => Ok(ColorChoice::Auto),
+            "always" => Ok(ColorChoice::Always),
+            "never" => Ok(ColorChoice::Never),
+            _ => Err(format!(
+                "unknown color setting {:?}; expected auto, always or never",
+                s
+            )),
+        }
+    }
+}
+
+impl Config {
+    /// Reads the config file at `path`. A missing file is an empty config.
+    pub fn load(path: &Path) -> Result<Config, String> {
+        match fs::read_to_string(path) {
+            Ok(text) => Config::parse(&text).map_err(|e| format!("{}: {}", path.display(), e)),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(Config::default()),
+            Err(e) => Err(format!("{}: {}", path.display(), e)),
+        }
+    }
+
+    /// Reads the config file at `config_path()`, if there is one, and the project settings for
+    /// the current directory over it.
+    pub fn load_default() -> Result<Config, String> {
+        let config = match config_path() {
+            Some(path) => Config::load(&path)?,
+            None => Config::default(),
+        };
+        let workspace = match env::current_dir().ok().and_then(|dir| workspace_path(&dir)) {
+            Some(path) => Config::load_workspace(&path)?,
+            None => return Ok(config),
+        };
+        Ok(config.merge(workspace))
+    }
+
+    /// Reads the project settings file at `path`.
+    pub fn load_workspace(path: &Path) -> Result<Config, String> {
+        let mut config = Config::load(path)?;
+        if let (Some(database), Some(dir)) = (config.database.as_mut(), path.parent()) {
+            *database = dir.join(&*database);
+        }
+        Ok(config)
+    }
+
+    /// This config with the settings that `other` has in place of its own. Aliases are kept
+    /// from both, with `other`'s winning.
+    pub fn merge(mut self, other: Config) -> Config {
+        if other.database.is_some() {
+            self.database = other.database;
+        }
+        if other.language.is_some() {
+            self.language = other.language;
+        }
+        if !other.languages.is_empty() {
+            self.languages = other.languages;
+        }
+        if !other.hide_languages.is_empty() {
+            self.hide_languages = other.hide_languages;
+        }
+        if other.color.is_some() {
+            self.color = other.color;
+        }
+        if other.width.is_some() {
+            self.width = other.width;
+        }
+        if other.expand_templates.is_some() {
+            self.expand_templates = other.expand_templates;
+        }
+        self.aliases.extend(other.aliases);
+        self
+    }
+
+    pub fn parse(text: &str) -> Result<Config, String> {
+        let mut config = Config::default();
+        for (i, line) in text.lines().enumerate() {
+            config
+                .parse_line(line)
+                .map_err(|e| format!("line {}: {}", i + 1, e))?;
+        }
+        Ok(config)
+    }
+
+    fn parse_line(&mut self, line: &str) -> Result<(), String> {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            return Ok(());
+        }
+        if line.starts_with('[') {
+            return Err("tables aren't supported; settings go at the top level".to_owned());
+        }
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| "expected key = value".to_owned())?;
+        let key = key.trim();
+        let value = parse_value(value.trim())?;
+        match key {
+            "database" => {
+                let path = value.into_string(key)?;
+                self.database = Some(match path.strip_prefix("~/").zip(dirs::home_dir()) {
+                    Some((rest, home)) => home.join(rest),
+                    None => PathBuf::from(path),
+                });
+            }
+            "language" => self.language = Some(value.into_string(key)?),
+            "languages" => self.languages = value.into_strings(key)?,
+            "hide_languages" => self.hide_languages = value.into_strings(key)?,
+            "color" => self.color = Some(value.into_string(key)?.parse()?),
+            "width" => match value {
+                Value::Integer(n) if n > 0 => self.width = Some(n as usize),
+                _ => return Err("width must be a positive integer".to_owned()),
+            },
+            "expand_templates" => match value {
+                Value::Boolean(b) => self.expand_templates = Some(b),
+                _ => return Err("expand_templates must be true or false".to_owned()),
+            },
+            _ => match key.strip_prefix("alias.") {
+                Some(name) if is_alias_name(name) => {
+                    let args = value.into_strings(key)?;
+                    self.aliases.insert(name.to_owned(), args);
+                }
+                Some(name) => return Err(format!("bad alias name {:?}", name)),
+                None => return Err(format!("unknown setting {:?}", key)),
+            },
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Value {
+    String(String),
+    Integer(i64),
+    Boolean(bool),
+    Array(Vec<Value>),
+}
+
+impl Value {
+    pub(crate) fn into_string(self, key: &str) -> Result<String, String> {
+        match self {
+            Value::String(s) => Ok(s),
+            _ => Err(format!("{} must be a string", key)),
+        }
+    }
+
+    fn into_strings(self, key: &str) -> Result<Vec<String>, String> {
+        match self {
+            Value::Array(values) => values
+                .into_iter()
+                .map(|value| match value {
+                    Value::String(s) => Ok(s),
+                    _ => Err(format!("{} must be an array of strings", key)),
+                })
+                .collect(),
+            _ => Err(format!("{} must be an array of strings", key)),
+        }
+    }
+}
+
+// Parses the value of a line, and the comment after it, if any.
+pub(crate) fn parse_value(s: &str) -> Result<Value, String> {
+    let (value, rest) = parse_value_prefix(s)?;
+    let rest = rest.trim_start();
+    if rest.is_empty() || rest.starts_with('#') {
+        Ok(value)
+    } else {
+        Err(format!("unexpected {:?} after the value", rest))
+    }
+}
+
+// Parses a value at the start of `s`, returning it and what follows it.
+fn parse_value_prefix(s: &str) -> Result<(Value, &str), String> {
+    if let Some(rest) = s.strip_prefix('"') {
+        let mut value = String::new();
+        let mut chars = rest.char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => return Ok((Value::String(value), &rest[i + 1..])),
+                '\\' => match chars.next() {
+                    Some((_, '"')) => value.push('"'),
+                    Some((_, '\\')) => value.push('\\'),
+                    Some((_, 'n')) => value.push('\n'),
+                    Some((_, 't')) => value.push('\t'),
+                    Some((_, c)) => return Err(format!("unknown escape \\{}", c)),
+                    None => break,
+                },
+                c => value.push(c),
+            }
+        }
+        Err("unterminated string".to_owned())
+    } else if let Some(rest) = s.strip_prefix('\'') {
+        let end = rest
+            .find('\'')
+            .ok_or_else(|| "unterminated string".to_owned())?;
+        Ok((Value::String(rest[..end].to_owned()), &rest[end + 1..]))
+    } else if let Some(mut rest) = s.strip_prefix('[') {
+        let mut values = Vec::new();
+        loop {
+            rest = rest.trim_start();
+            if let Some(after) = rest.strip_prefix(']') {
+                return Ok((Value::Array(values), after));
+            }
+            let (value, after) = parse_value_prefix(rest)?;
+            values.push(value);
+            rest = after.trim_start();
+            if let Some(after) = rest.strip_prefix(',') {
+                rest = after;
+            } else if !rest.starts_with(']') {
+                return Err("expected , or ] in the array".to_owned());
+            }
+        }
+    } else {
+        let end = s
+            .find(|c: char| c.is_whitespace() || c == ',' || c == ']' || c == '#')
+            .unwrap_or(s.len());
+        let (word, rest) = s.split_at(end);
+        let value = match word {
+            "true" => Value::Boolean(true),
+            "false" => Value::Boolean(false),
+            _ => Value::Integer(
+                word.replace('_', "")
+                    .parse()
+                    .map_err(|_| format!("can't understand the value {:?}", word))?,
+            ),
+        };
+        Ok((value, rest))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process;
+
+    use super::*;
+
+    const SAMPLE: &str = r#"
+# Where the database is, and what to show.
+database = "/srv/define3.sqlite3"
+languages = ["English", "de"]   # English first
+hide_languages = ['Translingual']
+color = "never"
+width = 1_00
+expand_templates = false
+alias.nouns = ["search", "--pos", "Noun"]
+"#;
+
+    #[test]
+    fn parses_a_sample() {
+        let config = Config::parse(SAMPLE).unwrap();
+        assert_eq!(config.database, Some(PathBuf::from("/srv/define3.sqlite3")));
+        assert_eq!(config.language, None);
+        assert_eq!(config.languages, ["English", "de"]);
+        assert_eq!(config.hide_languages, ["Translingual"]);
+        assert_eq!(config.color, Some(ColorChoice::Never));
+        assert_eq!(config.width, Some(100));
+        assert_eq!(config.expand_templates, Some(false));
+        assert_eq!(config.aliases["nouns"], ["search", "--pos", "Noun"]);
+        assert_eq!(Config::parse("").unwrap(), Config::default());
+    }
+
+    #[test]
+    fn rejects_unknown_keys() {
+        assert_eq!(
+            Config::parse("width = 80\ncolour = \"never\"").unwrap_err(),
+            "line 2: unknown setting \"colour\""
+        );
+        assert_eq!(
+            Config::parse("alias.no.dots = []").unwrap_err(),
+            "line 1: bad alias name \"no.dots\""
+        );
+        assert!(Config::parse("[defaults]").is_err());
+    }
+
+    #[test]
+    fn rejects_malformed_values() {
+        for (text, error) in [
+            ("width = 0", "line 1: width must be a positive integer"),
+            ("width = \"80\"", "line 1: width must be a positive integer"),
+            (
+                "width = eighty",
+                "line 1: can't understand the value \"eighty\"",
+            ),
+            (
+                "color = \"sometimes\"",
+                "line 1: unknown color setting \"sometimes\"; expected auto, always or never",
+            ),
+            (
+                "languages = \"English\"",
+                "line 1: languages must be an array of strings",
+            ),
+            (
+                "languages = [\"English\", 1]",
+                "line 1: languages must be an array of strings",
+            ),
+            (
+                "languages = [\"English\"",
+                "line 1: expected , or ] in the array",
+            ),
+            ("language = \"English", "line 1: unterminated string"),
+            (
+                "language = \"English\" German",
+                "line 1: unexpected \"German\" after the value",
+            ),
+            (
+                "expand_templates = yes",
+                "line 1: can't understand the value \"yes\"",
+            ),
+            ("width", "line 1: expected key = value"),
+        ] {
+            assert_eq!(Config::parse(text).unwrap_err(), error, "{}", text);
+        }
+    }
+
+    #[test]
+    fn loads_files() {
+        let path = env::temp_dir().join(format!("define3-test-{}.toml", process::id()));
+        let _ = fs::remove_file(&path);
+        assert_eq!(Config::load(&path).unwrap(), Config::default());
+        fs::write(&path, "width = 72\n").unwrap();
+        assert_eq!(Config::load(&path).unwrap().width, Some(72));
+        fs::write(&path, "width = -1\n").unwrap();
+        let error = Config::load(&path).unwrap_err();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(
+            error,
+            format!(
+                "{}: line 1: width must be a positive integer",
+                path.display()
+            )
+        );
+    }
+
+    #[test]
+    fn merges() {
+        let config = Config::parse("width = 80\ncolor = \"never\"\nalias.a = [\"x\"]").unwrap();
+        let project = Config::parse("width = 100\nalias.b = [\"y\"]").unwrap();
+        let merged = config.merge(project);
+        assert_eq!(merged.width, Some(100));
+        assert_eq!(merged.color, Some(ColorChoice::Never));
+        assert_eq!(merged.aliases.len(), 2);
+    }
+}
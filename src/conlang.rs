@@ -0,0 +1,151 @@
+//! Language formatters and input converters for constructed languages; see
+//! `render::LanguageFormatter` and `normalize::InputConverter`.
+
+use normalize::InputConverter;
+use render::LanguageFormatter;
+use Meaning;
+
+const LOJBAN_VOWELS: &str = "aeiou";
+const LOJBAN_CONSONANTS: &str = "bcdfgjklmnprstvxz";
+
+fn lojban_shape(word: &str) -> Option<String> {
+    word.chars()
+        .map(|c| {
+            if LOJBAN_VOWELS.contains(c) {
+                Some('V')
+            } else if LOJBAN_CONSONANTS.contains(c) {
+                Some('C')
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+/// Lojban root words (gismu) are CVCCV or CCVCV. Every gismu can be used in compounds through its
+/// rafsi: the gismu without its final vowel, and (assigned case by case, so not derivable here)
+/// up to two shorter ones.
+pub struct LojbanFormatter;
+
+impl LanguageFormatter for LojbanFormatter {
+    fn headword_lines(&self, word: &str, _meanings: &[&Meaning]) -> Vec<String> {
+        match lojban_shape(word).as_deref() {
+            Some("CVCCV") | Some("CCVCV") => {
+                vec![format!("gismu; rafsi: {}-", &word[..word.len() - 1])]
+            }
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// Looks up the gismu a four-letter rafsi comes from. The rafsi isn't a word itself, but its
+/// gismu is the rafsi plus one of the vowels.
+pub struct LojbanRafsi;
+
+impl InputConverter for LojbanRafsi {
+    fn name(&self) -> &'static str {
+        "rafsi"
+    }
+
+    fn language(&self) -> &'static str {
+        "Lojban"
+    }
+
+    fn convert(&self, input: &str) -> Vec<String> {
+        match lojban_shape(input).as_deref() {
+            Some("CVCC") | Some("CCVC") => LOJBAN_VOWELS
+                .chars()
+                .map(|vowel| format!("{}{}", input, vowel))
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+// The words of Toki Pona's official dictionary, in the order the UCSUR encoding of sitelen pona
+// (its logographic script) gives them code points from U+F1900.
+const TOKI_PONA_GLYPHS: &[&str] = &[
+    "a", "akesi", "ala", "alasa", "ale", "anpa", "ante", "anu", "awen", "e", "en", "esun", "ijo",
+    "ike", "ilo", "insa", "jaki", "jan", "jelo", "jo", "kala", "kalama", "kama", "kasi", "ken",
+    "kepeken", "kili", "kiwen", "ko", "kon", "kule", "kulupu", "kute", "la", "lape", "laso",
+    "lawa", "len", "lete", "li", "lili", "linja", "lipu", "loje", "lon", "luka", "lukin", "lupa",
+    "ma", "mama", "mani", "meli", "mi", "mije", "moku", "moli", "monsi", "mu", "mun", "musi",
+    "mute", "nanpa", "nasa", "nasin", "nena", "ni", "nimi", "noka", "o", "olin", "ona", "open",
+    "pakala", "pali", "palisa", "pan", "pana", "pi", "pilin", "pimeja", "pini", "pipi", "poka",
+    "poki", "pona", "pu", "sama", "seli", "selo", "seme", "sewi", "sijelo", "sike", "sin", "sina",
+    "sinpin", "sitelen", "sona", "soweli", "suli", "suno", "supa", "suwi", "tan", "taso", "tawa",
+    "telo", "tenpo", "toki", "tomo", "tu", "unpa", "uta", "utala", "walo", "wan", "waso", "wawa",
+    "weka", "wile",
+];
+
+const TOKI_PONA_GLYPHS_START: u32 = 0xf1900;
+
+/// Names the sitelen pona glyph for words that have one. The glyph itself is in a private use
+/// area, so it only shows up with a font that supports it; the code point is given too.
+pub struct TokiPonaFormatter;
+
+impl LanguageFormatter for TokiPonaFormatter {
+    fn headword_lines(&self, word: &str, _meanings: &[&Meaning]) -> Vec<String> {
+        TOKI_PONA_GLYPHS
+            .iter()
+            .position(|&glyph| glyph == word)
+            .map_or_else(Vec::new, |i| {
+                let code_point = TOKI_PONA_GLYPHS_START + i as u32;
+                let glyph = std::char::from_u32(code_point).unwrap();
+                vec![format!("sitelen pona: {} (U+{:X})", glyph, code_point)]
+            })
+    }
+}
+
+/// Accepts Esperanto typed in the x-system, where "cx" stands for "ĉ" and so on, for keyboards
+/// without the accented letters.
+pub struct EsperantoXSystem;
+
+fn from_x_system(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        let accented = match c {
+            'c' => 'ĉ',
+            'g' => 'ĝ',
+            'h' => 'ĥ',
+            'j' => 'ĵ',
+            's' => 'ŝ',
+            'u' => 'ŭ',
+            'C' => 'Ĉ',
+            'G' => 'Ĝ',
+            'H' => 'Ĥ',
+            'J' => 'Ĵ',
+            'S' => 'Ŝ',
+            'U' => 'Ŭ',
+            _ => c,
+        };
+        match chars.peek() {
+            Some('x') | Some('X') if accented != c => {
+                chars.next();
+                result.push(accented);
+            }
+            _ => result.push(c),
+        }
+    }
+    result
+}
+
+impl InputConverter for EsperantoXSystem {
+    fn name(&self) -> &'static str {
+        "x-system"
+    }
+
+    fn language(&self) -> &'static str {
+        "Esperanto"
+    }
+
+    fn convert(&self, input: &str) -> Vec<String> {
+        let converted = from_x_system(input);
+        if converted == input {
+            Vec::new()
+        } else {
+            vec![converted]
+        }
+    }
+}
@@ -0,0 +1,112 @@
+//! Picking the language and sense of a word from the text it was found in.
+
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+
+use rusqlite::Result;
+
+use dictionary::Dictionary;
+use expand::{expand_with, TemplatePolicies, TemplatePolicy};
+use query::DEFAULT_STOPWORDS;
+use {Meaning, Word};
+
+/// The lowercased words of a text, without stopwords.
+pub fn context_words(text: &str) -> HashSet<String> {
+    text.split(|c: char| !c.is_alphanumeric() && c != '\'' && c != '-')
+        .map(|word| word.trim_matches(|c| c == '\'' || c == '-').to_lowercase())
+        .filter(|word| !word.is_empty() && !DEFAULT_STOPWORDS.contains(&word.as_str()))
+        .collect()
+}
+
+/// The language that most of the other words in the context are in, out of `candidates`, or
+/// `None` if none of them are. `context` is what `Dictionary::lookup_many` found for them.
+pub fn detect_language(context: &[Word], candidates: &[&str]) -> Option<String> {
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for word in context {
+        let languages: HashSet<&str> = word
+            .meanings
+            .iter()
+            .map(|meaning| meaning.language.as_str())
+            .collect();
+        for language in languages {
+            if candidates.contains(&language) {
+                *counts.entry(language).or_default() += 1;
+            }
+        }
+    }
+    // Ties go to the language listed first.
+    candidates
+        .iter()
+        .filter_map(|&language| counts.get(language).map(|&count| (language, count)))
+        .fold(
+            None,
+            |best: Option<(&str, usize)>, (language, count)| match best {
+                Some((_, best_count)) if best_count >= count => best,
+                _ => Some((language, count)),
+            },
+        )
+        .map(|(language, _)| language.to_owned())
+}
+
+/// How many of the context's words a definition (as plain text) shares with it.
+pub fn overlap(context: &HashSet<String>, definition: &str) -> usize {
+    context_words(definition)
+        .iter()
+        .filter(|word| context.contains(*word))
+        .count()
+}
+
+/// A word's senses, ordered by how well they fit a context; see `Dictionary::rank_senses`.
+#[derive(Debug)]
+pub struct SenseRanking {
+    /// The language the context is in, if it could tell. Senses in other languages are left out.
+    pub language: Option<String>,
+    /// Best first, each with its `overlap` with the context. Senses that fit equally well keep
+    /// the dictionary's order.
+    pub senses: Vec<(Meaning, usize)>,
+}
+
+impl SenseRanking {
+    /// The sense that fits best, unless none of them overlap with the context at all.
+    pub fn best(&self) -> Option<&Meaning> {
+        self.senses
+            .first()
+            .filter(|&&(_, score)| score > 0)
+            .map(|(meaning, _)| meaning)
+    }
+}
+
+impl Dictionary {
+    /// Scores the senses of `word` by how many words they share with `context`, the text it was
+    /// found in, after picking the language the rest of the context is in.
+    pub fn rank_senses(&self, word: &str, context: &str) -> Result<SenseRanking> {
+        let mut words = context_words(context);
+        words.remove(&word.to_lowercase());
+        let mut meanings = self.lookup(word)?;
+        let mut candidates: Vec<&str> = meanings
+            .iter()
+            .map(|meaning| meaning.language.as_str())
+            .collect();
+        candidates.dedup();
+        let names: Vec<&str> = words.iter().map(String::as_str).collect();
+        let language = detect_language(&self.lookup_many(&names)?, &candidates);
+        if let Some(ref language) = language {
+            meanings.retain(|meaning| &meaning.language == language);
+        }
+        // Placeholders would only add noise.
+        let policies = TemplatePolicies {
+            default: TemplatePolicy::Strip,
+            ..TemplatePolicies::default()
+        };
+        let mut senses: Vec<(Meaning, usize)> = meanings
+            .into_iter()
+            .map(|meaning| {
+                let (text, _) = expand_with(&meaning.definition, &policies, self);
+                let score = overlap(&words, &text);
+                (meaning, score)
+            })
+            .collect();
+        senses.sort_by_key(|&(_, score)| Reverse(score));
+        Ok(SenseRanking { language, senses })
+    }
+}
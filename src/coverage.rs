@@ -0,0 +1,105 @@
+//! Which templates definitions use and which of them the expander can't handle, for deciding
+//! what to teach it next.
+
+use std::collections::{HashMap, HashSet};
+
+use rusqlite::Result;
+
+use dictionary::Dictionary;
+use expand::{expand_with, ExpansionIssue, TemplatePolicies};
+use parse_wikitext::plain_text;
+use templates::{parse_templates, Node};
+
+/// How many entries `TemplateUsage::examples` lists.
+pub const EXAMPLES: usize = 3;
+
+/// One template's share of the definitions scanned.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TemplateUsage {
+    pub name: String,
+    /// Definitions that use it directly.
+    pub uses: u64,
+    /// Definitions it failed in, whether they use it directly or through another template.
+    pub unhandled: u64,
+    /// A few of the headwords of the definitions it failed in.
+    pub examples: Vec<String>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Coverage {
+    /// How many definitions were scanned.
+    pub definitions: u64,
+    /// How many of them had a template that didn't expand.
+    pub unhandled: u64,
+    /// The templates that failed, the ones that failed most often first.
+    pub templates: Vec<TemplateUsage>,
+}
+
+// Adds the names of the templates in `nodes`, and those in their arguments, to `names`.
+fn template_names(nodes: &[Node], names: &mut HashSet<String>) {
+    for node in nodes {
+        if let Node::Template(template) = node {
+            names.insert(plain_text(&template.name));
+            for argument in &template.arguments {
+                template_names(&argument.value, names);
+            }
+        }
+    }
+}
+
+/// Expands every definition, in `language` only if given, or `sample` of them picked at random,
+/// and counts which templates didn't expand.
+pub fn template_coverage(
+    dictionary: &Dictionary,
+    language: Option<&str>,
+    sample: Option<usize>,
+) -> Result<Coverage> {
+    let policies = TemplatePolicies::default();
+    let mut coverage = Coverage::default();
+    let mut uses: HashMap<String, u64> = HashMap::new();
+    let mut failures: HashMap<String, (u64, Vec<String>)> = HashMap::new();
+    dictionary.for_each_meaning(language, sample, |name, meaning| {
+        coverage.definitions += 1;
+        let mut used = HashSet::new();
+        template_names(&parse_templates(&meaning.definition), &mut used);
+        for template in used {
+            *uses.entry(template).or_default() += 1;
+        }
+        let (_, issues) = expand_with(&meaning.definition, &policies, dictionary);
+        let failed: HashSet<&str> = issues
+            .iter()
+            .filter_map(|issue| match issue {
+                ExpansionIssue::UnknownTemplate(template)
+                | ExpansionIssue::MissingArgument(template)
+                | ExpansionIssue::TooDeep(template) => Some(template.as_str()),
+                // Not a template that didn't expand.
+                ExpansionIssue::UnresolvableLink(_) => None,
+            })
+            .collect();
+        if !failed.is_empty() {
+            coverage.unhandled += 1;
+        }
+        for template in failed {
+            let (count, examples) = failures.entry(template.to_owned()).or_default();
+            *count += 1;
+            if examples.len() < EXAMPLES && !examples.iter().any(|example| example == name) {
+                examples.push(name.to_owned());
+            }
+        }
+    })?;
+    coverage.templates = failures
+        .into_iter()
+        .map(|(name, (unhandled, examples))| TemplateUsage {
+            uses: uses.get(&name).cloned().unwrap_or(0),
+            name,
+            unhandled,
+            examples,
+        })
+        .collect();
+    coverage.templates.sort_by(|a, b| {
+        b.unhandled
+            .cmp(&a.unhandled)
+            .then_with(|| a.name.cmp(&b.name))
+    });
+    Ok(coverage)
+}
@@ -0,0 +1,643 @@
+//! A DICT protocol (RFC 2229) server, so that `dict`, GNOME Dictionary and other DICT clients can
+//! use the database. Each language is a database of its own, named by its ISO 639 code.
+
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc;
+use std::thread;
+
+use dictionary::Dictionary;
+use languages::language_by_name;
+use lookup::group_by_language;
+use query::{
+    contains_pattern, escape_glob, CancelToken, PartialSearchLimits, Query, QueryError,
+    QueryRequest, QueryResponse,
+};
+use render::Formatters;
+
+/// The port DICT servers listen on.
+pub const DEFAULT_PORT: u16 = 2628;
+
+// How many headwords a MATCH lists at most.
+const MAX_MATCHES: usize = 200;
+
+// (name, description) of each MATCH strategy. "." means prefix.
+const STRATEGIES: &[(&str, &str)] = &[
+    ("exact", "The headword exactly"),
+    ("prefix", "Headwords starting with the word"),
+    ("suffix", "Headwords ending with the word"),
+    ("substring", "Headwords containing the word"),
+    ("lev", "Headwords within a few typos of the word"),
+];
+
+// The longest command line, CRLF included, that RFC 2229 lets clients send.
+const MAX_LINE: usize = 1024;
+
+// Numbers the connections, for their message ids.
+static CONNECTIONS: AtomicU64 = AtomicU64::new(0);
+
+/// Answers DICT clients on `listener` until it fails, with the database at `path`. Each
+/// connection gets its own thread and its own connection to the database.
+pub fn serve(listener: TcpListener, path: &Path) -> io::Result<()> {
+    for stream in listener.incoming() {
+        let stream = stream?;
+        let path: PathBuf = path.to_owned();
+        thread::spawn(move || {
+            let peer = stream.peer_addr().ok();
+            if let Err(e) = handle(stream, &path) {
+                eprintln!("DICT connection from {:?}: {}", peer, e);
+            }
+        });
+    }
+    Ok(())
+}
+
+// A DICT database name for a language: its ISO 639 code if it has one, or its name without
+// spaces, since database names can't have any.
+fn database_name(language: &str) -> String {
+    match language_by_name(language) {
+        Some(language) => language.iso639_1.unwrap_or(language.iso639_3).to_owned(),
+        None => language.replace(' ', "_"),
+    }
+}
+
+// Splits a command line into words, which are separated by spaces unless they're quoted with
+// single or double quotes. Backslashes escape the next character.
+fn parse_command(line: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut chars = line.trim().chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c == ' ' || c == '\t' {
+            chars.next();
+            continue;
+        }
+        let mut word = String::new();
+        let mut quote = None;
+        while let Some(c) = chars.next() {
+            match (c, quote) {
+                ('\\', _) => word.extend(chars.next()),
+                ('"' | '\'', None) => quote = Some(c),
+                (c, Some(q)) if c == q => quote = None,
+                (' ' | '\t', None) => break,
+                (c, _) => word.push(c),
+            }
+        }
+        words.push(word);
+    }
+    words
+}
+
+fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+struct Session {
+    dictionary: Dictionary,
+    /// (database name, language) pairs, in the database's order.
+    databases: Vec<(String, String)>,
+    formatters: Formatters,
+    cancel: CancelToken,
+    /// Whether the client asked for MIME headers before each text.
+    mime: bool,
+    out: BufWriter<TcpStream>,
+}
+
+fn handle(stream: TcpStream, path: &Path) -> io::Result<()> {
+    let mut out = BufWriter::new(stream.try_clone()?);
+    let dictionary = match Dictionary::open(path).and_then(|dictionary| {
+        let languages = dictionary.languages()?;
+        Ok((dictionary, languages))
+    }) {
+        Ok(opened) => opened,
+        Err(e) => {
+            write!(out, "420 server temporarily unavailable\r\n")?;
+            out.flush()?;
+            return Err(io::Error::other(e));
+        }
+    };
+    let (dictionary, languages) = dictionary;
+    let mut session = Session {
+        dictionary,
+        databases: languages
+            .into_iter()
+            .map(|(language, _)| (database_name(&language), language))
+            .collect(),
+        formatters: Formatters::default(),
+        cancel: CancelToken::new(),
+        mime: false,
+        out,
+    };
+
+    // Commands are read on a thread of their own, so that a query still running when the client
+    // hangs up is cancelled instead of finishing for nobody. A line that's too long is sent as
+    // None, and ends the connection.
+    let (sender, receiver) = mpsc::channel();
+    let cancel = session.cancel.clone();
+    thread::spawn(move || {
+        let mut reader = BufReader::new(stream);
+        loop {
+            let mut line = Vec::new();
+            match (&mut reader)
+                .take(MAX_LINE as u64 + 1)
+                .read_until(b'\n', &mut line)
+            {
+                Ok(0) | Err(_) => break,
+                Ok(_) => (),
+            }
+            let line = if line.len() > MAX_LINE {
+                None
+            } else {
+                let line = line.strip_suffix(b"\n").unwrap_or(&line);
+                let line = line.strip_suffix(b"\r").unwrap_or(line);
+                match String::from_utf8(line.to_vec()) {
+                    Ok(line) => Some(line),
+                    Err(_) => break,
+                }
+            };
+            let too_long = line.is_none();
+            if sender.send(line).is_err() || too_long {
+                break;
+            }
+        }
+        cancel.cancel();
+    });
+
+    let id = CONNECTIONS.fetch_add(1, Ordering::Relaxed);
+    session.status(
+        220,
+        &format!(
+            "define3 {} <> <{}.{}@define3>",
+            env!("CARGO_PKG_VERSION"),
+            process::id(),
+            id
+        ),
+    )?;
+    for line in receiver {
+        let line = match line {
+            Some(line) => line,
+            None => {
+                session.status(500, "line too long")?;
+                break;
+            }
+        };
+        match session.command(&parse_command(&line)) {
+            Ok(true) => (),
+            Ok(false) => break,
+            Err(Error::Cancelled) => break,
+            Err(Error::Io(e)) => return Err(e),
+            Err(Error::Sqlite(e)) => {
+                eprintln!("DICT query {:?}: {}", line, e);
+                session.status(420, "server temporarily unavailable")?;
+            }
+        }
+    }
+    // The reader thread has the socket open too, until the client hangs up.
+    let _ = session.out.get_ref().shutdown(Shutdown::Both);
+    Ok(())
+}
+
+enum Error {
+    /// The client hung up.
+    Cancelled,
+    Io(io::Error),
+    Sqlite(rusqlite::Error),
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::Io(e)
+    }
+}
+
+impl From<rusqlite::Error> for Error {
+    fn from(e: rusqlite::Error) -> Error {
+        Error::from(QueryError::from(e))
+    }
+}
+
+impl From<QueryError> for Error {
+    fn from(e: QueryError) -> Error {
+        match e {
+            QueryError::Cancelled => Error::Cancelled,
+            QueryError::Sqlite(e) => Error::Sqlite(e),
+        }
+    }
+}
+
+impl Session {
+    fn status(&mut self, code: u32, text: &str) -> io::Result<()> {
+        write!(self.out, "{} {}\r\n", code, text)?;
+        self.out.flush()
+    }
+
+    // A text response, ended by a line with a single dot. Lines starting with a dot get another.
+    fn text(&mut self, text: &str) -> io::Result<()> {
+        if self.mime {
+            write!(self.out, "Content-type: text/plain; charset=utf-8\r\n\r\n")?;
+        }
+        for line in text.lines() {
+            if line.starts_with('.') {
+                self.out.write_all(b".")?;
+            }
+            write!(self.out, "{}\r\n", line)?;
+        }
+        write!(self.out, ".\r\n")
+    }
+
+    // The languages of the databases a command names: every one for `*`, and for `!` every one
+    // too, but only the first that has anything should be used. None if there's no such database.
+    fn languages(&self, database: &str) -> Option<Vec<String>> {
+        if database == "*" || database == "!" {
+            return Some(
+                self.databases
+                    .iter()
+                    .map(|(_, language)| language.clone())
+                    .collect(),
+            );
+        }
+        self.databases
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(database))
+            .map(|(_, language)| vec![language.clone()])
+    }
+
+    fn database(&self, language: &str) -> String {
+        self.databases
+            .iter()
+            .find(|(_, other)| other == language)
+            .map_or_else(|| database_name(language), |(name, _)| name.clone())
+    }
+
+    // Handles one command. Returns whether to keep going.
+    fn command(&mut self, words: &[String]) -> Result<bool, Error> {
+        let words: Vec<&str> = words.iter().map(String::as_str).collect();
+        let command = words.first().map(|word| word.to_uppercase());
+        let subcommand = words.get(1).map(|word| word.to_uppercase());
+        match (command.as_deref(), subcommand.as_deref(), words.len()) {
+            (Some("DEFINE"), _, 3) => self.define(words[1], words[2])?,
+            (Some("MATCH"), _, 4) => self.match_words(words[1], words[2], words[3])?,
+            (Some("SHOW"), Some("DB" | "DATABASES"), 2) => self.show_databases()?,
+            (Some("SHOW"), Some("STRAT" | "STRATEGIES"), 2) => self.show_strategies()?,
+            (Some("SHOW"), Some("INFO"), 3) => self.show_info(words[2])?,
+            (Some("SHOW"), Some("SERVER"), 2) => {
+                self.status(114, "server information follows")?;
+                let info = format!(
+                    "define3 {}, serving {} languages from Wiktionary.",
+                    env!("CARGO_PKG_VERSION"),
+                    self.databases.len()
+                );
+                self.text(&info)?;
+                self.status(250, "ok")?;
+            }
+            (Some("OPTION"), Some("MIME"), 2) => {
+                self.mime = true;
+                self.status(250, "ok - using MIME headers")?;
+            }
+            (Some("CLIENT"), _, _) => self.status(250, "ok")?,
+            (Some("STATUS"), _, 1) => self.status(210, "status ok")?,
+            (Some("HELP"), _, 1) => {
+                self.status(113, "help text follows")?;
+                self.text(
+                    "DEFINE database word         -- look up word in database\n\
+                     MATCH database strategy word -- match headwords in database\n\
+                     SHOW DB                      -- list all accessible databases\n\
+                     SHOW STRAT                   -- list available matching strategies\n\
+                     SHOW INFO database           -- provide information about the database\n\
+                     SHOW SERVER                  -- provide site-specific information\n\
+                     OPTION MIME                  -- use MIME headers\n\
+                     CLIENT info                  -- identify client to server\n\
+                     STATUS                       -- display timing information\n\
+                     HELP                         -- display this help information\n\
+                     QUIT                         -- terminate connection",
+                )?;
+                self.status(250, "ok")?;
+            }
+            (Some("QUIT"), _, 1) => {
+                self.status(221, "bye")?;
+                return Ok(false);
+            }
+            (Some("AUTH" | "SASLAUTH" | "SASLRESP"), _, _) => {
+                self.status(502, "command not implemented")?
+            }
+            (Some("DEFINE" | "MATCH" | "SHOW" | "OPTION" | "STATUS" | "HELP" | "QUIT"), _, _) => {
+                self.status(501, "syntax error, illegal parameters")?
+            }
+            _ => self.status(500, "unknown command")?,
+        }
+        Ok(true)
+    }
+
+    fn run(&self, request: &QueryRequest) -> Result<QueryResponse, Error> {
+        Ok(self.dictionary.run(request, &self.cancel)?)
+    }
+
+    fn define(&mut self, database: &str, word: &str) -> Result<(), Error> {
+        let languages = match self.languages(database) {
+            Some(languages) => languages,
+            None => return Ok(self.status(550, "invalid database")?),
+        };
+        let mut request = QueryRequest::new(Query::Lookup(word.to_owned()));
+        if let [ref language] = languages[..] {
+            request.language = Some(language.clone());
+        }
+        let meanings = match self.run(&request)? {
+            QueryResponse::Meanings(meanings) => meanings,
+            response => unreachable!("{:?}", response),
+        };
+        let mut groups = group_by_language(meanings);
+        groups.retain(|(language, _)| languages.contains(language));
+        if database == "!" {
+            groups.truncate(1);
+        }
+        if groups.is_empty() {
+            return Ok(self.status(552, "no match")?);
+        }
+        self.status(150, &format!("{} definitions retrieved", groups.len()))?;
+        for (language, parts_of_speech) in &groups {
+            let mut text = format!("{}\n", word);
+            for (part_of_speech, meanings) in parts_of_speech {
+                text.push_str(&format!("  {}\n", part_of_speech));
+                for (i, meaning) in meanings.iter().enumerate() {
+                    let definition = self.dictionary.expand(&meaning.definition);
+                    let definition = self.formatters.definition(language, definition);
+                    text.push_str(&format!("    {}. {}\n", i + 1, definition));
+                }
+            }
+            let header = format!(
+                "{} {} {}",
+                quote(word),
+                self.database(language),
+                quote(language)
+            );
+            self.status(151, &header)?;
+            self.text(&text)?;
+        }
+        Ok(self.status(250, "ok")?)
+    }
+
+    fn match_words(&mut self, database: &str, strategy: &str, word: &str) -> Result<(), Error> {
+        let languages = match self.languages(database) {
+            Some(languages) => languages,
+            None => return Ok(self.status(550, "invalid database")?),
+        };
+        let pattern = match strategy {
+            "exact" | "lev" => None,
+            "prefix" | "." => Some(format!("{}*", escape_glob(word))),
+            "suffix" => Some(format!("*{}", escape_glob(word))),
+            // Same as `define -p`, which refuses searches that would match nearly everything.
+            "substring" if PartialSearchLimits::default().check(word).is_err() => {
+                return Ok(self.status(552, "no match")?);
+            }
+            "substring" => Some(contains_pattern(word)),
+            _ => return Ok(self.status(551, "invalid strategy")?),
+        };
+        let mut names = vec![word.to_owned()];
+        if let Some(pattern) = pattern {
+            let request = QueryRequest::new(Query::Search {
+                pattern,
+                after: None,
+                limit: MAX_MATCHES,
+            });
+            names = match self.run(&request)? {
+                QueryResponse::Headwords(page) => page.items,
+                response => unreachable!("{:?}", response),
+            };
+        } else if strategy == "lev" {
+            names.extend(self.dictionary.suggestions(word, MAX_MATCHES)?);
+        }
+        let names: Vec<&str> = names.iter().map(String::as_str).collect();
+        let mut matches: Vec<(String, String)> = Vec::new();
+        for found in self.dictionary.lookup_many(&names)? {
+            let mut found_languages: Vec<&str> = found
+                .meanings
+                .iter()
+                .map(|meaning| meaning.language.as_str())
+                .collect();
+            found_languages.dedup();
+            for language in found_languages {
+                if languages.iter().any(|other| other == language) {
+                    matches.push((self.database(language), found.name.clone()));
+                }
+            }
+        }
+        if database == "!" {
+            if let Some((first, _)) = matches.first().cloned() {
+                matches.retain(|(database, _)| *database == first);
+            }
+        }
+        if matches.is_empty() {
+            return Ok(self.status(552, "no match")?);
+        }
+        self.status(152, &format!("{} matches found", matches.len()))?;
+        let list: Vec<String> = matches
+            .iter()
+            .map(|(database, name)| format!("{} {}", database, quote(name)))
+            .collect();
+        self.text(&list.join("\n"))?;
+        Ok(self.status(250, "ok")?)
+    }
+
+    fn show_databases(&mut self) -> io::Result<()> {
+        if self.databases.is_empty() {
+            return self.status(554, "no databases present");
+        }
+        self.status(110, &format!("{} databases present", self.databases.len()))?;
+        let list: Vec<String> = self
+            .databases
+            .iter()
+            .map(|(name, language)| format!("{} {}", name, quote(language)))
+            .collect();
+        self.text(&list.join("\n"))?;
+        self.status(250, "ok")
+    }
+
+    fn show_strategies(&mut self) -> io::Result<()> {
+        self.status(111, &format!("{} strategies present", STRATEGIES.len()))?;
+        let list: Vec<String> = STRATEGIES
+            .iter()
+            .map(|(name, description)| format!("{} {}", name, quote(description)))
+            .collect();
+        self.text(&list.join("\n"))?;
+        self.status(250, "ok")
+    }
+
+    fn show_info(&mut self, database: &str) -> Result<(), Error> {
+        let language = match self.languages(database) {
+            Some(ref languages) if database != "*" && database != "!" => languages[0].clone(),
+            _ => return Ok(self.status(550, "invalid database")?),
+        };
+        let count = self
+            .dictionary
+            .languages()?
+            .into_iter()
+            .find(|(other, _)| *other == language)
+            .map_or(0, |(_, count)| count);
+        self.status(112, "database information follows")?;
+        self.text(&format!("{}, from Wiktionary: {} words.", language, count))?;
+        Ok(self.status(250, "ok")?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use super::*;
+    use test_db::TestDb;
+
+    fn words() -> TestDb {
+        TestDb::baseline(&[
+            ("cat", "English", "Noun", "A feline."),
+            ("catalog", "English", "Noun", "A list."),
+            ("bobcat", "English", "Noun", "A wild cat."),
+            (".com", "English", "Noun", "A company on the web."),
+            ("chat", "French", "Noun", "cat"),
+        ])
+    }
+
+    fn start(db: &TestDb) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let path = db.path().to_owned();
+        thread::spawn(move || serve(listener, &path));
+        addr
+    }
+
+    // Everything the server sends in answer to `commands`, after its banner, up to QUIT's bye.
+    fn session(addr: SocketAddr, commands: &[&str]) -> String {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        for command in commands.iter().chain(&["QUIT"]) {
+            write!(stream, "{}\r\n", command).unwrap();
+        }
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("220 define3 "), "{}", response);
+        response.split_once("\r\n").unwrap().1.to_owned()
+    }
+
+    #[test]
+    fn commands_are_split_into_words() {
+        assert_eq!(parse_command("DEFINE en cat"), ["DEFINE", "en", "cat"]);
+        assert_eq!(
+            parse_command("  MATCH\t*  exact \"ice cream\"  "),
+            ["MATCH", "*", "exact", "ice cream"]
+        );
+        assert_eq!(
+            parse_command(r#"DEFINE en 'it"s' "a \"b\" \\c""#),
+            ["DEFINE", "en", "it\"s", "a \"b\" \\c"]
+        );
+        assert_eq!(
+            parse_command("DEFINE en can\\ opener"),
+            ["DEFINE", "en", "can opener"]
+        );
+        assert!(parse_command("   ").is_empty());
+    }
+
+    #[test]
+    fn quoting_round_trips() {
+        assert_eq!(quote("cat"), "\"cat\"");
+        assert_eq!(quote("a \"b\" \\c"), r#""a \"b\" \\c""#);
+        for s in &["cat", "ice cream", "a \"b\" \\c", "'"] {
+            assert_eq!(parse_command(&quote(s)), [*s]);
+        }
+    }
+
+    #[test]
+    fn defines() {
+        let db = words();
+        let addr = start(&db);
+        let response = session(addr, &["DEFINE en cat", "DEFINE * chat", "DEFINE en dog"]);
+        assert_eq!(
+            response,
+            "150 1 definitions retrieved\r\n\
+             151 \"cat\" en \"English\"\r\ncat\r\n  Noun\r\n    1. A feline.\r\n.\r\n250 ok\r\n\
+             150 1 definitions retrieved\r\n\
+             151 \"chat\" fr \"French\"\r\nchat\r\n  Noun\r\n    1. cat\r\n.\r\n250 ok\r\n\
+             552 no match\r\n221 bye\r\n"
+        );
+    }
+
+    // A line of text that starts with a dot gets another, so it isn't taken for the end.
+    #[test]
+    fn dot_stuffing() {
+        let db = words();
+        let addr = start(&db);
+        let response = session(addr, &["DEFINE en .com"]);
+        assert!(
+            response.contains("\r\n..com\r\n  Noun\r\n    1. A company on the web.\r\n.\r\n"),
+            "{}",
+            response
+        );
+    }
+
+    #[test]
+    fn match_strategies() {
+        let db = words();
+        let addr = start(&db);
+        let matches = |strategy: &str, word: &str| {
+            let response = session(addr, &[&format!("MATCH en {} {}", strategy, word)]);
+            let (status, rest) = response.split_once(' ').unwrap();
+            if status != "152" {
+                return Err(status.to_owned());
+            }
+            let list = rest.split_once("\r\n").unwrap().1;
+            let list = list.split("\r\n.\r\n").next().unwrap();
+            Ok(list.split("\r\n").map(str::to_owned).collect::<Vec<_>>())
+        };
+        assert_eq!(matches("exact", "cat").unwrap(), ["en \"cat\""]);
+        assert_eq!(
+            matches("prefix", "cat").unwrap(),
+            ["en \"cat\"", "en \"catalog\""]
+        );
+        assert_eq!(matches(".", "cata").unwrap(), ["en \"catalog\""]);
+        assert_eq!(
+            matches("suffix", "cat").unwrap(),
+            ["en \"bobcat\"", "en \"cat\""]
+        );
+        assert_eq!(matches("substring", "atal").unwrap(), ["en \"catalog\""]);
+        assert!(matches("lev", "caat")
+            .unwrap()
+            .contains(&"en \"cat\"".to_owned()));
+        assert_eq!(matches("exact", "chat"), Err("552".to_owned()));
+        assert_eq!(matches("soundex", "cat"), Err("551".to_owned()));
+    }
+
+    #[test]
+    fn errors() {
+        let db = words();
+        let addr = start(&db);
+        let response = session(
+            addr,
+            &[
+                "FROB",
+                "DEFINE xx cat",
+                "MATCH xx exact cat",
+                "DEFINE en",
+                "AUTH me secret",
+            ],
+        );
+        assert_eq!(
+            response,
+            "500 unknown command\r\n550 invalid database\r\n550 invalid database\r\n\
+             501 syntax error, illegal parameters\r\n502 command not implemented\r\n221 bye\r\n"
+        );
+    }
+
+    #[test]
+    fn lines_are_limited() {
+        let db = words();
+        let addr = start(&db);
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write!(stream, "DEFINE en {}\r\nSTATUS\r\n", "a".repeat(MAX_LINE)).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(
+            response.ends_with("\r\n500 line too long\r\n"),
+            "{}",
+            response
+        );
+    }
+}
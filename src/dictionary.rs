@@ -0,0 +1,1106 @@
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::rc::Rc;
+
+use rusqlite::types::{Type, Value, ValueRef};
+use rusqlite::{Connection, Error, OptionalExtension, Params, Result, Row, Statement};
+
+use cache::CACHE_TABLE;
+use compression::{Decompressor, DICTIONARY_TABLE};
+use error;
+use memory::limit_memory;
+use normalize::{fold, fold_diacritics, parse_folds, Fold, DEFAULT_FOLDS};
+use pagination::{Cursor, ResultPage};
+use query::{escape_glob, MeaningFilter};
+use ranking::commonness_penalty;
+use scripts::Script;
+use sql_functions::UNICODE_COLLATION;
+use {sql_functions, Etymology, Example, Headword, Meaning, Pronunciation, Relation, Word};
+
+/// A definitions database built by `build_definitions_db`.
+pub struct Dictionary {
+    conn: Connection,
+    // Databases built with `--pre-expand` have an `expanded` column in `words`.
+    pre_expanded: bool,
+    // Databases built before editions were tracked don't have an `edition` column.
+    has_editions: bool,
+    // Set if `define warm` has cached expanded definitions; see `cache`.
+    pub(crate) has_cache: bool,
+    // Databases built before relations had senses don't have a `sense` column in `relations`.
+    has_relation_senses: bool,
+    // Set if the database was built with `--compress`.
+    decompressor: Option<Decompressor>,
+    // What `normalized_name` was folded with. Databases built before names were normalized don't
+    // have the column.
+    normalized_folds: Option<Vec<Fold>>,
+    // Databases built before names were folded for `--fold-diacritics` don't have an
+    // `unaccented_name` column.
+    has_unaccented_names: bool,
+}
+
+fn has_column(conn: &Connection, table: &str, column: &str) -> Result<bool> {
+    conn.query_row(
+        "SELECT COUNT(*) > 0 FROM pragma_table_info(?1) WHERE name = ?2",
+        [table, column],
+        |row| row.get(0),
+    )
+}
+
+// Reads a column of text that is zstd-compressed in compressed databases. Rows merged in by an
+// import without `--compress` are plain text even there.
+pub(crate) fn text_column(
+    row: &Row,
+    name: &str,
+    decompressor: Option<&Decompressor>,
+) -> Result<Option<String>> {
+    let decompressor = match (decompressor, row.get_ref(name)?) {
+        (Some(decompressor), ValueRef::Blob(_)) => decompressor,
+        _ => return row.get(name),
+    };
+    let data: Option<Vec<u8>> = row.get(name)?;
+    match data {
+        None => Ok(None),
+        Some(data) => decompressor.decompress(&data).map(Some).map_err(|e| {
+            Error::FromSqlConversionFailure(
+                row.as_ref().column_index(name).unwrap_or(0),
+                Type::Blob,
+                Box::new(e),
+            )
+        }),
+    }
+}
+
+fn meaning_from_row(row: &Row, decompressor: Option<&Decompressor>) -> Result<Meaning> {
+    Ok(Meaning {
+        language: row.get("language")?,
+        part_of_speech: row.get("part_of_speech")?,
+        definition: text_column(row, "definition", decompressor)?.unwrap_or_default(),
+        expanded: text_column(row, "expanded", decompressor)?,
+        edition: row.get("edition")?,
+    })
+}
+
+/// An entry that wasn't in the first dump imported for its edition; see `Dictionary::new_words`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NewWord {
+    pub name: String,
+    pub language: String,
+    pub edition: String,
+    /// The date (YYYY-MM-DD) of the first imported dump it was in.
+    pub first_seen: String,
+}
+
+/// The relation read the other way around: if A lists B as a hypernym, B has A as a hyponym.
+pub fn inverse_relation(kind: &str) -> Option<&'static str> {
+    match kind {
+        "synonym" => Some("synonym"),
+        "antonym" => Some("antonym"),
+        "coordinate term" => Some("coordinate term"),
+        "alternative form" => Some("alternative form"),
+        "hypernym" => Some("hyponym"),
+        "hyponym" => Some("hypernym"),
+        "meronym" => Some("holonym"),
+        "holonym" => Some("meronym"),
+        _ => None,
+    }
+}
+
+impl Dictionary {
+    /// Opens the database at `path`, which has to be there already.
+    pub fn open<P: AsRef<Path>>(path: P) -> std::result::Result<Dictionary, error::Error> {
+        let path = path.as_ref();
+        // Opening a missing file would create an empty database and fail on the first query.
+        if !path.exists() {
+            return Err(error::Error::DatabaseNotFound(path.to_owned()));
+        }
+        Dictionary::open_existing(path).map_err(|e| error::Error::from_database(e, path))
+    }
+
+    fn open_existing(path: &Path) -> Result<Dictionary> {
+        let conn = Connection::open(path)?;
+        limit_memory(&conn)?;
+        sql_functions::register(&conn)?;
+        let pre_expanded = has_column(&conn, "words", "expanded")?;
+        let has_editions = has_column(&conn, "words", "edition")?;
+        let has_relation_senses = has_column(&conn, "relations", "sense")?;
+        let has_unaccented_names = has_column(&conn, "words", "unaccented_name")?;
+        let mut dictionary = Dictionary {
+            conn,
+            pre_expanded,
+            has_editions,
+            has_relation_senses,
+            has_cache: false,
+            decompressor: None,
+            normalized_folds: None,
+            has_unaccented_names,
+        };
+        if has_column(&dictionary.conn, "words", "normalized_name")? {
+            // Only builds from before the folds were configurable don't record them.
+            let folds = match dictionary.metadata("normalized_name_folds")? {
+                Some(folds) => parse_folds(&folds)
+                    .map_err(|e| Error::FromSqlConversionFailure(0, Type::Text, e.into()))?,
+                None => DEFAULT_FOLDS.to_vec(),
+            };
+            dictionary.normalized_folds = Some(folds);
+        }
+        dictionary.has_cache = dictionary.has_table(CACHE_TABLE)?;
+        if dictionary.has_table(DICTIONARY_TABLE)? {
+            let zstd_dictionary: Vec<u8> = dictionary.conn.query_row(
+                &format!("SELECT content FROM {}", DICTIONARY_TABLE),
+                [],
+                |row| row.get(0),
+            )?;
+            sql_functions::register_decompress(&dictionary.conn, &zstd_dictionary)?;
+            dictionary.decompressor = Some(Decompressor::new(&zstd_dictionary));
+        }
+        Ok(dictionary)
+    }
+
+    // What to select for `Meaning::expanded` and `Meaning::edition`.
+    fn optional_columns(&self) -> String {
+        let cached = format!(
+            "(SELECT expanded FROM {0} WHERE {0}.rowid = words.rowid)",
+            CACHE_TABLE
+        );
+        format!(
+            "{}, {}",
+            match (self.pre_expanded, self.has_cache) {
+                (true, true) => format!("COALESCE(expanded, {}) AS expanded", cached),
+                (true, false) => "expanded".to_owned(),
+                (false, true) => format!("{} AS expanded", cached),
+                (false, false) => "NULL AS expanded".to_owned(),
+            },
+            if self.has_editions {
+                "edition"
+            } else {
+                "NULL AS edition"
+            }
+        )
+    }
+
+    pub fn connection(&self) -> &Connection {
+        &self.conn
+    }
+
+    /// All meanings of a word, ordered by language and then by their order on the page.
+    pub fn lookup(&self, word: &str) -> Result<Vec<Meaning>> {
+        let mut stmt = self.conn.prepare_cached(&format!(
+            "SELECT language, part_of_speech, definition, {} FROM words WHERE name = ?1
+             ORDER BY language COLLATE {}, rowid",
+            self.optional_columns(),
+            UNICODE_COLLATION
+        ))?;
+        let meanings = stmt.query_map([word], |row| {
+            meaning_from_row(row, self.decompressor.as_ref())
+        })?;
+        meanings.collect()
+    }
+
+    /// Whether `word` is a headword, in any language.
+    pub fn contains(&self, word: &str) -> Result<bool> {
+        let mut stmt = self
+            .conn
+            .prepare_cached("SELECT EXISTS (SELECT 1 FROM words WHERE name = ?1)")?;
+        stmt.query_row([word], |row| row.get(0))
+    }
+
+    /// The best-ranked meaning of a word in the first language it has (of those allowed): the
+    /// first one without usage labels like "obsolete", or failing that the least uncommon one.
+    /// It stops reading as soon as it finds an unlabelled one, so it's quicker than `lookup` for
+    /// words with many meanings.
+    pub fn top_meaning(
+        &self,
+        word: &str,
+        language: Option<&str>,
+        edition: Option<&str>,
+    ) -> Result<Option<Meaning>> {
+        if edition.is_some() && !self.has_editions {
+            return Ok(None);
+        }
+        let edition_filter = if self.has_editions {
+            "AND (?3 IS NULL OR edition = ?3)"
+        } else {
+            "AND ?3 IS NULL"
+        };
+        let mut stmt = self.conn.prepare_cached(&format!(
+            "SELECT language, part_of_speech, definition, {} FROM words
+             WHERE name = ?1 AND (?2 IS NULL OR language = ?2) {}
+             ORDER BY language COLLATE {}, rowid",
+            self.optional_columns(),
+            edition_filter,
+            UNICODE_COLLATION
+        ))?;
+        let mut rows = stmt.query(rusqlite::params![word, language, edition])?;
+        let mut best: Option<(u32, Meaning)> = None;
+        while let Some(row) = rows.next()? {
+            let meaning = meaning_from_row(row, self.decompressor.as_ref())?;
+            if let Some((_, ref top)) = best {
+                if top.language != meaning.language {
+                    break;
+                }
+            }
+            let penalty = commonness_penalty(&meaning.definition);
+            if best.as_ref().is_none_or(|&(lowest, _)| penalty < lowest) {
+                best = Some((penalty, meaning));
+            }
+            if penalty == 0 {
+                break;
+            }
+        }
+        Ok(best.map(|(_, meaning)| meaning))
+    }
+
+    /// The word and meaning in one row of the words table.
+    pub(crate) fn meaning_by_rowid(&self, rowid: i64) -> Result<(String, Meaning)> {
+        let mut stmt = self.conn.prepare_cached(&format!(
+            "SELECT name, language, part_of_speech, definition, {} FROM words WHERE rowid = ?1",
+            self.optional_columns()
+        ))?;
+        stmt.query_row([rowid], |row| {
+            Ok((
+                row.get("name")?,
+                meaning_from_row(row, self.decompressor.as_ref())?,
+            ))
+        })
+    }
+
+    /// Calls `f` with every meaning and the word it's of, in `language` only if given, or with
+    /// `sample` of them picked at random.
+    pub fn for_each_meaning<F>(
+        &self,
+        language: Option<&str>,
+        sample: Option<usize>,
+        mut f: F,
+    ) -> Result<()>
+    where
+        F: FnMut(&str, Meaning),
+    {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT name, language, part_of_speech, definition, {} FROM words
+             WHERE ?1 IS NULL OR language = ?1 {}",
+            self.optional_columns(),
+            if sample.is_some() {
+                "ORDER BY random() LIMIT ?2"
+            } else {
+                "LIMIT ?2"
+            }
+        ))?;
+        // A negative limit is none.
+        let limit = sample.map_or(-1, |sample| sample as i64);
+        let mut rows = stmt.query(rusqlite::params![language, limit])?;
+        while let Some(row) = rows.next()? {
+            let name: String = row.get("name")?;
+            f(&name, meaning_from_row(row, self.decompressor.as_ref())?);
+        }
+        Ok(())
+    }
+
+    /// Calls `f` with every word in `language`, in order, and all of its meanings in it. `f` can
+    /// fail with errors of its own, as long as database errors can be made into them.
+    pub fn for_each_word<F, E>(&self, language: &str, f: F) -> std::result::Result<(), E>
+    where
+        F: FnMut(&str, Vec<Meaning>) -> std::result::Result<(), E>,
+        E: From<Error>,
+    {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT name, language, part_of_speech, definition, {} FROM words
+             WHERE language = ?1 ORDER BY name, rowid",
+            self.optional_columns()
+        ))?;
+        self.for_each_name(&mut stmt, [language], f)
+    }
+
+    /// Like `for_each_word`, for every word in the database, with its meanings in all languages.
+    pub fn for_each_entry<F, E>(&self, f: F) -> std::result::Result<(), E>
+    where
+        F: FnMut(&str, Vec<Meaning>) -> std::result::Result<(), E>,
+        E: From<Error>,
+    {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT name, language, part_of_speech, definition, {} FROM words
+             ORDER BY name, rowid",
+            self.optional_columns()
+        ))?;
+        self.for_each_name(&mut stmt, [], f)
+    }
+
+    // Calls `f` with each name that `stmt` selects, in order of name, and its meanings.
+    fn for_each_name<P, F, E>(
+        &self,
+        stmt: &mut Statement,
+        params: P,
+        mut f: F,
+    ) -> std::result::Result<(), E>
+    where
+        P: Params,
+        F: FnMut(&str, Vec<Meaning>) -> std::result::Result<(), E>,
+        E: From<Error>,
+    {
+        let mut rows = stmt.query(params)?;
+        let mut current: Option<(String, Vec<Meaning>)> = None;
+        while let Some(row) = rows.next()? {
+            let name: String = row.get("name")?;
+            let meaning = meaning_from_row(row, self.decompressor.as_ref())?;
+            match current {
+                Some((ref word, ref mut meanings)) if *word == name => meanings.push(meaning),
+                _ => {
+                    if let Some((word, meanings)) = current.replace((name, vec![meaning])) {
+                        f(&word, meanings)?;
+                    }
+                }
+            }
+        }
+        if let Some((word, meanings)) = current {
+            f(&word, meanings)?;
+        }
+        Ok(())
+    }
+
+    /// Headwords other than `word` itself that fold to the same thing with the folds the
+    /// database was built with (see `normalize::Fold`), e.g. "amō" for "amo".
+    pub fn normalized_matches(&self, word: &str) -> Result<Vec<String>> {
+        let folds = match self.normalized_folds {
+            Some(ref folds) => folds,
+            None => return Ok(Vec::new()),
+        };
+        let mut stmt = self.conn.prepare_cached(&format!(
+            "SELECT DISTINCT name FROM words WHERE normalized_name = ?1 AND name != ?2
+             ORDER BY name COLLATE {}",
+            UNICODE_COLLATION
+        ))?;
+        let names = stmt.query_map([&fold(word, folds), word], |row| row.get(0))?;
+        names.collect()
+    }
+
+    /// The headwords that are `word` but for the case of ASCII letters, `word` itself first if
+    /// it's there.
+    pub fn case_variants(&self, word: &str) -> Result<Vec<String>> {
+        let mut stmt = self.conn.prepare_cached(&format!(
+            "SELECT DISTINCT name FROM words WHERE name = ?1 COLLATE NOCASE
+             ORDER BY name != ?1, name COLLATE {}",
+            UNICODE_COLLATION
+        ))?;
+        let names = stmt.query_map([word], |row| row.get(0))?;
+        names.collect()
+    }
+
+    /// Whether the database can find words without their diacritics; see `unaccented_matches`.
+    pub fn can_fold_diacritics(&self) -> bool {
+        self.has_unaccented_names
+    }
+
+    /// Headwords other than `word` that are the same without diacritics, e.g. "façade" for
+    /// "facade". Empty if the database can't tell.
+    pub fn unaccented_matches(&self, word: &str) -> Result<Vec<String>> {
+        if !self.has_unaccented_names {
+            return Ok(Vec::new());
+        }
+        let mut stmt = self.conn.prepare_cached(&format!(
+            "SELECT DISTINCT name FROM words WHERE unaccented_name = ?1 AND name != ?2
+             ORDER BY name COLLATE {}",
+            UNICODE_COLLATION
+        ))?;
+        let names = stmt.query_map([&fold_diacritics(word), word], |row| row.get(0))?;
+        names.collect()
+    }
+
+    /// Looks up many words with a single query. Returns one `Word` per requested word, in the
+    /// order requested; words that aren't in the dictionary have no meanings. Only meanings are
+    /// filled in.
+    pub fn lookup_many(&self, words: &[&str]) -> Result<Vec<Word>> {
+        let names: Rc<Vec<Value>> = Rc::new(
+            words
+                .iter()
+                .map(|&word| Value::from(word.to_owned()))
+                .collect(),
+        );
+        let mut stmt = self.conn.prepare_cached(&format!(
+            "SELECT name, language, part_of_speech, definition, {} FROM words
+             WHERE name IN rarray(?1)
+             ORDER BY language COLLATE {}, rowid",
+            self.optional_columns(),
+            UNICODE_COLLATION
+        ))?;
+        let mut rows = stmt.query([names])?;
+        let mut meanings_by_name: HashMap<String, Vec<Meaning>> = HashMap::new();
+        while let Some(row) = rows.next()? {
+            meanings_by_name
+                .entry(row.get("name")?)
+                .or_default()
+                .push(meaning_from_row(row, self.decompressor.as_ref())?);
+        }
+        Ok(words
+            .iter()
+            .map(|&word| Word {
+                name: word.to_owned(),
+                meanings: meanings_by_name.get(word).cloned().unwrap_or_default(),
+                relations: Vec::new(),
+                etymologies: Vec::new(),
+                pronunciations: Vec::new(),
+                examples: Vec::new(),
+                headwords: Vec::new(),
+            })
+            .collect())
+    }
+
+    /// Headwords matching a glob pattern (`*` for any run of characters, `?` for one), `limit` at
+    /// a time in name order, starting after `after`.
+    pub fn search(
+        &self,
+        pattern: &str,
+        after: Option<&Cursor>,
+        limit: usize,
+    ) -> Result<ResultPage<String>> {
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT DISTINCT name FROM words WHERE name GLOB ?1 AND name > ?2
+             ORDER BY name LIMIT ?3",
+        )?;
+        let after_key = after.map_or("", |cursor| cursor.key.as_str());
+        // One more than asked for tells us whether there's another page.
+        let names = stmt.query_map(
+            rusqlite::params![pattern, after_key, limit as i64 + 1],
+            |row| row.get(0),
+        )?;
+        let mut items: Vec<String> = names.collect::<Result<_>>()?;
+        let next = if items.len() > limit {
+            items.truncate(limit);
+            items.last().map(|name| Cursor {
+                rank: 0,
+                key: name.clone(),
+            })
+        } else {
+            None
+        };
+        Ok(ResultPage { items, next })
+    }
+
+    /// Meanings that pass `filter` and are in `language` and `edition` if given, ordered by
+    /// headword, a page at a time. Everything is filtered in one statement, so only the prefix
+    /// filter uses an index; the rest are checked row by row.
+    pub fn matching(
+        &self,
+        filter: &MeaningFilter,
+        language: Option<&str>,
+        edition: Option<&str>,
+        after: Option<&Cursor>,
+        limit: usize,
+    ) -> Result<ResultPage<(String, Meaning)>> {
+        if edition.is_some() && !self.has_editions {
+            return Ok(ResultPage {
+                items: Vec::new(),
+                next: None,
+            });
+        }
+        let mut conditions: Vec<&str> = Vec::new();
+        let mut params: Vec<Value> = Vec::new();
+        if let Some(ref prefix) = filter.name_prefix {
+            conditions.push("name GLOB ?");
+            params.push(Value::Text(format!("{}*", escape_glob(prefix))));
+        }
+        if let Some(ref pattern) = filter.name_pattern {
+            conditions.push("name GLOB ?");
+            params.push(Value::Text(pattern.clone()));
+        }
+        if let Some(ref text) = filter.definition_contains {
+            // Compressed definitions are compared decompressed.
+            conditions.push(if self.decompressor.is_some() {
+                "decompress(definition) LIKE ? ESCAPE '\\'"
+            } else {
+                "definition LIKE ? ESCAPE '\\'"
+            });
+            let escaped = text
+                .replace('\\', "\\\\")
+                .replace('%', "\\%")
+                .replace('_', "\\_");
+            params.push(Value::Text(format!("%{}%", escaped)));
+        }
+        if let Some(ref part_of_speech) = filter.part_of_speech {
+            conditions.push("part_of_speech = ?");
+            params.push(Value::Text(part_of_speech.clone()));
+        }
+        if let Some(language) = language {
+            conditions.push("language = ?");
+            params.push(Value::Text(language.to_owned()));
+        }
+        if let Some(edition) = edition {
+            conditions.push("edition = ?");
+            params.push(Value::Text(edition.to_owned()));
+        }
+        // The cursor's rank is the rowid, which orders meanings of the same headword.
+        if let Some(cursor) = after {
+            conditions.push("(name, rowid) > (?, ?)");
+            params.push(Value::Text(cursor.key.clone()));
+            params.push(Value::Integer(cursor.rank));
+        }
+        if conditions.is_empty() {
+            conditions.push("1");
+        }
+        // One more than asked for tells us whether there's another page.
+        params.push(Value::Integer(limit as i64 + 1));
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT rowid, name, language, part_of_speech, definition, {} FROM words
+             WHERE {} ORDER BY name, rowid LIMIT ?",
+            self.optional_columns(),
+            conditions.join(" AND ")
+        ))?;
+        let mut rows = stmt.query(rusqlite::params_from_iter(params))?;
+        let mut items = Vec::new();
+        let mut last = None;
+        while let Some(row) = rows.next()? {
+            if items.len() == limit {
+                return Ok(ResultPage { items, next: last });
+            }
+            let name: String = row.get("name")?;
+            last = Some(Cursor {
+                rank: row.get("rowid")?,
+                key: name.clone(),
+            });
+            items.push((name, meaning_from_row(row, self.decompressor.as_ref())?));
+        }
+        Ok(ResultPage { items, next: None })
+    }
+
+    /// Every language in the dictionary with its number of words, in collated order.
+    pub fn languages(&self) -> Result<Vec<(String, i64)>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT language, COUNT(DISTINCT name) FROM words GROUP BY language
+             ORDER BY language COLLATE {}",
+            UNICODE_COLLATION
+        ))?;
+        let languages = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        languages.collect()
+    }
+
+    /// The Wiktionary editions imported into the dictionary, e.g. "en" and "fr".
+    pub fn editions(&self) -> Result<Vec<String>> {
+        if !self.has_editions {
+            return Ok(Vec::new());
+        }
+        let mut stmt = self
+            .conn
+            .prepare("SELECT DISTINCT edition FROM words ORDER BY edition")?;
+        let editions = stmt.query_map([], |row| row.get(0))?;
+        editions.collect()
+    }
+
+    /// The source of a template, without its `<noinclude>` parts.
+    pub fn template(&self, name: &str) -> Result<Option<String>> {
+        let mut stmt = self
+            .conn
+            .prepare_cached("SELECT content FROM templates WHERE name = ?1")?;
+        stmt.query_row([name], |row| {
+            text_column(row, "content", self.decompressor.as_ref())
+        })
+        .optional()
+        .map(Option::flatten)
+    }
+
+    /// Entries first seen in a dump from `since` on, which can be a year, a month (YYYY-MM) or a
+    /// day. What was in the first dump imported for an edition was there all along, as far as we
+    /// know, so those never count. Grouped by language, newest first.
+    pub fn new_words(&self, since: &str, language: Option<&str>) -> Result<Vec<NewWord>> {
+        if !self.has_table("first_seen")? {
+            return Ok(Vec::new());
+        }
+        let mut stmt = self.conn.prepare_cached(&format!(
+            "SELECT name, language, edition, dump_date FROM first_seen
+             WHERE dump_date >= ?1 AND (?2 IS NULL OR language = ?2)
+             AND dump_date > (SELECT MIN(dump_date) FROM first_seen AS baseline
+                              WHERE baseline.edition = first_seen.edition)
+             ORDER BY language COLLATE {0}, dump_date DESC, name COLLATE {0}",
+            UNICODE_COLLATION
+        ))?;
+        let words = stmt.query_map(rusqlite::params![since, language], |row| {
+            Ok(NewWord {
+                name: row.get(0)?,
+                language: row.get(1)?,
+                edition: row.get(2)?,
+                first_seen: row.get(3)?,
+            })
+        })?;
+        words.collect()
+    }
+
+    /// Identifies the import that built (or last merged into) the database, for telling whether
+    /// anything cached from it is stale. Databases from before this was recorded don't have one.
+    pub fn build_id(&self) -> Result<Option<String>> {
+        self.metadata("build_id")
+    }
+
+    /// The date of the latest dump imported for `edition`, as YYYY-MM-DD, if it was recorded.
+    pub fn dump_date(&self, edition: &str) -> Result<Option<String>> {
+        if !self.has_table("first_seen")? {
+            return Ok(None);
+        }
+        self.conn.query_row(
+            "SELECT MAX(dump_date) FROM first_seen WHERE edition = ?1",
+            [edition],
+            |row| row.get(0),
+        )
+    }
+
+    fn metadata(&self, key: &str) -> Result<Option<String>> {
+        if !self.has_table("metadata")? {
+            return Ok(None);
+        }
+        self.conn
+            .query_row("SELECT value FROM metadata WHERE key = ?1", [key], |row| {
+                row.get(0)
+            })
+            .optional()
+    }
+
+    pub fn has_table(&self, name: &str) -> Result<bool> {
+        self.conn.query_row(
+            "SELECT COUNT(*) > 0 FROM sqlite_master WHERE type = 'table' AND name = ?1",
+            [name],
+            |row| row.get(0),
+        )
+    }
+
+    /// Where a word comes from, in `language` only if given, in the order its page gives them.
+    /// Databases built before etymologies were imported don't have any.
+    pub fn etymologies(&self, word: &str, language: Option<&str>) -> Result<Vec<Etymology>> {
+        if !self.has_table("etymologies")? {
+            return Ok(Vec::new());
+        }
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT language, number, text, parts_of_speech FROM etymologies
+             WHERE name = ?1 AND (?2 IS NULL OR language = ?2) ORDER BY rowid",
+        )?;
+        let mut etymologies: Vec<Etymology> = Vec::new();
+        let mut rows = stmt.query(rusqlite::params![word, language])?;
+        while let Some(row) = rows.next()? {
+            let (language, number, text): (String, u32, String) =
+                (row.get(0)?, row.get(1)?, row.get(2)?);
+            let parts_of_speech: String = row.get(3)?;
+            // Wiktextract gives each part of speech its own row, and other editions can repeat
+            // the same one, so rows that say the same thing are one etymology.
+            let i = match etymologies.iter().position(|etymology| {
+                etymology.language == language
+                    && etymology.number == number
+                    && etymology.text == text
+            }) {
+                Some(i) => i,
+                None => {
+                    etymologies.push(Etymology {
+                        language,
+                        number,
+                        text,
+                        parts_of_speech: Vec::new(),
+                    });
+                    etymologies.len() - 1
+                }
+            };
+            for pos in parts_of_speech.split('\t').filter(|pos| !pos.is_empty()) {
+                if !etymologies[i].parts_of_speech.iter().any(|p| p == pos) {
+                    etymologies[i].parts_of_speech.push(pos.to_owned());
+                }
+            }
+        }
+        Ok(etymologies)
+    }
+
+    /// How a word is said, in `language` only if given, one for each language in the order
+    /// they were imported. Databases built before pronunciations were imported don't have any.
+    pub fn pronunciations(&self, word: &str, language: Option<&str>) -> Result<Vec<Pronunciation>> {
+        if !self.has_table("pronunciations")? {
+            return Ok(Vec::new());
+        }
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT language, kind, value FROM pronunciations
+             WHERE name = ?1 AND (?2 IS NULL OR language = ?2) ORDER BY rowid",
+        )?;
+        let mut pronunciations: Vec<Pronunciation> = Vec::new();
+        let mut rows = stmt.query(rusqlite::params![word, language])?;
+        while let Some(row) = rows.next()? {
+            let (language, kind, value): (String, String, String) =
+                (row.get(0)?, row.get(1)?, row.get(2)?);
+            let i = match pronunciations.iter().position(|p| p.language == language) {
+                Some(i) => i,
+                None => {
+                    pronunciations.push(Pronunciation {
+                        language,
+                        ..Pronunciation::default()
+                    });
+                    pronunciations.len() - 1
+                }
+            };
+            let values = match kind.as_str() {
+                "ipa" => &mut pronunciations[i].ipa,
+                "audio" => &mut pronunciations[i].audio,
+                "hyphenation" => &mut pronunciations[i].hyphenation,
+                _ => continue,
+            };
+            // Other editions can say the same thing.
+            if !values.contains(&value) {
+                values.push(value);
+            }
+        }
+        Ok(pronunciations)
+    }
+
+    /// The grammar of a word's parts of speech from their headword templates, in `language` only
+    /// if given. Databases built before headwords were imported don't have any.
+    pub fn headwords(&self, word: &str, language: Option<&str>) -> Result<Vec<Headword>> {
+        if !self.has_table("headwords")? {
+            return Ok(Vec::new());
+        }
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT language, part_of_speech, label, value FROM headwords
+             WHERE name = ?1 AND (?2 IS NULL OR language = ?2) ORDER BY rowid",
+        )?;
+        let mut headwords: Vec<Headword> = Vec::new();
+        let mut rows = stmt.query(rusqlite::params![word, language])?;
+        while let Some(row) = rows.next()? {
+            let (language, part_of_speech): (String, String) = (row.get(0)?, row.get(1)?);
+            let form: (String, String) = (row.get(2)?, row.get(3)?);
+            let i = match headwords.iter().position(|headword| {
+                headword.language == language && headword.part_of_speech == part_of_speech
+            }) {
+                Some(i) => i,
+                None => {
+                    headwords.push(Headword {
+                        language,
+                        part_of_speech,
+                        forms: Vec::new(),
+                    });
+                    headwords.len() - 1
+                }
+            };
+            // Other editions, and other etymologies with the same part of speech, can say the
+            // same thing.
+            if !headwords[i].forms.contains(&form) {
+                headwords[i].forms.push(form);
+            }
+        }
+        Ok(headwords)
+    }
+
+    /// Examples of a word's definitions in use, in `language` only if given, in the order its
+    /// page gives them. Databases built before examples were imported don't have any.
+    pub fn examples(&self, word: &str, language: Option<&str>) -> Result<Vec<Example>> {
+        if !self.has_table("examples")? {
+            return Ok(Vec::new());
+        }
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT language, part_of_speech, definition, text, translation FROM examples
+             WHERE name = ?1 AND (?2 IS NULL OR language = ?2) ORDER BY rowid",
+        )?;
+        let mut examples: Vec<Example> = Vec::new();
+        let mut rows = stmt.query(rusqlite::params![word, language])?;
+        while let Some(row) = rows.next()? {
+            let example = Example {
+                language: row.get(0)?,
+                part_of_speech: row.get(1)?,
+                definition: row.get(2)?,
+                text: row.get(3)?,
+                translation: row.get(4)?,
+            };
+            // Other editions can give the same one.
+            if !examples.contains(&example) {
+                examples.push(example);
+            }
+        }
+        Ok(examples)
+    }
+
+    /// Relations of a word, both those listed on its own page and those that other pages list
+    /// pointing back at it. Only the ones on its own page know which of its senses they're of.
+    /// Databases built before relations were imported don't have any.
+    pub fn relations(&self, word: &str, language: Option<&str>) -> Result<Vec<Relation>> {
+        let mut relations: Vec<Relation> = Vec::new();
+        if !self.has_table("relations")? {
+            return Ok(relations);
+        }
+        let mut stmt = self.conn.prepare_cached(&format!(
+            "SELECT language, part_of_speech, relation, target, {} FROM relations
+             WHERE name = ?1 AND (?2 IS NULL OR language = ?2) ORDER BY rowid",
+            if self.has_relation_senses {
+                "sense"
+            } else {
+                "NULL AS sense"
+            }
+        ))?;
+        for relation in stmt.query_map(rusqlite::params![word, language], |row| {
+            Ok(Relation {
+                language: row.get(0)?,
+                part_of_speech: row.get(1)?,
+                kind: row.get(2)?,
+                target: row.get(3)?,
+                sense: row.get(4)?,
+            })
+        })? {
+            relations.push(relation?);
+        }
+
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT language, part_of_speech, relation, name FROM relations
+             WHERE target = ?1 AND (?2 IS NULL OR language = ?2) ORDER BY rowid",
+        )?;
+        let mut rows = stmt.query(rusqlite::params![word, language])?;
+        while let Some(row) = rows.next()? {
+            let kind: String = row.get(2)?;
+            if let Some(inverse) = inverse_relation(&kind) {
+                let relation = Relation {
+                    language: row.get(0)?,
+                    part_of_speech: row.get(1)?,
+                    kind: inverse.to_owned(),
+                    target: row.get(3)?,
+                    sense: None,
+                };
+                let listed = relations.iter().any(|listed| {
+                    listed.language == relation.language
+                        && listed.kind == relation.kind
+                        && listed.target == relation.target
+                });
+                if !listed {
+                    relations.push(relation);
+                }
+            }
+        }
+        Ok(relations)
+    }
+
+    /// The spellings of `word` in other scripts, like the Cyrillic мачка for the Serbo-Croatian
+    /// mačka, from the alternative forms either entry lists.
+    pub fn other_scripts(&self, word: &str, language: Option<&str>) -> Result<Vec<Relation>> {
+        let script = Script::of(word);
+        let mut spellings: Vec<Relation> = Vec::new();
+        for relation in self.relations(word, language)? {
+            let other = Script::of(&relation.target);
+            if relation.kind == "alternative form"
+                && other != script
+                && other != Script::Other
+                && !spellings.iter().any(|spelling| {
+                    spelling.language == relation.language && spelling.target == relation.target
+                })
+            {
+                spellings.push(relation);
+            }
+        }
+        Ok(spellings)
+    }
+
+    /// Walks the relation graph breadth-first from `word`, following only relations of the given
+    /// kinds (or all of them if `kinds` is empty), at most `depth` steps away. Returns each edge
+    /// once, as (from, relation), in the order they were found.
+    pub fn related(
+        &self,
+        word: &str,
+        language: Option<&str>,
+        kinds: &[String],
+        depth: usize,
+    ) -> Result<Vec<(String, Relation)>> {
+        let mut edges: Vec<(String, Relation)> = Vec::new();
+        let mut seen_edges: HashSet<(String, String, String)> = HashSet::new();
+        let mut visited: HashSet<String> = HashSet::new();
+        let mut frontier = vec![word.to_owned()];
+        visited.insert(word.to_owned());
+
+        for _ in 0..depth {
+            let mut next = Vec::new();
+            for from in &frontier {
+                for relation in self.relations(from, language)? {
+                    if !kinds.is_empty() && !kinds.contains(&relation.kind) {
+                        continue;
+                    }
+                    // A synonym found from both ends is still one edge.
+                    let reverse = inverse_relation(&relation.kind)
+                        .map(|inverse| (relation.target.clone(), inverse.to_owned(), from.clone()));
+                    if reverse.is_some_and(|reverse| seen_edges.contains(&reverse)) {
+                        continue;
+                    }
+                    let edge = (from.clone(), relation.kind.clone(), relation.target.clone());
+                    if !seen_edges.insert(edge) {
+                        continue;
+                    }
+                    if visited.insert(relation.target.clone()) {
+                        next.push(relation.target.clone());
+                    }
+                    edges.push((from.clone(), relation));
+                }
+            }
+            frontier = next;
+        }
+        Ok(edges)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use test_db::TestDb;
+
+    // What `build_definitions_db` made before it imported anything but words, templates and
+    // modules.
+    fn baseline() -> TestDb {
+        TestDb::baseline(&[
+            ("cat", "English", "Noun", "A feline."),
+            ("cat", "English", "Verb", "To vomit."),
+        ])
+    }
+
+    #[test]
+    fn baseline_schema() {
+        let db = baseline();
+        let dictionary = db.open();
+        assert_eq!(dictionary.lookup("cat").unwrap().len(), 2);
+        assert!(dictionary.relations("cat", None).unwrap().is_empty());
+        assert!(dictionary.other_scripts("cat", None).unwrap().is_empty());
+        assert!(dictionary.related("cat", None, &[], 2).unwrap().is_empty());
+        assert!(dictionary.etymologies("cat", None).unwrap().is_empty());
+        assert!(dictionary.pronunciations("cat", None).unwrap().is_empty());
+        assert!(dictionary.headwords("cat", None).unwrap().is_empty());
+        assert!(dictionary.examples("cat", None).unwrap().is_empty());
+        assert!(dictionary.new_words("2000", None).unwrap().is_empty());
+        assert_eq!(dictionary.build_id().unwrap(), None);
+        assert!(dictionary.normalized_matches("cat").unwrap().is_empty());
+        assert!(dictionary.unaccented_matches("cat").unwrap().is_empty());
+        assert!(!dictionary.can_fold_diacritics());
+    }
+
+    #[test]
+    fn lookup_many_keeps_the_order_asked_for() {
+        let db = TestDb::baseline(&[
+            ("cat", "English", "Noun", "A feline."),
+            ("chat", "French", "Noun", "cat"),
+            ("cat", "Catalan", "Noun", "Catalan."),
+            ("cat", "English", "Verb", "To vomit."),
+        ]);
+        let dictionary = db.open();
+        let words = dictionary.lookup_many(&["dog", "cat", "chat"]).unwrap();
+        let names: Vec<_> = words.iter().map(|word| word.name.as_str()).collect();
+        assert_eq!(names, ["dog", "cat", "chat"]);
+        assert!(words[0].meanings.is_empty());
+        // The same meanings, in the same order, as looking each word up on its own.
+        assert_eq!(words[1].meanings, dictionary.lookup("cat").unwrap());
+        assert_eq!(words[1].meanings[0].language, "Catalan");
+        assert_eq!(words[2].meanings.len(), 1);
+        assert!(dictionary.lookup_many(&[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn contains_and_case_variants() {
+        let db = TestDb::baseline(&[
+            ("Polish", "English", "Adjective", "Of Poland."),
+            ("polish", "English", "Verb", "To shine."),
+        ]);
+        let dictionary = db.open();
+        assert!(dictionary.contains("Polish").unwrap());
+        assert!(!dictionary.contains("POLISH").unwrap());
+        assert_eq!(
+            dictionary.case_variants("polish").unwrap(),
+            ["polish", "Polish"]
+        );
+        // Then in collated order, lowercase first.
+        assert_eq!(
+            dictionary.case_variants("POLISH").unwrap(),
+            ["polish", "Polish"]
+        );
+        assert_eq!(
+            dictionary.case_variants("Polish").unwrap(),
+            ["Polish", "polish"]
+        );
+    }
+
+    #[test]
+    fn top_meaning_skips_labelled_senses() {
+        let db = TestDb::baseline(&[
+            ("wight", "English", "Noun", "{{lb|en|obsolete}} A creature."),
+            ("wight", "English", "Noun", "A ghost."),
+            ("wight", "English", "Adjective", "{{lb|en|archaic}} Brave."),
+        ]);
+        let dictionary = db.open();
+        let top = dictionary
+            .top_meaning("wight", None, None)
+            .unwrap()
+            .unwrap();
+        assert_eq!(top.definition, "A ghost.");
+        assert!(dictionary
+            .top_meaning("wight", Some("French"), None)
+            .unwrap()
+            .is_none());
+        // The baseline tables don't say which edition anything came from.
+        assert!(dictionary
+            .top_meaning("wight", None, Some("en"))
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn words_in_order_with_their_meanings() {
+        let db = baseline();
+        db.connection()
+            .execute_batch(
+                "INSERT INTO words VALUES ('abacus', 'English', 'Noun', 'A counting frame.');
+                 INSERT INTO words VALUES ('chat', 'French', 'Noun', 'cat');",
+            )
+            .unwrap();
+        let dictionary = db.open();
+        let mut words = Vec::new();
+        dictionary
+            .for_each_word("English", |word, meanings| -> super::Result<()> {
+                words.push((word.to_owned(), meanings.len()));
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(words, [("abacus".to_owned(), 1), ("cat".to_owned(), 2)]);
+        assert_eq!(
+            dictionary.languages().unwrap(),
+            [("English".to_owned(), 2), ("French".to_owned(), 1)]
+        );
+    }
+
+    #[test]
+    fn searches_a_page_at_a_time() {
+        let db = TestDb::baseline(&[
+            ("cab", "English", "Noun", "A taxi."),
+            ("cat", "English", "Noun", "A feline."),
+            ("cat", "English", "Verb", "To vomit."),
+            ("dog", "English", "Noun", "A canine."),
+        ]);
+        let dictionary = db.open();
+        let page = dictionary.search("ca?", None, 1).unwrap();
+        assert_eq!(page.items, ["cab"]);
+        let page = dictionary.search("ca?", page.next.as_ref(), 1).unwrap();
+        assert_eq!(page.items, ["cat"]);
+        assert!(page.next.is_none());
+    }
+
+    #[test]
+    fn relations_both_ways() {
+        let db = TestDb::baseline(&[
+            ("mačka", "Serbo-Croatian", "Noun", "cat"),
+            ("мачка", "Serbo-Croatian", "Noun", "{{sh-see|mačka}}"),
+        ])
+        .with(
+            "CREATE TABLE relations (name text not null, language text not null,
+                 part_of_speech text, relation text not null, target text not null,
+                 edition text not null, sense text);
+             INSERT INTO relations VALUES
+                 ('big', 'English', 'Adjective', 'synonym', 'large', 'en', 'of size'),
+                 ('мачка', 'Serbo-Croatian', 'Noun', 'alternative form', 'mačka', 'en', NULL);",
+        );
+        let dictionary = db.open();
+        let large = dictionary.relations("large", None).unwrap();
+        assert_eq!(large.len(), 1);
+        assert_eq!(large[0].kind, "synonym");
+        assert_eq!(large[0].target, "big");
+        // Only the page that lists a relation knows its sense.
+        assert_eq!(large[0].sense, None);
+        let big = dictionary.relations("big", Some("English")).unwrap();
+        assert_eq!(big[0].sense.as_deref(), Some("of size"));
+        assert!(dictionary
+            .relations("big", Some("French"))
+            .unwrap()
+            .is_empty());
+
+        let spellings = dictionary.other_scripts("mačka", None).unwrap();
+        assert_eq!(spellings.len(), 1);
+        assert_eq!(spellings[0].target, "мачка");
+        assert_eq!(
+            dictionary.other_scripts("мачка", None).unwrap()[0].target,
+            "mačka"
+        );
+    }
+}
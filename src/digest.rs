@@ -0,0 +1,121 @@
+//! A digest of the words looked up lately, with their definitions, for reviewing them. It's
+//! meant to be mailed out from cron, so it's a whole document rather than a fragment.
+
+use std::fmt::Write;
+
+use rusqlite::Result;
+
+use dictionary::Dictionary;
+use embed::meanings_html;
+use expand::TemplatePolicies;
+use locale::Locale;
+use render::{escape_html, Formatters};
+use Meaning;
+
+// Like the embed's, but for a whole page.
+const DIGEST_STYLE: &str = "\
+body{font:15px/1.4 sans-serif;color:#222;max-width:40em;margin:1em auto;padding:0 1em}\
+h1{font-size:1.4em}\
+h2{font-size:1.2em;margin:1.2em 0 .3em;border-bottom:1px solid #ccc}\
+h3{font-size:1em;color:#2a7a2a;margin:.5em 0 .2em}\
+h4{font-size:.9em;font-style:italic;font-weight:normal;margin:.3em 0 .1em}\
+ol{margin:0;padding-left:1.6em}\
+.lookups{color:#888;font-size:.8em;font-weight:normal}\
+.headword{color:#666;font-size:.9em;margin:0 0 .2em}";
+
+pub struct DigestEntry {
+    pub word: String,
+    /// How many times it was looked up.
+    pub lookups: i64,
+    /// Empty if it's no longer in the dictionary.
+    pub meanings: Vec<Meaning>,
+}
+
+/// Looks up each of `words`, (word, lookups) pairs as from `History::words_since`.
+pub fn digest_entries(
+    dictionary: &Dictionary,
+    words: &[(String, i64)],
+) -> Result<Vec<DigestEntry>> {
+    words
+        .iter()
+        .map(|(word, lookups)| {
+            Ok(DigestEntry {
+                word: word.clone(),
+                lookups: *lookups,
+                meanings: dictionary.lookup(word)?,
+            })
+        })
+        .collect()
+}
+
+fn lookups(n: i64) -> String {
+    if n == 1 {
+        "looked up once".to_owned()
+    } else {
+        format!("looked up {} times", Locale::current().number(n))
+    }
+}
+
+/// The digest as an HTML document headed `title`.
+pub fn digest_html(entries: &[DigestEntry], title: &str) -> String {
+    let policies = TemplatePolicies::default();
+    let formatters = Formatters::default();
+    let mut html = format!(
+        "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><title>{0}</title><style>{1}</style>\
+         </head><body><h1>{0}</h1>",
+        escape_html(title),
+        DIGEST_STYLE
+    );
+    if entries.is_empty() {
+        html.push_str("<p>No words were looked up.</p>");
+    }
+    for entry in entries {
+        write!(
+            html,
+            "<h2>{} <span class=\"lookups\">{}</span></h2>",
+            escape_html(&entry.word),
+            lookups(entry.lookups)
+        )
+        .unwrap();
+        if entry.meanings.is_empty() {
+            html.push_str("<p>No longer in the dictionary.</p>");
+        }
+        html.push_str(&meanings_html(
+            &entry.word,
+            &entry.meanings,
+            &policies,
+            &formatters,
+            false,
+        ));
+    }
+    html.push_str("</body></html>\n");
+    html
+}
+
+/// The digest as plain text headed `title`, expanding templates with the ones in `dictionary`.
+pub fn digest_text(dictionary: &Dictionary, entries: &[DigestEntry], title: &str) -> String {
+    let mut text = format!("{}\n\n", title);
+    if entries.is_empty() {
+        text.push_str("No words were looked up.\n");
+    }
+    for entry in entries {
+        writeln!(text, "{} ({})", entry.word, lookups(entry.lookups)).unwrap();
+        if entry.meanings.is_empty() {
+            text.push_str("  No longer in the dictionary.\n");
+        }
+        let mut heading: Option<(&str, &str)> = None;
+        for meaning in &entry.meanings {
+            if heading.is_none_or(|(language, _)| language != meaning.language) {
+                writeln!(text, "  {}", meaning.language).unwrap();
+                heading = None;
+            }
+            if heading.is_none_or(|(_, part_of_speech)| part_of_speech != meaning.part_of_speech) {
+                writeln!(text, "    {}", meaning.part_of_speech).unwrap();
+            }
+            heading = Some((&meaning.language, &meaning.part_of_speech));
+            writeln!(text, "      {}", dictionary.expand(&meaning.definition)).unwrap();
+        }
+        text.push('\n');
+    }
+    text
+}
@@ -0,0 +1,29 @@
+/// An edge of a Graphviz graph.
+pub struct Edge<'a> {
+    pub from: &'a str,
+    pub to: &'a str,
+    pub label: &'a str,
+    /// Edges for symmetric relations like synonymy are drawn without an arrowhead.
+    pub directed: bool,
+}
+
+/// Quotes a string as a DOT ID.
+pub fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Renders edges as a DOT digraph, for feeding to e.g. `dot -Tsvg`.
+pub fn to_dot(name: &str, edges: &[Edge]) -> String {
+    let mut dot = format!("digraph {} {{\n", quote(name));
+    for edge in edges {
+        dot.push_str(&format!(
+            "    {} -> {} [label={}{}];\n",
+            quote(edge.from),
+            quote(edge.to),
+            quote(edge.label),
+            if edge.directed { "" } else { ", dir=none" }
+        ));
+    }
+    dot.push_str("}\n");
+    dot
+}
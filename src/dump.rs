@@ -0,0 +1,202 @@
+//! The whole dictionary as JSON Lines, one entry per line in the form `define --json` prints, for
+//! anyone who wants Wiktionary as data rather than as lookups. Entries are written as they're read
+//! and expanded, so the dump never has to fit in memory, and compressed on the way out with zstd
+//! or, if it's installed, `gzip`.
+
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::process::{Child, Command, Stdio};
+use std::str::FromStr;
+
+use dictionary::Dictionary;
+use expand::TemplatePolicies;
+use json::entry_json;
+use lookup::group_by_language;
+use render::Formatters;
+use Meaning;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DumpFormat {
+    Jsonl,
+    JsonlGz,
+    JsonlZst,
+}
+
+impl FromStr for DumpFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<DumpFormat, String> {
+        match s {
+            "jsonl" => Ok(DumpFormat::Jsonl),
+            "jsonl.gz" => Ok(DumpFormat::JsonlGz),
+            "jsonl.zst" => Ok(DumpFormat::JsonlZst),
+            _ => Err(format!(
+                "unknown format {:?}; expected jsonl, jsonl.gz or jsonl.zst",
+                s
+            )),
+        }
+    }
+}
+
+impl DumpFormat {
+    /// The format a file's name says it's in, e.g. jsonl.zst for "wiktionary.jsonl.zst".
+    pub fn from_path(path: &Path) -> Option<DumpFormat> {
+        let name = path.file_name()?.to_str()?;
+        [
+            ("jsonl.gz", DumpFormat::JsonlGz),
+            ("jsonl.zst", DumpFormat::JsonlZst),
+            ("jsonl", DumpFormat::Jsonl),
+        ]
+        .iter()
+        .find(|(extension, _)| name.ends_with(&format!(".{}", extension)))
+        .map(|&(_, format)| format)
+    }
+}
+
+/// What `dump` wrote.
+#[derive(Debug)]
+pub struct Dumped {
+    pub entries: usize,
+    pub definitions: usize,
+}
+
+enum Error {
+    Io(io::Error),
+    Database(rusqlite::Error),
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::Io(e)
+    }
+}
+
+impl From<rusqlite::Error> for Error {
+    fn from(e: rusqlite::Error) -> Error {
+        Error::Database(e)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Io(ref e) => write!(f, "{}", e),
+            Error::Database(ref e) => write!(f, "{}", e),
+        }
+    }
+}
+
+// zstd's default level; higher ones are much slower for a little less.
+const ZSTD_LEVEL: i32 = 3;
+
+// Where the lines go, compressed as they're written.
+enum Output {
+    Plain(BufWriter<Box<dyn Write>>),
+    Zstd(zstd::stream::write::Encoder<'static, BufWriter<Box<dyn Write>>>),
+    Gzip(Child),
+}
+
+impl Output {
+    // Writes to `file`, or to stdout without one.
+    fn new(format: DumpFormat, file: Option<File>) -> io::Result<Output> {
+        if format == DumpFormat::JsonlGz {
+            let stdout = file.map_or_else(Stdio::inherit, Stdio::from);
+            return Command::new("gzip")
+                .arg("-c")
+                .stdin(Stdio::piped())
+                .stdout(stdout)
+                .spawn()
+                .map(Output::Gzip)
+                .map_err(|e| match e.kind() {
+                    io::ErrorKind::NotFound => io::Error::new(
+                        e.kind(),
+                        "gzip isn't installed; dump as jsonl.zst or jsonl instead",
+                    ),
+                    _ => io::Error::new(e.kind(), format!("Could not run gzip: {}", e)),
+                });
+        }
+        let out: Box<dyn Write> = match file {
+            Some(file) => Box::new(file),
+            None => Box::new(io::stdout()),
+        };
+        let out = BufWriter::new(out);
+        Ok(match format {
+            DumpFormat::JsonlZst => {
+                Output::Zstd(zstd::stream::write::Encoder::new(out, ZSTD_LEVEL)?)
+            }
+            _ => Output::Plain(out),
+        })
+    }
+
+    fn writer(&mut self) -> &mut dyn Write {
+        match *self {
+            Output::Plain(ref mut out) => out,
+            Output::Zstd(ref mut out) => out,
+            Output::Gzip(ref mut child) => child.stdin.as_mut().unwrap(),
+        }
+    }
+
+    fn finish(self) -> io::Result<()> {
+        match self {
+            Output::Plain(mut out) => out.flush(),
+            Output::Zstd(out) => out.finish()?.flush(),
+            Output::Gzip(mut child) => {
+                drop(child.stdin.take());
+                let status = child.wait()?;
+                if status.success() {
+                    Ok(())
+                } else {
+                    Err(io::Error::other(format!("gzip failed: {}", status)))
+                }
+            }
+        }
+    }
+}
+
+fn write_entries(
+    dictionary: &Dictionary,
+    language: Option<&str>,
+    out: &mut dyn Write,
+) -> Result<Dumped, Error> {
+    let policies = TemplatePolicies::default();
+    let formatters = Formatters::default();
+    let mut dumped = Dumped {
+        entries: 0,
+        definitions: 0,
+    };
+    let mut write = |word: &str, meanings: Vec<Meaning>| -> Result<(), Error> {
+        dumped.entries += 1;
+        dumped.definitions += meanings.len();
+        let langs = group_by_language(meanings);
+        let entry = entry_json(word, word, &langs, &policies, dictionary, &formatters, None);
+        serde_json::to_writer(&mut *out, &entry).map_err(io::Error::from)?;
+        out.write_all(b"\n")?;
+        Ok(())
+    };
+    match language {
+        Some(language) => dictionary.for_each_word(language, &mut write)?,
+        None => dictionary.for_each_entry(&mut write)?,
+    }
+    Ok(dumped)
+}
+
+/// Writes every entry, or only the definitions in `language`, to `path` (stdout for "-") as
+/// `format`, one line of JSON per word with its definitions expanded and raw.
+pub fn dump(
+    dictionary: &Dictionary,
+    language: Option<&str>,
+    format: DumpFormat,
+    path: &Path,
+) -> Result<Dumped, String> {
+    let file = if path == Path::new("-") {
+        None
+    } else {
+        Some(File::create(path).map_err(|e| format!("{}: {}", path.display(), e))?)
+    };
+    let mut output = Output::new(format, file).map_err(|e| e.to_string())?;
+    let dumped = write_entries(dictionary, language, output.writer()).map_err(|e| e.to_string())?;
+    output.finish().map_err(|e| e.to_string())?;
+    Ok(dumped)
+}
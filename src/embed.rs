@@ -0,0 +1,170 @@
+use expand::{expand_markup, TemplatePolicies};
+use std::collections::HashMap;
+
+use permalink::{encode_path_segment, language_anchor, part_of_speech_anchor, sense_anchor};
+use render::{escape_html, html_to_html, Formatters};
+use Meaning;
+
+// Scoped to the fragment's own class, so it neither leaks into nor picks up much from the page
+// it's embedded in.
+const EMBED_STYLE: &str = "\
+.define3-embed{font:15px/1.4 sans-serif;color:#222;background:#fff;border:1px solid #ccc;\
+border-radius:4px;padding:.6em 1em;max-width:40em}\
+.define3-embed h2{font-size:1.2em;margin:0 0 .3em}\
+.define3-embed h3{font-size:1em;color:#2a7a2a;margin:.5em 0 .2em}\
+.define3-embed h4{font-size:.9em;font-style:italic;font-weight:normal;margin:.3em 0 .1em}\
+.define3-embed ol{margin:0;padding-left:1.6em}\
+.define3-embed .edition{color:#888;font-size:.8em}\
+.define3-embed .headword{color:#666;font-size:.9em;margin:0 0 .2em}";
+
+// Default size of the iframe in oEmbed responses, before the consumer's maxwidth/maxheight.
+const EMBED_WIDTH: u32 = 480;
+const EMBED_HEIGHT: u32 = 320;
+
+/// A self-contained HTML fragment showing a word's meanings, grouped by language and part of
+/// speech in the order given. `show_editions` tags each one with the edition it came from.
+pub fn embed_html(
+    word: &str,
+    meanings: &[Meaning],
+    policies: &TemplatePolicies,
+    formatters: &Formatters,
+    show_editions: bool,
+) -> String {
+    let mut html = format!(
+        "<div class=\"define3-embed\"><style>{}</style><h2>{}</h2>",
+        EMBED_STYLE,
+        escape_html(word)
+    );
+    if meanings.is_empty() {
+        html.push_str("<p>No results found.</p>");
+    }
+    html.push_str(&meanings_html(
+        word,
+        meanings,
+        policies,
+        formatters,
+        show_editions,
+    ));
+    html.push_str("</div>");
+    html
+}
+
+/// A word's meanings as HTML headings and lists, grouped by language and part of speech in the
+/// order given, for putting in a page of one's own. `show_editions` tags each one with the edition
+/// it came from. Languages, parts of speech and senses have ids from `permalink` to link to, like
+/// `English-Verb-3`.
+pub fn meanings_html(
+    word: &str,
+    meanings: &[Meaning],
+    policies: &TemplatePolicies,
+    formatters: &Formatters,
+    show_editions: bool,
+) -> String {
+    let mut html = String::new();
+    let mut language: Option<&str> = None;
+    let mut part_of_speech: Option<&str> = None;
+    // Senses so far by (language, part of speech), which can come up in more than one place.
+    let mut senses: HashMap<(&str, &str), usize> = HashMap::new();
+    for meaning in meanings {
+        let key = (meaning.language.as_str(), meaning.part_of_speech.as_str());
+        let new_language = language != Some(meaning.language.as_str());
+        if new_language || part_of_speech != Some(meaning.part_of_speech.as_str()) {
+            if part_of_speech.is_some() {
+                html.push_str("</ol>");
+            }
+            if new_language {
+                html.push_str(&format!(
+                    "<h3 id=\"{}\">{}</h3>",
+                    escape_html(&language_anchor(&meaning.language)),
+                    escape_html(&meaning.language)
+                ));
+                let in_language: Vec<&Meaning> = meanings
+                    .iter()
+                    .filter(|other| other.language == meaning.language)
+                    .collect();
+                for line in formatters.headword_lines(&meaning.language, word, &in_language) {
+                    html.push_str(&format!("<p class=\"headword\">{}</p>", escape_html(&line)));
+                }
+            }
+            match senses.get(&key) {
+                None => html.push_str(&format!(
+                    "<h4 id=\"{}\">{}</h4><ol>",
+                    escape_html(&part_of_speech_anchor(
+                        &meaning.language,
+                        &meaning.part_of_speech
+                    )),
+                    escape_html(&meaning.part_of_speech)
+                )),
+                // Carrying on with the numbers from before.
+                Some(n) => html.push_str(&format!(
+                    "<h4>{}</h4><ol start=\"{}\">",
+                    escape_html(&meaning.part_of_speech),
+                    n + 1
+                )),
+            }
+            language = Some(&meaning.language);
+            part_of_speech = Some(&meaning.part_of_speech);
+        }
+        let (markup, _) = expand_markup(&meaning.definition, policies);
+        let sense = senses.entry(key).or_insert(0);
+        *sense += 1;
+        html.push_str(&format!(
+            "<li id=\"{}\">",
+            escape_html(&sense_anchor(
+                &meaning.language,
+                &meaning.part_of_speech,
+                *sense
+            ))
+        ));
+        if let (true, Some(edition)) = (show_editions, &meaning.edition) {
+            html.push_str(&format!(
+                "<span class=\"edition\">[{}]</span> ",
+                escape_html(edition)
+            ));
+        }
+        html.push_str(&html_to_html(&markup));
+        html.push_str("</li>");
+    }
+    if part_of_speech.is_some() {
+        html.push_str("</ol>");
+    }
+    html
+}
+
+/// The URL of a word's embeddable fragment on the server at `base_url`.
+pub fn embed_url(base_url: &str, word: &str) -> String {
+    format!(
+        "{}/embed/{}",
+        base_url.trim_end_matches('/'),
+        encode_path_segment(word)
+    )
+}
+
+/// An oEmbed (https://oembed.com) "rich" response for a word, framing its fragment from the
+/// server at `base_url` within the consumer's size limits.
+pub fn oembed_json(
+    base_url: &str,
+    word: &str,
+    max_width: Option<u32>,
+    max_height: Option<u32>,
+) -> String {
+    let width = max_width.map_or(EMBED_WIDTH, |max| max.min(EMBED_WIDTH));
+    let height = max_height.map_or(EMBED_HEIGHT, |max| max.min(EMBED_HEIGHT));
+    let html = format!(
+        "<iframe src=\"{}\" width=\"{}\" height=\"{}\" frameborder=\"0\"></iframe>",
+        escape_html(&embed_url(base_url, word)),
+        width,
+        height
+    );
+    serde_json::json!({
+        "version": "1.0",
+        "type": "rich",
+        "title": word,
+        "provider_name": "define3",
+        "provider_url": base_url,
+        "html": html,
+        "width": width,
+        "height": height,
+    })
+    .to_string()
+}
@@ -0,0 +1,62 @@
+//! How a page's definitions in the database differ from the same page as it is now, for telling
+//! how stale a database is for a word.
+
+use Meaning;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Change<'a> {
+    Same(&'a Meaning),
+    /// Only in the database.
+    Removed(&'a Meaning),
+    /// Only on the page now.
+    Added(&'a Meaning),
+}
+
+impl<'a> Change<'a> {
+    pub fn meaning(self) -> &'a Meaning {
+        match self {
+            Change::Same(meaning) | Change::Removed(meaning) | Change::Added(meaning) => meaning,
+        }
+    }
+}
+
+fn same(a: &Meaning, b: &Meaning) -> bool {
+    a.language == b.language && a.part_of_speech == b.part_of_speech && a.definition == b.definition
+}
+
+/// The changes from `stored` to `live`, grouped by language and otherwise in page order, keeping
+/// as many definitions the same as possible.
+pub fn diff_meanings<'a>(stored: &'a [Meaning], live: &'a [Meaning]) -> Vec<Change<'a>> {
+    let mut old: Vec<&Meaning> = stored.iter().collect();
+    let mut new: Vec<&Meaning> = live.iter().collect();
+    old.sort_by(|a, b| a.language.cmp(&b.language));
+    new.sort_by(|a, b| a.language.cmp(&b.language));
+
+    // lcs[i][j] is the length of the longest common subsequence of old[i..] and new[j..].
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if same(old[i], new[j]) {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+    let mut changes = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && same(old[i], new[j]) {
+            changes.push(Change::Same(new[j]));
+            i += 1;
+            j += 1;
+        } else if j < new.len() && (i == old.len() || lcs[i][j + 1] >= lcs[i + 1][j]) {
+            changes.push(Change::Added(new[j]));
+            j += 1;
+        } else {
+            changes.push(Change::Removed(old[i]));
+            i += 1;
+        }
+    }
+    changes
+}
@@ -0,0 +1,142 @@
+//! What can go wrong opening and reading the database, told apart so that `define` can say what
+//! to do about it and end with a different exit code for each.
+
+use std::io;
+use std::path::{Path, PathBuf};
+
+use rusqlite::ErrorCode;
+
+use query::QueryError;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(
+        "database not found at {}; run `define fetch` to download one, or pass -d with the path \
+         to one",
+        .0.display()
+    )]
+    DatabaseNotFound(PathBuf),
+    /// The file is there but isn't an SQLite database, or is damaged.
+    #[error("{} isn't a define3 database; pass -d with the path to one", .0.display())]
+    NotADatabase(PathBuf),
+    #[error("{0}")]
+    Io(io::Error),
+    #[error("the database couldn't be read: {0}")]
+    Query(rusqlite::Error),
+    #[error("the query was cancelled")]
+    Cancelled,
+}
+
+// By hand rather than with #[from], whose generated code names `::core`, which a 2015 edition
+// crate doesn't have at its root.
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::Io(e)
+    }
+}
+
+impl From<rusqlite::Error> for Error {
+    fn from(e: rusqlite::Error) -> Error {
+        Error::Query(e)
+    }
+}
+
+impl From<QueryError> for Error {
+    fn from(e: QueryError) -> Error {
+        match e {
+            QueryError::Cancelled => Error::Cancelled,
+            QueryError::Sqlite(e) => Error::Query(e),
+        }
+    }
+}
+
+impl Error {
+    /// `e`, from the database at `path`, telling a file that isn't a database apart from a
+    /// query that failed.
+    pub fn from_database(e: rusqlite::Error, path: &Path) -> Error {
+        match e.sqlite_error_code() {
+            Some(ErrorCode::NotADatabase) | Some(ErrorCode::DatabaseCorrupt) => {
+                Error::NotADatabase(path.to_owned())
+            }
+            Some(ErrorCode::CannotOpen) | Some(ErrorCode::PermissionDenied) => {
+                Error::Io(io::Error::other(format!("{}: {}", path.display(), e)))
+            }
+            _ => Error::Query(e),
+        }
+    }
+
+    /// The status `define` exits with: 3 when there's no database, 4 for I/O errors (including
+    /// a file that isn't a database), and 5 for queries that fail or are cancelled.
+    pub fn exit_code(&self) -> i32 {
+        match *self {
+            Error::DatabaseNotFound(_) => 3,
+            Error::NotADatabase(_) | Error::Io(_) => 4,
+            Error::Query(_) | Error::Cancelled => 5,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    use super::*;
+    use dictionary::Dictionary;
+    use lookup::SearchOptions;
+    use test_db::TestDb;
+
+    #[test]
+    fn missing_database() {
+        let path = env::temp_dir().join(format!("define3-test-{}-missing", process::id()));
+        let e = Dictionary::open(&path).err().unwrap();
+        assert!(matches!(e, Error::DatabaseNotFound(_)), "{:?}", e);
+        assert_eq!(e.exit_code(), 3);
+    }
+
+    #[test]
+    fn not_a_database() {
+        let path = env::temp_dir().join(format!("define3-test-{}-not-a-db", process::id()));
+        fs::write(
+            &path,
+            "definitely not SQLite, but long enough to have a header".repeat(10),
+        )
+        .unwrap();
+        let e = Dictionary::open(&path).err();
+        let _ = fs::remove_file(&path);
+        let e = e.unwrap();
+        assert!(matches!(e, Error::NotADatabase(_)), "{:?}", e);
+        assert_eq!(e.exit_code(), 4);
+    }
+
+    // The crash 280 fixed: a database from before the optional tables has to be fine to look
+    // words up in, not a query error.
+    #[test]
+    fn baseline_database_looks_up() {
+        let db = TestDb::baseline(&[("cat", "English", "Noun", "A feline.")]);
+        let dictionary = db.open();
+        let options = SearchOptions {
+            ignore_case: true,
+            ..SearchOptions::default()
+        };
+        let found = dictionary.lookup_with("cat", &options).map_err(Error::from);
+        assert_eq!(found.unwrap().meanings.len(), 1);
+        let found = dictionary.lookup_with("dog", &options).map_err(Error::from);
+        assert!(found.unwrap().meanings.is_empty());
+    }
+
+    #[test]
+    fn failed_queries() {
+        let db = TestDb::baseline(&[]).with("DROP TABLE words");
+        let dictionary = db.open();
+        let e = Error::from(
+            dictionary
+                .lookup_with("cat", &SearchOptions::default())
+                .unwrap_err(),
+        );
+        assert!(matches!(e, Error::Query(_)), "{:?}", e);
+        assert_eq!(e.exit_code(), 5);
+        assert_eq!(Error::from(QueryError::Cancelled).exit_code(), 5);
+    }
+}
@@ -0,0 +1,129 @@
+//! The words an etymology says a word comes from, read from its templates, as in "From
+//! {{inh|en|enm|cat}}, from {{inh|en|ang|catt}}", for drawing a word's family tree.
+
+use languages::find_language;
+use parse_wikitext::plain_text;
+use templates::{parse_templates, Node};
+
+/// A word that a word, or another origin, comes from.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Origin {
+    pub term: String,
+    /// The language's name, or its code if it isn't one we know the name of.
+    pub language: String,
+    /// How it was passed on: "inherited", "derived", "borrowed", "calque" or "part" (of a
+    /// compound or affixed word).
+    pub kind: &'static str,
+    /// The origin this one is the origin of, by index, or None for the word itself.
+    pub parent: Option<usize>,
+}
+
+// Etymology templates, with what they say about the term and the positions of its language code
+// and the term. Position 1 is the language of the word itself.
+fn etymology_template(name: &str) -> Option<(&'static str, usize, usize)> {
+    match name {
+        "inh" | "inh+" | "inh-lite" | "inherited" => Some(("inherited", 2, 3)),
+        "der" | "der+" | "der-lite" | "derived" | "uder" => Some(("derived", 2, 3)),
+        "bor" | "bor+" | "borrowed" | "lbor" | "slbor" | "obor" | "ubor" => {
+            Some(("borrowed", 2, 3))
+        }
+        "cal" | "calque" => Some(("calque", 2, 3)),
+        _ => None,
+    }
+}
+
+// Templates for words made of parts, like `{{af|en|cat|-s}}`, whose parts are all in the word's
+// own language.
+fn is_parts_template(name: &str) -> bool {
+    matches!(
+        name,
+        "af" | "affix" | "compound" | "com" | "prefix" | "pre" | "suffix" | "suf" | "confix"
+    )
+}
+
+fn language_name(code: &str) -> String {
+    find_language(code).map_or_else(|| code.to_owned(), |language| language.name.to_owned())
+}
+
+/// The origins a raw etymology names. Each one inherited, derived or borrowed is taken to be the
+/// origin of the one before, as in "From X, from Y", while parts belong to the word or origin
+/// before them.
+pub fn origins(etymology: &str) -> Vec<Origin> {
+    let mut origins: Vec<Origin> = Vec::new();
+    let mut current: Option<usize> = None;
+    for node in parse_templates(etymology) {
+        let template = match node {
+            Node::Template(template) => template,
+            _ => continue,
+        };
+        let name = plain_text(&template.name);
+        let args: Vec<String> = template.positional().map(plain_text).collect();
+        let arg = |i: usize| args.get(i - 1).filter(|arg| !arg.is_empty());
+        if let Some((kind, language_arg, term_arg)) = etymology_template(&name) {
+            if let (Some(code), Some(term)) = (arg(language_arg), arg(term_arg)) {
+                origins.push(Origin {
+                    term: term.clone(),
+                    language: language_name(code),
+                    kind,
+                    parent: current,
+                });
+                current = Some(origins.len() - 1);
+            }
+        } else if is_parts_template(&name) {
+            let language = match arg(1) {
+                Some(code) => language_name(code),
+                None => continue,
+            };
+            for part in args.iter().skip(1).filter(|part| !part.is_empty()) {
+                origins.push(Origin {
+                    term: part.clone(),
+                    language: language.clone(),
+                    kind: "part",
+                    parent: current,
+                });
+            }
+        }
+    }
+    origins
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn origin(term: &str, language: &str, kind: &'static str, parent: Option<usize>) -> Origin {
+        Origin {
+            term: term.to_owned(),
+            language: language.to_owned(),
+            kind,
+            parent,
+        }
+    }
+
+    #[test]
+    fn chain_of_origins() {
+        assert_eq!(
+            origins(
+                "From {{inh|en|enm|cat}}, from {{inh|en|ang|catt}}, from {{bor|en|la|cattus}}."
+            ),
+            [
+                origin("cat", "Middle English", "inherited", None),
+                origin("catt", "Old English", "inherited", Some(0)),
+                origin("cattus", "Latin", "borrowed", Some(1)),
+            ]
+        );
+    }
+
+    #[test]
+    fn parts_belong_to_the_word_before() {
+        assert_eq!(
+            origins("{{af|en|kit|-y}}, from {{der|en|enm|kitte}}"),
+            [
+                origin("kit", "English", "part", None),
+                origin("-y", "English", "part", None),
+                origin("kitte", "Middle English", "derived", None),
+            ]
+        );
+        assert_eq!(origins("Unknown. Compare {{m|en|cat}}."), []);
+    }
+}
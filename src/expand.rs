@@ -0,0 +1,653 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::mem;
+use std::str::FromStr;
+
+use dictionary::Dictionary;
+use locale::Locale;
+use references::find_links;
+use render::html_to_text;
+use templates::{parse_templates, Argument, Node, Template};
+
+/// What to print in place of a template that we don't know how to expand.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TemplatePolicy {
+    /// Leave the raw `{{...}}` in place.
+    Keep,
+    /// Remove it entirely.
+    Strip,
+    /// Replace it with `[template:NAME]`.
+    Placeholder,
+}
+
+impl FromStr for TemplatePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<TemplatePolicy, String> {
+        match s {
+            "keep" => Ok(TemplatePolicy::Keep),
+            "strip" => Ok(TemplatePolicy::Strip),
+            "placeholder" => Ok(TemplatePolicy::Placeholder),
+            _ => Err(format!(
+                "unknown template policy {:?} (expected keep, strip or placeholder)",
+                s
+            )),
+        }
+    }
+}
+
+pub struct TemplatePolicies {
+    pub default: TemplatePolicy,
+    pub by_name: HashMap<String, TemplatePolicy>,
+}
+
+impl Default for TemplatePolicies {
+    fn default() -> Self {
+        TemplatePolicies {
+            default: TemplatePolicy::Placeholder,
+            by_name: HashMap::new(),
+        }
+    }
+}
+
+impl TemplatePolicies {
+    /// Parses `POLICY` (sets the default) and `NAME=POLICY` (sets it for one template) specs.
+    pub fn parse(specs: &[String]) -> Result<TemplatePolicies, String> {
+        let mut policies = TemplatePolicies::default();
+        for spec in specs {
+            match spec.rfind('=') {
+                None => policies.default = spec.parse()?,
+                Some(i) => {
+                    policies
+                        .by_name
+                        .insert(spec[..i].to_owned(), spec[i + 1..].parse()?);
+                }
+            }
+        }
+        Ok(policies)
+    }
+
+    pub fn apply(&self, name: &str, raw: &str) -> String {
+        match self.by_name.get(name).unwrap_or(&self.default) {
+            TemplatePolicy::Keep => raw.to_owned(),
+            TemplatePolicy::Strip => String::new(),
+            TemplatePolicy::Placeholder => format!("[template:{}]", name),
+        }
+    }
+}
+
+/// A non-fatal problem found while expanding a definition.
+#[derive(Debug, PartialEq)]
+pub enum ExpansionIssue {
+    UnknownTemplate(String),
+    MissingArgument(String),
+    /// Templates whose bodies use it nest more than `MAX_DEPTH` deep, probably because they
+    /// use each other.
+    TooDeep(String),
+    /// A `[[link]]` to a headword that isn't in the dictionary.
+    UnresolvableLink(String),
+}
+
+impl fmt::Display for ExpansionIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExpansionIssue::UnknownTemplate(name) => write!(f, "unknown template {:?}", name),
+            ExpansionIssue::MissingArgument(name) => {
+                write!(f, "template {:?} is missing an argument", name)
+            }
+            ExpansionIssue::TooDeep(name) => write!(f, "template {:?} nests too deeply", name),
+            ExpansionIssue::UnresolvableLink(target) => {
+                write!(f, "link to {:?}, which isn't in the dictionary", target)
+            }
+        }
+    }
+}
+
+// Label templates expand to their labels between these markers, so that adjacent groups can be
+// merged once everything is expanded; see `finish_label_groups`.
+const LABELS_START: char = '\u{1}';
+const LABELS_END: char = '\u{2}';
+
+/// Joins labels the way Module:labels does: with commas, except around "_" (a plain space) and
+/// "and"/"or".
+fn label_group(args: &[&str]) -> Option<String> {
+    let mut labels = String::new();
+    let mut separator = "";
+    for label in args.iter().map(|arg| arg.trim()) {
+        if label.is_empty() || label.contains('=') {
+            continue;
+        }
+        match label {
+            "_" => separator = " ",
+            "and" | "or" => {
+                labels.push(' ');
+                labels.push_str(label);
+                separator = " ";
+            }
+            _ => {
+                labels.push_str(separator);
+                labels.push_str(label);
+                separator = ", ";
+            }
+        }
+    }
+    if labels.is_empty() {
+        None
+    } else {
+        Some(format!("{}{}{}", LABELS_START, labels.trim(), LABELS_END))
+    }
+}
+
+// Templates for spellings that are marked as a kind of their own, like gender-neutral forms in
+// Spanish and Portuguese, or inclusive writing in French. They take the language and the term it's
+// a form of, and print as a label so the kind isn't lost: (template, label, what it's a form of).
+const ANNOTATED_FORMS: &[(&str, &str, &str)] = &[
+    ("inclusive form of", "inclusive", "Form of"),
+    ("inclusive of", "inclusive", "Form of"),
+    ("gender-neutral form of", "gender-neutral", "Form of"),
+    ("gender neutral form of", "gender-neutral", "Form of"),
+    ("reformed spelling of", "reformed spelling", "Spelling of"),
+];
+
+/// Turns label groups into parentheticals, merging adjacent ones: `{{lb|en|slang}} {{q|dated}}`
+/// becomes "(slang, dated)".
+fn finish_label_groups(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(start) = rest.find(LABELS_START) {
+        result.push_str(&rest[..start]);
+        let mut labels: Vec<&str> = Vec::new();
+        rest = &rest[start..];
+        while let Some(group) = rest.strip_prefix(LABELS_START) {
+            let end = group.find(LABELS_END).unwrap_or(group.len());
+            labels.push(&group[..end]);
+            rest = group.get(end + LABELS_END.len_utf8()..).unwrap_or("");
+            let after_space = rest.trim_start();
+            if !after_space.starts_with(LABELS_START) {
+                break;
+            }
+            rest = after_space;
+        }
+        result.push_str(&format!("({})", labels.join(", ")));
+    }
+    result.push_str(rest);
+    result
+}
+
+/// Where the bodies of templates that aren't built in come from.
+pub trait TemplateSource {
+    /// The body of the template, if there is one by that name.
+    fn body(&self, name: &str) -> Option<String>;
+
+    /// Whether a link to `target` leads to an entry. Without a dictionary to ask, every link is
+    /// taken to.
+    fn resolves(&self, _target: &str) -> bool {
+        true
+    }
+}
+
+/// No template bodies at all, so only the built-in templates expand.
+pub struct NoTemplates;
+
+impl TemplateSource for NoTemplates {
+    fn body(&self, _name: &str) -> Option<String> {
+        None
+    }
+}
+
+impl TemplateSource for Dictionary {
+    fn body(&self, name: &str) -> Option<String> {
+        self.template(name).ok().flatten()
+    }
+
+    fn resolves(&self, target: &str) -> bool {
+        self.contains(target).unwrap_or(true)
+    }
+}
+
+// How deeply templates can be nested inside each other's bodies; MediaWiki's old default.
+const MAX_DEPTH: usize = 40;
+
+// A template body's arguments, keyed by name or by number (from 1).
+type Frame = HashMap<String, String>;
+
+struct Expander<'e> {
+    policies: &'e TemplatePolicies,
+    templates: &'e dyn TemplateSource,
+    issues: Vec<ExpansionIssue>,
+    depth: usize,
+}
+
+// Whether two #ifeq or #switch values are equal. Like ParserFunctions, numbers compare as numbers,
+// so "01" and "1.0" are equal.
+fn values_equal(a: &str, b: &str) -> bool {
+    fn number(s: &str) -> Option<f64> {
+        if s.chars().all(|c| c.is_ascii_digit() || "+-.eE".contains(c)) {
+            s.parse().ok()
+        } else {
+            None
+        }
+    }
+    match (number(a), number(b)) {
+        (Some(a), Some(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+impl<'e> Expander<'e> {
+    fn expand_nodes(&mut self, nodes: &[Node], frame: &Frame) -> String {
+        let mut result = String::new();
+        for node in nodes {
+            match node {
+                Node::Text(text) => result.push_str(text),
+                Node::Template(template) => {
+                    let expanded = self.replace_template(template, frame);
+                    result.push_str(&expanded)
+                }
+                Node::Parameter(parameter) => {
+                    let name = self.expand_nodes(&parameter.name, frame);
+                    match (frame.get(name.trim()), &parameter.default) {
+                        (Some(value), _) => result.push_str(value),
+                        (None, Some(default)) => {
+                            let default = self.expand_nodes(default, frame);
+                            result.push_str(&default)
+                        }
+                        (None, None) => result.push_str(parameter.raw),
+                    }
+                }
+            }
+        }
+        result
+    }
+
+    // For now, we just hardcode a couple common templates, and fall back to the template's body if
+    // there is one. Arguments are expanded first, so they can contain templates themselves.
+    fn replace_template(&mut self, template: &Template, frame: &Frame) -> String {
+        let name = self.expand_nodes(&template.name, frame);
+        let name = name.trim();
+        if let Some(function) = name.strip_prefix('#') {
+            let (function, first) = function.split_at(function.find(':').unwrap_or(function.len()));
+            let first = first.get(1..).unwrap_or("").trim();
+            return match self.parser_function(function, first, template, frame) {
+                Some(expanded) => expanded,
+                None => {
+                    let name = format!("#{}", function);
+                    self.issues
+                        .push(ExpansionIssue::UnknownTemplate(name.clone()));
+                    self.policies.apply(&name, template.raw)
+                }
+            };
+        }
+        let args: Vec<String> = template
+            .positional()
+            .map(|arg| self.expand_nodes(arg, frame))
+            .collect();
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        // Numbered from 1, like in wikitext.
+        let arg = |i: usize| args.get(i - 1).cloned();
+        if let Some(&(_, label, form_of)) = ANNOTATED_FORMS.iter().find(|form| form.0 == name) {
+            if let (Some(term), Some(labels)) = (arg(2), label_group(&[label])) {
+                return format!("{} {} {}", labels, form_of, term);
+            }
+        }
+        let expanded = match name {
+            "," => Some(",".to_owned()),
+            "ngd" | "unsupported" | "non-gloss definition" => arg(1).map(str::to_owned),
+            "defdate" | "defdt" => arg(1).map(|x| format!("[{}]", Locale::current().dates(x))),
+            // {{alternative form of|en|color}}, or the older {{alternative form of|color|lang=en}}.
+            "alternative form of" => {
+                let target = if template.named().any(|(key, _)| key == "lang") {
+                    arg(1)
+                } else {
+                    arg(2)
+                };
+                target.map(|x| format!("Alternative form of {}", x))
+            }
+            "ja-romanization of" => arg(1).map(|x| format!("Rōmaji transcription of {}", x)),
+            "sumti" => arg(1).map(|x| format!("x{}", x)),
+            "ja-def" => arg(1).map(|x| format!("{}:", x)),
+            "qualifier" | "qual" | "q" | "i" => label_group(&args),
+            "lb" | "lbl" | "label" | "tlb" | "term-label" => {
+                label_group(args.get(1..).unwrap_or(&[]))
+            }
+            "m" | "l" => arg(2).map(str::to_owned),
+            // {{sh-see|mačka}}, on a spelling in another script.
+            _ if name.ends_with("-see") => arg(1).map(|x| format!("See {}", x)),
+            _ => return self.transclude(name, template, &args, frame),
+        };
+        expanded.unwrap_or_else(|| {
+            self.issues
+                .push(ExpansionIssue::MissingArgument(name.to_owned()));
+            template.raw.to_owned()
+        })
+    }
+
+    // Expands a template's body with its arguments. Bodies that need templates we can't expand
+    // (most often Lua modules, through #invoke) are treated as unknown, rather than printing
+    // whatever part of them did expand.
+    fn transclude(
+        &mut self,
+        name: &str,
+        template: &Template,
+        args: &[&str],
+        frame: &Frame,
+    ) -> String {
+        let body = match self.templates.body(name) {
+            Some(body) if self.depth < MAX_DEPTH => body,
+            Some(_) => {
+                self.issues.push(ExpansionIssue::TooDeep(name.to_owned()));
+                return self.policies.apply(name, template.raw);
+            }
+            None => {
+                self.issues
+                    .push(ExpansionIssue::UnknownTemplate(name.to_owned()));
+                return self.policies.apply(name, template.raw);
+            }
+        };
+        let mut arguments: Frame = args
+            .iter()
+            .enumerate()
+            .map(|(i, &arg)| ((i + 1).to_string(), arg.to_owned()))
+            .collect();
+        for (key, value) in template.named() {
+            let value = self.expand_nodes(value, frame);
+            arguments.insert(key.to_owned(), value.trim().to_owned());
+        }
+        let outer_issues = mem::take(&mut self.issues);
+        self.depth += 1;
+        let expanded = self.expand_nodes(&parse_templates(&body), &arguments);
+        self.depth -= 1;
+        let issues = mem::replace(&mut self.issues, outer_issues);
+        let incomplete = issues.iter().any(|issue| match issue {
+            ExpansionIssue::UnknownTemplate(_) | ExpansionIssue::TooDeep(_) => true,
+            ExpansionIssue::MissingArgument(_) | ExpansionIssue::UnresolvableLink(_) => false,
+        });
+        self.issues.extend(issues);
+        if incomplete {
+            self.issues
+                .push(ExpansionIssue::UnknownTemplate(name.to_owned()));
+            return self.policies.apply(name, template.raw);
+        }
+        expanded
+    }
+
+    // https://www.mediawiki.org/wiki/Help:Extension:ParserFunctions. Only the branch that's taken
+    // is expanded, so the others can't add issues.
+    fn parser_function(
+        &mut self,
+        function: &str,
+        first: &str,
+        template: &Template,
+        frame: &Frame,
+    ) -> Option<String> {
+        let arguments = &template.arguments;
+        match function {
+            "if" => {
+                let branch = if first.is_empty() { 1 } else { 0 };
+                Some(self.branch(arguments.get(branch), frame))
+            }
+            "ifeq" => {
+                let other = self.branch(arguments.first(), frame);
+                let branch = if values_equal(first, &other) { 1 } else { 2 };
+                Some(self.branch(arguments.get(branch), frame))
+            }
+            "switch" => Some(self.switch(first, arguments, frame)),
+            _ => None,
+        }
+    }
+
+    // A parser function's branch: the whole argument, since `=` means nothing to #if and #ifeq, and
+    // trimmed.
+    fn branch(&mut self, argument: Option<&Argument>, frame: &Frame) -> String {
+        let expanded = match argument {
+            None => String::new(),
+            Some(argument) if argument.key.is_none() => self.expand_nodes(&argument.value, frame),
+            Some(argument) => self.expand_nodes(&parse_templates(argument.raw), frame),
+        };
+        expanded.trim().to_owned()
+    }
+
+    // `{{#switch: value | case = result | case2 | case3 = shared result | #default = result }}`.
+    // A case without a result falls through to the next one that has one, and a last argument
+    // without a key is the default too.
+    fn switch(&mut self, value: &str, arguments: &[Argument], frame: &Frame) -> String {
+        let mut falling_through = false;
+        let mut default = None;
+        for (i, argument) in arguments.iter().enumerate() {
+            match argument.key {
+                Some(key) => {
+                    if falling_through || values_equal(key, value) {
+                        let result = self.expand_nodes(&argument.value, frame);
+                        return result.trim().to_owned();
+                    }
+                    if key == "#default" {
+                        default = Some(&argument.value);
+                    }
+                }
+                None => {
+                    let case = self.expand_nodes(&argument.value, frame);
+                    let case = case.trim();
+                    if i == arguments.len() - 1 {
+                        return case.to_owned();
+                    }
+                    if values_equal(case, value) {
+                        falling_through = true;
+                    }
+                }
+            }
+        }
+        match default {
+            Some(default) => self.expand_nodes(default, frame).trim().to_owned(),
+            None => String::new(),
+        }
+    }
+}
+
+/// Expands the templates in a definition, leaving any HTML in it alone. Also returns the problems
+/// found along the way, each listed once.
+pub fn expand_markup(
+    definition: &str,
+    policies: &TemplatePolicies,
+) -> (String, Vec<ExpansionIssue>) {
+    expand_markup_with(definition, policies, &NoTemplates)
+}
+
+/// Like `expand_markup`, but templates that aren't built in are expanded from their bodies in
+/// `templates`, parser functions and all.
+pub fn expand_markup_with(
+    definition: &str,
+    policies: &TemplatePolicies,
+    templates: &dyn TemplateSource,
+) -> (String, Vec<ExpansionIssue>) {
+    let mut expander = Expander {
+        policies,
+        templates,
+        issues: Vec::new(),
+        depth: 0,
+    };
+    let result = expander.expand_nodes(&parse_templates(definition), &Frame::new());
+    // Links can come from template bodies as well as the definition.
+    for link in find_links(&result) {
+        if !templates.resolves(&link.target) {
+            expander
+                .issues
+                .push(ExpansionIssue::UnresolvableLink(link.target));
+        }
+    }
+    let mut unique_issues: Vec<ExpansionIssue> = Vec::new();
+    for issue in expander.issues {
+        if !unique_issues.contains(&issue) {
+            unique_issues.push(issue);
+        }
+    }
+    (finish_label_groups(&result), unique_issues)
+}
+
+/// Expands the templates in a definition and renders it as plain text.
+pub fn expand(definition: &str, policies: &TemplatePolicies) -> (String, Vec<ExpansionIssue>) {
+    expand_with(definition, policies, &NoTemplates)
+}
+
+/// Like `expand`, with template bodies from `templates`; see `expand_markup_with`.
+pub fn expand_with(
+    definition: &str,
+    policies: &TemplatePolicies,
+    templates: &dyn TemplateSource,
+) -> (String, Vec<ExpansionIssue>) {
+    let (markup, issues) = expand_markup_with(definition, policies, templates);
+    (html_to_text(&markup), issues)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_db::TestDb;
+
+    struct Bodies(&'static [(&'static str, &'static str)]);
+
+    impl TemplateSource for Bodies {
+        fn body(&self, name: &str) -> Option<String> {
+            self.0
+                .iter()
+                .find(|(template, _)| *template == name)
+                .map(|(_, body)| body.to_string())
+        }
+    }
+
+    fn expanded(definition: &str) -> String {
+        expand_markup(definition, &TemplatePolicies::default()).0
+    }
+
+    #[test]
+    fn policies() {
+        let specs = ["strip".to_owned(), "m=keep".to_owned()];
+        let policies = TemplatePolicies::parse(&specs).unwrap();
+        assert_eq!(policies.apply("foo", "{{foo}}"), "");
+        assert_eq!(policies.apply("m", "{{m}}"), "{{m}}");
+        assert_eq!(
+            TemplatePolicies::default().apply("foo", "{{foo}}"),
+            "[template:foo]"
+        );
+        assert!(TemplatePolicies::parse(&["m=drop".to_owned()]).is_err());
+    }
+
+    #[test]
+    fn built_in_templates() {
+        assert_eq!(
+            expanded("{{l|en|feline}} or {{m|la|cattus}}"),
+            "feline or cattus"
+        );
+        assert_eq!(
+            expanded("{{alternative form of|en|color}}"),
+            "Alternative form of color"
+        );
+        assert_eq!(
+            expanded("{{alternative form of|colour|lang=en}}"),
+            "Alternative form of colour"
+        );
+        assert_eq!(expanded("{{sh-see|mačka}}"), "See mačka");
+        assert_eq!(
+            expanded("{{inclusive form of|fr|étudiant}}"),
+            "(inclusive) Form of étudiant"
+        );
+        let (result, issues) = expand_markup("{{m|en}}", &TemplatePolicies::default());
+        assert_eq!(result, "{{m|en}}");
+        assert_eq!(issues, [ExpansionIssue::MissingArgument("m".to_owned())]);
+    }
+
+    #[test]
+    fn labels_merge() {
+        assert_eq!(
+            expanded("{{lb|en|slang}} {{q|dated}} A cat."),
+            "(slang, dated) A cat."
+        );
+        assert_eq!(
+            expanded("{{lb|en|US|_|informal|or|humorous}} A cat."),
+            "(US informal or humorous) A cat."
+        );
+        assert_eq!(expanded("{{lb|en|slang}}, {{q|dated}}"), "(slang), (dated)");
+    }
+
+    #[test]
+    fn unknown_templates() {
+        let (result, issues) = expand_markup("A {{foo|x}}.", &TemplatePolicies::default());
+        assert_eq!(result, "A [template:foo].");
+        assert_eq!(issues, [ExpansionIssue::UnknownTemplate("foo".to_owned())]);
+    }
+
+    #[test]
+    fn transcludes_bodies() {
+        let bodies = Bodies(&[
+            ("gloss", "({{{1}}}, {{{pos|noun}}})"),
+            ("outer", "{{gloss|{{{1}}}|pos=verb}}"),
+            ("needs module", "{{#invoke:foo|bar}}"),
+        ]);
+        let policies = TemplatePolicies::default();
+        let expand = |s| expand_markup_with(s, &policies, &bodies);
+        assert_eq!(expand("{{gloss|cat}}"), ("(cat, noun)".to_owned(), vec![]));
+        assert_eq!(expand("{{outer|cat}}"), ("(cat, verb)".to_owned(), vec![]));
+        assert_eq!(expand("{{gloss}}").0, "({{{1}}}, noun)");
+        let (result, issues) = expand("{{needs module|x}}");
+        assert_eq!(result, "[template:needs module]");
+        assert_eq!(
+            issues,
+            [
+                ExpansionIssue::UnknownTemplate("#invoke".to_owned()),
+                ExpansionIssue::UnknownTemplate("needs module".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn parser_functions() {
+        let bodies = Bodies(&[
+            ("if", "{{#if:{{{1|}}}|yes|no}}"),
+            ("ifeq", "{{#ifeq:{{{1}}}|1.0|one|other}}"),
+            ("switch", "{{#switch:{{{1}}}|a=A|b|c=B or C|#default=D}}"),
+        ]);
+        let policies = TemplatePolicies::default();
+        let expand = |s| expand_markup_with(s, &policies, &bodies).0;
+        assert_eq!(expand("{{if|x}} {{if}} {{if| }}"), "yes no no");
+        assert_eq!(expand("{{ifeq|01}} {{ifeq|1}} {{ifeq|x}}"), "one one other");
+        assert_eq!(
+            expand("{{switch|a}} {{switch|b}} {{switch|c}} {{switch|z}}"),
+            "A B or C B or C D"
+        );
+    }
+
+    #[test]
+    fn templates_that_use_each_other() {
+        let bodies = Bodies(&[("ping", "{{pong}}"), ("pong", "{{ping}}")]);
+        let (result, issues) =
+            expand_markup_with("{{ping}}", &TemplatePolicies::default(), &bodies);
+        assert_eq!(result, "[template:ping]");
+        assert!(
+            issues.contains(&ExpansionIssue::TooDeep("ping".to_owned())),
+            "{:?}",
+            issues
+        );
+    }
+
+    #[test]
+    fn renders_text() {
+        let (text, _) = expand("A <b>{{m|en|small}}</b> cat.", &TemplatePolicies::default());
+        assert_eq!(text, "A small cat.");
+    }
+
+    #[test]
+    fn links_to_missing_headwords() {
+        let db = TestDb::baseline(&[("cat", "English", "Noun", "A feline.")]);
+        let dictionary = db.open();
+        let (_, issues) = expand_markup_with(
+            "A young [[cat]], or [[kitty#English|kitty]]; see [[w:Cat]].",
+            &TemplatePolicies::default(),
+            &dictionary,
+        );
+        assert_eq!(
+            issues,
+            [ExpansionIssue::UnresolvableLink("kitty".to_owned())]
+        );
+        let (_, issues) = expand_markup("A [[kitty]].", &TemplatePolicies::default());
+        assert!(issues.is_empty());
+    }
+}
@@ -0,0 +1,451 @@
+//! Writes a language's definitions out as a dictionary for other programs: StarDict's format, for
+//! GoldenDict, KOReader and most e-readers, or dictd's, for `dictd` and the DICT clients. The
+//! definitions are expanded and formatted as plain text, numbered under their parts of speech,
+//! the way `define` prints them.
+//!
+//! dictd's data file is normally compressed with `dictzip`; without it the data is left
+//! uncompressed, which dictd reads just as well.
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::str::FromStr;
+
+use dictionary::Dictionary;
+use lookup::group_by_language;
+use render::Formatters;
+use Meaning;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ExportFormat {
+    StarDict,
+    Dictd,
+}
+
+impl FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<ExportFormat, String> {
+        match s {
+            "stardict" => Ok(ExportFormat::StarDict),
+            "dictd" => Ok(ExportFormat::Dictd),
+            _ => Err(format!(
+                "unknown format {:?}; expected stardict or dictd",
+                s
+            )),
+        }
+    }
+}
+
+/// What `export` wrote.
+#[derive(Debug)]
+pub struct Exported {
+    pub words: usize,
+    pub files: Vec<PathBuf>,
+    /// Words left out because they're too long for the format.
+    pub skipped: usize,
+}
+
+enum Error {
+    Io(io::Error),
+    Database(rusqlite::Error),
+    Other(String),
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::Io(e)
+    }
+}
+
+impl From<rusqlite::Error> for Error {
+    fn from(e: rusqlite::Error) -> Error {
+        Error::Database(e)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Io(ref e) => write!(f, "{}", e),
+            Error::Database(ref e) => write!(f, "{}", e),
+            Error::Other(ref e) => write!(f, "{}", e),
+        }
+    }
+}
+
+// StarDict headwords are under 256 bytes, with the NUL after them.
+const MAX_STARDICT_WORD: usize = 255;
+
+const DESCRIPTION: &str = "Definitions from Wiktionary (https://www.wiktionary.org), available \
+                           under the Creative Commons Attribution-ShareAlike License.";
+
+// A word's definitions as text, with each line after `indent`.
+fn entry_text(
+    dictionary: &Dictionary,
+    formatters: &Formatters,
+    language: &str,
+    meanings: Vec<Meaning>,
+    indent: &str,
+) -> String {
+    let mut text = String::new();
+    for (_, parts_of_speech) in group_by_language(meanings) {
+        for (part_of_speech, meanings) in parts_of_speech {
+            text.push_str(&format!("{}{}\n", indent, part_of_speech));
+            for (i, meaning) in meanings.iter().enumerate() {
+                let definition = dictionary.expand(&meaning.definition);
+                let definition = formatters.definition(language, definition);
+                text.push_str(&format!("{}  {}. {}\n", indent, i + 1, definition));
+            }
+        }
+    }
+    text
+}
+
+// The order StarDict looks words up in: case-insensitively for ASCII, then byte by byte.
+fn stardict_order(a: &str, b: &str) -> Ordering {
+    let lower = |s: &str| {
+        s.bytes()
+            .map(|b| b.to_ascii_lowercase())
+            .collect::<Vec<u8>>()
+    };
+    lower(a).cmp(&lower(b)).then_with(|| a.cmp(b))
+}
+
+// An offset or length in a dictd index: base 64, most significant digit first, no padding.
+fn dictd_number(mut n: u64) -> String {
+    const DIGITS: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut digits = vec![DIGITS[(n % 64) as usize]];
+    n /= 64;
+    while n > 0 {
+        digits.push(DIGITS[(n % 64) as usize]);
+        n /= 64;
+    }
+    digits.reverse();
+    String::from_utf8(digits).unwrap()
+}
+
+fn one_line(s: &str) -> String {
+    s.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn export_stardict(
+    dictionary: &Dictionary,
+    language: &str,
+    dir: &Path,
+    name: &str,
+) -> Result<Exported, Error> {
+    let formatters = Formatters::default();
+    let dict_path = dir.join(format!("{}.dict", name));
+    let mut dict = BufWriter::new(File::create(&dict_path)?);
+    let mut offset: u64 = 0;
+    let mut index: Vec<(String, u64, usize)> = Vec::new();
+    let mut skipped = 0;
+    dictionary.for_each_word(language, |word, meanings| -> Result<(), Error> {
+        if word.len() > MAX_STARDICT_WORD || word.contains('\0') {
+            skipped += 1;
+            return Ok(());
+        }
+        let text = entry_text(dictionary, &formatters, language, meanings, "");
+        dict.write_all(text.as_bytes())?;
+        index.push((word.to_owned(), offset, text.len()));
+        offset += text.len() as u64;
+        Ok(())
+    })?;
+    dict.flush()?;
+    if offset > u64::from(u32::MAX) {
+        return Err(Error::Other(format!(
+            "{} definitions are too many for one StarDict dictionary; its offsets only go up to \
+             4 GB",
+            language
+        )));
+    }
+    index.sort_by(|a, b| stardict_order(&a.0, &b.0));
+
+    let idx_path = dir.join(format!("{}.idx", name));
+    let mut idx = BufWriter::new(File::create(&idx_path)?);
+    let mut idx_size = 0;
+    for (word, offset, size) in &index {
+        idx.write_all(word.as_bytes())?;
+        idx.write_all(&[0])?;
+        idx.write_all(&(*offset as u32).to_be_bytes())?;
+        idx.write_all(&(*size as u32).to_be_bytes())?;
+        idx_size += word.len() + 9;
+    }
+    idx.flush()?;
+
+    let ifo_path = dir.join(format!("{}.ifo", name));
+    let mut ifo = format!(
+        "StarDict's dict ifo file\nversion=2.4.2\nbookname={}\nwordcount={}\nidxfilesize={}\n\
+         sametypesequence=m\ndescription={}\n",
+        one_line(&format!("{} (define3)", language)),
+        index.len(),
+        idx_size,
+        DESCRIPTION
+    );
+    let date = match dictionary.editions()?.first() {
+        Some(edition) => dictionary.dump_date(edition)?,
+        None => None,
+    };
+    if let Some(date) = date {
+        ifo.push_str(&format!("date={}\n", one_line(&date)));
+    }
+    fs::write(&ifo_path, ifo)?;
+    Ok(Exported {
+        words: index.len(),
+        files: vec![ifo_path, idx_path, dict_path],
+        skipped,
+    })
+}
+
+fn export_dictd(
+    dictionary: &Dictionary,
+    language: &str,
+    dir: &Path,
+    name: &str,
+) -> Result<Exported, Error> {
+    let formatters = Formatters::default();
+    let dict_path = dir.join(format!("{}.dict", name));
+    let mut dict = BufWriter::new(File::create(&dict_path)?);
+    let mut offset: u64 = 0;
+    let mut index: Vec<(String, u64, usize)> = Vec::new();
+    let mut add = |dict: &mut BufWriter<File>, word: &str, text: &str| -> io::Result<()> {
+        dict.write_all(text.as_bytes())?;
+        index.push((word.to_owned(), offset, text.len()));
+        offset += text.len() as u64;
+        Ok(())
+    };
+    // The entries dictd reads to describe the database. utf8 and allchars say that headwords are
+    // UTF-8 and are looked up with all their characters, not just letters and digits.
+    let short = one_line(&format!("{} (define3)", language));
+    let headers = [
+        ("00-database-short", short),
+        ("00-database-info", DESCRIPTION.to_owned()),
+        ("00-database-url", "https://www.wiktionary.org".to_owned()),
+        ("00-database-utf8", String::new()),
+        ("00-database-allchars", String::new()),
+    ];
+    for (word, text) in &headers {
+        add(&mut dict, word, &format!("{}\n{}\n", word, text))?;
+    }
+    let mut skipped = 0;
+    dictionary.for_each_word(language, |word, meanings| -> Result<(), Error> {
+        // Each line of the index is a headword, a tab, and two numbers.
+        if word.contains(['\t', '\n']) {
+            skipped += 1;
+            return Ok(());
+        }
+        let text = entry_text(dictionary, &formatters, language, meanings, "  ");
+        add(&mut dict, word, &format!("{}\n{}", word, text))?;
+        Ok(())
+    })?;
+    dict.flush()?;
+    drop(dict);
+    let words = index.len() - headers.len();
+    // dictd looks words up case-insensitively.
+    index.sort_by(|a, b| {
+        a.0.to_lowercase()
+            .cmp(&b.0.to_lowercase())
+            .then_with(|| a.0.cmp(&b.0))
+    });
+
+    let index_path = dir.join(format!("{}.index", name));
+    let mut out = BufWriter::new(File::create(&index_path)?);
+    for (word, offset, size) in &index {
+        writeln!(
+            out,
+            "{}\t{}\t{}",
+            word,
+            dictd_number(*offset),
+            dictd_number(*size as u64)
+        )?;
+    }
+    out.flush()?;
+
+    let compressed = dir.join(format!("{}.dict.dz", name));
+    let _ = fs::remove_file(&compressed);
+    let dict_path = match Command::new("dictzip").arg(&dict_path).status() {
+        Ok(status) if status.success() => compressed,
+        Ok(_) => {
+            return Err(Error::Other(format!(
+                "dictzip failed on {}",
+                dict_path.display()
+            )))
+        }
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => dict_path,
+        Err(e) => return Err(Error::Other(format!("Could not run dictzip: {}", e))),
+    };
+    Ok(Exported {
+        words,
+        files: vec![index_path, dict_path],
+        skipped,
+    })
+}
+
+/// Writes the definitions in `language` to `dir` as `format`, in files called `name` with the
+/// format's extensions.
+pub fn export(
+    dictionary: &Dictionary,
+    language: &str,
+    format: ExportFormat,
+    dir: &Path,
+    name: &str,
+) -> Result<Exported, String> {
+    fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+    // Both formats keep their definitions in NAME.dict.
+    let (other, extension) = match format {
+        ExportFormat::StarDict => ("dictd", "index"),
+        ExportFormat::Dictd => ("StarDict", "ifo"),
+    };
+    if dir.join(format!("{}.{}", name, extension)).exists() {
+        return Err(format!(
+            "{} already has a {} dictionary called {}; export to another directory or under \
+             another name",
+            dir.display(),
+            other,
+            name
+        ));
+    }
+    match format {
+        ExportFormat::StarDict => export_stardict(dictionary, language, dir, name),
+        ExportFormat::Dictd => export_dictd(dictionary, language, dir, name),
+    }
+    .map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::process;
+
+    use super::*;
+    use test_db::TestDb;
+
+    fn scratch(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("define3-test-{}-export-{}", process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn words() -> TestDb {
+        TestDb::baseline(&[
+            ("cat", "English", "Noun", "A feline."),
+            ("cat", "English", "Verb", "To hoist an anchor."),
+            ("Cab", "English", "Noun", "A taxi."),
+            ("chat", "French", "Noun", "cat"),
+        ])
+    }
+
+    #[test]
+    fn dictd_numbers() {
+        assert_eq!(dictd_number(0), "A");
+        assert_eq!(dictd_number(63), "/");
+        assert_eq!(dictd_number(64), "BA");
+        assert_eq!(dictd_number(4096 + 65), "BBB");
+    }
+
+    #[test]
+    fn stardict_sorts_ascii_case_insensitively() {
+        let mut words = vec!["cat", "Cab", "Cat", "ábaco", "abc"];
+        words.sort_by(|a, b| stardict_order(a, b));
+        assert_eq!(words, ["abc", "Cab", "Cat", "cat", "ábaco"]);
+    }
+
+    #[test]
+    fn exports_stardict() {
+        let db = words();
+        let dir = scratch("stardict");
+        let exported = export(&db.open(), "English", ExportFormat::StarDict, &dir, "en").unwrap();
+        assert_eq!(exported.words, 2);
+        assert_eq!(exported.skipped, 0);
+
+        let ifo = fs::read_to_string(dir.join("en.ifo")).unwrap();
+        assert!(
+            ifo.starts_with("StarDict's dict ifo file\nversion=2.4.2\n"),
+            "{}",
+            ifo
+        );
+        assert!(
+            ifo.contains("\nbookname=English (define3)\nwordcount=2\n"),
+            "{}",
+            ifo
+        );
+        let idx = fs::read(dir.join("en.idx")).unwrap();
+        assert!(
+            ifo.contains(&format!("\nidxfilesize={}\n", idx.len())),
+            "{}",
+            ifo
+        );
+
+        // Each index entry is the word, a NUL, and its offset and size in the .dict.
+        let dict = fs::read(dir.join("en.dict")).unwrap();
+        let mut entries = Vec::new();
+        let mut rest = &idx[..];
+        while !rest.is_empty() {
+            let nul = rest.iter().position(|&b| b == 0).unwrap();
+            let word = String::from_utf8(rest[..nul].to_vec()).unwrap();
+            let number = |at: usize| {
+                let mut bytes = [0; 4];
+                bytes.copy_from_slice(&rest[at..at + 4]);
+                u32::from_be_bytes(bytes) as usize
+            };
+            let (offset, size) = (number(nul + 1), number(nul + 5));
+            entries.push((
+                word,
+                String::from_utf8(dict[offset..offset + size].to_vec()).unwrap(),
+            ));
+            rest = &rest[nul + 9..];
+        }
+        assert_eq!(
+            entries,
+            [
+                ("Cab".to_owned(), "Noun\n  1. A taxi.\n".to_owned()),
+                (
+                    "cat".to_owned(),
+                    "Noun\n  1. A feline.\nVerb\n  1. To hoist an anchor.\n".to_owned()
+                ),
+            ]
+        );
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn exports_dictd() {
+        let db = words();
+        let dir = scratch("dictd");
+        let exported = export(&db.open(), "French", ExportFormat::Dictd, &dir, "fr").unwrap();
+        assert_eq!(exported.words, 1);
+        let index = fs::read_to_string(dir.join("fr.index")).unwrap();
+        let headwords: Vec<_> = index
+            .lines()
+            .map(|line| line.split('\t').next().unwrap())
+            .collect();
+        assert_eq!(
+            headwords,
+            [
+                "00-database-allchars",
+                "00-database-info",
+                "00-database-short",
+                "00-database-url",
+                "00-database-utf8",
+                "chat",
+            ]
+        );
+        if dir.join("fr.dict").exists() {
+            let dict = fs::read_to_string(dir.join("fr.dict")).unwrap();
+            assert!(dict.ends_with("chat\n  Noun\n    1. cat\n"), "{}", dict);
+        }
+
+        // The .dict files of the two formats would be the same file.
+        let e = export(&db.open(), "French", ExportFormat::StarDict, &dir, "fr").unwrap_err();
+        assert!(
+            e.contains("already has a dictd dictionary called fr"),
+            "{}",
+            e
+        );
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
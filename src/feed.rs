@@ -0,0 +1,238 @@
+//! An Atom feed of the word of the day, for people to follow a self-hosted define3 in their feed
+//! readers.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use rusqlite::Result;
+
+use checksum::entry_checksum;
+use dictionary::Dictionary;
+use embed::meanings_html;
+use expand::TemplatePolicies;
+use permalink::encode_path_segment;
+use render::{escape_html, Formatters};
+
+/// Today in UTC, as days since 1970-01-01.
+pub fn today() -> i64 {
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs() as i64);
+    seconds.div_euclid(86_400)
+}
+
+/// A day counted from 1970-01-01 as YYYY-MM-DD, by Howard Hinnant's `civil_from_days`.
+pub fn date(days: i64) -> String {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+// How many rows `word_of_the_day` tries before it takes one that isn't a headword's first.
+const WORD_OF_THE_DAY_TRIES: usize = 64;
+
+impl Dictionary {
+    /// The word of the day for `date` (YYYY-MM-DD), optionally in one language, as (headword,
+    /// language). It's the same all day for everyone using the same database.
+    ///
+    /// A hash of the date picks a rowid in the range the words (in `language`) take up, and the
+    /// first row from there on is the pick if it's its headword's first in its language. If it
+    /// isn't, the date is hashed again, so every headword is about as likely as the others
+    /// however many definitions it has, and each try is a seek on an index rather than a scan.
+    pub fn word_of_the_day(
+        &self,
+        date: &str,
+        language: Option<&str>,
+    ) -> Result<Option<(String, String)>> {
+        let (first, last): (Option<i64>, Option<i64>) = match language {
+            Some(language) => self.connection().query_row(
+                "SELECT min(rowid), max(rowid) FROM words WHERE language = ?1",
+                [language],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )?,
+            None => self.connection().query_row(
+                "SELECT min(rowid), max(rowid) FROM words",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )?,
+        };
+        let (first, last) = match (first, last) {
+            (Some(first), Some(last)) => (first, last),
+            _ => return Ok(None),
+        };
+        // Written out for each case so that the language's index can be used.
+        let mut seek = self.connection().prepare_cached(if language.is_some() {
+            "SELECT rowid, name, language FROM words WHERE language = ?2 AND rowid >= ?1
+             ORDER BY rowid LIMIT 1"
+        } else {
+            "SELECT rowid, name, language FROM words WHERE rowid >= ?1 AND ?2 IS NULL
+             ORDER BY rowid LIMIT 1"
+        })?;
+        let mut is_first = self.connection().prepare_cached(
+            "SELECT NOT EXISTS (SELECT 1 FROM words WHERE name = ?1 AND language = ?2
+                                AND rowid < ?3)",
+        )?;
+        let mut pick = None;
+        for attempt in 0..WORD_OF_THE_DAY_TRIES {
+            let attempt = attempt.to_string();
+            let hash =
+                u64::from_str_radix(&entry_checksum("word of the day", date, &[&attempt]), 16)
+                    .unwrap();
+            let rowid = first + (hash % (last - first + 1) as u64) as i64;
+            let (rowid, name, found_language): (i64, String, String) = seek
+                .query_row(rusqlite::params![rowid, language], |row| {
+                    Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+                })?;
+            let first_row: bool = is_first
+                .query_row(rusqlite::params![name, found_language, rowid], |row| {
+                    row.get(0)
+                })?;
+            pick = Some((name, found_language));
+            if first_row {
+                break;
+            }
+        }
+        Ok(pick)
+    }
+}
+
+/// An Atom feed of the words of the last `days` days up to `today` (see `today`), newest first,
+/// each with its definitions and linked to its page on the server at `base_url`.
+pub fn atom_feed(
+    dictionary: &Dictionary,
+    base_url: &str,
+    today: i64,
+    days: usize,
+    language: Option<&str>,
+) -> Result<String> {
+    let base_url = base_url.trim_end_matches('/');
+    let policies = TemplatePolicies::default();
+    let formatters = Formatters::default();
+    let mut entries = String::new();
+    for day in (0..days as i64).map(|ago| today - ago) {
+        let date = date(day);
+        let (word, language) = match dictionary.word_of_the_day(&date, language)? {
+            Some(word) => word,
+            None => break,
+        };
+        let mut meanings = dictionary.lookup(&word)?;
+        meanings.retain(|meaning| meaning.language == language);
+        let html = meanings_html(&word, &meanings, &policies, &formatters, false);
+        entries.push_str(&format!(
+            "<entry><title>{}</title><id>{}/feed.xml#{}</id><updated>{}T00:00:00Z</updated>\
+             <link href=\"{}/define/{}\"/><content type=\"html\">{}</content></entry>",
+            escape_html(&word),
+            escape_html(base_url),
+            date,
+            date,
+            escape_html(base_url),
+            escape_html(&encode_path_segment(&word)),
+            escape_html(&html)
+        ));
+    }
+    Ok(format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\
+         <feed xmlns=\"http://www.w3.org/2005/Atom\"><title>define3 word of the day</title>\
+         <id>{0}/feed.xml</id><updated>{1}T00:00:00Z</updated>\
+         <link rel=\"self\" href=\"{0}/feed.xml\"/>{2}</feed>",
+        escape_html(base_url),
+        date(today),
+        entries
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_db::TestDb;
+
+    #[test]
+    fn dates() {
+        assert_eq!(date(0), "1970-01-01");
+        assert_eq!(date(-1), "1969-12-31");
+        assert_eq!(date(59), "1970-03-01");
+        assert_eq!(date(11_016), "2000-02-29");
+        assert_eq!(date(20_740), "2026-10-14");
+        assert_eq!(date(-719_468), "0000-03-01");
+    }
+
+    #[test]
+    fn feed_of_the_last_days() {
+        let db = TestDb::baseline(&[
+            ("chat", "French", "Noun", "cat"),
+            ("chat", "English", "Verb", "To talk."),
+        ]);
+        let dictionary = db.open();
+        let feed = atom_feed(
+            &dictionary,
+            "http://dict.example/",
+            20_740,
+            3,
+            Some("French"),
+        )
+        .unwrap();
+        assert!(
+            feed.contains("<id>http://dict.example/feed.xml</id><updated>2026-10-14T00:00:00Z"),
+            "{}",
+            feed
+        );
+        assert_eq!(feed.matches("<entry>").count(), 3);
+        let newest = feed.find("feed.xml#2026-10-14").unwrap();
+        assert!(newest < feed.find("feed.xml#2026-10-12").unwrap());
+        assert!(
+            feed.contains("<link href=\"http://dict.example/define/chat\"/>"),
+            "{}",
+            feed
+        );
+        assert!(!feed.contains("To talk."), "{}", feed);
+
+        let empty =
+            atom_feed(&dictionary, "http://dict.example", 20_740, 3, Some("Latin")).unwrap();
+        assert!(!empty.contains("<entry>"), "{}", empty);
+        assert!(empty.ends_with("</feed>"), "{}", empty);
+    }
+
+    #[test]
+    fn words_of_the_day_are_headwords_not_definitions() {
+        let mut words = vec![("dog", "English", "Noun", "A canine.")];
+        // A word with many definitions is no likelier than one with one.
+        for _ in 0..50 {
+            words.push(("set", "English", "Verb", "To put."));
+        }
+        words.push(("chat", "French", "Noun", "A cat."));
+        let db = TestDb::baseline(&words);
+        let dictionary = db.open();
+        let mut picks: Vec<(String, String)> = (0..60)
+            .map(|day| {
+                dictionary
+                    .word_of_the_day(&date(day), None)
+                    .unwrap()
+                    .unwrap()
+            })
+            .collect();
+        let sets = picks.iter().filter(|(name, _)| name == "set").count();
+        assert!(sets < 40, "set was picked {} times out of 60", sets);
+        picks.sort();
+        picks.dedup();
+        assert_eq!(picks.len(), 3);
+        assert_eq!(
+            dictionary
+                .word_of_the_day("2026-10-14", Some("French"))
+                .unwrap(),
+            Some(("chat".to_owned(), "French".to_owned()))
+        );
+        assert_eq!(
+            dictionary
+                .word_of_the_day("2026-10-14", Some("Latin"))
+                .unwrap(),
+            None
+        );
+    }
+}
@@ -0,0 +1,449 @@
+//! Downloads a prebuilt database, for using define without building one from a dump first.
+//!
+//! A release is described by a small JSON manifest:
+//!
+//! ```json
+//! {"version": "2026-10-01", "url": "define3-2026-10-01.sqlite3.zst", "sha256": "…", "size": 123}
+//! ```
+//!
+//! where `url` may be relative to the manifest's own URL and `size` is optional. The file is
+//! downloaded through `curl` next to the database, under a name with the version in it, so an
+//! interrupted download picks up where it left off the next time. It's checked against `sha256`
+//! before anything is replaced, and decompressed first if it ends in `.zst`.
+//!
+//! No releases are published yet, so there's no default manifest to fetch; the manifest's URL
+//! always has to be given.
+
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use serde_json::{json, Value};
+
+const USER_AGENT: &str = concat!("define3/", env!("CARGO_PKG_VERSION"));
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Release {
+    pub version: String,
+    /// The database file, made absolute.
+    pub url: String,
+    /// The SHA-256 of the file as downloaded, in hex.
+    pub sha256: String,
+    /// The size of the file as downloaded, in bytes, if the manifest says.
+    pub size: Option<u64>,
+}
+
+// `url` as seen from `base`: unchanged if it's absolute, otherwise in place of base's last part.
+fn join_url(base: &str, url: &str) -> String {
+    if url.contains("://") {
+        return url.to_owned();
+    }
+    match base.rfind('/') {
+        Some(i) => format!("{}/{}", &base[..i], url.trim_start_matches('/')),
+        None => url.to_owned(),
+    }
+}
+
+impl Release {
+    /// Reads a manifest fetched from `manifest_url`.
+    pub fn from_manifest(manifest_url: &str, manifest: &str) -> Result<Release, String> {
+        let manifest: Value = serde_json::from_str(manifest).map_err(|e| {
+            format!(
+                "Could not understand the manifest at {}: {}",
+                manifest_url, e
+            )
+        })?;
+        let field = |name: &str| {
+            manifest[name]
+                .as_str()
+                .map(str::to_owned)
+                .ok_or_else(|| format!("The manifest at {} has no {:?}", manifest_url, name))
+        };
+        let sha256 = field("sha256")?.to_lowercase();
+        if sha256.len() != 64 || !sha256.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(format!(
+                "The manifest at {} has a bad sha256: {:?}",
+                manifest_url, sha256
+            ));
+        }
+        Ok(Release {
+            version: field("version")?,
+            url: join_url(manifest_url, &field("url")?),
+            sha256,
+            size: manifest["size"].as_u64(),
+        })
+    }
+
+    /// Fetches and reads the manifest at `manifest_url`.
+    pub fn fetch(manifest_url: &str) -> Result<Release, String> {
+        let output = Command::new("curl")
+            .args(["--silent", "--show-error", "--fail", "--location"])
+            .args(["--user-agent", USER_AGENT])
+            .arg(manifest_url)
+            .output()
+            .map_err(|e| format!("Could not run curl: {}", e))?;
+        if !output.status.success() {
+            return Err(format!(
+                "Could not fetch {}: {}",
+                manifest_url,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+        Release::from_manifest(manifest_url, &String::from_utf8_lossy(&output.stdout))
+    }
+
+    fn compressed(&self) -> bool {
+        self.url.ends_with(".zst")
+    }
+
+    /// Where the download goes while it's under way, next to `database`.
+    pub fn partial_path(&self, database: &Path) -> PathBuf {
+        // Versions come from the manifest, so keep them from reaching outside the directory.
+        let version: String = self
+            .version
+            .chars()
+            .map(|c| {
+                if c.is_alphanumeric() || c == '.' || c == '-' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        let mut name = database.file_name().unwrap_or_default().to_os_string();
+        name.push(format!(".{}.part", version));
+        database.with_file_name(name)
+    }
+
+    /// Downloads the file to `partial`, carrying on from what's already there. curl's progress
+    /// bar goes to stderr.
+    pub fn download(&self, partial: &Path) -> Result<(), String> {
+        let have = fs::metadata(partial).map(|m| m.len()).unwrap_or(0);
+        // Asking for the rest of a file that's all there is an error to most servers (416), so
+        // check first, by its checksum if the manifest doesn't give a size.
+        if have > 0 {
+            let complete = match self.size {
+                Some(size) => have == size,
+                None => sha256_file(partial).ok().as_ref() == Some(&self.sha256),
+            };
+            if complete {
+                return Ok(());
+            }
+        }
+        let mut command = Command::new("curl");
+        command
+            .args(["--fail", "--location", "--progress-bar"])
+            .args(["--user-agent", USER_AGENT])
+            .arg("--output")
+            .arg(partial);
+        if have > 0 {
+            command.args(["--continue-at", "-"]);
+        }
+        let status = command
+            .arg(&self.url)
+            .status()
+            .map_err(|e| format!("Could not run curl: {}", e))?;
+        if !status.success() {
+            return Err(format!(
+                "Could not download {}; run fetch again to carry on",
+                self.url
+            ));
+        }
+        Ok(())
+    }
+
+    /// Checks the download at `partial` and puts the database it holds at `database`, replacing
+    /// what was there. A download that doesn't match its checksum is deleted, so the next try
+    /// starts over.
+    pub fn install(&self, partial: &Path, database: &Path) -> Result<(), String> {
+        let error = |e: io::Error| format!("{}: {}", partial.display(), e);
+        let checksum = sha256_file(partial).map_err(error)?;
+        if checksum != self.sha256 {
+            let _ = fs::remove_file(partial);
+            return Err(format!(
+                "The download of {} is corrupt (its SHA-256 is {}, not {}); run fetch again",
+                self.url, checksum, self.sha256
+            ));
+        }
+        if self.compressed() {
+            let mut name = database.file_name().unwrap_or_default().to_os_string();
+            name.push(".new");
+            let new = database.with_file_name(name);
+            let error = |e: io::Error| format!("Could not decompress to {}: {}", new.display(), e);
+            let mut decoder =
+                zstd::stream::read::Decoder::new(File::open(partial).map_err(&error)?)
+                    .map_err(&error)?;
+            let mut out = BufWriter::new(File::create(&new).map_err(&error)?);
+            io::copy(&mut decoder, &mut out)
+                .and_then(|_| out.flush())
+                .map_err(&error)?;
+            drop(out);
+            fs::rename(&new, database).map_err(|e| format!("{}: {}", database.display(), e))?;
+            let _ = fs::remove_file(partial);
+        } else {
+            fs::rename(partial, database).map_err(|e| format!("{}: {}", database.display(), e))?;
+        }
+        Ok(())
+    }
+}
+
+/// Where the version of a fetched database is recorded, next to it.
+pub fn version_path(database: &Path) -> PathBuf {
+    let mut name = database.file_name().unwrap_or_default().to_os_string();
+    name.push(".fetched");
+    database.with_file_name(name)
+}
+
+/// The version of the release that `database` was fetched from, if it was fetched and hasn't been
+/// rebuilt since.
+pub fn installed_version(database: &Path) -> Option<String> {
+    let record: Value =
+        serde_json::from_str(&fs::read_to_string(version_path(database)).ok()?).ok()?;
+    // A database built over the fetched one leaves the record behind, but not the size.
+    if record["database_size"].as_u64() != Some(fs::metadata(database).ok()?.len()) {
+        return None;
+    }
+    record["version"].as_str().map(str::to_owned)
+}
+
+/// Records that `database` is now `release`.
+pub fn record_version(database: &Path, release: &Release) -> io::Result<()> {
+    let record = json!({
+        "version": release.version,
+        "url": release.url,
+        "sha256": release.sha256,
+        "database_size": fs::metadata(database)?.len(),
+    });
+    fs::write(version_path(database), format!("{}\n", record))
+}
+
+const K: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+fn sha256_block(state: &mut [u32; 8], block: &[u8]) {
+    let mut w = [0u32; 64];
+    for (i, word) in block.chunks(4).enumerate() {
+        w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+    }
+    for i in 16..64 {
+        let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+        let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+        w[i] = w[i - 16]
+            .wrapping_add(s0)
+            .wrapping_add(w[i - 7])
+            .wrapping_add(s1);
+    }
+    let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = *state;
+    for i in 0..64 {
+        let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+        let ch = (e & f) ^ (!e & g);
+        let t1 = h
+            .wrapping_add(s1)
+            .wrapping_add(ch)
+            .wrapping_add(K[i])
+            .wrapping_add(w[i]);
+        let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+        let maj = (a & b) ^ (a & c) ^ (b & c);
+        let t2 = s0.wrapping_add(maj);
+        h = g;
+        g = f;
+        f = e;
+        e = d.wrapping_add(t1);
+        d = c;
+        c = b;
+        b = a;
+        a = t1.wrapping_add(t2);
+    }
+    for (s, x) in state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+        *s = s.wrapping_add(x);
+    }
+}
+
+/// The SHA-256 of everything `reader` reads, in hex. Written out rather than taken from a crate
+/// since it's only needed here, on files read once.
+pub fn sha256<R: Read>(mut reader: R) -> io::Result<String> {
+    let mut state: [u32; 8] = [
+        0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab,
+        0x5be0cd19,
+    ];
+    let mut buffer = vec![0u8; 64 * 1024];
+    let mut pending: Vec<u8> = Vec::with_capacity(64);
+    let mut length: u64 = 0;
+    loop {
+        let n = reader.read(&mut buffer)?;
+        if n == 0 {
+            break;
+        }
+        length += n as u64;
+        let mut data = &buffer[..n];
+        if !pending.is_empty() {
+            let take = (64 - pending.len()).min(data.len());
+            pending.extend_from_slice(&data[..take]);
+            data = &data[take..];
+            if pending.len() == 64 {
+                sha256_block(&mut state, &pending);
+                pending.clear();
+            }
+        }
+        let mut blocks = data.chunks_exact(64);
+        for block in &mut blocks {
+            sha256_block(&mut state, block);
+        }
+        pending.extend_from_slice(blocks.remainder());
+    }
+    pending.push(0x80);
+    while pending.len() % 64 != 56 {
+        pending.push(0);
+    }
+    pending.extend_from_slice(&(length * 8).to_be_bytes());
+    for block in pending.chunks(64) {
+        sha256_block(&mut state, block);
+    }
+    Ok(state.iter().map(|s| format!("{:08x}", s)).collect())
+}
+
+/// The SHA-256 of the file at `path`, in hex.
+pub fn sha256_file(path: &Path) -> io::Result<String> {
+    sha256(BufReader::new(File::open(path)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Hands out what it reads a few bytes at a time, so that blocks span reads.
+    struct Trickle<'a>(&'a [u8]);
+
+    impl<'a> Read for Trickle<'a> {
+        fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+            let n = self.0.len().min(buffer.len()).min(7);
+            buffer[..n].copy_from_slice(&self.0[..n]);
+            self.0 = &self.0[n..];
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn sha256_known_answers() {
+        assert_eq!(
+            sha256(&b""[..]).unwrap(),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_eq!(
+            sha256(&b"abc"[..]).unwrap(),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(
+            sha256(&b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"[..]).unwrap(),
+            "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"
+        );
+    }
+
+    #[test]
+    fn sha256_over_many_reads() {
+        let million = vec![b'a'; 1_000_000];
+        let expected = "cdc76e5c9914fb9281a1c7e284d73e67f1809a48a497200e046d39ccc7112cd0";
+        // More than one buffer's worth, in reads that end in the middle of blocks.
+        assert_eq!(sha256(&million[..]).unwrap(), expected);
+        assert_eq!(sha256(Trickle(&million)).unwrap(), expected);
+    }
+
+    #[test]
+    fn join_url_relative_and_absolute() {
+        let base = "https://example.org/releases/manifest.json";
+        assert_eq!(
+            join_url(base, "define3.sqlite3.zst"),
+            "https://example.org/releases/define3.sqlite3.zst"
+        );
+        assert_eq!(
+            join_url(base, "/define3.sqlite3.zst"),
+            "https://example.org/releases/define3.sqlite3.zst"
+        );
+        assert_eq!(
+            join_url(base, "https://mirror.example.com/define3.sqlite3"),
+            "https://mirror.example.com/define3.sqlite3"
+        );
+        assert_eq!(
+            join_url("manifest.json", "define3.sqlite3"),
+            "define3.sqlite3"
+        );
+    }
+
+    const SHA256: &str = "BA7816BF8F01CFEA414140DE5DAE2223B00361A396177A9CB410FF61F20015AD";
+
+    #[test]
+    fn from_manifest() {
+        let manifest = format!(
+            r#"{{"version": "2026-10-01", "url": "db.sqlite3.zst", "sha256": "{}", "size": 3}}"#,
+            SHA256
+        );
+        let release =
+            Release::from_manifest("https://example.org/latest/manifest.json", &manifest).unwrap();
+        assert_eq!(
+            release,
+            Release {
+                version: "2026-10-01".to_owned(),
+                url: "https://example.org/latest/db.sqlite3.zst".to_owned(),
+                sha256: SHA256.to_lowercase(),
+                size: Some(3),
+            }
+        );
+        assert!(release.compressed());
+    }
+
+    #[test]
+    fn from_manifest_without_size() {
+        let manifest = format!(
+            r#"{{"version": "1", "url": "db.sqlite3", "sha256": "{}"}}"#,
+            SHA256
+        );
+        let release = Release::from_manifest("m.json", &manifest).unwrap();
+        assert_eq!(release.size, None);
+        assert!(!release.compressed());
+    }
+
+    #[test]
+    fn from_manifest_errors() {
+        let bad = [
+            "not json".to_owned(),
+            format!(r#"{{"url": "db.sqlite3", "sha256": "{}"}}"#, SHA256),
+            r#"{"version": "1", "url": "db.sqlite3", "sha256": "abc"}"#.to_owned(),
+            format!(
+                r#"{{"version": "1", "url": "db", "sha256": "g{}"}}"#,
+                &SHA256[1..]
+            ),
+        ];
+        for manifest in &bad {
+            assert!(
+                Release::from_manifest("m.json", manifest).is_err(),
+                "{}",
+                manifest
+            );
+        }
+    }
+
+    #[test]
+    fn download_skips_a_complete_file_without_a_size() {
+        let path = std::env::temp_dir().join(format!("define3-test-{}.part", std::process::id()));
+        fs::write(&path, b"abc").unwrap();
+        let release = Release {
+            version: "1".to_owned(),
+            // Nothing is fetched, so the URL is never used.
+            url: "http://localhost:9/db.sqlite3".to_owned(),
+            sha256: SHA256.to_lowercase(),
+            size: None,
+        };
+        let downloaded = release.download(&path);
+        let _ = fs::remove_file(&path);
+        assert_eq!(downloaded, Ok(()));
+    }
+}
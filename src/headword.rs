@@ -0,0 +1,416 @@
+//! The grammar that a headword template, on the line under a part of speech heading, gives the
+//! word: `{{en-noun|geese}}` for a plural, `{{en-adj|er}}` for a comparative, `{{fr-noun|m}}` for
+//! a gender, and so on. Only the common templates are understood, and those only in their common
+//! forms; anything else is left out rather than guessed at.
+
+use parse_wikitext::plain_text;
+use templates::{parse_templates, Node, Template};
+
+// Headword templates are `head` or named after the language code and part of speech, as in
+// `en-noun` or `de-verb-strong`.
+fn is_headword_template(name: &str) -> bool {
+    match name.split_once('-') {
+        Some((code, rest)) => {
+            (2..=3).contains(&code.len())
+                && code.bytes().all(|b| b.is_ascii_lowercase())
+                && !rest.is_empty()
+        }
+        None => name == "head",
+    }
+}
+
+/// The headword template on a line, as written, if it has one.
+pub fn headword_template(line: &str) -> Option<&str> {
+    parse_templates(line)
+        .into_iter()
+        .find_map(|node| match node {
+            Node::Template(template) if is_headword_template(&plain_text(&template.name)) => {
+                Some(template.raw)
+            }
+            _ => None,
+        })
+}
+
+fn is_vowel(c: char) -> bool {
+    "aeiou".contains(c)
+}
+
+// Whether `word` ends in a consonant followed by a y, as in "fly" but not "day".
+fn consonant_y(word: &str) -> bool {
+    let mut chars = word.chars().rev();
+    chars.next() == Some('y') && chars.next().is_some_and(|c| !is_vowel(c))
+}
+
+// The regular English plural, or third-person singular of a verb.
+fn english_s(word: &str) -> String {
+    if consonant_y(word) {
+        format!("{}ies", &word[..word.len() - 1])
+    } else if ["s", "x", "z", "ch", "sh"]
+        .iter()
+        .any(|end| word.ends_with(end))
+    {
+        format!("{}es", word)
+    } else {
+        format!("{}s", word)
+    }
+}
+
+// `word` with `ending` ("ed", "er", "est", "ing") added the regular way.
+fn english_suffix(word: &str, ending: &str) -> String {
+    if ending != "ing" && consonant_y(word) {
+        format!("{}i{}", &word[..word.len() - 1], ending)
+    } else if ending == "ing" && word.ends_with("ie") {
+        format!("{}ying", &word[..word.len() - 2])
+    } else if word.ends_with('e') && !word.ends_with("ee") && !word.ends_with("ye") {
+        format!("{}{}", &word[..word.len() - 1], ending)
+    } else {
+        format!("{}{}", word, ending)
+    }
+}
+
+// `word` with its last letter doubled before `ending`, as in "catting" and "catted".
+fn doubled(word: &str, ending: &str) -> String {
+    match word.chars().last() {
+        Some(last) => format!("{}{}{}", word, last, ending),
+        None => ending.to_owned(),
+    }
+}
+
+fn field(label: &str, value: &str) -> (String, String) {
+    (label.to_owned(), value.to_owned())
+}
+
+fn en_noun(args: &[String], word: &str) -> Vec<(String, String)> {
+    let mut fields = Vec::new();
+    let mut plurals: Vec<String> = Vec::new();
+    for arg in args {
+        match arg.as_str() {
+            "-" => fields.push(field("uncountable", "")),
+            "~" => {
+                fields.push(field("countable and uncountable", ""));
+                plurals.push(english_s(word));
+            }
+            "!" => fields.push(field("plural not attested", "")),
+            "?" => (),
+            "" | "+" => plurals.push(english_s(word)),
+            "s" | "es" => plurals.push(format!("{}{}", word, arg)),
+            _ => plurals.push(arg.clone()),
+        }
+    }
+    if args.is_empty() {
+        plurals.push(english_s(word));
+    }
+    plurals.dedup();
+    if !plurals.is_empty() {
+        fields.push(field("plural", &plurals.join(" or ")));
+    }
+    fields
+}
+
+fn en_verb(args: &[String], word: &str) -> Vec<(String, String)> {
+    let doubles = args.iter().any(|arg| arg == "++");
+    let default = |ending: &str| {
+        if doubles && ending != "s" {
+            doubled(word, ending)
+        } else if ending == "s" {
+            english_s(word)
+        } else {
+            english_suffix(word, ending)
+        }
+    };
+    // Third-person singular, present participle, simple past and past participle, in order.
+    let form = |i: usize, ending: &str| match args.get(i).map(String::as_str) {
+        None | Some("") | Some("+") | Some("++") => default(ending),
+        Some(form) => form.to_owned(),
+    };
+    let past = form(2, "ed");
+    let participle = match args.get(3).map(String::as_str) {
+        None | Some("") | Some("+") => past.clone(),
+        _ => form(3, "ed"),
+    };
+    let mut fields = vec![
+        field("third-person singular simple present", &form(0, "s")),
+        field("present participle", &form(1, "ing")),
+    ];
+    if participle == past {
+        fields.push(field("simple past and past participle", &past));
+    } else {
+        fields.push(field("simple past", &past));
+        fields.push(field("past participle", &participle));
+    }
+    fields
+}
+
+fn en_comparable(args: &[String], word: &str, template: &Template) -> Vec<(String, String)> {
+    let superlative = named(template, &["sup"]);
+    match args.first().map(String::as_str) {
+        Some("-") => vec![field("not comparable", "")],
+        Some("er") => vec![
+            field("comparative", &english_suffix(word, "er")),
+            field("superlative", &english_suffix(word, "est")),
+        ],
+        None | Some("") | Some("more") | Some("+") => vec![
+            field("comparative", &format!("more {}", word)),
+            field("superlative", &format!("most {}", word)),
+        ],
+        Some(comparative) => {
+            let superlative = superlative.or_else(|| {
+                comparative
+                    .strip_prefix("more ")
+                    .map(|rest| format!("most {}", rest))
+                    .or_else(|| {
+                        comparative
+                            .strip_suffix("er")
+                            .map(|stem| format!("{}est", stem))
+                    })
+            });
+            let mut fields = vec![field("comparative", comparative)];
+            if let Some(superlative) = superlative {
+                fields.push(field("superlative", &superlative));
+            }
+            fields
+        }
+    }
+}
+
+// Gender codes, as in `{{fr-noun|m}}` or `g=f`: m, f, n or c, maybe with a number, e.g. "m-p".
+fn is_gender(arg: &str) -> bool {
+    let (gender, number) = arg.split_once('-').unwrap_or((arg, ""));
+    ["m", "f", "n", "c", "mf", "m-f"].contains(&gender) && ["", "s", "p"].contains(&number)
+        || arg == "p"
+}
+
+fn named(template: &Template, keys: &[&str]) -> Option<String> {
+    template
+        .named()
+        .find(|(key, _)| keys.contains(&key.trim()))
+        .map(|(_, value)| plain_text(value))
+        .filter(|value| !value.is_empty())
+}
+
+// `{{de-noun|f,,n}}`: gender, genitive and plural, separated by commas. The genitive and plural
+// can be given whole or as an ending, or "-" for none.
+fn de_noun(spec: &str, word: &str) -> Vec<(String, String)> {
+    let mut parts = spec.split(',').map(str::trim);
+    let mut fields = Vec::new();
+    if let Some(gender) = parts.next().filter(|gender| is_gender(gender)) {
+        fields.push(field("gender", gender));
+    }
+    for label in ["genitive", "plural"] {
+        match parts.next() {
+            None | Some("") | Some("-") => (),
+            Some(form) if form.starts_with(char::is_uppercase) => fields.push(field(label, form)),
+            Some(ending) => fields.push(field(label, &format!("{}{}", word, ending))),
+        }
+    }
+    fields
+}
+
+/// (label, form) pairs for what `template` says about `word`, e.g. ("plural", "geese"). Labels
+/// that say all there is to say, like "uncountable", come with an empty form.
+pub fn grammar(template: &str, word: &str) -> Vec<(String, String)> {
+    let nodes = parse_templates(template);
+    let template = match nodes.first() {
+        Some(Node::Template(template)) => template,
+        _ => return Vec::new(),
+    };
+    let name = plain_text(&template.name);
+    let args: Vec<String> = template.positional().map(plain_text).collect();
+    let mut fields = match name.as_str() {
+        "en-noun" => en_noun(&args, word),
+        "en-verb" => en_verb(&args, word),
+        "en-adj" | "en-adv" => en_comparable(&args, word, template),
+        "de-noun" => args
+            .first()
+            .map_or_else(Vec::new, |spec| de_noun(spec, word)),
+        // {{head|en|noun|plural|geese}}: labels and forms in pairs after the part of speech.
+        "head" => args
+            .get(2..)
+            .unwrap_or(&[])
+            .chunks(2)
+            .map(|pair| field(&pair[0], pair.get(1).map_or("", String::as_str)))
+            .filter(|(label, _)| !label.is_empty())
+            .collect(),
+        _ => {
+            let mut fields = Vec::new();
+            if let Some(gender) = args.first().filter(|arg| is_gender(arg)) {
+                fields.push(field("gender", gender));
+            }
+            match args.first().map(String::as_str) {
+                Some(class @ "weak")
+                | Some(class @ "strong")
+                | Some(class @ "irregular")
+                | Some(class @ "mixed") => fields.push(field("conjugation", class)),
+                _ => (),
+            }
+            if let Some(class) = name.strip_prefix("de-verb-") {
+                fields.push(field("conjugation", class));
+            }
+            fields
+        }
+    };
+    for key in ["g", "g2", "g3"] {
+        if let Some(gender) = named(template, &[key]).filter(|gender| is_gender(gender)) {
+            fields.push(field("gender", &gender));
+        }
+    }
+    let named_fields = [
+        ("plural", &["pl", "pl2"][..]),
+        ("feminine", &["f", "f2"][..]),
+        ("masculine", &["m", "m2"][..]),
+        ("class", &["class"][..]),
+    ];
+    for (label, keys) in named_fields {
+        if fields.iter().any(|(existing, _)| existing == label) {
+            continue;
+        }
+        for &key in keys {
+            if let Some(value) = named(template, &[key]) {
+                fields.push(field(label, &value));
+            }
+        }
+    }
+    fields
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The fields as "label: form", or just the label when there's no form.
+    fn fields(template: &str, word: &str) -> Vec<String> {
+        grammar(template, word)
+            .into_iter()
+            .map(|(label, form)| {
+                if form.is_empty() {
+                    label
+                } else {
+                    format!("{}: {}", label, form)
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn finds_headword_templates() {
+        assert_eq!(
+            headword_template("{{en-noun|geese}}"),
+            Some("{{en-noun|geese}}")
+        );
+        assert_eq!(
+            headword_template("{{head|en|noun}} stuff"),
+            Some("{{head|en|noun}}")
+        );
+        assert_eq!(
+            headword_template("{{also|cat}} {{en-noun}}"),
+            Some("{{en-noun}}")
+        );
+        assert_eq!(
+            headword_template("{{de-verb-weak}}"),
+            Some("{{de-verb-weak}}")
+        );
+        assert_eq!(headword_template("# {{lb|en|informal}} A cat."), None);
+        assert_eq!(headword_template("{{ENG-noun}}"), None);
+        assert_eq!(headword_template("no templates"), None);
+    }
+
+    #[test]
+    fn english_nouns() {
+        assert_eq!(fields("{{en-noun}}", "cat"), ["plural: cats"]);
+        assert_eq!(fields("{{en-noun}}", "fly"), ["plural: flies"]);
+        assert_eq!(fields("{{en-noun}}", "church"), ["plural: churches"]);
+        assert_eq!(fields("{{en-noun|es}}", "box"), ["plural: boxes"]);
+        assert_eq!(fields("{{en-noun|geese}}", "goose"), ["plural: geese"]);
+        assert_eq!(fields("{{en-noun|-}}", "water"), ["uncountable"]);
+        assert_eq!(
+            fields("{{en-noun|~}}", "coffee"),
+            ["countable and uncountable", "plural: coffees"]
+        );
+    }
+
+    #[test]
+    fn english_verbs() {
+        assert_eq!(
+            fields("{{en-verb}}", "cry"),
+            [
+                "third-person singular simple present: cries",
+                "present participle: crying",
+                "simple past and past participle: cried",
+            ]
+        );
+        assert_eq!(
+            fields("{{en-verb}}", "bake")[1],
+            "present participle: baking"
+        );
+        assert_eq!(fields("{{en-verb}}", "die")[1], "present participle: dying");
+        assert_eq!(
+            fields("{{en-verb|++}}", "cat")[1],
+            "present participle: catting"
+        );
+        assert_eq!(
+            fields("{{en-verb|goes|going|went|gone}}", "go")[2..],
+            ["simple past: went", "past participle: gone"]
+        );
+    }
+
+    #[test]
+    fn english_comparisons() {
+        assert_eq!(
+            fields("{{en-adj}}", "beautiful"),
+            ["comparative: more beautiful", "superlative: most beautiful"]
+        );
+        assert_eq!(
+            fields("{{en-adj|er}}", "happy"),
+            ["comparative: happier", "superlative: happiest"]
+        );
+        assert_eq!(
+            fields("{{en-adj|better|sup=best}}", "good"),
+            ["comparative: better", "superlative: best"]
+        );
+        assert_eq!(
+            fields("{{en-adj|bigger}}", "big"),
+            ["comparative: bigger", "superlative: biggest"]
+        );
+        assert_eq!(fields("{{en-adj|-}}", "dead"), ["not comparable"]);
+    }
+
+    #[test]
+    fn other_languages() {
+        assert_eq!(
+            fields("{{de-noun|m,s,e}}", "Hund"),
+            ["gender: m", "genitive: Hunds", "plural: Hunde"]
+        );
+        assert_eq!(
+            fields("{{de-noun|n,Hauses,Häuser}}", "Haus"),
+            ["gender: n", "genitive: Hauses", "plural: Häuser"]
+        );
+        assert_eq!(
+            fields("{{de-noun|f,,n}}", "Katze"),
+            ["gender: f", "plural: Katzen"]
+        );
+        assert_eq!(
+            fields("{{de-verb-strong}}", "gehen"),
+            ["conjugation: strong"]
+        );
+        assert_eq!(
+            fields("{{fr-noun|m|f=chatte}}", "chat"),
+            ["gender: m", "feminine: chatte"]
+        );
+        assert_eq!(
+            fields("{{it-noun|g=m|pl=gatti}}", "gatto"),
+            ["gender: m", "plural: gatti"]
+        );
+        assert_eq!(
+            fields("{{head|en|noun|plural|geese}}", "goose"),
+            ["plural: geese"]
+        );
+    }
+
+    // Templates it doesn't know say nothing, rather than something guessed.
+    #[test]
+    fn unknown_templates() {
+        assert!(fields("{{xx-noun|wat}}", "x").is_empty());
+        assert!(fields("not a template", "x").is_empty());
+        assert!(fields("", "x").is_empty());
+    }
+}
@@ -0,0 +1,165 @@
+//! A log of the words looked up, for learners who track their progress. It's kept in its own
+//! database, so that rebuilding the dictionary doesn't lose it.
+
+use std::fmt::Write;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use rusqlite::{Connection, Result};
+
+use memory::limit_memory;
+
+pub struct History {
+    conn: Connection,
+}
+
+/// A summary of the history; see `History::stats`.
+#[derive(Debug, Default)]
+pub struct StudyStats {
+    pub lookups: i64,
+    /// (date, lookups) pairs in local time, oldest first.
+    pub lookups_by_day: Vec<(String, i64)>,
+    /// (word, lookups) pairs, most looked up first.
+    pub top_words: Vec<(String, i64)>,
+    /// (language, lookups, distinct words) for every language a looked-up word was found in,
+    /// most looked up first.
+    pub languages: Vec<(String, i64, i64)>,
+}
+
+impl History {
+    /// Opens the history at `path`, creating it if there isn't one yet.
+    pub fn open(path: &Path) -> Result<History> {
+        let conn = Connection::open(path)?;
+        limit_memory(&conn)?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS lookups (
+                 id             integer primary key,
+                 word           text not null,
+                 looked_up_at   integer not null
+             );
+             CREATE TABLE IF NOT EXISTS lookup_languages (
+                 lookup_id      integer not null references lookups (id),
+                 language       text not null
+             );",
+        )?;
+        Ok(History { conn })
+    }
+
+    /// Records a lookup of `word` that found it in `languages`.
+    pub fn record(&self, word: &str, languages: &[&str]) -> Result<()> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_secs() as i64);
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute(
+            "INSERT INTO lookups (word, looked_up_at) VALUES (?1, ?2)",
+            rusqlite::params![word, now],
+        )?;
+        let id = tx.last_insert_rowid();
+        for language in languages {
+            tx.execute(
+                "INSERT INTO lookup_languages (lookup_id, language) VALUES (?1, ?2)",
+                rusqlite::params![id, language],
+            )?;
+        }
+        tx.commit()
+    }
+
+    /// The words looked up since `since` (seconds since 1970), with how many times each was,
+    /// most looked up first.
+    pub fn words_since(&self, since: i64) -> Result<Vec<(String, i64)>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT word, COUNT(*) AS count FROM lookups WHERE looked_up_at >= ?1
+             GROUP BY word ORDER BY count DESC, word",
+        )?;
+        let words = stmt
+            .query_map([since], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect();
+        words
+    }
+
+    /// Summarizes the history, listing the `top` most looked-up words.
+    pub fn stats(&self, top: usize) -> Result<StudyStats> {
+        let lookups = self
+            .conn
+            .query_row("SELECT COUNT(*) FROM lookups", [], |row| row.get(0))?;
+        let mut stmt = self.conn.prepare(
+            "SELECT date(looked_up_at, 'unixepoch', 'localtime') AS day, COUNT(*) FROM lookups
+             GROUP BY day ORDER BY day",
+        )?;
+        let lookups_by_day = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<_>>()?;
+        let mut stmt = self.conn.prepare(
+            "SELECT word, COUNT(*) AS count FROM lookups
+             GROUP BY word ORDER BY count DESC, word LIMIT ?1",
+        )?;
+        let top_words = stmt
+            .query_map([top as i64], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<_>>()?;
+        let mut stmt = self.conn.prepare(
+            "SELECT language, COUNT(*) AS count, COUNT(DISTINCT word) FROM lookup_languages
+             JOIN lookups ON lookups.id = lookup_id
+             GROUP BY language ORDER BY count DESC, language",
+        )?;
+        let languages = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+            .collect::<Result<_>>()?;
+        Ok(StudyStats {
+            lookups,
+            lookups_by_day,
+            top_words,
+            languages,
+        })
+    }
+}
+
+// Quotes a CSV field if it needs it.
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_owned()
+    }
+}
+
+impl StudyStats {
+    pub fn to_json(&self) -> String {
+        let stats = serde_json::json!({
+            "lookups": self.lookups,
+            "lookups_by_day": self.lookups_by_day.iter().map(|(day, lookups)| {
+                serde_json::json!({"date": day, "lookups": lookups})
+            }).collect::<Vec<_>>(),
+            "top_words": self.top_words.iter().map(|(word, lookups)| {
+                serde_json::json!({"word": word, "lookups": lookups})
+            }).collect::<Vec<_>>(),
+            "languages": self.languages.iter().map(|(language, lookups, words)| {
+                serde_json::json!({"language": language, "lookups": lookups, "words": words})
+            }).collect::<Vec<_>>(),
+        });
+        serde_json::to_string_pretty(&stats).unwrap()
+    }
+
+    /// One table for everything, with the kind of row in the first column: `day`, `word` or
+    /// `language`. Only languages have a word count.
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("kind,name,lookups,words\n");
+        for (day, lookups) in &self.lookups_by_day {
+            writeln!(csv, "day,{},{},", day, lookups).unwrap();
+        }
+        for (word, lookups) in &self.top_words {
+            writeln!(csv, "word,{},{},", csv_field(word), lookups).unwrap();
+        }
+        for (language, lookups, words) in &self.languages {
+            writeln!(
+                csv,
+                "language,{},{},{}",
+                csv_field(language),
+                lookups,
+                words
+            )
+            .unwrap();
+        }
+        csv
+    }
+}
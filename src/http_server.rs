@@ -0,0 +1,847 @@
+//! An HTTP API for the dictionary, to back web frontends and chat bots. It's a thread per
+//! connection on std::net rather than anything async: lookups are quick, and this way the server
+//! needs nothing beyond what define3 already depends on.
+//!
+//! | Route            | What it returns                                                    |
+//! |------------------|--------------------------------------------------------------------|
+//! | `/define/{word}` | the entry, as JSON like `define --json` or as HTML                 |
+//! | `/search`        | a page of matching headwords, as JSON                              |
+//! | `/embed/{word}`  | an HTML snippet of the entry, for embedding                        |
+//! | `/oembed`        | oEmbed JSON for an `/embed` or `/define` URL                       |
+//! | `/feed.xml`      | an Atom feed of the words of the day                               |
+//! | `/openapi.json`  | the OpenAPI document; only built with `--features openapi`         |
+
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use checksum::{entry_checksum, etag, if_none_match};
+use dictionary::Dictionary;
+use embed::{embed_html, meanings_html, oembed_json};
+use expand::TemplatePolicies;
+use feed::{atom_feed, today};
+use json::entry_json;
+use languages::normalize_language;
+use lookup::{group_by_language, SearchOptions};
+use pagination::Cursor;
+use permalink::{local_url, percent_decode};
+use query::{
+    contains_pattern, escape_glob, CancelToken, PartialSearchLimits, Query, QueryError,
+    QueryRequest, QueryResponse,
+};
+use render::{escape_html, Formatters};
+use reverse::SEARCH_TABLE;
+
+/// Where the server listens unless told otherwise.
+pub const DEFAULT_ADDRESS: &str = "127.0.0.1:8080";
+
+// How many headwords /search returns unless asked for fewer, and the most it returns at once.
+const DEFAULT_SEARCH_LIMIT: usize = 50;
+const MAX_SEARCH_LIMIT: usize = 500;
+
+// How many similar words a /define that finds nothing suggests.
+const SUGGESTIONS: usize = 5;
+
+// How many days of words of the day /feed.xml has.
+const FEED_DAYS: usize = 7;
+
+// Requests with more than this in their request line and headers are refused.
+const MAX_REQUEST_HEAD: u64 = 16 * 1024;
+
+// How long a client has to send its request line and headers, all told, and how long writing the
+// response may block.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+const WRITE_TIMEOUT: Duration = Duration::from_secs(30);
+
+// How many connections are handled at once. Any more are answered with a 503 and closed.
+const MAX_CONNECTIONS: usize = 64;
+
+pub(crate) struct Request {
+    method: String,
+    path: String,
+    query: Vec<(String, String)>,
+    /// With lowercased names.
+    headers: Vec<(String, String)>,
+}
+
+impl Request {
+    fn param(&self, name: &str) -> Option<&str> {
+        self.query
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+// Reads from a stream until a deadline, however slowly the other end sends: each read may only
+// wait for what's left of the time.
+struct Deadline<'s> {
+    stream: &'s TcpStream,
+    deadline: Instant,
+}
+
+impl<'s> Read for Deadline<'s> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let left = self.deadline.saturating_duration_since(Instant::now());
+        if left == Duration::ZERO {
+            return Err(io::ErrorKind::TimedOut.into());
+        }
+        self.stream.set_read_timeout(Some(left))?;
+        let mut stream = self.stream;
+        stream.read(buf)
+    }
+}
+
+// The request line and headers; bodies are never needed, since everything is a GET. None if the
+// client hung up, took longer than `timeout`, or sent something that isn't HTTP.
+fn read_request(stream: &TcpStream, timeout: Duration) -> Option<Request> {
+    let deadline = Deadline {
+        stream,
+        deadline: Instant::now() + timeout,
+    };
+    let mut reader = BufReader::new(deadline.take(MAX_REQUEST_HEAD));
+    let mut line = String::new();
+    reader.read_line(&mut line).ok()?;
+    let mut parts = line.split_whitespace();
+    let method = parts.next()?.to_owned();
+    let target = parts.next()?;
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let path = path.to_owned();
+    let query = query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (percent_decode(key, true), percent_decode(value, true))
+        })
+        .collect();
+    let mut headers = Vec::new();
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line).ok()? == 0 {
+            return None;
+        }
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            headers.push((name.trim().to_lowercase(), value.trim().to_owned()));
+        }
+    }
+    Some(Request {
+        method,
+        path,
+        query,
+        headers,
+    })
+}
+
+pub(crate) struct Response {
+    status: u16,
+    content_type: &'static str,
+    headers: Vec<(&'static str, String)>,
+    body: String,
+}
+
+impl Response {
+    fn new(status: u16, content_type: &'static str, body: String) -> Response {
+        Response {
+            status,
+            content_type,
+            headers: Vec::new(),
+            body,
+        }
+    }
+
+    fn json(status: u16, value: &serde_json::Value) -> Response {
+        let body = serde_json::to_string_pretty(value).unwrap();
+        Response::new(status, "application/json", body)
+    }
+
+    fn error(status: u16, message: &str) -> Response {
+        Response::json(status, &serde_json::json!({ "error": message }))
+    }
+
+    fn write_to(&self, stream: &mut TcpStream, head_only: bool) -> io::Result<()> {
+        let reason = match self.status {
+            200 => "OK",
+            304 => "Not Modified",
+            400 => "Bad Request",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            500 => "Internal Server Error",
+            501 => "Not Implemented",
+            _ => "Service Unavailable",
+        };
+        let mut head = format!(
+            "HTTP/1.1 {} {}\r\nContent-Type: {}; charset=utf-8\r\nContent-Length: {}\r\n\
+             Access-Control-Allow-Origin: *\r\nConnection: close\r\n",
+            self.status,
+            reason,
+            self.content_type,
+            self.body.len()
+        );
+        for (name, value) in &self.headers {
+            head.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        head.push_str("\r\n");
+        stream.write_all(head.as_bytes())?;
+        if !head_only && self.status != 304 {
+            stream.write_all(self.body.as_bytes())?;
+        }
+        stream.flush()
+    }
+}
+
+pub(crate) struct Server {
+    dictionary: Dictionary,
+    base_url: String,
+    cancel: CancelToken,
+    policies: TemplatePolicies,
+    formatters: Formatters,
+}
+
+// Counts a connection as open for as long as it's alive.
+struct OpenConnection(Arc<AtomicUsize>);
+
+impl Drop for OpenConnection {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Answers HTTP requests on `listener` until it fails, with the database at `path`. `base_url`
+/// is where clients reach the server, for the links in responses. Each connection gets its own
+/// thread and its own connection to the database, up to `MAX_CONNECTIONS` at a time.
+pub fn serve(listener: TcpListener, path: &Path, base_url: &str) -> io::Result<()> {
+    let open = Arc::new(AtomicUsize::new(0));
+    for stream in listener.incoming() {
+        let mut stream = stream?;
+        stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+        // Answered without reading the request, which would mean waiting for it. The 503 goes
+        // before the end of the stream, which goes before the reset that closing a socket with
+        // unread data sends.
+        if open.load(Ordering::SeqCst) >= MAX_CONNECTIONS {
+            let response = Response::error(503, "too many connections; try again later");
+            let _ = response.write_to(&mut stream, false);
+            let _ = stream.shutdown(Shutdown::Write);
+            continue;
+        }
+        open.fetch_add(1, Ordering::SeqCst);
+        let connection = OpenConnection(open.clone());
+        let path: PathBuf = path.to_owned();
+        let base_url = base_url.trim_end_matches('/').to_owned();
+        thread::spawn(move || {
+            let _connection = connection;
+            let peer = stream.peer_addr().ok();
+            if let Err(e) = handle(stream, &path, base_url) {
+                eprintln!("HTTP connection from {:?}: {}", peer, e);
+            }
+        });
+    }
+    Ok(())
+}
+
+fn handle(mut stream: TcpStream, path: &Path, base_url: String) -> io::Result<()> {
+    let request = match read_request(&stream, REQUEST_TIMEOUT) {
+        Some(request) => request,
+        None => return Ok(()),
+    };
+    let head_only = request.method == "HEAD";
+    if request.method != "GET" && !head_only {
+        let mut response = Response::error(405, "only GET and HEAD are supported");
+        response.headers.push(("Allow", "GET, HEAD".to_owned()));
+        return response.write_to(&mut stream, false);
+    }
+    let dictionary = match Dictionary::open(path) {
+        Ok(dictionary) => dictionary,
+        Err(e) => {
+            Response::error(503, "the dictionary can't be opened").write_to(&mut stream, false)?;
+            return Err(io::Error::other(e));
+        }
+    };
+    let server = Server {
+        dictionary,
+        base_url,
+        cancel: CancelToken::new(),
+        policies: TemplatePolicies::default(),
+        formatters: Formatters::default(),
+    };
+
+    // The client has nothing more to send, so anything showing up on the socket means it hung
+    // up, and whatever it asked for can be cancelled. Shutting the socket down once the response
+    // is written ends the watch. The socket still has the request's read timeout, which only
+    // means the client is waiting.
+    let watched = stream.try_clone()?;
+    let cancel = server.cancel.clone();
+    thread::spawn(move || loop {
+        match watched.peek(&mut [0]) {
+            Err(ref e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) =>
+            {
+                continue
+            }
+            Ok(0) | Err(_) => return cancel.cancel(),
+            Ok(_) => return,
+        }
+    });
+
+    let response = match route(&server, &request) {
+        Ok(response) => response,
+        Err(QueryError::Cancelled) => return Ok(()),
+        Err(QueryError::Sqlite(e)) => {
+            eprintln!("HTTP {}: {}", request.path, e);
+            Response::error(500, "the query failed")
+        }
+    };
+    let written = response.write_to(&mut stream, head_only);
+    let _ = stream.shutdown(Shutdown::Both);
+    written
+}
+
+fn route(server: &Server, request: &Request) -> Result<Response, QueryError> {
+    let path = request.path.as_str();
+    if let Some(word) = path.strip_prefix("/define/") {
+        return define(server, request, &percent_decode(word, false));
+    }
+    if let Some(word) = path.strip_prefix("/embed/") {
+        return embed(server, &percent_decode(word, false));
+    }
+    match path {
+        "/search" => search(server, request),
+        "/oembed" => Ok(oembed(server, request)),
+        "/feed.xml" => feed(server, request),
+        #[cfg(feature = "openapi")]
+        "/openapi.json" => Ok(Response::new(
+            200,
+            "application/json",
+            ::openapi::openapi_json(),
+        )),
+        _ => Ok(Response::error(404, "no such endpoint")),
+    }
+}
+
+/// A word's entry, as JSON like `define --json` (with a link to each language's section) or as
+/// an HTML page. Responses carry the entry's checksum as an ETag.
+#[cfg_attr(feature = "openapi", utoipa::path(
+    get,
+    path = "/define/{word}",
+    params(
+        ("word" = String, Path, description = "The word to look up"),
+        ("lang" = Option<String>, Query, description = "Only this language, by name or code"),
+        ("edition" = Option<String>, Query, description = "Only this Wiktionary edition"),
+        ("format" = Option<String>, Query, description = "json (the default) or html"),
+    ),
+    responses(
+        (status = 200, description = "The entry", body = ::openapi::Entry),
+        (status = 304, description = "The entry hasn't changed since the ETag sent"),
+        (status = 400, description = "A bad format", body = ::openapi::ErrorResponse),
+        (status = 404, description = "Not found; JSON responses suggest similar words",
+         body = ::openapi::Entry),
+    )
+))]
+pub(crate) fn define(
+    server: &Server,
+    request: &Request,
+    word: &str,
+) -> Result<Response, QueryError> {
+    let format = request.param("format").unwrap_or("json");
+    if format != "json" && format != "html" {
+        return Ok(Response::error(400, "format must be json or html"));
+    }
+    let options = SearchOptions {
+        language: request.param("lang").map(normalize_language),
+        edition: request.param("edition").map(str::to_owned),
+        ..SearchOptions::default()
+    };
+    let found = server
+        .dictionary
+        .lookup_cancellable(word, &options, &server.cancel)?;
+
+    let mut checksum_options = vec!["http".to_owned(), format!("format={}", format)];
+    checksum_options.extend(options.language.iter().map(|l| format!("language={}", l)));
+    checksum_options.extend(options.edition.iter().map(|e| format!("edition={}", e)));
+    let checksum_options: Vec<&str> = checksum_options.iter().map(String::as_str).collect();
+    let checksum = server
+        .dictionary
+        .build_id()?
+        .map(|build_id| entry_checksum(&found.headword, &build_id, &checksum_options));
+    if let (Some(checksum), Some(header)) = (&checksum, request.header("if-none-match")) {
+        if if_none_match(header, checksum) {
+            let mut response = Response::new(304, "application/json", String::new());
+            response.headers.push(("ETag", etag(checksum)));
+            return Ok(response);
+        }
+    }
+
+    let status = if found.meanings.is_empty() { 404 } else { 200 };
+    let suggestions = if found.meanings.is_empty() {
+        server.dictionary.suggestions(word, SUGGESTIONS)?
+    } else {
+        Vec::new()
+    };
+    let mut response = if format == "html" {
+        let mut html = format!(
+            "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><title>{0}</title></head>\
+             <body><h1>{0}</h1>",
+            escape_html(&found.headword)
+        );
+        if found.meanings.is_empty() {
+            html.push_str("<p>No results found.</p>");
+        }
+        html.push_str(&meanings_html(
+            &found.headword,
+            &found.meanings,
+            &server.policies,
+            &server.formatters,
+            false,
+        ));
+        if !suggestions.is_empty() {
+            let links: Vec<String> = suggestions
+                .iter()
+                .map(|suggestion| {
+                    format!(
+                        "<a href=\"{}\">{}</a>",
+                        escape_html(&local_url(&server.base_url, suggestion, "")),
+                        escape_html(suggestion)
+                    )
+                })
+                .collect();
+            html.push_str(&format!("<p>Did you mean: {}?</p>", links.join(", ")));
+        }
+        html.push_str("</body></html>");
+        Response::new(status, "text/html", html)
+    } else {
+        let langs = group_by_language(found.meanings);
+        let mut entry = entry_json(
+            word,
+            &found.headword,
+            &langs,
+            &server.policies,
+            &server.dictionary,
+            &server.formatters,
+            checksum.as_deref(),
+        );
+        if let Some(languages) = entry["languages"].as_array_mut() {
+            for language in languages {
+                let url = local_url(
+                    &server.base_url,
+                    &found.headword,
+                    language["language"].as_str().unwrap_or_default(),
+                );
+                language["url"] = serde_json::json!(url);
+            }
+        }
+        if langs.is_empty() {
+            entry["suggestions"] = serde_json::json!(suggestions);
+        }
+        Response::json(status, &entry)
+    };
+    if let Some(ref checksum) = checksum {
+        response.headers.push(("ETag", etag(checksum)));
+    }
+    Ok(response)
+}
+
+/// Headwords matching `q`, a page at a time, or definitions matching it for a reverse search.
+#[cfg_attr(feature = "openapi", utoipa::path(
+    get,
+    path = "/search",
+    params(
+        ("q" = String, Query, description = "What to search for"),
+        ("mode" = Option<String>, Query, description = "prefix (the default), contains, glob \
+         (with * and ?), or reverse (words whose definitions match q)"),
+        ("limit" = Option<usize>, Query, description = "How many results to return, at most 500"),
+        ("cursor" = Option<String>, Query, description = "The next token from the previous \
+         page"),
+        ("lang" = Option<String>, Query, description = "For reverse searches, only this \
+         language"),
+    ),
+    responses(
+        (status = 200, description = "A page of headwords", body = ::pagination::ResultPage<String>),
+        (status = 400, description = "A missing or bad parameter",
+         body = ::openapi::ErrorResponse),
+    )
+))]
+pub(crate) fn search(server: &Server, request: &Request) -> Result<Response, QueryError> {
+    let q = match request.param("q") {
+        Some(q) if !q.trim().is_empty() => q,
+        _ => return Ok(Response::error(400, "q is required")),
+    };
+    let limit = match request.param("limit").map(str::parse::<usize>) {
+        None => DEFAULT_SEARCH_LIMIT,
+        Some(Ok(limit)) if (1..=MAX_SEARCH_LIMIT).contains(&limit) => limit,
+        Some(_) => {
+            let message = format!("limit must be a number from 1 to {}", MAX_SEARCH_LIMIT);
+            return Ok(Response::error(400, &message));
+        }
+    };
+    let after = match request.param("cursor").map(Cursor::decode) {
+        None => None,
+        Some(Some(cursor)) => Some(cursor),
+        Some(None) => return Ok(Response::error(400, "cursor isn't one this server made")),
+    };
+    let pattern = match request.param("mode").unwrap_or("prefix") {
+        "prefix" => format!("{}*", escape_glob(q)),
+        "glob" => q.to_owned(),
+        "contains" => match PartialSearchLimits::default().check(q) {
+            Ok(()) => contains_pattern(q),
+            Err(e) => return Ok(Response::error(400, &e)),
+        },
+        "reverse" => return reverse_search(server, request, q, after.as_ref(), limit),
+        _ => {
+            let message = "mode must be prefix, contains, glob or reverse";
+            return Ok(Response::error(400, message));
+        }
+    };
+    let request = QueryRequest::new(Query::Search {
+        pattern,
+        after,
+        limit,
+    });
+    let page = match server.dictionary.run(&request, &server.cancel)? {
+        QueryResponse::Headwords(page) => page,
+        response => unreachable!("{:?}", response),
+    };
+    Ok(Response::json(
+        200,
+        &serde_json::json!({
+            "items": page.items,
+            "next": page.next.map(|cursor| cursor.encode()),
+        }),
+    ))
+}
+
+// Reverse searches come best match first, paged like the others.
+fn reverse_search(
+    server: &Server,
+    request: &Request,
+    q: &str,
+    after: Option<&Cursor>,
+    limit: usize,
+) -> Result<Response, QueryError> {
+    if !server.dictionary.has_table(SEARCH_TABLE)? {
+        let message = "this database has no index for reverse searches";
+        return Ok(Response::error(501, message));
+    }
+    let language = request.param("lang").map(normalize_language);
+    let page = server
+        .dictionary
+        .reverse_search(q, language.as_deref(), after, limit)?;
+    let items: Vec<serde_json::Value> = page
+        .items
+        .iter()
+        .map(|(word, meaning)| {
+            serde_json::json!({
+                "word": word,
+                "language": meaning.language,
+                "part_of_speech": meaning.part_of_speech,
+                "definition": server.dictionary.expand(&meaning.definition),
+            })
+        })
+        .collect();
+    Ok(Response::json(
+        200,
+        &serde_json::json!({
+            "items": items,
+            "next": page.next.map(|cursor| cursor.encode()),
+        }),
+    ))
+}
+
+/// A self-contained HTML fragment of a word's entry, for framing in other pages.
+#[cfg_attr(feature = "openapi", utoipa::path(
+    get,
+    path = "/embed/{word}",
+    params(("word" = String, Path, description = "The word to look up")),
+    responses(
+        (status = 200, description = "The entry as HTML"),
+        (status = 404, description = "Not found"),
+    )
+))]
+pub(crate) fn embed(server: &Server, word: &str) -> Result<Response, QueryError> {
+    let found =
+        server
+            .dictionary
+            .lookup_cancellable(word, &SearchOptions::default(), &server.cancel)?;
+    let status = if found.meanings.is_empty() { 404 } else { 200 };
+    let html = embed_html(
+        &found.headword,
+        &found.meanings,
+        &server.policies,
+        &server.formatters,
+        false,
+    );
+    Ok(Response::new(status, "text/html", html))
+}
+
+/// The oEmbed response for a /define or /embed URL on this server.
+#[cfg_attr(feature = "openapi", utoipa::path(
+    get,
+    path = "/oembed",
+    params(
+        ("url" = String, Query, description = "A /define or /embed URL on this server"),
+        ("maxwidth" = Option<u32>, Query, description = "The most width the consumer has"),
+        ("maxheight" = Option<u32>, Query, description = "The most height the consumer has"),
+    ),
+    responses(
+        (status = 200, description = "An oEmbed rich response"),
+        (status = 404, description = "Not a URL for a word"),
+    )
+))]
+pub(crate) fn oembed(server: &Server, request: &Request) -> Response {
+    if request
+        .param("format")
+        .is_some_and(|format| format != "json")
+    {
+        return Response::error(501, "only JSON is supported");
+    }
+    let url = request.param("url").unwrap_or_default();
+    let word = ["/define/", "/embed/"].iter().find_map(|prefix| {
+        let start = url.find(prefix)? + prefix.len();
+        let word = url[start..].split(['#', '?']).next()?;
+        Some(percent_decode(word, false))
+    });
+    let word = match word {
+        Some(word) if !word.is_empty() => word,
+        _ => return Response::error(404, "url isn't a word on this server"),
+    };
+    let size = |name: &str| request.param(name).and_then(|size| size.parse().ok());
+    let json = oembed_json(&server.base_url, &word, size("maxwidth"), size("maxheight"));
+    Response::new(200, "application/json", json)
+}
+
+/// An Atom feed of the last week's words of the day.
+#[cfg_attr(feature = "openapi", utoipa::path(
+    get,
+    path = "/feed.xml",
+    params(("lang" = Option<String>, Query, description = "Only words in this language")),
+    responses((status = 200, description = "An Atom feed"))
+))]
+pub(crate) fn feed(server: &Server, request: &Request) -> Result<Response, QueryError> {
+    let language = request.param("lang").map(normalize_language);
+    let feed = atom_feed(
+        &server.dictionary,
+        &server.base_url,
+        today(),
+        FEED_DAYS,
+        language.as_deref(),
+    )?;
+    Ok(Response::new(200, "application/atom+xml", feed))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use super::*;
+    use test_db::TestDb;
+
+    // A server on a port of its own for the test's database, left running until the tests end.
+    fn start(db: &TestDb) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let path = db.path().to_owned();
+        thread::spawn(move || serve(listener, &path, "http://dict.example/"));
+        addr
+    }
+
+    // The status, headers and body of the response to `method target`.
+    fn send(addr: SocketAddr, method: &str, target: &str) -> (u16, String, String) {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write!(
+            stream,
+            "{} {} HTTP/1.1\r\nHost: dict.example\r\n\r\n",
+            method, target
+        )
+        .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        let status = head[9..12].parse().unwrap();
+        (status, head.to_owned(), body.to_owned())
+    }
+
+    fn get(addr: SocketAddr, target: &str) -> (u16, serde_json::Value) {
+        let (status, head, body) = send(addr, "GET", target);
+        assert!(head.contains("Content-Type: application/json"), "{}", head);
+        (status, serde_json::from_str(&body).unwrap())
+    }
+
+    fn words() -> TestDb {
+        TestDb::baseline(&[
+            ("cat", "English", "Noun", "A feline."),
+            ("chat", "French", "Noun", "cat"),
+            ("caterpillar", "English", "Noun", "A larva."),
+        ])
+    }
+
+    #[test]
+    fn defines() {
+        let db = words();
+        let addr = start(&db);
+        let (status, entry) = get(addr, "/define/cat");
+        assert_eq!(status, 200);
+        assert_eq!(entry["languages"][0]["language"], "English");
+        assert_eq!(
+            entry["languages"][0]["url"],
+            "http://dict.example/define/cat#English"
+        );
+
+        let (status, entry) = get(addr, "/define/chat?lang=fr");
+        assert_eq!(status, 200);
+        assert_eq!(entry["languages"].as_array().unwrap().len(), 1);
+
+        let (status, entry) = get(addr, "/define/cats");
+        assert_eq!(status, 404);
+        assert!(entry["suggestions"]
+            .as_array()
+            .unwrap()
+            .contains(&serde_json::json!("cat")));
+
+        let (status, head, body) = send(addr, "GET", "/define/cat?format=html");
+        assert_eq!(status, 200);
+        assert!(head.contains("Content-Type: text/html"), "{}", head);
+        assert!(body.contains("<h1>cat</h1>"), "{}", body);
+
+        let (status, entry) = get(addr, "/define/cat?format=xml");
+        assert_eq!(
+            (status, entry["error"].as_str()),
+            (400, Some("format must be json or html"))
+        );
+    }
+
+    #[test]
+    fn searches() {
+        let db = words();
+        let addr = start(&db);
+        let (status, page) = get(addr, "/search?q=cat");
+        assert_eq!(status, 200);
+        assert_eq!(page["items"], serde_json::json!(["cat", "caterpillar"]));
+
+        let (_, page) = get(addr, "/search?q=cat&limit=1");
+        assert_eq!(page["items"], serde_json::json!(["cat"]));
+        let next = page["next"].as_str().unwrap().to_owned();
+        let (_, page) = get(addr, &format!("/search?q=cat&limit=1&cursor={}", next));
+        assert_eq!(page["items"], serde_json::json!(["caterpillar"]));
+
+        assert_eq!(get(addr, "/search").0, 400);
+        assert_eq!(get(addr, "/search?q=cat&limit=0").0, 400);
+        assert_eq!(get(addr, "/search?q=cat&cursor=junk").0, 400);
+        assert_eq!(get(addr, "/search?q=cat&mode=fuzzy").0, 400);
+        // The baseline tables have no index for reverse searches.
+        assert_eq!(get(addr, "/search?q=feline&mode=reverse").0, 501);
+    }
+
+    #[test]
+    fn reverse_searches_are_paged() {
+        let db = words();
+        ::reverse::build_search_index(&db.connection()).unwrap();
+        let addr = start(&db);
+        let (status, page) = get(addr, "/search?q=cat+larva&mode=reverse&limit=1");
+        assert_eq!(status, 200);
+        let first = page["items"][0]["word"].as_str().unwrap().to_owned();
+        let next = page["next"].as_str().unwrap().to_owned();
+        let (_, page) = get(
+            addr,
+            &format!("/search?q=cat+larva&mode=reverse&limit=1&cursor={}", next),
+        );
+        let second = page["items"][0]["word"].as_str().unwrap().to_owned();
+        assert_ne!(first, second);
+        assert!(page["next"].is_null(), "{}", page);
+    }
+
+    // A client sending a byte at a time still has to be done by the deadline.
+    #[test]
+    fn requests_have_a_deadline() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (server, _) = listener.accept().unwrap();
+        let trickle = thread::spawn(move || {
+            for _ in 0..20 {
+                if client.write_all(b"G").is_err() {
+                    break;
+                }
+                thread::sleep(Duration::from_millis(20));
+            }
+        });
+        let started = Instant::now();
+        assert!(read_request(&server, Duration::from_millis(100)).is_none());
+        let elapsed = started.elapsed();
+        assert!(elapsed < Duration::from_millis(300), "{:?}", elapsed);
+        drop(server);
+        trickle.join().unwrap();
+    }
+
+    #[test]
+    fn connections_are_limited() {
+        let db = words();
+        let addr = start(&db);
+        let idle: Vec<TcpStream> = (0..MAX_CONNECTIONS)
+            .map(|_| TcpStream::connect(addr).unwrap())
+            .collect();
+        let mut refused = String::new();
+        TcpStream::connect(addr)
+            .unwrap()
+            .read_to_string(&mut refused)
+            .unwrap();
+        assert!(refused.starts_with("HTTP/1.1 503 "), "{}", refused);
+        drop(idle);
+        // The idle connections' threads see them close and finish. Until they have, a request
+        // can be refused, and reset before it's read.
+        let started = Instant::now();
+        loop {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            let mut response = String::new();
+            let answered = write!(stream, "GET /define/cat HTTP/1.1\r\n\r\n")
+                .and_then(|_| stream.read_to_string(&mut response));
+            if answered.is_ok() && response.starts_with("HTTP/1.1 200 ") {
+                break;
+            }
+            assert!(started.elapsed() < Duration::from_secs(5), "{}", response);
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn other_routes_and_methods() {
+        let db = words();
+        let addr = start(&db);
+        let (status, oembed) = get(addr, "/oembed?url=http%3A%2F%2Fdict.example%2Fdefine%2Fcat");
+        assert_eq!(status, 200);
+        assert_eq!(oembed["type"], "rich");
+        assert_eq!(get(addr, "/oembed?url=http%3A%2F%2Fdict.example%2F").0, 404);
+
+        let (status, head, body) = send(addr, "GET", "/embed/cat");
+        assert_eq!(status, 200);
+        assert!(head.contains("Content-Type: text/html"), "{}", head);
+        assert!(body.contains("feline"), "{}", body);
+
+        assert_eq!(get(addr, "/nowhere").0, 404);
+
+        let (status, head, body) = send(addr, "HEAD", "/define/cat");
+        assert_eq!(status, 200);
+        assert!(!head.contains("Content-Length: 0\r"), "{}", head);
+        assert_eq!(body, "");
+
+        let (status, head, _) = send(addr, "POST", "/define/cat");
+        assert_eq!(status, 405);
+        assert!(head.contains("Allow: GET, HEAD"), "{}", head);
+    }
+}
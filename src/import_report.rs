@@ -0,0 +1,233 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+
+use locale::Locale;
+use parse_wikitext::ParsedWikitext;
+use Word;
+
+// How many example titles or lines the report keeps for each kind of problem.
+const SAMPLES: usize = 20;
+
+// How many of the most common unknown headers and skipped languages the report lists.
+const TOP: usize = 30;
+
+/// What an import found, for spotting data regressions between dumps.
+#[derive(Debug, Default)]
+pub struct ImportReport {
+    pub pages: u64,
+    /// Pages that no definitions were imported from.
+    pub pages_without_definitions: u64,
+    pub pages_without_definitions_samples: Vec<String>,
+    pub unparseable_lines: u64,
+    pub unparseable_line_samples: Vec<String>,
+    pub unknown_headers: HashMap<String, u64>,
+    /// Near-miss headers that were corrected, as "header -> correction", for checking that the
+    /// corrections were right.
+    pub corrected_headers: HashMap<String, u64>,
+    /// Malformed lines that were made sense of anyway, by kind; see `ParsedWikitext::anomalies`.
+    pub anomalies: HashMap<String, u64>,
+    pub anomaly_samples: Vec<String>,
+    pub skipped_languages: HashMap<String, u64>,
+    pub definitions_by_language: BTreeMap<String, u64>,
+    /// The same counts from the database this import replaces, if there was one.
+    pub previous_definitions_by_language: Option<BTreeMap<String, u64>>,
+}
+
+// Most common first.
+fn by_count(counts: &HashMap<String, u64>) -> Vec<(&str, u64)> {
+    let mut counts: Vec<(&str, u64)> = counts
+        .iter()
+        .map(|(name, &count)| (name.as_str(), count))
+        .collect();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+    counts
+}
+
+fn most_common(counts: &HashMap<String, u64>) -> Vec<(&str, u64)> {
+    let mut counts = by_count(counts);
+    counts.truncate(TOP);
+    counts
+}
+
+impl ImportReport {
+    pub fn add_page(&mut self, word: &Word, parsed: &ParsedWikitext) {
+        self.pages += 1;
+        if word.meanings.is_empty() {
+            self.pages_without_definitions += 1;
+            if self.pages_without_definitions_samples.len() < SAMPLES {
+                self.pages_without_definitions_samples
+                    .push(word.name.clone());
+            }
+        }
+        for line in &parsed.unparseable_lines {
+            self.unparseable_lines += 1;
+            if self.unparseable_line_samples.len() < SAMPLES {
+                self.unparseable_line_samples
+                    .push(format!("{}: {}", word.name, line));
+            }
+        }
+        for (kind, line) in &parsed.anomalies {
+            *self.anomalies.entry(kind.to_string()).or_default() += 1;
+            if self.anomaly_samples.len() < SAMPLES {
+                self.anomaly_samples
+                    .push(format!("{}: {}: {}", word.name, kind, line));
+            }
+        }
+        for header in &parsed.unknown_headers {
+            *self.unknown_headers.entry(header.clone()).or_default() += 1;
+        }
+        for (header, corrected) in &parsed.corrected_headers {
+            *self
+                .corrected_headers
+                .entry(format!("{} -> {}", header, corrected))
+                .or_default() += 1;
+        }
+        for language in &parsed.skipped_languages {
+            *self.skipped_languages.entry(language.clone()).or_default() += 1;
+        }
+        for meaning in &word.meanings {
+            *self
+                .definitions_by_language
+                .entry(meaning.language.clone())
+                .or_default() += 1;
+        }
+    }
+
+    // (language, previous count, count) for every language in either import.
+    fn language_changes(&self) -> Vec<(&str, Option<u64>, u64)> {
+        let mut languages: Vec<&str> = self
+            .definitions_by_language
+            .keys()
+            .map(String::as_str)
+            .collect();
+        if let Some(ref previous) = self.previous_definitions_by_language {
+            languages.extend(previous.keys().map(String::as_str));
+            languages.sort_unstable();
+            languages.dedup();
+        }
+        languages
+            .into_iter()
+            .map(|language| {
+                let previous = self
+                    .previous_definitions_by_language
+                    .as_ref()
+                    .map(|previous| previous.get(language).cloned().unwrap_or(0));
+                let count = self
+                    .definitions_by_language
+                    .get(language)
+                    .cloned()
+                    .unwrap_or(0);
+                (language, previous, count)
+            })
+            .collect()
+    }
+
+    pub fn to_json(&self) -> String {
+        let languages: Vec<serde_json::Value> = self
+            .language_changes()
+            .into_iter()
+            .map(|(language, previous, count)| {
+                serde_json::json!({
+                    "language": language,
+                    "definitions": count,
+                    "previous_definitions": previous,
+                })
+            })
+            .collect();
+        let counts = |counts: Vec<(&str, u64)>| -> Vec<serde_json::Value> {
+            counts
+                .into_iter()
+                .map(|(name, count)| serde_json::json!({"name": name, "count": count}))
+                .collect()
+        };
+        let report = serde_json::json!({
+            "pages": self.pages,
+            "pages_without_definitions": {
+                "count": self.pages_without_definitions,
+                "samples": self.pages_without_definitions_samples,
+            },
+            "unparseable_lines": {
+                "count": self.unparseable_lines,
+                "samples": self.unparseable_line_samples,
+            },
+            "anomalies": {
+                "kinds": counts(most_common(&self.anomalies)),
+                "samples": self.anomaly_samples,
+            },
+            "unknown_headers": counts(most_common(&self.unknown_headers)),
+            // All of them, since each one is a guess someone might want to check.
+            "corrected_headers": counts(by_count(&self.corrected_headers)),
+            "skipped_languages": counts(most_common(&self.skipped_languages)),
+            "languages": languages,
+        });
+        serde_json::to_string_pretty(&report).unwrap()
+    }
+
+    /// A table of the report for printing at the end of an import.
+    pub fn summary(&self) -> String {
+        let locale = Locale::current();
+        let number = |n: u64| locale.number(n as i64);
+        let mut summary = String::new();
+        writeln!(summary, "{:<30} {:>10}", "Pages", number(self.pages)).unwrap();
+        writeln!(
+            summary,
+            "{:<30} {:>10}",
+            "Pages without definitions",
+            number(self.pages_without_definitions)
+        )
+        .unwrap();
+        writeln!(
+            summary,
+            "{:<30} {:>10}",
+            "Unparseable lines",
+            number(self.unparseable_lines)
+        )
+        .unwrap();
+        let anomalies: u64 = self.anomalies.values().sum();
+        writeln!(
+            summary,
+            "{:<30} {:>10}",
+            "Recovered anomalies",
+            number(anomalies)
+        )
+        .unwrap();
+        let unknown: u64 = self.unknown_headers.values().sum();
+        writeln!(summary, "{:<30} {:>10}", "Unknown headers", number(unknown)).unwrap();
+        if !self.corrected_headers.is_empty() {
+            let corrected: u64 = self.corrected_headers.values().sum();
+            writeln!(
+                summary,
+                "{:<30} {:>10}",
+                "Corrected headers",
+                number(corrected)
+            )
+            .unwrap();
+        }
+        writeln!(summary).unwrap();
+        writeln!(
+            summary,
+            "{:<30} {:>10} {:>10} {:>8}",
+            "Language", "Previous", "Now", "Change"
+        )
+        .unwrap();
+        for (language, previous, count) in self.language_changes() {
+            let (previous, change) = match previous {
+                None => (String::new(), String::new()),
+                Some(previous) => (
+                    number(previous),
+                    locale.signed_number(count as i64 - previous as i64),
+                ),
+            };
+            writeln!(
+                summary,
+                "{:<30} {:>10} {:>10} {:>8}",
+                language,
+                previous,
+                number(count),
+                change
+            )
+            .unwrap();
+        }
+        summary
+    }
+}
@@ -0,0 +1,77 @@
+//! The JSON form of an entry, for editors, bots and scripts that would otherwise have to scrape
+//! the colored output.
+
+use expand::{expand_with, TemplatePolicies, TemplateSource};
+use lookup::Grouped;
+use references::find_references;
+use render::{math_formulas, Formatters};
+use Meaning;
+
+fn definition_json(
+    meaning: &Meaning,
+    policies: &TemplatePolicies,
+    templates: &dyn TemplateSource,
+    formatters: &Formatters,
+) -> serde_json::Value {
+    let (text, issues) = expand_with(&meaning.definition, policies, templates);
+    let references: Vec<serde_json::Value> = find_references(&meaning.definition)
+        .into_iter()
+        .map(|reference| {
+            serde_json::json!({
+                "target": reference.target,
+                "language": reference.language,
+                "anchor": reference.anchor,
+            })
+        })
+        .collect();
+    serde_json::json!({
+        "text": formatters.definition(&meaning.language, text),
+        "raw": meaning.definition,
+        "edition": meaning.edition,
+        "math": math_formulas(&meaning.definition),
+        "references": references,
+        "warnings": issues.iter().map(ToString::to_string).collect::<Vec<_>>(),
+    })
+}
+
+/// An entry as word → languages → parts of speech → definitions, in the order given. Each
+/// definition has its expanded text along with the raw wikitext, the LaTeX of its formulas, the
+/// entries it refers to and any problems expanding it. `query` is what was looked up, in case it
+/// fell back to another spelling; `checksum` is the entry's `checksum::entry_checksum`, if known.
+pub fn entry_json(
+    query: &str,
+    word: &str,
+    langs: &Grouped,
+    policies: &TemplatePolicies,
+    templates: &dyn TemplateSource,
+    formatters: &Formatters,
+    checksum: Option<&str>,
+) -> serde_json::Value {
+    let languages: Vec<serde_json::Value> = langs
+        .iter()
+        .map(|(language, poses)| {
+            let meanings: Vec<&Meaning> = poses.values().flatten().collect();
+            let parts_of_speech: Vec<serde_json::Value> = poses
+                .iter()
+                .map(|(pos, meanings)| {
+                    let definitions: Vec<serde_json::Value> = meanings
+                        .iter()
+                        .map(|meaning| definition_json(meaning, policies, templates, formatters))
+                        .collect();
+                    serde_json::json!({"part_of_speech": pos, "definitions": definitions})
+                })
+                .collect();
+            serde_json::json!({
+                "language": language,
+                "headword_lines": formatters.headword_lines(language, word, &meanings),
+                "parts_of_speech": parts_of_speech,
+            })
+        })
+        .collect();
+    serde_json::json!({
+        "query": query,
+        "word": word,
+        "checksum": checksum,
+        "languages": languages,
+    })
+}
@@ -0,0 +1,335 @@
+/// A language as named by Wiktionary, with its ISO 639 codes.
+#[derive(Debug)]
+pub struct Language {
+    /// The name Wiktionary uses for the language's section headers.
+    pub name: &'static str,
+    /// ISO 639-1 (two-letter) code, if the language has one.
+    pub iso639_1: Option<&'static str>,
+    /// ISO 639-3 (three-letter) code.
+    pub iso639_3: &'static str,
+}
+
+macro_rules! language {
+    ($name:expr, -, $iso639_3:expr) => {
+        Language {
+            name: $name,
+            iso639_1: None,
+            iso639_3: $iso639_3,
+        }
+    };
+    ($name:expr, $iso639_1:expr, $iso639_3:expr) => {
+        Language {
+            name: $name,
+            iso639_1: Some($iso639_1),
+            iso639_3: $iso639_3,
+        }
+    };
+}
+
+/// Every ISO 639-1 language, plus the common languages that only have ISO 639-3 codes.
+pub static LANGUAGES: &[Language] = &[
+    language!("Afar", "aa", "aar"),
+    language!("Abkhaz", "ab", "abk"),
+    language!("Avestan", "ae", "ave"),
+    language!("Afrikaans", "af", "afr"),
+    language!("Akan", "ak", "aka"),
+    language!("Amharic", "am", "amh"),
+    language!("Aragonese", "an", "arg"),
+    language!("Arabic", "ar", "ara"),
+    language!("Assamese", "as", "asm"),
+    language!("Avar", "av", "ava"),
+    language!("Aymara", "ay", "aym"),
+    language!("Azerbaijani", "az", "aze"),
+    language!("Bashkir", "ba", "bak"),
+    language!("Belarusian", "be", "bel"),
+    language!("Bulgarian", "bg", "bul"),
+    language!("Bislama", "bi", "bis"),
+    language!("Bambara", "bm", "bam"),
+    language!("Bengali", "bn", "ben"),
+    language!("Tibetan", "bo", "bod"),
+    language!("Breton", "br", "bre"),
+    language!("Bosnian", "bs", "bos"),
+    language!("Catalan", "ca", "cat"),
+    language!("Chechen", "ce", "che"),
+    language!("Chamorro", "ch", "cha"),
+    language!("Corsican", "co", "cos"),
+    language!("Cree", "cr", "cre"),
+    language!("Czech", "cs", "ces"),
+    language!("Old Church Slavonic", "cu", "chu"),
+    language!("Chuvash", "cv", "chv"),
+    language!("Welsh", "cy", "cym"),
+    language!("Danish", "da", "dan"),
+    language!("German", "de", "deu"),
+    language!("Dhivehi", "dv", "div"),
+    language!("Dzongkha", "dz", "dzo"),
+    language!("Ewe", "ee", "ewe"),
+    language!("Greek", "el", "ell"),
+    language!("English", "en", "eng"),
+    language!("Esperanto", "eo", "epo"),
+    language!("Spanish", "es", "spa"),
+    language!("Estonian", "et", "est"),
+    language!("Basque", "eu", "eus"),
+    language!("Persian", "fa", "fas"),
+    language!("Fula", "ff", "ful"),
+    language!("Finnish", "fi", "fin"),
+    language!("Fijian", "fj", "fij"),
+    language!("Faroese", "fo", "fao"),
+    language!("French", "fr", "fra"),
+    language!("West Frisian", "fy", "fry"),
+    language!("Irish", "ga", "gle"),
+    language!("Scottish Gaelic", "gd", "gla"),
+    language!("Galician", "gl", "glg"),
+    language!("Guarani", "gn", "grn"),
+    language!("Gujarati", "gu", "guj"),
+    language!("Manx", "gv", "glv"),
+    language!("Hausa", "ha", "hau"),
+    language!("Hebrew", "he", "heb"),
+    language!("Hindi", "hi", "hin"),
+    language!("Hiri Motu", "ho", "hmo"),
+    language!("Croatian", "hr", "hrv"),
+    language!("Haitian Creole", "ht", "hat"),
+    language!("Hungarian", "hu", "hun"),
+    language!("Armenian", "hy", "hye"),
+    language!("Herero", "hz", "her"),
+    language!("Interlingua", "ia", "ina"),
+    language!("Indonesian", "id", "ind"),
+    language!("Interlingue", "ie", "ile"),
+    language!("Igbo", "ig", "ibo"),
+    language!("Sichuan Yi", "ii", "iii"),
+    language!("Inupiaq", "ik", "ipk"),
+    language!("Ido", "io", "ido"),
+    language!("Icelandic", "is", "isl"),
+    language!("Italian", "it", "ita"),
+    language!("Inuktitut", "iu", "iku"),
+    language!("Japanese", "ja", "jpn"),
+    language!("Javanese", "jv", "jav"),
+    language!("Georgian", "ka", "kat"),
+    language!("Kongo", "kg", "kon"),
+    language!("Kikuyu", "ki", "kik"),
+    language!("Kwanyama", "kj", "kua"),
+    language!("Kazakh", "kk", "kaz"),
+    language!("Greenlandic", "kl", "kal"),
+    language!("Khmer", "km", "khm"),
+    language!("Kannada", "kn", "kan"),
+    language!("Korean", "ko", "kor"),
+    language!("Kanuri", "kr", "kau"),
+    language!("Kashmiri", "ks", "kas"),
+    language!("Kurdish", "ku", "kur"),
+    language!("Komi", "kv", "kom"),
+    language!("Cornish", "kw", "cor"),
+    language!("Kyrgyz", "ky", "kir"),
+    language!("Latin", "la", "lat"),
+    language!("Luxembourgish", "lb", "ltz"),
+    language!("Luganda", "lg", "lug"),
+    language!("Limburgish", "li", "lim"),
+    language!("Lingala", "ln", "lin"),
+    language!("Lao", "lo", "lao"),
+    language!("Lithuanian", "lt", "lit"),
+    language!("Luba-Katanga", "lu", "lub"),
+    language!("Latvian", "lv", "lav"),
+    language!("Malagasy", "mg", "mlg"),
+    language!("Marshallese", "mh", "mah"),
+    language!("Maori", "mi", "mri"),
+    language!("Macedonian", "mk", "mkd"),
+    language!("Malayalam", "ml", "mal"),
+    language!("Mongolian", "mn", "mon"),
+    language!("Marathi", "mr", "mar"),
+    language!("Malay", "ms", "msa"),
+    language!("Maltese", "mt", "mlt"),
+    language!("Burmese", "my", "mya"),
+    language!("Nauruan", "na", "nau"),
+    language!("Norwegian Bokmål", "nb", "nob"),
+    language!("Northern Ndebele", "nd", "nde"),
+    language!("Nepali", "ne", "nep"),
+    language!("Ndonga", "ng", "ndo"),
+    language!("Dutch", "nl", "nld"),
+    language!("Norwegian Nynorsk", "nn", "nno"),
+    language!("Norwegian", "no", "nor"),
+    language!("Southern Ndebele", "nr", "nbl"),
+    language!("Navajo", "nv", "nav"),
+    language!("Chichewa", "ny", "nya"),
+    language!("Occitan", "oc", "oci"),
+    language!("Ojibwe", "oj", "oji"),
+    language!("Oromo", "om", "orm"),
+    language!("Odia", "or", "ori"),
+    language!("Ossetian", "os", "oss"),
+    language!("Punjabi", "pa", "pan"),
+    language!("Pali", "pi", "pli"),
+    language!("Polish", "pl", "pol"),
+    language!("Pashto", "ps", "pus"),
+    language!("Portuguese", "pt", "por"),
+    language!("Quechua", "qu", "que"),
+    language!("Romansch", "rm", "roh"),
+    language!("Rundi", "rn", "run"),
+    language!("Romanian", "ro", "ron"),
+    language!("Russian", "ru", "rus"),
+    language!("Rwanda-Rundi", "rw", "kin"),
+    language!("Sanskrit", "sa", "san"),
+    language!("Sardinian", "sc", "srd"),
+    language!("Sindhi", "sd", "snd"),
+    language!("Northern Sami", "se", "sme"),
+    language!("Sango", "sg", "sag"),
+    language!("Serbo-Croatian", "sh", "hbs"),
+    language!("Sinhalese", "si", "sin"),
+    language!("Slovak", "sk", "slk"),
+    language!("Slovene", "sl", "slv"),
+    language!("Samoan", "sm", "smo"),
+    language!("Shona", "sn", "sna"),
+    language!("Somali", "so", "som"),
+    language!("Albanian", "sq", "sqi"),
+    language!("Serbian", "sr", "srp"),
+    language!("Swazi", "ss", "ssw"),
+    language!("Sotho", "st", "sot"),
+    language!("Sundanese", "su", "sun"),
+    language!("Swedish", "sv", "swe"),
+    language!("Swahili", "sw", "swa"),
+    language!("Tamil", "ta", "tam"),
+    language!("Telugu", "te", "tel"),
+    language!("Tajik", "tg", "tgk"),
+    language!("Thai", "th", "tha"),
+    language!("Tigrinya", "ti", "tir"),
+    language!("Turkmen", "tk", "tuk"),
+    language!("Tagalog", "tl", "tgl"),
+    language!("Tswana", "tn", "tsn"),
+    language!("Tongan", "to", "ton"),
+    language!("Turkish", "tr", "tur"),
+    language!("Tsonga", "ts", "tso"),
+    language!("Tatar", "tt", "tat"),
+    language!("Twi", "tw", "twi"),
+    language!("Tahitian", "ty", "tah"),
+    language!("Uyghur", "ug", "uig"),
+    language!("Ukrainian", "uk", "ukr"),
+    language!("Urdu", "ur", "urd"),
+    language!("Uzbek", "uz", "uzb"),
+    language!("Venda", "ve", "ven"),
+    language!("Vietnamese", "vi", "vie"),
+    language!("Volapük", "vo", "vol"),
+    language!("Walloon", "wa", "wln"),
+    language!("Wolof", "wo", "wol"),
+    language!("Xhosa", "xh", "xho"),
+    language!("Yiddish", "yi", "yid"),
+    language!("Yoruba", "yo", "yor"),
+    language!("Zhuang", "za", "zha"),
+    language!("Chinese", "zh", "zho"),
+    language!("Zulu", "zu", "zul"),
+    language!("Old English", -, "ang"),
+    language!("Aramaic", -, "arc"),
+    language!("Asturian", -, "ast"),
+    language!("Mandarin", -, "cmn"),
+    language!("Egyptian", -, "egy"),
+    language!("Middle English", -, "enm"),
+    language!("Middle French", -, "frm"),
+    language!("Old French", -, "fro"),
+    language!("Middle High German", -, "gmh"),
+    language!("Old High German", -, "goh"),
+    language!("Gothic", -, "got"),
+    language!("Ancient Greek", -, "grc"),
+    language!("Alemannic German", -, "gsw"),
+    language!("Hawaiian", -, "haw"),
+    language!("Lojban", -, "jbo"),
+    language!("Ladino", -, "lad"),
+    language!("Translingual", -, "mul"),
+    language!("Low German", -, "nds"),
+    language!("Old Norse", -, "non"),
+    language!("Pennsylvania German", -, "pdc"),
+    language!("Scots", -, "sco"),
+    language!("Sicilian", -, "scn"),
+    language!("Toki Pona", -, "tok"),
+    language!("Tok Pisin", -, "tpi"),
+    language!("Cantonese", -, "yue"),
+];
+
+/// Finds a language by name or by ISO 639-1 or 639-3 code, ignoring case.
+pub fn find_language(name_or_code: &str) -> Option<&'static Language> {
+    LANGUAGES.iter().find(|language| {
+        language.name.eq_ignore_ascii_case(name_or_code)
+            || language
+                .iso639_1
+                .is_some_and(|code| code.eq_ignore_ascii_case(name_or_code))
+            || language.iso639_3.eq_ignore_ascii_case(name_or_code)
+    })
+}
+
+/// Finds a language by the name Wiktionary gives it.
+pub fn language_by_name(name: &str) -> Option<&'static Language> {
+    LANGUAGES.iter().find(|language| language.name == name)
+}
+
+/// Turns a language name or code as typed by a user into the name used in the database. Names
+/// that aren't in the table are passed through, since the database has many more languages, except
+/// that ones typed all in lowercase are capitalized the way Wiktionary's nearly always are, e.g.
+/// "old english" as "Old English".
+pub fn normalize_language(name_or_code: &str) -> String {
+    match find_language(name_or_code) {
+        Some(language) => language.name.to_owned(),
+        None if name_or_code.chars().any(char::is_uppercase) => name_or_code.to_owned(),
+        None => capitalize_words(name_or_code),
+    }
+}
+
+// "proto-indo-european" as "Proto-Indo-European".
+fn capitalize_words(s: &str) -> String {
+    let mut capitalized = String::with_capacity(s.len());
+    let mut start = true;
+    for c in s.chars() {
+        if start {
+            capitalized.extend(c.to_uppercase());
+        } else {
+            capitalized.push(c);
+        }
+        start = c == ' ' || c == '-';
+    }
+    capitalized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_by_name_and_code() {
+        assert_eq!(find_language("German").unwrap().iso639_3, "deu");
+        assert_eq!(find_language("de").unwrap().name, "German");
+        assert_eq!(find_language("deu").unwrap().name, "German");
+        assert_eq!(find_language("ang").unwrap().name, "Old English");
+        assert_eq!(find_language("Old English").unwrap().iso639_1, None);
+    }
+
+    #[test]
+    fn ignores_case() {
+        assert_eq!(find_language("gERMAN").unwrap().name, "German");
+        assert_eq!(find_language("DE").unwrap().name, "German");
+        assert_eq!(find_language("volapük").unwrap().name, "Volapük");
+        // Only by the name Wiktionary uses, as it is.
+        assert_eq!(language_by_name("Toki Pona").unwrap().iso639_3, "tok");
+        assert!(language_by_name("toki pona").is_none());
+    }
+
+    #[test]
+    fn unknown_languages() {
+        assert!(find_language("Klingon").is_none());
+        assert!(find_language("").is_none());
+        assert_eq!(normalize_language("fr"), "French");
+        assert_eq!(
+            normalize_language("proto-indo-european"),
+            "Proto-Indo-European"
+        );
+        assert_eq!(normalize_language("Proto-Germanic"), "Proto-Germanic");
+        assert_eq!(normalize_language("klingon"), "Klingon");
+    }
+
+    #[test]
+    fn codes_are_unique() {
+        for (i, language) in LANGUAGES.iter().enumerate() {
+            assert!(
+                LANGUAGES[..i]
+                    .iter()
+                    .all(|other| other.name != language.name
+                        && other.iso639_3 != language.iso639_3
+                        && (language.iso639_1.is_none() || other.iso639_1 != language.iso639_1)),
+                "{:?}",
+                language
+            );
+        }
+    }
+}
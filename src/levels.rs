@@ -0,0 +1,157 @@
+//! Flashcards from graded word lists like JLPT N4, HSK 3 or CEFR B1: each word on a list with its
+//! reading and definitions, as CSV or TSV for importing into a flashcard program.
+//!
+//! The lists themselves don't come with define3, since most are published under terms that don't
+//! allow it; `define export-level --import` saves one from a file.
+
+use std::str::FromStr;
+
+use rusqlite::Result;
+
+use dictionary::Dictionary;
+use scripts::Script;
+
+/// A word on a list.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ListEntry {
+    pub word: String,
+    /// How it's read, for lists of words in scripts that don't say, like Japanese.
+    pub reading: Option<String>,
+}
+
+/// The language of a standard list, from its name, e.g. Japanese for jlpt-n4. CEFR lists are
+/// for any language, so they don't have one.
+pub fn list_language(name: &str) -> Option<&'static str> {
+    let name = name.to_lowercase();
+    if name.starts_with("jlpt") {
+        Some("Japanese")
+    } else if name.starts_with("hsk") {
+        Some("Chinese")
+    } else if name.starts_with("topik") {
+        Some("Korean")
+    } else {
+        None
+    }
+}
+
+/// Parses a list: a word per line, optionally followed by a tab or comma and its reading. Blank
+/// lines and lines starting with `#` are skipped.
+pub fn parse_list(text: &str) -> Vec<ListEntry> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            let (word, reading) = match line.split_once(['\t', ',']) {
+                Some((word, reading)) => (word.trim(), Some(reading.trim())),
+                None => (line, None),
+            };
+            ListEntry {
+                word: word.to_owned(),
+                reading: reading
+                    .filter(|reading| !reading.is_empty())
+                    .map(str::to_owned),
+            }
+        })
+        .collect()
+}
+
+/// A row of the export.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Flashcard {
+    pub word: String,
+    pub reading: String,
+    /// Every definition in the list's language, expanded, separated by semicolons. Empty if the
+    /// word isn't in the dictionary.
+    pub definition: String,
+}
+
+// A word written only in kana reads as it's written.
+fn kana_reading(word: &str) -> Option<String> {
+    let kana = word
+        .chars()
+        .filter_map(Script::of_char)
+        .all(|script| script == Script::Kana);
+    if kana && Script::of(word) == Script::Kana {
+        Some(word.to_owned())
+    } else {
+        None
+    }
+}
+
+/// Looks up every word on a list at once, in `language` only if given.
+pub fn flashcards(
+    dictionary: &Dictionary,
+    entries: &[ListEntry],
+    language: Option<&str>,
+) -> Result<Vec<Flashcard>> {
+    let words: Vec<&str> = entries.iter().map(|entry| entry.word.as_str()).collect();
+    let found = dictionary.lookup_many(&words)?;
+    Ok(entries
+        .iter()
+        .zip(found)
+        .map(|(entry, word)| {
+            let definitions: Vec<String> = word
+                .meanings
+                .iter()
+                .filter(|meaning| language.is_none_or(|language| meaning.language == language))
+                .map(|meaning| dictionary.expand(&meaning.definition))
+                .collect();
+            Flashcard {
+                word: entry.word.clone(),
+                reading: entry
+                    .reading
+                    .clone()
+                    .or_else(|| kana_reading(&entry.word))
+                    .unwrap_or_default(),
+                definition: definitions.join("; "),
+            }
+        })
+        .collect())
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CardFormat {
+    Csv,
+    Tsv,
+}
+
+impl FromStr for CardFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<CardFormat, String> {
+        match s {
+            "csv" => Ok(CardFormat::Csv),
+            "tsv" => Ok(CardFormat::Tsv),
+            _ => Err(format!("unknown format {:?}; expected csv or tsv", s)),
+        }
+    }
+}
+
+// A field as CSV quotes it (RFC 4180), or as TSV without tabs or line breaks, which it has no way
+// of escaping.
+fn field(s: &str, format: CardFormat) -> String {
+    match format {
+        CardFormat::Csv if s.contains([',', '"', '\n', '\r']) => {
+            format!("\"{}\"", s.replace('"', "\"\""))
+        }
+        CardFormat::Csv => s.to_owned(),
+        CardFormat::Tsv => s.replace(['\t', '\n', '\r'], " "),
+    }
+}
+
+/// The cards with a header row, in `format`.
+pub fn write_cards(cards: &[Flashcard], format: CardFormat) -> String {
+    let separator = match format {
+        CardFormat::Csv => ",",
+        CardFormat::Tsv => "\t",
+    };
+    let mut out = ["word", "reading", "definition"].join(separator);
+    out.push('\n');
+    for card in cards {
+        let fields = [&card.word, &card.reading, &card.definition];
+        let fields: Vec<String> = fields.iter().map(|s| field(s, format)).collect();
+        out.push_str(&fields.join(separator));
+        out.push('\n');
+    }
+    out
+}
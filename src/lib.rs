@@ -1,11 +1,144 @@
+extern crate dirs;
+extern crate feruca;
+#[cfg(unix)]
+extern crate libc;
+extern crate nom;
+extern crate regex;
+extern crate rusqlite;
+extern crate serde_json;
+extern crate textwrap;
+extern crate thiserror;
+extern crate unicode_normalization;
+#[cfg(feature = "openapi")]
+extern crate utoipa;
+extern crate zstd;
+
+pub mod abbreviations;
+pub mod anki;
+pub mod cache;
+pub mod checksum;
+pub mod compression;
+pub mod config;
+pub mod conlang;
+pub mod context;
+pub mod coverage;
+pub mod dict_server;
+pub mod dictionary;
+pub mod digest;
+pub mod dot;
+pub mod dump;
+pub mod embed;
+pub mod entry_diff;
+pub mod error;
+pub mod etymology;
+pub mod expand;
+pub mod export;
+pub mod feed;
+pub mod fetch;
+pub mod headword;
+pub mod history;
+pub mod http_server;
+pub mod import_report;
+pub mod json;
+pub mod languages;
+pub mod levels;
+pub mod locale;
+pub mod lookup;
+pub mod memory;
+pub mod normalize;
+#[cfg(feature = "openapi")]
+pub mod openapi;
+pub mod pagination;
 pub mod parse_wikitext;
 pub mod parse_xml;
+pub mod permalink;
+pub mod query;
+pub mod ranking;
+pub mod references;
+pub mod render;
+pub mod reverse;
+pub mod romanization;
+pub mod scrape;
+pub mod scripts;
+pub mod sql_functions;
+pub mod template_corpus;
+pub mod templates;
+#[cfg(test)]
+mod test_db;
+#[cfg(unix)]
+pub mod tui;
+pub mod wiktextract;
 
-#[derive(Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct Meaning {
     pub language: String,
     pub part_of_speech: String,
     pub definition: String,
+    /// The definition with its templates expanded, if the database was built with
+    /// `--pre-expand`.
+    pub expanded: Option<String>,
+    /// The Wiktionary edition the definition was imported from, e.g. "en", if known.
+    pub edition: Option<String>,
+}
+
+/// Where a word comes from, from one of its "Etymology" sections.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Etymology {
+    pub language: String,
+    /// N for words with several origins, under "Etymology 1", "Etymology 2" and so on, or 0 for
+    /// one under a plain "Etymology".
+    pub number: u32,
+    pub text: String,
+    /// The parts of speech listed under it, i.e. the ones it's the origin of.
+    pub parts_of_speech: Vec<String>,
+}
+
+/// How a word is said in one language, from its "Pronunciation" sections.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Pronunciation {
+    pub language: String,
+    /// Transcriptions with their slashes or brackets, e.g. "/kæt/" or "[kʰæt]".
+    pub ipa: Vec<String>,
+    /// Names of recordings on Wikimedia Commons, e.g. "En-us-cat.ogg".
+    pub audio: Vec<String>,
+    /// Ways to hyphenate it, with the parts separated by "‧".
+    pub hyphenation: Vec<String>,
+}
+
+/// What a part of speech's headword template says about its grammar.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Headword {
+    pub language: String,
+    pub part_of_speech: String,
+    /// (label, form) pairs, e.g. ("plural", "geese"), in the order the template gives them.
+    /// Labels that say all there is to say, like "uncountable", come with an empty form.
+    pub forms: Vec<(String, String)>,
+}
+
+/// A sentence showing a definition in use, from a `{{ux}}` or a quotation under it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Example {
+    pub language: String,
+    pub part_of_speech: String,
+    /// The definition it's under, as in its `Meaning`.
+    pub definition: String,
+    pub text: String,
+    /// What it means in English, for examples in other languages.
+    pub translation: Option<String>,
+}
+
+/// A semantic relation to another word, e.g. a synonym.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Relation {
+    pub language: String,
+    /// Relation sections under a part of speech only apply to it.
+    pub part_of_speech: Option<String>,
+    /// "synonym", "antonym", "hypernym", ...
+    pub kind: String,
+    pub target: String,
+    /// The sense of the word it's a relation of, if the page says: a gloss from `{{sense|...}}`,
+    /// or the definition that a `{{syn|...}}` under it belongs to.
+    pub sense: Option<String>,
 }
 
 #[derive(Debug)]
@@ -18,6 +151,11 @@ pub struct Page {
 pub struct Word {
     pub name: String,
     pub meanings: Vec<Meaning>,
+    pub relations: Vec<Relation>,
+    pub etymologies: Vec<Etymology>,
+    pub pronunciations: Vec<Pronunciation>,
+    pub examples: Vec<Example>,
+    pub headwords: Vec<Headword>,
 }
 
 #[derive(Debug)]
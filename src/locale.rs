@@ -0,0 +1,337 @@
+//! Numbers, dates and centuries written the way the user's locale writes them, for output meant
+//! to be read rather than parsed: `{{defdate}}`, the digest, and counts in reports. JSON and CSV
+//! stay as they are.
+//!
+//! Only a few common locales are known. Anything else, including no locale at all (the "C"
+//! locale), gets ungrouped numbers, ISO dates and English centuries.
+
+use std::env;
+use std::sync::OnceLock;
+
+use regex::{Captures, Regex};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Locale {
+    /// The language code, e.g. "de", or "C" for none.
+    language: String,
+    /// The region code, e.g. "GB", if given.
+    region: Option<String>,
+}
+
+const ROMAN_NUMERALS: &[(u32, &str)] = &[
+    (1000, "M"),
+    (900, "CM"),
+    (500, "D"),
+    (400, "CD"),
+    (100, "C"),
+    (90, "XC"),
+    (50, "L"),
+    (40, "XL"),
+    (10, "X"),
+    (9, "IX"),
+    (5, "V"),
+    (4, "IV"),
+    (1, "I"),
+];
+
+fn roman(mut n: u32) -> String {
+    let mut roman = String::new();
+    for &(value, numeral) in ROMAN_NUMERALS {
+        while n >= value {
+            roman.push_str(numeral);
+            n -= value;
+        }
+    }
+    roman
+}
+
+/// The English ordinal of `n`, e.g. "21st".
+pub fn english_ordinal(n: u32) -> String {
+    let suffix = match (n % 10, n % 100) {
+        (_, 11..=13) => "th",
+        (1, _) => "st",
+        (2, _) => "nd",
+        (3, _) => "rd",
+        _ => "th",
+    };
+    format!("{}{}", n, suffix)
+}
+
+// "15th c.", "15th century", or "14th-16th c.", as `{{defdate}}`s write them.
+fn century_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| {
+        Regex::new(
+            r"\b(?P<from>\d{1,2})(?:st|nd|rd|th)(?:\s*[-–]\s*(?P<to>\d{1,2})(?:st|nd|rd|th))? (?:c\.|century\b|centuries\b)",
+        )
+        .unwrap()
+    })
+}
+
+// "1842-1850" or "1842–50".
+fn year_range_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"\b(?P<from>\d{3,4})\s*[-–]\s*(?P<to>\d{2,4})\b").unwrap())
+}
+
+impl Default for Locale {
+    /// The "C" locale.
+    fn default() -> Self {
+        Locale {
+            language: "C".to_owned(),
+            region: None,
+        }
+    }
+}
+
+impl Locale {
+    /// Parses a locale name like "de_DE.UTF-8", "en-GB" or "C".
+    pub fn parse(name: &str) -> Locale {
+        let name = name.split(['.', '@']).next().unwrap_or("");
+        let mut parts = name.split(['_', '-']);
+        let language = parts.next().unwrap_or("").to_lowercase();
+        if language.is_empty() || language == "c" || language == "posix" {
+            return Locale::default();
+        }
+        Locale {
+            language,
+            region: parts.next().map(str::to_uppercase),
+        }
+    }
+
+    /// The locale set for numbers in the environment, by `LC_ALL`, `LC_NUMERIC` or `LANG`, in
+    /// that order, like the C library picks it.
+    pub fn current() -> &'static Locale {
+        static LOCALE: OnceLock<Locale> = OnceLock::new();
+        LOCALE.get_or_init(|| {
+            ["LC_ALL", "LC_NUMERIC", "LANG"]
+                .iter()
+                .filter_map(|name| env::var(name).ok())
+                .find(|value| !value.is_empty())
+                .map_or_else(Locale::default, |value| Locale::parse(&value))
+        })
+    }
+
+    // (thousands separator, decimal separator)
+    fn separators(&self) -> (&'static str, &'static str) {
+        match self.language.as_str() {
+            "en" | "ja" | "zh" | "ko" => (",", "."),
+            "de" | "es" | "it" | "pt" | "nl" | "da" | "id" | "tr" => (".", ","),
+            // A narrow no-break space, so the number doesn't wrap.
+            "fr" => ("\u{202f}", ","),
+            "ru" | "uk" | "pl" | "cs" | "sv" | "fi" | "nb" | "no" => ("\u{a0}", ","),
+            _ => ("", "."),
+        }
+    }
+
+    /// A count, with its thousands grouped, e.g. "1,234,567" or "1.234.567".
+    pub fn number(&self, n: i64) -> String {
+        let (thousands, _) = self.separators();
+        let digits = n.unsigned_abs().to_string();
+        let mut grouped = String::new();
+        for (i, digit) in digits.chars().enumerate() {
+            if i > 0 && (digits.len() - i).is_multiple_of(3) {
+                grouped.push_str(thousands);
+            }
+            grouped.push(digit);
+        }
+        if n < 0 {
+            format!("-{}", grouped)
+        } else {
+            grouped
+        }
+    }
+
+    /// A change in a count, always with its sign, e.g. "+1,234".
+    pub fn signed_number(&self, n: i64) -> String {
+        if n < 0 {
+            self.number(n)
+        } else {
+            format!("+{}", self.number(n))
+        }
+    }
+
+    /// `x` with `places` decimal places, e.g. "4.8" or "4,8".
+    pub fn decimal(&self, x: f64, places: usize) -> String {
+        let (_, decimal) = self.separators();
+        let s = format!("{:.*}", places, x.abs());
+        let (whole, fraction) = s.split_once('.').unwrap_or((&s, ""));
+        let mut formatted = self.number(whole.parse().unwrap_or(0));
+        if x < 0.0 && s.bytes().any(|b| b.is_ascii_digit() && b != b'0') {
+            formatted.insert(0, '-');
+        }
+        if !fraction.is_empty() {
+            formatted.push_str(decimal);
+            formatted.push_str(fraction);
+        }
+        formatted
+    }
+
+    /// A date given as YYYY-MM-DD, in the order and with the separators the locale uses. Anything
+    /// that isn't a date like that is returned as it is.
+    pub fn date(&self, iso: &str) -> String {
+        let parts: Vec<&str> = iso.split('-').collect();
+        let (year, month, day) = match parts[..] {
+            [year, month, day]
+                if year.len() == 4
+                    && month.len() == 2
+                    && day.len() == 2
+                    && parts
+                        .iter()
+                        .all(|part| part.bytes().all(|b| b.is_ascii_digit())) =>
+            {
+                (year, month, day)
+            }
+            _ => return iso.to_owned(),
+        };
+        match (self.language.as_str(), self.region.as_deref()) {
+            ("en", Some("US")) | ("en", None) => format!("{}/{}/{}", month, day, year),
+            ("en", _) | ("fr", _) | ("es", _) | ("it", _) | ("pt", _) => {
+                format!("{}/{}/{}", day, month, year)
+            }
+            ("de", _)
+            | ("ru", _)
+            | ("pl", _)
+            | ("cs", _)
+            | ("fi", _)
+            | ("nb", _)
+            | ("no", _)
+            | ("da", _)
+            | ("tr", _)
+            | ("uk", _) => format!("{}.{}.{}", day, month, year),
+            ("nl", _) => format!("{}-{}-{}", day, month, year),
+            ("ja", _) | ("zh", _) => format!("{}/{}/{}", year, month, day),
+            _ => iso.to_owned(),
+        }
+    }
+
+    /// The `n`th century, abbreviated the way dictionaries do, e.g. "15th c." or "XVe s.".
+    pub fn century(&self, n: u32) -> String {
+        match self.language.as_str() {
+            "fr" => format!("{}e s.", roman(n)),
+            "es" => format!("s. {}", roman(n)),
+            "it" => format!("{} sec.", roman(n)),
+            "pt" => format!("séc. {}", roman(n)),
+            "ru" | "uk" => format!("{} в.", roman(n)),
+            "de" => format!("{}. Jh.", n),
+            "nl" => format!("{}e eeuw", n),
+            "ja" | "zh" => format!("{}世紀", n),
+            _ => format!("{} c.", english_ordinal(n)),
+        }
+    }
+
+    /// Free text about when something was used, like a `{{defdate}}`'s "from 15th c.", with its
+    /// centuries and ranges of years written the locale's way.
+    pub fn dates(&self, text: &str) -> String {
+        let text = century_regex().replace_all(text, |caps: &Captures| {
+            let from = caps["from"].parse().unwrap_or(0);
+            match caps.name("to").and_then(|to| to.as_str().parse().ok()) {
+                Some(to) => self.century_range(from, to),
+                None => self.century(from),
+            }
+        });
+        year_range_regex()
+            .replace_all(&text, |caps: &Captures| {
+                self.year_range(&caps["from"], &caps["to"])
+            })
+            .into_owned()
+    }
+
+    /// The years from `from` to `to`, with an en dash. English can shorten the second one, as in
+    /// "1842–50"; other languages get it in full.
+    pub fn year_range(&self, from: &str, to: &str) -> String {
+        if self.language == "en" || self.language == "C" || to.len() >= from.len() {
+            return format!("{}–{}", from, to);
+        }
+        format!("{}–{}{}", from, &from[..from.len() - to.len()], to)
+    }
+
+    /// The centuries from `from` to `to`, e.g. "14th–16th c." or "XIVe–XVIe s.".
+    pub fn century_range(&self, from: u32, to: u32) -> String {
+        match self.language.as_str() {
+            "fr" => format!("{}e–{}e s.", roman(from), roman(to)),
+            "es" => format!("s. {}–{}", roman(from), roman(to)),
+            "it" => format!("{}–{} sec.", roman(from), roman(to)),
+            "pt" => format!("séc. {}–{}", roman(from), roman(to)),
+            "ru" | "uk" => format!("{}–{} вв.", roman(from), roman(to)),
+            "de" => format!("{}.–{}. Jh.", from, to),
+            "nl" => format!("{}e–{}e eeuw", from, to),
+            "ja" | "zh" => format!("{}–{}世紀", from, to),
+            _ => format!("{}–{} c.", english_ordinal(from), english_ordinal(to)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn locale(language: &str, region: Option<&str>) -> Locale {
+        Locale {
+            language: language.to_owned(),
+            region: region.map(str::to_owned),
+        }
+    }
+
+    #[test]
+    fn parses_names() {
+        assert_eq!(Locale::parse("pt_BR.UTF-8"), locale("pt", Some("BR")));
+        assert_eq!(Locale::parse("en-gb"), locale("en", Some("GB")));
+        assert_eq!(Locale::parse("de"), locale("de", None));
+        assert_eq!(Locale::parse("ca_ES@valencia"), locale("ca", Some("ES")));
+        for name in ["C", "POSIX", "C.UTF-8", "posix", ""] {
+            assert_eq!(Locale::parse(name), Locale::default(), "{:?}", name);
+        }
+    }
+
+    // pt_BR is written like pt-BR, which falls back to pt, and a language that isn't known
+    // falls back to the C locale.
+    #[test]
+    fn falls_back() {
+        let brazil = Locale::parse("pt_BR.UTF-8");
+        assert_eq!(brazil.number(1234567), "1.234.567");
+        assert_eq!(brazil.date("2024-03-09"), "09/03/2024");
+        assert_eq!(brazil.century(15), "séc. XV");
+        assert_eq!(
+            brazil.date("2024-03-09"),
+            Locale::parse("pt").date("2024-03-09")
+        );
+        let unknown = Locale::parse("xx_YY.UTF-8");
+        assert_eq!(unknown.number(1234567), "1234567");
+        assert_eq!(unknown.date("2024-03-09"), "2024-03-09");
+        assert_eq!(unknown.century(15), "15th c.");
+    }
+
+    #[test]
+    fn c_locale() {
+        let c = Locale::parse("C");
+        assert_eq!(c.number(-1234567), "-1234567");
+        assert_eq!(c.signed_number(12), "+12");
+        assert_eq!(c.decimal(4.75, 1), "4.8");
+        assert_eq!(c.date("2024-03-09"), "2024-03-09");
+        assert_eq!(
+            c.dates("from 14th-16th c., 1842-50"),
+            "from 14th–16th c., 1842–50"
+        );
+    }
+
+    #[test]
+    fn formats() {
+        let us = Locale::parse("en_US.UTF-8");
+        let gb = Locale::parse("en_GB.UTF-8");
+        let de = Locale::parse("de_DE.UTF-8");
+        let fr = Locale::parse("fr_FR.UTF-8");
+        assert_eq!(us.number(1234567), "1,234,567");
+        assert_eq!(fr.number(1234), "1\u{202f}234");
+        assert_eq!(de.decimal(-0.04, 1), "0,0");
+        assert_eq!(de.decimal(-1234.5, 1), "-1.234,5");
+        assert_eq!(us.date("2024-03-09"), "03/09/2024");
+        assert_eq!(gb.date("2024-03-09"), "09/03/2024");
+        assert_eq!(de.date("2024-03-09"), "09.03.2024");
+        assert_eq!(de.date("March 2024"), "March 2024");
+        assert_eq!(fr.dates("from 15th c."), "from XVe s.");
+        assert_eq!(de.dates("1842-50"), "1842–1850");
+        assert_eq!(english_ordinal(112), "112th");
+        assert_eq!(english_ordinal(21), "21st");
+    }
+}
@@ -0,0 +1,462 @@
+//! Looking words up the way the `define` CLI does, for tools that embed define3 instead of
+//! running it.
+
+use std::collections::BTreeMap;
+
+use dictionary::Dictionary;
+use expand::{expand_with, TemplatePolicies};
+use normalize::{swap_initial_case, Normalizers};
+use query::{CancelToken, Query, QueryError, QueryRequest, QueryResponse};
+use ranking::commonness_penalty;
+use Meaning;
+
+/// How `Dictionary::lookup_with` looks a word up.
+#[derive(Default)]
+pub struct SearchOptions {
+    /// Only this language (a normalized name), if set.
+    pub language: Option<String>,
+    /// Only these languages (normalized names), listed in this order, if any.
+    pub languages: Vec<String>,
+    /// Languages listed before the others, in this order.
+    pub preferred_languages: Vec<String>,
+    /// Languages not listed unless asked for with `language` or `languages`.
+    pub hidden_languages: Vec<String>,
+    /// Only this Wiktionary edition, if set.
+    pub edition: Option<String>,
+    /// What to try, in order, when the word itself isn't in the dictionary.
+    pub normalizers: Normalizers,
+    /// Only the best-ranked meaning, found without reading the rest.
+    pub first: bool,
+    /// Also find every headword that differs from the word only in the case of ASCII letters,
+    /// like both "polish" and "Polish"; see `LookupResult::others`.
+    pub ignore_case: bool,
+    /// Whether to try headwords that are the word but for diacritics, like "façade" for
+    /// "facade", when nothing else is found.
+    pub fold_diacritics: bool,
+}
+
+impl SearchOptions {
+    // Whether which languages are listed, and in what order, is up to these options rather than
+    // the dictionary.
+    fn arranges_languages(&self) -> bool {
+        !self.languages.is_empty()
+            || !self.preferred_languages.is_empty()
+            || !self.hidden_languages.is_empty()
+    }
+
+    // `meanings` in only the languages to list, and in their order. With `first`, only the one
+    // that ranks best in the first language.
+    fn arrange(&self, mut meanings: Vec<Meaning>) -> Vec<Meaning> {
+        if !self.arranges_languages() {
+            return meanings;
+        }
+        let order = if self.languages.is_empty() {
+            meanings.retain(|meaning| {
+                self.language.is_some() || !self.hidden_languages.contains(&meaning.language)
+            });
+            &self.preferred_languages
+        } else {
+            meanings.retain(|meaning| self.languages.contains(&meaning.language));
+            &self.languages
+        };
+        // Stable, so the rest keep the dictionary's order and each language stays together.
+        meanings.sort_by_key(|meaning| {
+            order
+                .iter()
+                .position(|language| *language == meaning.language)
+                .unwrap_or(order.len())
+        });
+        if self.first && !meanings.is_empty() {
+            let language = meanings[0].language.clone();
+            let best = meanings
+                .into_iter()
+                .take_while(|meaning| meaning.language == language)
+                .min_by_key(|meaning| commonness_penalty(&meaning.definition));
+            return best.into_iter().collect();
+        }
+        meanings
+    }
+}
+
+/// What `Dictionary::lookup_with` found.
+#[derive(Debug)]
+pub struct LookupResult {
+    /// The word as looked up, or the spelling that `SearchOptions::normalizers` turned up.
+    pub headword: String,
+    /// Empty if nothing was found.
+    pub meanings: Vec<Meaning>,
+    /// With `SearchOptions::ignore_case`, the other headwords that are the word but for case,
+    /// each with its own meanings. `headword` is the word as typed if it's there, or else the
+    /// first of them in collated order.
+    pub others: Vec<LookupResult>,
+}
+
+/// (language, meanings by part of speech) pairs.
+pub type Grouped = Vec<(String, BTreeMap<String, Vec<Meaning>>)>;
+
+/// Groups meanings by language, in the order each language first comes up, and then by part of
+/// speech.
+pub fn group_by_language(meanings: Vec<Meaning>) -> Grouped {
+    let mut groups: Grouped = Vec::new();
+    for meaning in meanings {
+        let i = match groups
+            .iter()
+            .position(|(language, _)| *language == meaning.language)
+        {
+            Some(i) => i,
+            None => {
+                groups.push((meaning.language.clone(), BTreeMap::new()));
+                groups.len() - 1
+            }
+        };
+        groups[i]
+            .1
+            .entry(meaning.part_of_speech.clone())
+            .or_default()
+            .push(meaning);
+    }
+    groups
+}
+
+// How many edits away from a word of `length` characters a suggestion can be. Longer words have
+// more room for typos.
+fn max_suggestion_distance(length: usize) -> usize {
+    1 + length / 5
+}
+
+impl Dictionary {
+    /// Looks up a word with filters, falling back to other spellings of it (e.g. Latin typed
+    /// without length marks, or Japanese in romaji) if it isn't there as typed.
+    pub fn lookup_with(
+        &self,
+        word: &str,
+        options: &SearchOptions,
+    ) -> Result<LookupResult, QueryError> {
+        self.lookup_cancellable(word, options, &CancelToken::new())
+    }
+
+    /// `lookup_with` that another thread can stop; see `Dictionary::run`.
+    pub fn lookup_cancellable(
+        &self,
+        word: &str,
+        options: &SearchOptions,
+        cancel: &CancelToken,
+    ) -> Result<LookupResult, QueryError> {
+        let query = |word: &str| {
+            // The best meaning that `Query::First` finds might be in a language that isn't
+            // listed, or isn't listed first.
+            if options.first && !options.arranges_languages() {
+                Query::First(word.to_owned())
+            } else {
+                Query::Lookup(word.to_owned())
+            }
+        };
+        let mut request = QueryRequest::new(query(word));
+        request.language = options.language.clone();
+        request.edition = options.edition.clone();
+        if options.ignore_case {
+            let mut found: Vec<LookupResult> = Vec::new();
+            for name in self.case_variants(word)? {
+                request.query = query(&name);
+                let meanings = options.arrange(self.lookup_request(&request, cancel)?);
+                if !meanings.is_empty() {
+                    found.push(LookupResult {
+                        headword: name,
+                        meanings,
+                        others: Vec::new(),
+                    });
+                }
+                // Only the best one is wanted, and the word as typed comes first.
+                if options.first && !found.is_empty() {
+                    break;
+                }
+            }
+            if !found.is_empty() {
+                let mut result = found.remove(0);
+                if options.first {
+                    result.meanings.truncate(1);
+                }
+                result.others = found;
+                return Ok(result);
+            }
+        } else {
+            let mut meanings = options.arrange(self.lookup_request(&request, cancel)?);
+            if options.first {
+                meanings.truncate(1);
+            }
+            if !meanings.is_empty() {
+                return Ok(LookupResult {
+                    headword: word.to_owned(),
+                    meanings,
+                    others: Vec::new(),
+                });
+            }
+        }
+        let mut alternatives = Vec::new();
+        if options.normalizers.initial_case {
+            alternatives.extend(swap_initial_case(word));
+        }
+        if options.normalizers.fold {
+            alternatives.extend(self.normalized_matches(word)?);
+        }
+        if options.fold_diacritics {
+            for name in self.unaccented_matches(word)? {
+                if !alternatives.contains(&name) {
+                    alternatives.push(name);
+                }
+            }
+        }
+        alternatives.extend(
+            options
+                .normalizers
+                .convert(options.language.as_deref(), word),
+        );
+        for alternative in alternatives {
+            request.query = query(&alternative);
+            let meanings = options.arrange(self.lookup_request(&request, cancel)?);
+            if !meanings.is_empty() {
+                return Ok(LookupResult {
+                    headword: alternative,
+                    meanings,
+                    others: Vec::new(),
+                });
+            }
+        }
+        Ok(LookupResult {
+            headword: word.to_owned(),
+            meanings: Vec::new(),
+            others: Vec::new(),
+        })
+    }
+
+    /// `lookup_with` for a list of words, with the ones that are there as typed read in one query
+    /// rather than one each. The rest, and every word when `ignore_case` or `first` need queries
+    /// of their own, are looked up one at a time.
+    pub fn lookup_many_with(
+        &self,
+        words: &[&str],
+        options: &SearchOptions,
+    ) -> Result<Vec<LookupResult>, QueryError> {
+        if options.ignore_case || (options.first && !options.arranges_languages()) {
+            return words
+                .iter()
+                .map(|word| self.lookup_with(word, options))
+                .collect();
+        }
+        let mut results = Vec::with_capacity(words.len());
+        for word in self.lookup_many(words)? {
+            let mut meanings = word.meanings;
+            // As `Query::Lookup` filters them.
+            meanings.retain(|meaning| {
+                options
+                    .language
+                    .as_ref()
+                    .is_none_or(|language| meaning.language == *language)
+                    && (options.edition.is_none() || meaning.edition == options.edition)
+            });
+            let mut meanings = options.arrange(meanings);
+            if options.first {
+                meanings.truncate(1);
+            }
+            results.push(if meanings.is_empty() {
+                self.lookup_with(&word.name, options)?
+            } else {
+                LookupResult {
+                    headword: word.name,
+                    meanings,
+                    others: Vec::new(),
+                }
+            });
+        }
+        Ok(results)
+    }
+
+    fn lookup_request(
+        &self,
+        request: &QueryRequest,
+        cancel: &CancelToken,
+    ) -> Result<Vec<Meaning>, QueryError> {
+        match self.run(request, cancel)? {
+            QueryResponse::Meanings(meanings) => Ok(meanings),
+            response => unreachable!("{:?}", response),
+        }
+    }
+
+    /// Headwords close to `word` by edit distance, closest first, for suggesting when it isn't in
+    /// the dictionary. Only those starting with the same letter (in either case) are considered,
+    /// which keeps it from comparing against every headword and is where typos are rarest.
+    pub fn suggestions(&self, word: &str, limit: usize) -> rusqlite::Result<Vec<String>> {
+        let first = match word.chars().next() {
+            Some(first) => first,
+            None => return Ok(Vec::new()),
+        };
+        // [start, end) ranges of headwords starting with each form of the first letter.
+        let range = |c: char| {
+            let end = char::from_u32(c as u32 + 1).unwrap_or(char::MAX);
+            (c.to_string(), end.to_string())
+        };
+        let (lower_start, lower_end) = range(first.to_lowercase().next().unwrap_or(first));
+        let (upper_start, upper_end) = range(first.to_uppercase().next().unwrap_or(first));
+        let length = word.chars().count();
+        let max_distance = max_suggestion_distance(length);
+        let mut stmt = self.connection().prepare_cached(
+            "SELECT name, editdist(name, ?1) AS distance FROM (
+                 SELECT DISTINCT name FROM words
+                 WHERE ((name >= ?2 AND name < ?3) OR (name >= ?4 AND name < ?5))
+                   AND length(name) BETWEEN ?6 AND ?7 AND name != ?1
+             )
+             WHERE distance <= ?8 ORDER BY distance, name LIMIT ?9",
+        )?;
+        let names = stmt.query_map(
+            rusqlite::params![
+                word,
+                lower_start,
+                lower_end,
+                upper_start,
+                upper_end,
+                length.saturating_sub(max_distance) as i64,
+                (length + max_distance) as i64,
+                max_distance as i64,
+                limit as i64,
+            ],
+            |row| row.get(0),
+        )?;
+        names.collect()
+    }
+
+    /// Expands the templates in a definition as plain text, with the template bodies in the
+    /// dictionary and the default policies for the ones that can't be expanded.
+    pub fn expand(&self, definition: &str) -> String {
+        expand_with(definition, &TemplatePolicies::default(), self).0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_db::TestDb;
+
+    fn meaning(language: &str, part_of_speech: &str, definition: &str) -> Meaning {
+        Meaning {
+            language: language.to_owned(),
+            part_of_speech: part_of_speech.to_owned(),
+            definition: definition.to_owned(),
+            expanded: None,
+            edition: None,
+        }
+    }
+
+    #[test]
+    fn group_by_language_merges_runs_apart() {
+        let grouped = group_by_language(vec![
+            meaning("English", "Noun", "a"),
+            meaning("French", "Noun", "b"),
+            meaning("English", "Proper noun", "c"),
+        ]);
+        let languages: Vec<&str> = grouped
+            .iter()
+            .map(|(language, _)| language.as_str())
+            .collect();
+        assert_eq!(languages, ["English", "French"]);
+        assert_eq!(grouped[0].1.len(), 2);
+    }
+
+    fn cats() -> TestDb {
+        TestDb::baseline(&[
+            ("cat", "English", "Noun", "A feline."),
+            ("Cat", "English", "Proper noun", "A nickname for Catherine."),
+            ("CAT", "English", "Noun", "Computerized axial tomography."),
+        ])
+    }
+
+    #[test]
+    fn ignore_case_keeps_headwords_apart() {
+        let db = cats();
+        let dictionary = db.open();
+        let options = SearchOptions {
+            ignore_case: true,
+            ..SearchOptions::default()
+        };
+        let found = dictionary.lookup_with("Cat", &options).unwrap();
+        assert_eq!(found.headword, "Cat");
+        assert_eq!(found.meanings.len(), 1);
+        assert_eq!(found.meanings[0].part_of_speech, "Proper noun");
+        let others: Vec<&str> = found
+            .others
+            .iter()
+            .map(|other| other.headword.as_str())
+            .collect();
+        assert_eq!(others.len(), 2);
+        assert!(others.contains(&"cat") && others.contains(&"CAT"));
+        for other in &found.others {
+            assert_eq!(other.meanings.len(), 1);
+            assert!(other.others.is_empty());
+        }
+
+        // Spelt in none of the ways that are there, the first that is leads.
+        let found = dictionary.lookup_with("cAt", &options).unwrap();
+        assert!(found
+            .meanings
+            .iter()
+            .all(|meaning| meaning.language == "English"));
+        assert_eq!(found.others.len(), 2);
+    }
+
+    #[test]
+    fn ignore_case_first() {
+        let db = cats();
+        let dictionary = db.open();
+        let options = SearchOptions {
+            ignore_case: true,
+            first: true,
+            ..SearchOptions::default()
+        };
+        let found = dictionary.lookup_with("cat", &options).unwrap();
+        assert_eq!(found.headword, "cat");
+        assert_eq!(found.meanings.len(), 1);
+        assert!(found.others.is_empty());
+    }
+
+    #[test]
+    fn without_ignore_case() {
+        let db = cats();
+        let dictionary = db.open();
+        let found = dictionary
+            .lookup_with("Cat", &SearchOptions::default())
+            .unwrap();
+        assert_eq!(found.headword, "Cat");
+        assert!(found.others.is_empty());
+        // Nothing called "cAt", and swapping its first letter's case doesn't help either.
+        let found = dictionary
+            .lookup_with("cAt", &SearchOptions::default())
+            .unwrap();
+        assert!(found.meanings.is_empty());
+    }
+
+    #[test]
+    fn lookup_many_with_finds_what_lookup_with_does() {
+        let db = TestDb::baseline(&[
+            ("cat", "English", "Noun", "A feline."),
+            ("cat", "French", "Noun", "A catamaran."),
+            ("Dog", "English", "Proper noun", "A dog's name."),
+        ]);
+        let dictionary = db.open();
+        let options = SearchOptions {
+            language: Some("English".to_owned()),
+            normalizers: Normalizers {
+                initial_case: true,
+                ..Normalizers::default()
+            },
+            ..SearchOptions::default()
+        };
+        let words = ["cat", "dog", "bird"];
+        let found = dictionary.lookup_many_with(&words, &options).unwrap();
+        let headwords: Vec<&str> = found.iter().map(|found| found.headword.as_str()).collect();
+        assert_eq!(headwords, ["cat", "Dog", "bird"]);
+        for (word, found) in words.iter().zip(found) {
+            let expected = dictionary.lookup_with(word, &options).unwrap();
+            assert_eq!(found.headword, expected.headword);
+            assert_eq!(found.meanings, expected.meanings);
+        }
+    }
+}
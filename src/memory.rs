@@ -0,0 +1,61 @@
+//! A low-memory profile, for running define3 on something like a 512MB Raspberry Pi serving the
+//! house its dictionary. It's on if asked for or if the machine looks small, and then every
+//! database define3 opens keeps less in memory, trading some speed for it.
+
+use std::fs;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use rusqlite::{Connection, Result};
+
+// Machines with less memory than this get the profile unless told otherwise.
+const LOW_MEMORY_TOTAL_KB: u64 = 1024 * 1024;
+
+// SQLite's page cache per connection with the profile, in KiB. Its default is 2MB, which adds up
+// with a connection per client.
+const LOW_MEMORY_CACHE_KB: u64 = 512;
+
+static LOW_MEMORY: AtomicBool = AtomicBool::new(false);
+
+/// Turns the profile on or off for databases opened from now on.
+pub fn set_low_memory(on: bool) {
+    LOW_MEMORY.store(on, Ordering::Relaxed);
+}
+
+pub fn low_memory() -> bool {
+    LOW_MEMORY.load(Ordering::Relaxed)
+}
+
+/// Whether this machine has little enough memory to want the profile. Only Linux says, through
+/// /proc/meminfo; elsewhere it's never on unless asked for.
+pub fn detect_low_memory() -> bool {
+    let meminfo = match fs::read_to_string("/proc/meminfo") {
+        Ok(meminfo) => meminfo,
+        Err(_) => return false,
+    };
+    meminfo
+        .lines()
+        .find_map(|line| line.strip_prefix("MemTotal:"))
+        .and_then(|total| {
+            total
+                .trim()
+                .trim_end_matches("kB")
+                .trim()
+                .parse::<u64>()
+                .ok()
+        })
+        .is_some_and(|total| total < LOW_MEMORY_TOTAL_KB)
+}
+
+/// Makes `conn` keep as little in memory as it reasonably can, if the profile is on: a small page
+/// cache, no memory-mapped I/O, and temporary tables and indexes on disk.
+pub fn limit_memory(conn: &Connection) -> Result<()> {
+    if !low_memory() {
+        return Ok(());
+    }
+    conn.execute_batch(&format!(
+        "PRAGMA cache_size = -{};
+         PRAGMA mmap_size = 0;
+         PRAGMA temp_store = FILE;",
+        LOW_MEMORY_CACHE_KB
+    ))
+}
@@ -0,0 +1,324 @@
+//! How what's typed is turned into what's looked up. Folds make headwords and queries compare
+//! equal despite differences people don't type (case, length marks, accents) and apply to both:
+//! headwords are folded into `words.normalized_name` at import time. Input converters turn
+//! romanizations and other input systems into headwords, and only apply to queries.
+
+use std::fmt;
+use std::str::FromStr;
+
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+
+use conlang::{EsperantoXSystem, LojbanRafsi};
+use romanization::{NumberedPinyin, RevisedRomanization, Romaji};
+
+/// Removes macrons and breves, precomposed or combining, which Latin and some romanizations use
+/// to mark vowel length but hardly anyone types.
+pub fn fold_length_marks(s: &str) -> String {
+    s.chars()
+        .filter(|&c| c != '\u{304}' && c != '\u{306}')
+        .map(|c| match c {
+            'ā' | 'ă' => 'a',
+            'ē' | 'ĕ' => 'e',
+            'ī' | 'ĭ' => 'i',
+            'ō' | 'ŏ' => 'o',
+            'ū' | 'ŭ' => 'u',
+            'ȳ' => 'y',
+            'Ā' | 'Ă' => 'A',
+            'Ē' | 'Ĕ' => 'E',
+            'Ī' | 'Ĭ' => 'I',
+            'Ō' | 'Ŏ' => 'O',
+            'Ū' | 'Ŭ' => 'U',
+            'Ȳ' => 'Y',
+            _ => c,
+        })
+        .collect()
+}
+
+/// `word` with its first letter in the other case, e.g. "Polish" for "polish" and back, if that
+/// makes a different word.
+pub fn swap_initial_case(word: &str) -> Option<String> {
+    let mut chars = word.chars();
+    let first = chars.next()?;
+    let swapped: String = if first.is_lowercase() {
+        first.to_uppercase().collect()
+    } else {
+        first.to_lowercase().collect()
+    };
+    let swapped = swapped + chars.as_str();
+    Some(swapped).filter(|swapped| swapped != word)
+}
+
+/// Takes the accents and other marks off letters, as in "façade" to "facade" and "résumé" to
+/// "resume", by decomposing them (NFKD) and dropping the combining marks. Compatibility forms are
+/// decomposed too, so "ﬁ" is "fi".
+pub fn fold_diacritics(s: &str) -> String {
+    s.nfkd().filter(|&c| !is_combining_mark(c)).collect()
+}
+
+/// A difference between spellings that lookups can ignore.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Fold {
+    Case,
+    /// See `fold_length_marks`.
+    LengthMarks,
+    /// See `fold_diacritics`.
+    Diacritics,
+}
+
+// What `build_definitions_db` folds names with unless told otherwise.
+pub const DEFAULT_FOLDS: &[Fold] = &[Fold::LengthMarks];
+
+impl Fold {
+    const ALL: &'static [Fold] = &[Fold::Case, Fold::LengthMarks, Fold::Diacritics];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Fold::Case => "case",
+            Fold::LengthMarks => "length-marks",
+            Fold::Diacritics => "diacritics",
+        }
+    }
+
+    pub fn apply(self, s: &str) -> String {
+        match self {
+            Fold::Case => s.to_lowercase(),
+            Fold::LengthMarks => fold_length_marks(s),
+            Fold::Diacritics => fold_diacritics(s),
+        }
+    }
+}
+
+impl fmt::Display for Fold {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Fold {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Fold, String> {
+        Fold::ALL
+            .iter()
+            .cloned()
+            .find(|fold| fold.name() == s)
+            .ok_or_else(|| format!("unknown fold {:?}", s))
+    }
+}
+
+/// Applies `folds` in order.
+pub fn fold(s: &str, folds: &[Fold]) -> String {
+    folds
+        .iter()
+        .fold(s.to_owned(), |folded, fold| fold.apply(&folded))
+}
+
+/// Comma-separated fold names, as stored in a database's metadata.
+pub fn folds_to_string(folds: &[Fold]) -> String {
+    folds
+        .iter()
+        .map(|fold| fold.name())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Parses `folds_to_string`'s output.
+pub fn parse_folds(s: &str) -> Result<Vec<Fold>, String> {
+    s.split(',')
+        .filter(|name| !name.is_empty())
+        .map(str::parse)
+        .collect()
+}
+
+/// Turns input typed one way into headwords written another, e.g. romanized Japanese into kana.
+pub trait InputConverter: Send + Sync {
+    /// What the step is called in a `Normalizers` spec.
+    fn name(&self) -> &'static str;
+
+    /// The (normalized) name of the language it produces words in.
+    fn language(&self) -> &'static str;
+
+    /// Headwords to try, most likely first. Empty if `input` isn't something this converts.
+    fn convert(&self, input: &str) -> Vec<String>;
+}
+
+fn builtin_converters() -> Vec<Box<dyn InputConverter>> {
+    vec![
+        Box::new(NumberedPinyin),
+        Box::new(EsperantoXSystem),
+        Box::new(Romaji),
+        Box::new(RevisedRomanization),
+        Box::new(LojbanRafsi),
+    ]
+}
+
+/// The steps a query goes through, in order, when nothing has exactly the name it asked for.
+pub struct Normalizers {
+    /// Whether to try the word with its first letter's case swapped; see `swap_initial_case`.
+    pub initial_case: bool,
+    /// Whether to look for headwords with the same folded name. Which folds that means is up to
+    /// the database; see `Dictionary::normalized_matches`.
+    pub fold: bool,
+    pub converters: Vec<Box<dyn InputConverter>>,
+}
+
+impl Default for Normalizers {
+    /// Everything built in.
+    fn default() -> Self {
+        Normalizers {
+            initial_case: true,
+            fold: true,
+            converters: builtin_converters(),
+        }
+    }
+}
+
+impl Normalizers {
+    /// Every step's name, for help texts.
+    pub fn names() -> Vec<&'static str> {
+        let mut names = vec!["initial-case", "fold"];
+        names.extend(
+            builtin_converters()
+                .iter()
+                .map(|converter| converter.name()),
+        );
+        names
+    }
+
+    /// Parses a comma-separated list of step names (see `names`), or "none".
+    pub fn parse(spec: &str) -> Result<Normalizers, String> {
+        let mut normalizers = Normalizers {
+            initial_case: false,
+            fold: false,
+            converters: Vec::new(),
+        };
+        let mut converters = builtin_converters();
+        for name in spec.split(',').map(str::trim) {
+            if name == "none" {
+                continue;
+            } else if name == "initial-case" {
+                normalizers.initial_case = true;
+            } else if name == "fold" {
+                normalizers.fold = true;
+            } else if let Some(i) = converters.iter().position(|c| c.name() == name) {
+                normalizers.converters.push(converters.remove(i));
+            } else {
+                return Err(format!(
+                    "unknown normalizer {:?} (expected {} or none)",
+                    name,
+                    Normalizers::names().join(", ")
+                ));
+            }
+        }
+        Ok(normalizers)
+    }
+
+    /// What the converters make of `input`, for `language` only if it's set, without duplicates.
+    pub fn convert(&self, language: Option<&str>, input: &str) -> Vec<String> {
+        let mut candidates: Vec<String> = Vec::new();
+        for converter in &self.converters {
+            if language.is_some_and(|language| language != converter.language()) {
+                continue;
+            }
+            for candidate in converter.convert(input) {
+                if candidate != input && !candidates.contains(&candidate) {
+                    candidates.push(candidate);
+                }
+            }
+        }
+        candidates
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn length_marks() {
+        assert_eq!(fold_length_marks("cārus"), "carus");
+        assert_eq!(fold_length_marks("Rōma ămō"), "Roma amo");
+        assert_eq!(fold_length_marks("ca\u{304}rus"), "carus");
+        assert_eq!(fold_length_marks("façade"), "façade");
+    }
+
+    #[test]
+    fn initial_case() {
+        assert_eq!(swap_initial_case("polish").as_deref(), Some("Polish"));
+        assert_eq!(swap_initial_case("Polish").as_deref(), Some("polish"));
+        assert_eq!(swap_initial_case("ébène").as_deref(), Some("Ébène"));
+        assert_eq!(swap_initial_case("123"), None);
+        assert_eq!(swap_initial_case(""), None);
+    }
+
+    #[test]
+    fn diacritics() {
+        assert_eq!(fold_diacritics("façade"), "facade");
+        assert_eq!(fold_diacritics("résumé"), "resume");
+        assert_eq!(fold_diacritics("ﬁne"), "fine");
+        assert_eq!(fold_diacritics("猫"), "猫");
+    }
+
+    #[test]
+    fn folds_in_order() {
+        assert_eq!(
+            fold("Cārus Façade", &[Fold::Case, Fold::LengthMarks]),
+            "carus façade"
+        );
+        assert_eq!(
+            fold("Cārus Façade", &[Fold::Diacritics, Fold::Case]),
+            "carus facade"
+        );
+        assert_eq!(fold("Cārus", &[]), "Cārus");
+    }
+
+    #[test]
+    fn fold_names_round_trip() {
+        let folds = [Fold::Case, Fold::LengthMarks, Fold::Diacritics];
+        let names = folds_to_string(&folds);
+        assert_eq!(names, "case,length-marks,diacritics");
+        assert_eq!(parse_folds(&names).unwrap(), folds);
+        assert_eq!(parse_folds("").unwrap(), []);
+        assert_eq!(Fold::LengthMarks.to_string(), "length-marks");
+        assert!(parse_folds("case,accents").is_err());
+    }
+
+    #[test]
+    fn parse_normalizers() {
+        let normalizers = Normalizers::parse("fold, romaji").unwrap();
+        assert!(!normalizers.initial_case);
+        assert!(normalizers.fold);
+        let names: Vec<_> = normalizers.converters.iter().map(|c| c.name()).collect();
+        assert_eq!(names, ["romaji"]);
+
+        let none = Normalizers::parse("none").unwrap();
+        assert!(!none.initial_case && !none.fold && none.converters.is_empty());
+
+        let e = Normalizers::parse("initial-case,klingon").err().unwrap();
+        assert!(e.contains("\"klingon\""), "{}", e);
+        assert!(e.contains("x-system"), "{}", e);
+    }
+
+    #[test]
+    fn names_every_step() {
+        let names = Normalizers::names();
+        assert_eq!(&names[..2], ["initial-case", "fold"]);
+        for converter in builtin_converters() {
+            assert!(names.contains(&converter.name()), "{}", converter.name());
+        }
+    }
+
+    #[test]
+    fn converts_only_for_the_language() {
+        let normalizers = Normalizers::default();
+        assert_eq!(normalizers.convert(None, "cxevalo"), ["ĉevalo"]);
+        assert_eq!(
+            normalizers.convert(Some("Esperanto"), "cxevalo"),
+            ["ĉevalo"]
+        );
+        assert!(normalizers.convert(Some("Japanese"), "cxevalo").is_empty());
+        let none = Normalizers::parse("none").unwrap();
+        assert!(none.convert(None, "cxevalo").is_empty());
+    }
+}
@@ -0,0 +1,101 @@
+use utoipa::{OpenApi, ToSchema};
+
+use http_server;
+use pagination::ResultPage;
+
+// The shapes below are what `json::entry_json` builds, which builds them as serde_json values,
+// so they're only here to be described. Keep them in step with it.
+
+/// A word's entry, from `/define/{word}`.
+#[derive(ToSchema)]
+#[allow(dead_code)]
+pub struct Entry {
+    /// What was looked up.
+    query: String,
+    /// The headword found, which can differ from the query, e.g. in case.
+    word: String,
+    /// The entry's checksum, also sent as its ETag, if the database has a build id.
+    checksum: Option<String>,
+    languages: Vec<EntryLanguage>,
+    /// Similar words, only when nothing was found.
+    suggestions: Option<Vec<String>>,
+}
+
+#[derive(ToSchema)]
+#[allow(dead_code)]
+pub struct EntryLanguage {
+    language: String,
+    /// This language's section of the entry on this server.
+    url: String,
+    /// Extra lines to show under the headword, e.g. its spelling without length marks.
+    headword_lines: Vec<String>,
+    parts_of_speech: Vec<EntryPartOfSpeech>,
+}
+
+#[derive(ToSchema)]
+#[allow(dead_code)]
+pub struct EntryPartOfSpeech {
+    part_of_speech: String,
+    definitions: Vec<EntryDefinition>,
+}
+
+#[derive(ToSchema)]
+#[allow(dead_code)]
+pub struct EntryDefinition {
+    /// With templates expanded, as plain text.
+    text: String,
+    /// The wikitext it was imported as.
+    raw: String,
+    /// The Wiktionary edition it's from, e.g. "en", if known.
+    edition: Option<String>,
+    /// The LaTeX of each formula in it.
+    math: Vec<String>,
+    references: Vec<EntryReference>,
+    /// Problems found while expanding it.
+    warnings: Vec<String>,
+}
+
+/// Another entry a definition refers to.
+#[derive(ToSchema)]
+#[allow(dead_code)]
+pub struct EntryReference {
+    target: String,
+    language: Option<String>,
+    /// The anchor of the language's section on the target's page.
+    anchor: Option<String>,
+}
+
+/// What an endpoint that fails sends, with a status of 400 or more.
+#[derive(ToSchema)]
+#[allow(dead_code)]
+pub struct ErrorResponse {
+    error: String,
+}
+
+/// The OpenAPI 3 document describing the HTTP API, built from the types it returns.
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "define3", description = "Definitions from Wiktionary."),
+    paths(
+        http_server::define,
+        http_server::search,
+        http_server::embed,
+        http_server::oembed,
+        http_server::feed
+    ),
+    components(schemas(
+        Entry,
+        EntryLanguage,
+        EntryPartOfSpeech,
+        EntryDefinition,
+        EntryReference,
+        ErrorResponse,
+        ResultPage<String>
+    ))
+)]
+pub struct ApiDoc;
+
+/// `ApiDoc` as pretty-printed JSON, for serving at `/openapi.json`.
+pub fn openapi_json() -> String {
+    ApiDoc::openapi().to_pretty_json().unwrap()
+}
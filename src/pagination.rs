@@ -0,0 +1,100 @@
+use std::fmt::Write;
+
+/// Where a page of results left off: the sort key of its last item. Results are ordered by
+/// `(rank, key)`, so the next page is everything after it. Unlike an offset, this stays right
+/// when rows are added or removed between requests.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cursor {
+    /// Lower is better. Searches that don't rank their results use 0 for everything.
+    pub rank: i64,
+    pub key: String,
+}
+
+impl Cursor {
+    /// Encodes the cursor as an opaque, URL-safe token for clients to hand back.
+    pub fn encode(&self) -> String {
+        let plain = format!("{}:{}", self.rank, self.key);
+        let mut token = String::with_capacity(plain.len() * 2);
+        for byte in plain.bytes() {
+            write!(token, "{:02x}", byte).unwrap();
+        }
+        token
+    }
+
+    /// The inverse of `encode`; `None` if the token wasn't made by it.
+    pub fn decode(token: &str) -> Option<Cursor> {
+        if !token.len().is_multiple_of(2) {
+            return None;
+        }
+        let bytes = (0..token.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(token.get(i..i + 2)?, 16).ok())
+            .collect::<Option<Vec<u8>>>()?;
+        let plain = String::from_utf8(bytes).ok()?;
+        let (rank, key) = plain.split_once(':')?;
+        Some(Cursor {
+            rank: rank.parse().ok()?,
+            key: key.to_owned(),
+        })
+    }
+}
+
+/// One page of results, and the cursor for the next one if there's more.
+#[derive(Debug)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct ResultPage<T> {
+    pub items: Vec<T>,
+    /// Sent to clients as the token from `Cursor::encode`.
+    #[cfg_attr(feature = "openapi", schema(value_type = Option<String>))]
+    pub next: Option<Cursor>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips() {
+        for cursor in &[
+            Cursor {
+                rank: 0,
+                key: "cat".to_owned(),
+            },
+            Cursor {
+                rank: -42,
+                key: "a:b:c".to_owned(),
+            },
+            Cursor {
+                rank: i64::MAX,
+                key: "猫 façade".to_owned(),
+            },
+            Cursor {
+                rank: 7,
+                key: String::new(),
+            },
+        ] {
+            let token = cursor.encode();
+            assert!(token.bytes().all(|b| b.is_ascii_hexdigit()), "{}", token);
+            assert_eq!(Cursor::decode(&token).as_ref(), Some(cursor));
+        }
+    }
+
+    #[test]
+    fn rejects_tokens_it_did_not_make() {
+        let token = Cursor {
+            rank: 1,
+            key: "cat".to_owned(),
+        }
+        .encode();
+        assert_eq!(Cursor::decode(&token[1..]), None);
+        assert_eq!(Cursor::decode("zz"), None);
+        assert_eq!(Cursor::decode("é1"), None);
+        // "cat", with no rank.
+        assert_eq!(Cursor::decode("636174"), None);
+        // "x:cat", whose rank isn't a number.
+        assert_eq!(Cursor::decode("783a636174"), None);
+        // Not UTF-8.
+        assert_eq!(Cursor::decode("303aff"), None);
+        assert_eq!(Cursor::decode(""), None);
+    }
+}
@@ -1,6 +1,13 @@
+use std::borrow::Cow;
 use std::collections::HashSet;
 
-use Meaning;
+use regex::Regex;
+
+use headword::headword_template;
+use references::find_references;
+use sql_functions::levenshtein;
+use templates::{parse_templates, Node};
+use {Etymology, Example, Meaning, Pronunciation, Relation};
 
 #[derive(Debug, PartialEq)]
 pub enum WikiContext {
@@ -15,25 +22,37 @@ pub enum WikiContext {
 use parse_wikitext::WikiContext::*;
 
 impl WikiContext {
+    /// A heading at `level`, 1 to 6.
+    pub fn new(level: usize, text: String) -> WikiContext {
+        match level {
+            1 => Heading1(text),
+            2 => Heading2(text),
+            3 => Heading3(text),
+            4 => Heading4(text),
+            5 => Heading5(text),
+            _ => Heading6(text),
+        }
+    }
+
     pub fn precedence(&self) -> u32 {
-        match self {
-            &Heading1(_) => 1,
-            &Heading2(_) => 2,
-            &Heading3(_) => 3,
-            &Heading4(_) => 4,
-            &Heading5(_) => 5,
-            &Heading6(_) => 6,
+        match *self {
+            Heading1(_) => 1,
+            Heading2(_) => 2,
+            Heading3(_) => 3,
+            Heading4(_) => 4,
+            Heading5(_) => 5,
+            Heading6(_) => 6,
         }
     }
 
     pub fn text(&self) -> &String {
         match self {
-            &Heading1(ref x) => &x,
-            &Heading2(ref x) => &x,
-            &Heading3(ref x) => &x,
-            &Heading4(ref x) => &x,
-            &Heading5(ref x) => &x,
-            &Heading6(ref x) => &x,
+            Heading1(x) => x,
+            Heading2(x) => x,
+            Heading3(x) => x,
+            Heading4(x) => x,
+            Heading5(x) => x,
+            Heading6(x) => x,
         }
     }
 }
@@ -44,6 +63,12 @@ pub struct ContextStack {
     pub part_of_speech: Option<String>,
 }
 
+impl Default for ContextStack {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl ContextStack {
     pub fn apply(
         &mut self,
@@ -54,10 +79,7 @@ impl ContextStack {
         let new_prec = context.precedence();
         // leave only lower-precedence contexts in the stack
         let contexts = &mut self.contexts;
-        while contexts
-            .last()
-            .map_or(false, |c| c.precedence() >= new_prec)
-        {
+        while contexts.last().is_some_and(|c| c.precedence() >= new_prec) {
             match contexts.pop() {
                 None => (),
                 Some(context) => {
@@ -79,6 +101,11 @@ impl ContextStack {
         contexts.push(context);
     }
 
+    /// Whether no heading has been seen yet.
+    pub fn is_empty(&self) -> bool {
+        self.contexts.is_empty()
+    }
+
     pub fn new() -> ContextStack {
         ContextStack {
             contexts: Vec::new(),
@@ -88,82 +115,1194 @@ impl ContextStack {
     }
 }
 
+/// Everything we extract from a page's wikitext.
+#[derive(Debug, Default)]
+pub struct ParsedWikitext {
+    pub meanings: Vec<Meaning>,
+    pub relations: Vec<Relation>,
+    pub etymologies: Vec<Etymology>,
+    /// One for each language with a pronunciation section.
+    pub pronunciations: Vec<Pronunciation>,
+    pub examples: Vec<Example>,
+    /// (language, part of speech, template) for the headword template under each part of speech
+    /// heading, for `headword::grammar` once the word is known.
+    pub headword_templates: Vec<(String, String, String)>,
+    /// Lines that looked like headings but weren't.
+    pub unparseable_lines: Vec<String>,
+    /// Section headers we don't know what to do with, e.g. a part of speech we don't import.
+    pub unknown_headers: Vec<String>,
+    /// Languages on the page that aren't imported.
+    pub skipped_languages: Vec<String>,
+    /// (kind, line) for lines that were malformed but could be made sense of anyway.
+    pub anomalies: Vec<(&'static str, String)>,
+    /// (header, what it was corrected to) for near-miss headers, with a `HeaderCorrector`.
+    pub corrected_headers: Vec<(String, String)>,
+}
+
+// TODO: figure out list of languages automatically. Other editions also name their language and
+// part-of-speech headers in their own language, so for now only en pages parse well.
+/// The languages the importer keeps.
+pub const IMPORTED_LANGUAGES: &[&str] = &[
+    "Alemannic German",
+    "Chinese",
+    "English",
+    "Esperanto",
+    "French",
+    "German",
+    "Japanese",
+    "Kazakh",
+    "Korean",
+    "Latin",
+    "Lojban",
+    "Serbo-Croatian",
+    "Toki Pona",
+    "Translingual",
+];
+
+// TODO: figure out POS list automatically
+/// The part-of-speech headers the importer keeps definitions from.
+pub const IMPORTED_PARTS_OF_SPEECH: &[&str] = &[
+    "Adjective",
+    "Adverb",
+    "Brivla",
+    "Cmavo",
+    "Conjunction",
+    "Definitions",
+    "Gismu",
+    "Hanja",
+    "Hanzi",
+    "Infix",
+    "Initialism",
+    "Interjection",
+    "Kanji",
+    "Noun",
+    "Phrase",
+    "Proper noun",
+    "Rafsi",
+    "Romanization",
+    "Symbol",
+    "Verb",
+];
+
+/// Takes the markup that the importer doesn't keep out of definitions: links become their text,
+/// bold and italics plain text, and comments go.
+pub struct MarkupCleaner {
+    html_comment: Regex,
+    display_link: Regex,
+    link: Regex,
+    bold: Regex,
+    italic: Regex,
+}
+
+impl Default for MarkupCleaner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MarkupCleaner {
+    pub fn new() -> MarkupCleaner {
+        MarkupCleaner {
+            html_comment: Regex::new(r"<!--(?s:.)*?-->").unwrap(),
+            // TODO: combine link REs into one
+            display_link: Regex::new(r"\[\[[^\]]*?\|(?P<text>.*?)\]\]").unwrap(),
+            link: Regex::new(r"\[\[(?P<text>.*?)\]\]").unwrap(),
+            // This technically doesn't work if some jerk decided to format a single quote.
+            bold: Regex::new(r"'''(?P<text>[^']*?)'''").unwrap(),
+            italic: Regex::new(r"''(?P<text>[^']*?)''").unwrap(),
+        }
+    }
+
+    pub fn strip_comments<'t>(&self, s: &'t str) -> Cow<'t, str> {
+        self.html_comment.replace_all(s, "")
+    }
+
+    pub fn clean(&self, s: &str) -> String {
+        let s = self.display_link.replace_all(s, "$text");
+        let s = self.link.replace_all(&s, "$text");
+        let s = self.html_comment.replace_all(&s, "");
+        let s = self.bold.replace_all(&s, "$text");
+        let s = self.italic.replace_all(&s, "$text");
+        s.into_owned()
+    }
+}
+
+// Sections that we know about but don't import.
+const IGNORED_SECTIONS: &[&str] = &[
+    "Etymology",
+    "Pronunciation",
+    "Alternative forms",
+    "Alternative scripts",
+    "Derived terms",
+    "Related terms",
+    "Descendants",
+    "Translations",
+    "See also",
+    "References",
+    "Further reading",
+    "External links",
+    "Anagrams",
+    "Usage notes",
+    "Conjugation",
+    "Declension",
+    "Inflection",
+    "Mutation",
+    "Quotations",
+    "Notes",
+    "Compounds",
+    "Idioms",
+    "Proverbs",
+    "Statistics",
+    "Trivia",
+    "Gallery",
+    "Glyph origin",
+    "Readings",
+];
+
+// (section header, the kind of relation it lists)
+const RELATION_SECTIONS: &[(&str, &str)] = &[
+    ("Synonyms", "synonym"),
+    ("Antonyms", "antonym"),
+    ("Hypernyms", "hypernym"),
+    ("Hyponyms", "hyponym"),
+    ("Meronyms", "meronym"),
+    ("Holonyms", "holonym"),
+    ("Troponyms", "troponym"),
+    ("Coordinate terms", "coordinate term"),
+];
+
+// Languages written in more than one script, whose entries in one list the same word in the
+// others under their alternative forms, or define it only with a pointer like `{{sh-see|mačka}}`.
+const MULTI_SCRIPT_LANGUAGES: &[&str] = &[
+    "Azerbaijani",
+    "Crimean Tatar",
+    "Kazakh",
+    "Serbo-Croatian",
+    "Tatar",
+    "Turkmen",
+    "Uzbek",
+];
+
+// Sections that list a word's other spellings, including those in other scripts.
+const ALTERNATIVE_SECTIONS: &[&str] = &["Alternative forms", "Alternative scripts"];
+
+// A header without the number that tells apart sections of the same kind, e.g. "Etymology 2".
+fn without_number(header: &str) -> &str {
+    header.trim_end_matches(|c: char| c.is_ascii_digit() || c == ' ')
+}
+
+fn is_ignored_section(header: &str) -> bool {
+    IGNORED_SECTIONS.contains(&without_number(header))
+}
+
+/// The kind of relation listed under a section header, e.g. "synonym" for "Synonyms".
+pub fn relation_kind(header: &str) -> Option<&'static str> {
+    let header = header.trim();
+    RELATION_SECTIONS
+        .iter()
+        .find(|&&(section, _)| section == header)
+        .map(|&(_, kind)| kind)
+}
+
+/// The entry in another script that a definition does nothing but point to, like the `mačka` of
+/// `{{sh-see|mačka}}` on the Cyrillic spelling's page.
+pub fn script_pointer(definition: &str) -> Option<String> {
+    match parse_templates(definition.trim())[..] {
+        [Node::Template(ref template)] if plain_text(&template.name).ends_with("-see") => template
+            .positional()
+            .next()
+            .map(plain_text)
+            .filter(|target| !target.is_empty()),
+        _ => None,
+    }
+}
+
+/// Corrects section headers that are a typo away from one we know, like "Pronounciation" or
+/// "Adjectiv", which would otherwise be unknown headers, losing the definitions under a misspelt
+/// part of speech. Only clear near misses are corrected: short headers only for case, and never
+/// when two known headers are as close.
+pub struct HeaderCorrector<'a> {
+    known: Vec<&'a str>,
+    /// Headers that are a typo away from a known one but are what they say, left alone.
+    whitelist: HashSet<String>,
+}
+
+impl<'a> HeaderCorrector<'a> {
+    pub fn new(parts_of_speech: &HashSet<&'a str>, whitelist: HashSet<String>) -> Self {
+        let mut known: Vec<&str> = parts_of_speech.iter().cloned().collect();
+        known.extend(IGNORED_SECTIONS);
+        known.extend(RELATION_SECTIONS.iter().map(|&(section, _)| section));
+        // Ties are broken alphabetically rather than by hash order.
+        known.sort_unstable();
+        known.dedup();
+        HeaderCorrector { known, whitelist }
+    }
+
+    /// The header `header` was meant to be, if it's a near miss.
+    pub fn correct(&self, header: &str) -> Option<String> {
+        if self.whitelist.contains(header) {
+            return None;
+        }
+        let base = without_number(header);
+        let number = &header[base.len()..];
+        if base.is_empty() || self.known.contains(&base) {
+            return None;
+        }
+        let lowercase = base.to_lowercase();
+        let mut best: Option<(usize, &str)> = None;
+        let mut tied = false;
+        for &known in &self.known {
+            let length = known.chars().count();
+            let max_distance = match length {
+                0..=4 => 0,
+                5..=7 => 1,
+                _ => 2,
+            };
+            let known_lowercase = known.to_lowercase();
+            // Typos are rarely in the first letter, and headers that differ there are usually
+            // different words.
+            if length.abs_diff(lowercase.chars().count()) > max_distance
+                || known_lowercase.chars().next() != lowercase.chars().next()
+            {
+                continue;
+            }
+            let distance = levenshtein(&lowercase, &known_lowercase);
+            if distance > max_distance {
+                continue;
+            }
+            match best {
+                Some((best_distance, _)) if distance > best_distance => {}
+                Some((best_distance, _)) if distance == best_distance => tied = true,
+                _ => {
+                    best = Some((distance, known));
+                    tied = false;
+                }
+            }
+        }
+        match best {
+            Some((_, known)) if !tied => Some(format!("{}{}", known, number)),
+            _ => None,
+        }
+    }
+}
+
+// Terms listed on a line of a relation section, e.g. `* {{l|en|moggy}}, [[kitty]]`.
+fn relation_targets(line: &str) -> Vec<String> {
+    let mut targets: Vec<String> = find_references(line)
+        .into_iter()
+        .map(|reference| reference.target)
+        .collect();
+    // {{col3|en|a|b|c}} and friends list every term as an argument.
+    let trimmed = line.trim_start_matches('*').trim();
+    if trimmed.starts_with("{{col") && trimmed.ends_with("}}") {
+        let args = &trimmed[2..trimmed.len() - 2];
+        targets.extend(
+            args.split('|')
+                .skip(2)
+                .filter(|arg| !arg.contains('=') && !arg.trim().is_empty())
+                .map(|arg| arg.trim().to_owned()),
+        );
+    }
+    // {{alter|sh|мачка||Cyrillic}}: the forms after the language code, up to an empty argument
+    // before their labels.
+    for node in parse_templates(line) {
+        if let Node::Template(template) = node {
+            if ["alter", "alt"].contains(&plain_text(&template.name).as_str()) {
+                targets.extend(
+                    template
+                        .positional()
+                        .skip(1)
+                        .map(plain_text)
+                        .take_while(|term| !term.is_empty()),
+                );
+            }
+        }
+    }
+    targets
+}
+
+// The gloss that a line in a relation section starts with, as in `* {{sense|animal}} [[moggy]]`.
+fn sense_gloss(line: &str) -> Option<String> {
+    parse_templates(line).iter().find_map(|node| match node {
+        Node::Template(template)
+            if ["sense", "s"].contains(&plain_text(&template.name).as_str()) =>
+        {
+            template
+                .positional()
+                .next()
+                .map(plain_text)
+                .filter(|gloss| !gloss.is_empty())
+        }
+        _ => None,
+    })
+}
+
+// (kind, target) for the terms listed by templates under a definition, like
+// `#: {{syn|en|moggy|kitty}}`. Terms can have inline modifiers (`kitty<q:informal>`), which aren't
+// part of the target, and ones on other pages like `Thesaurus:cat` aren't headwords.
+fn inline_relations(line: &str) -> Vec<(&'static str, String)> {
+    let mut relations = Vec::new();
+    for node in parse_templates(line) {
+        let template = match node {
+            Node::Template(template) => template,
+            _ => continue,
+        };
+        let kind = match plain_text(&template.name).as_str() {
+            "syn" | "synonyms" => "synonym",
+            "ant" | "antonyms" => "antonym",
+            "hyper" | "hypernyms" => "hypernym",
+            "hypo" | "hyponyms" => "hyponym",
+            "cot" | "coordinate terms" => "coordinate term",
+            _ => continue,
+        };
+        // After the language code.
+        for term in template.positional().skip(1).map(plain_text) {
+            let target = term.split('<').next().unwrap_or("").trim();
+            if !target.is_empty() && !target.contains(':') {
+                relations.push((kind, target.to_owned()));
+            }
+        }
+    }
+    relations
+}
+
+// The wikitext of a template argument as written, templates and all.
+fn source(nodes: &[Node]) -> String {
+    let mut text = String::new();
+    for node in nodes {
+        match node {
+            Node::Text(s) => text.push_str(s),
+            Node::Template(template) => text.push_str(template.raw),
+            Node::Parameter(parameter) => text.push_str(parameter.raw),
+        }
+    }
+    text.trim().to_owned()
+}
+
+// (text, translation) for the examples on a line under a definition, like
+// `#: {{ux|fr|Le chat dort.|The cat is sleeping.}}` or
+// `#* {{quote-book|en|year=1900|passage=The cat sat.}}`.
+fn examples(line: &str) -> Vec<(String, Option<String>)> {
+    let mut examples = Vec::new();
+    for node in parse_templates(line) {
+        let template = match node {
+            Node::Template(template) => template,
+            _ => continue,
+        };
+        let named = |keys: &[&str]| {
+            template
+                .named()
+                .find(|(key, _)| keys.contains(key))
+                .map(|(_, value)| source(value))
+        };
+        let name = plain_text(&template.name);
+        let (text, translation) = match name.as_str() {
+            "ux" | "uxi" | "usex" => {
+                // After the language code.
+                let mut args = template.positional().skip(1).map(source);
+                let text = args.next();
+                (text, named(&["t", "translation"]).or_else(|| args.next()))
+            }
+            _ if name.starts_with("quote-") => {
+                (named(&["passage", "text"]), named(&["t", "translation"]))
+            }
+            _ => continue,
+        };
+        if let Some(text) = text.filter(|text| !text.is_empty()) {
+            examples.push((text, translation.filter(|t| !t.is_empty())));
+        }
+    }
+    examples
+}
+
+// A heading line's level and text. Editors get these wrong in a few ways that MediaWiki itself
+// renders sensibly enough that nobody notices, so they're tolerated here and noted as anomalies:
+// a different number of `=`s on each side (the opening ones are taken to be what was meant), and
+// a comment after the closing ones. None if it isn't a heading at all.
+fn heading(line: &str) -> Option<(usize, &str, Option<&'static str>)> {
+    let mut anomaly = None;
+    let mut line = line.trim_end();
+    if line.ends_with("-->") {
+        if let Some(start) = line.rfind("<!--") {
+            line = line[..start].trim_end();
+            anomaly = Some("comment after heading");
+        }
+    }
+    let opening = line.len() - line.trim_start_matches('=').len();
+    let closing = line.len() - line.trim_end_matches('=').len();
+    if opening == line.len() || closing == 0 {
+        return None;
+    }
+    if opening != closing {
+        anomaly = Some("unbalanced heading");
+    }
+    let text = line[opening..line.len() - closing].trim();
+    if text.is_empty() {
+        return None;
+    }
+    Some((opening.min(6), text, anomaly))
+}
+
+// The text of a template argument, without anything templated in it.
+pub(crate) fn plain_text(nodes: &[Node]) -> String {
+    let mut text = String::new();
+    for node in nodes {
+        if let Node::Text(s) = node {
+            text.push_str(s);
+        }
+    }
+    text.trim().to_owned()
+}
+
+fn push_new(values: &mut Vec<String>, value: String) {
+    if !value.is_empty() && !values.contains(&value) {
+        values.push(value);
+    }
+}
+
+// Adds what the templates on a line of a pronunciation section say, e.g.
+// `* {{IPA|en|/kæt/|[kʰæt]}}`, `* {{audio|en|En-us-cat.ogg|Audio (US)}}` or
+// `* {{hyphenation|en|kit|ty}}`.
+fn read_pronunciation(line: &str, pronunciation: &mut Pronunciation) {
+    for node in parse_templates(line) {
+        let template = match node {
+            Node::Template(template) => template,
+            _ => continue,
+        };
+        let args: Vec<String> = template.positional().map(plain_text).collect();
+        // The language code comes first, except in old uses that don't give one.
+        let code_first = |looks_like_value: fn(&str) -> bool| {
+            args.first().is_some_and(|first| !looks_like_value(first)) as usize
+        };
+        match plain_text(&template.name).as_str() {
+            "IPA" | "IPA-lite" => {
+                let skip = code_first(|arg| arg.starts_with(['/', '[']));
+                for ipa in args.iter().skip(skip) {
+                    if ipa.starts_with(['/', '[']) {
+                        push_new(&mut pronunciation.ipa, ipa.clone());
+                    }
+                }
+            }
+            "audio" | "audio-IPA" => {
+                let skip = code_first(|arg| arg.contains('.'));
+                if let Some(file) = args.get(skip) {
+                    push_new(&mut pronunciation.audio, file.clone());
+                }
+            }
+            "hyphenation" | "hyph" => {
+                // After the language code; an empty argument separates alternatives.
+                for parts in args.get(1..).unwrap_or(&[]).split(String::is_empty) {
+                    push_new(&mut pronunciation.hyphenation, parts.join("‧"));
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
 pub fn parse_wikitext(
     text: String,
     languages: &HashSet<&str>,
     parts_of_speech: &HashSet<&str>,
-) -> Vec<Meaning> {
-    let mut result: Vec<Meaning> = Vec::new();
-    let mut context_stack: ContextStack = ContextStack::new();
+) -> ParsedWikitext {
+    parse_wikitext_with(text, languages, parts_of_speech, None)
+}
 
-    let stack_apply = |context_stack: &mut ContextStack,
-                       wiki_context: &dyn Fn(String) -> WikiContext,
-                       line: &str,
-                       slice: &Option<&str>| {
-        slice.map_or_else(
-            || {
-                println!("Could not parse line: {}", line);
-            },
-            |slice| {
-                context_stack.apply(wiki_context(slice.to_owned()), languages, parts_of_speech);
-            },
-        );
-    };
+/// Like `parse_wikitext`, correcting near-miss section headers with `corrector` if given.
+pub fn parse_wikitext_with(
+    text: String,
+    languages: &HashSet<&str>,
+    parts_of_speech: &HashSet<&str>,
+    corrector: Option<&HeaderCorrector>,
+) -> ParsedWikitext {
+    let mut result = ParsedWikitext::default();
+    let mut context_stack: ContextStack = ContextStack::new();
+    // The relation listed by the section we're in, if any.
+    let mut relation: Option<&str> = None;
+    // The last etymology of the language we're in, which the parts of speech after it come from,
+    // and whether we're still in its section.
+    let mut etymology: Option<usize> = None;
+    let mut in_etymology = false;
+    let mut in_pronunciation = false;
+    // Whether we're between a part of speech heading and its first definition.
+    let mut in_headword = false;
 
     for line in text.lines() {
-        if line.starts_with("======") && line.len() > 12 {
-            stack_apply(
-                &mut context_stack,
-                &|x| Heading6(x),
-                line,
-                &line.get(6..line.len() - 6),
-            );
-        } else if line.starts_with("=====") && line.len() > 10 {
-            stack_apply(
-                &mut context_stack,
-                &|x| Heading5(x),
-                line,
-                &line.get(5..line.len() - 5),
-            );
-        } else if line.starts_with("====") && line.len() > 8 {
-            stack_apply(
-                &mut context_stack,
-                &|x| Heading4(x),
-                line,
-                &line.get(4..line.len() - 4),
-            );
-        } else if line.starts_with("===") && line.len() > 6 {
-            stack_apply(
-                &mut context_stack,
-                &|x| Heading3(x),
-                line,
-                &line.get(3..line.len() - 3),
-            );
-        } else if line.starts_with("==") && line.len() > 4 {
-            stack_apply(
-                &mut context_stack,
-                &|x| Heading2(x),
-                line,
-                &line.get(2..line.len() - 2),
-            );
-        } else if line.starts_with("=") && line.len() > 2 {
-            stack_apply(
-                &mut context_stack,
-                &|x| Heading1(x),
-                line,
-                &line.get(1..line.len() - 1),
+        if line.starts_with('=') {
+            let (mut level, header, anomaly) = match heading(line) {
+                Some(heading) => heading,
+                None => {
+                    result.unparseable_lines.push(line.to_owned());
+                    continue;
+                }
+            };
+            if let Some(anomaly) = anomaly {
+                result.anomalies.push((anomaly, line.to_owned()));
+            }
+            // Languages are at level 2, and aren't corrected.
+            let corrected = corrector
+                .filter(|_| level > 2)
+                .and_then(|corrector| corrector.correct(header));
+            let header = match corrected {
+                Some(ref corrected) => {
+                    result
+                        .corrected_headers
+                        .push((header.to_owned(), corrected.clone()));
+                    corrected.as_str()
+                }
+                None => header,
+            };
+            relation = relation_kind(header);
+            let is_section = parts_of_speech.contains(header)
+                || relation.is_some()
+                || is_ignored_section(header);
+            // Entries start at level 2, so a level 1 heading is a language (or a section) that
+            // was meant to be level 2, and a section at level 2 belongs to the language it's in
+            // rather than starting a new one.
+            if level == 1 {
+                result.anomalies.push(("level 1 heading", line.to_owned()));
+                level = 2;
+            }
+            if level == 2 && is_section && !context_stack.is_empty() {
+                result
+                    .anomalies
+                    .push(("section at language level", line.to_owned()));
+                level = 3;
+            }
+            if level == 2 {
+                if !languages.contains(header) {
+                    result.skipped_languages.push(header.to_owned());
+                }
+            } else if !is_section {
+                result.unknown_headers.push(header.to_owned());
+            }
+            context_stack.apply(
+                WikiContext::new(level, header.to_owned()),
+                languages,
+                parts_of_speech,
             );
-        } else if line.starts_with("# ") {
+            let multi_script = context_stack
+                .language
+                .as_deref()
+                .is_some_and(|language| MULTI_SCRIPT_LANGUAGES.contains(&language));
+            if multi_script && ALTERNATIVE_SECTIONS.contains(&without_number(header)) {
+                relation = Some("alternative form");
+            }
+            if level == 2 {
+                etymology = None;
+            }
+            in_etymology = false;
+            in_pronunciation = false;
+            in_headword = parts_of_speech.contains(header);
+            if let Some(ref language) = context_stack.language {
+                if without_number(header) == "Etymology" {
+                    result.etymologies.push(Etymology {
+                        language: language.clone(),
+                        number: header["Etymology".len()..].trim().parse().unwrap_or(0),
+                        text: String::new(),
+                        parts_of_speech: Vec::new(),
+                    });
+                    etymology = Some(result.etymologies.len() - 1);
+                    in_etymology = true;
+                } else if without_number(header) == "Pronunciation" {
+                    if !result
+                        .pronunciations
+                        .iter()
+                        .any(|p| &p.language == language)
+                    {
+                        result.pronunciations.push(Pronunciation {
+                            language: language.clone(),
+                            ..Pronunciation::default()
+                        });
+                    }
+                    in_pronunciation = true;
+                } else if let (true, Some(i)) = (parts_of_speech.contains(header), etymology) {
+                    let parts_of_speech = &mut result.etymologies[i].parts_of_speech;
+                    if !parts_of_speech.iter().any(|pos| pos == header) {
+                        parts_of_speech.push(header.to_owned());
+                    }
+                }
+            }
+        } else if in_etymology && !line.starts_with('#') {
+            let line = line.trim();
+            // Images that illustrate the word often go at the top of its section.
+            if !line.is_empty() && !line.starts_with("[[File:") && !line.starts_with("[[Image:") {
+                let text = &mut result.etymologies[etymology.unwrap()].text;
+                if !text.is_empty() {
+                    text.push('\n');
+                }
+                text.push_str(line);
+            }
+        } else if in_pronunciation {
+            // Always the last one, since there's one per language and languages don't repeat.
+            let language = context_stack.language.as_ref();
+            if let Some(pronunciation) = result
+                .pronunciations
+                .iter_mut()
+                .rfind(|pronunciation| Some(&pronunciation.language) == language)
+            {
+                read_pronunciation(line, pronunciation);
+            }
+        } else if in_headword && !line.starts_with('#') {
+            if let (Some(language), Some(part_of_speech), Some(template)) = (
+                context_stack.language.as_ref(),
+                context_stack.part_of_speech.as_ref(),
+                headword_template(line),
+            ) {
+                result.headword_templates.push((
+                    language.clone(),
+                    part_of_speech.clone(),
+                    template.to_owned(),
+                ));
+                in_headword = false;
+            }
+        } else if line.starts_with("#:") || line.starts_with("#*") {
+            if let Some(ref language) = context_stack.language {
+                // The definition the line is under.
+                let sense = result
+                    .meanings
+                    .last()
+                    .filter(|meaning| {
+                        &meaning.language == language
+                            && Some(&meaning.part_of_speech)
+                                == context_stack.part_of_speech.as_ref()
+                    })
+                    .map(|meaning| meaning.definition.clone());
+                if let (Some(part_of_speech), Some(definition)) =
+                    (context_stack.part_of_speech.as_ref(), sense.as_ref())
+                {
+                    for (text, translation) in examples(line) {
+                        result.examples.push(Example {
+                            language: language.clone(),
+                            part_of_speech: part_of_speech.clone(),
+                            definition: definition.clone(),
+                            text,
+                            translation,
+                        });
+                    }
+                }
+                for (kind, target) in inline_relations(line) {
+                    result.relations.push(Relation {
+                        language: language.clone(),
+                        part_of_speech: context_stack.part_of_speech.clone(),
+                        kind: kind.to_owned(),
+                        target,
+                        sense: sense.clone(),
+                    });
+                }
+            }
+        } else if line.starts_with('#') && !line[1..].starts_with(['#', '*', ':']) {
+            in_headword = false;
+            if !line.starts_with("# ") {
+                result
+                    .anomalies
+                    .push(("definition without a space", line.to_owned()));
+            }
             context_stack.language.as_ref().and_then(|language| {
                 context_stack.part_of_speech.as_ref().map(|part_of_speech| {
-                    result.push(Meaning {
+                    result.meanings.push(Meaning {
                         language: language.clone(),
                         part_of_speech: part_of_speech.clone(),
-                        definition: String::from(&line[2..]),
+                        definition: line[1..].trim_start().to_owned(),
+                        expanded: None,
+                        edition: None,
                     })
                 })
             });
+            if let (Some(language), Some(target)) = (
+                context_stack
+                    .language
+                    .as_ref()
+                    .filter(|language| MULTI_SCRIPT_LANGUAGES.contains(&language.as_str())),
+                script_pointer(&line[1..]),
+            ) {
+                result.relations.push(Relation {
+                    language: language.clone(),
+                    part_of_speech: context_stack.part_of_speech.clone(),
+                    kind: "alternative form".to_owned(),
+                    target,
+                    sense: None,
+                });
+            }
+        } else if line.starts_with('*') {
+            if let (Some(kind), Some(language)) = (relation, context_stack.language.as_ref()) {
+                let sense = sense_gloss(line);
+                for target in relation_targets(line) {
+                    result.relations.push(Relation {
+                        language: language.clone(),
+                        part_of_speech: context_stack.part_of_speech.clone(),
+                        kind: kind.to_owned(),
+                        target,
+                        sense: sense.clone(),
+                    });
+                }
+            }
         }
     }
     result
+        .etymologies
+        .retain(|etymology| !etymology.text.is_empty());
+    result.pronunciations.retain(|pronunciation| {
+        !(pronunciation.ipa.is_empty()
+            && pronunciation.audio.is_empty()
+            && pronunciation.hyphenation.is_empty())
+    });
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use headword::grammar;
+
+    fn parse(text: &str) -> ParsedWikitext {
+        let languages: HashSet<&str> = ["English", "French", "Translingual"].into();
+        let parts_of_speech: HashSet<&str> = ["Noun", "Verb", "Symbol"].into();
+        parse_wikitext(text.to_owned(), &languages, &parts_of_speech)
+    }
+
+    // (language, part of speech, definition) for each meaning.
+    fn meanings(parsed: &ParsedWikitext) -> Vec<(&str, &str, &str)> {
+        parsed
+            .meanings
+            .iter()
+            .map(|meaning| {
+                (
+                    meaning.language.as_str(),
+                    meaning.part_of_speech.as_str(),
+                    meaning.definition.as_str(),
+                )
+            })
+            .collect()
+    }
+
+    fn anomalies(parsed: &ParsedWikitext) -> Vec<&str> {
+        parsed.anomalies.iter().map(|&(kind, _)| kind).collect()
+    }
+
+    // Like [[a]]: Translingual comes before the other languages.
+    #[test]
+    fn translingual() {
+        let parsed = parse(
+            "==Translingual==\n\
+             ===Etymology===\n\
+             From the Latin alphabet.\n\
+             ===Symbol===\n\
+             {{mul-symbol}}\n\
+             # {{lb|mul|metrology}} [[are]], a unit of area.\n\
+             \n\
+             ----\n\
+             \n\
+             ==English==\n\
+             ===Noun===\n\
+             # The first letter of the alphabet.\n",
+        );
+        assert_eq!(
+            meanings(&parsed),
+            [
+                (
+                    "Translingual",
+                    "Symbol",
+                    "{{lb|mul|metrology}} [[are]], a unit of area."
+                ),
+                ("English", "Noun", "The first letter of the alphabet."),
+            ]
+        );
+        assert!(parsed.anomalies.is_empty());
+        assert!(parsed.unknown_headers.is_empty());
+    }
+
+    // Like [[bank]]: each etymology has its own parts of speech a level down, and sections at the
+    // etymology's level in between end it.
+    #[test]
+    fn multiple_etymologies() {
+        let parsed = parse(
+            "==English==\n\
+             ===Pronunciation===\n\
+             * {{IPA|en|/bæŋk/}}\n\
+             ===Etymology 1===\n\
+             From Middle English.\n\
+             ====Noun====\n\
+             # An institution where one can place and borrow money.\n\
+             =====Synonyms=====\n\
+             * [[depository]]\n\
+             ====Verb====\n\
+             # To deposit in a bank.\n\
+             ===Etymology 2===\n\
+             From Old Norse.\n\
+             ====Noun====\n\
+             # An edge of a river.\n\
+             ===Anagrams===\n\
+             # Not a definition.\n",
+        );
+        assert_eq!(
+            meanings(&parsed),
+            [
+                (
+                    "English",
+                    "Noun",
+                    "An institution where one can place and borrow money."
+                ),
+                ("English", "Verb", "To deposit in a bank."),
+                ("English", "Noun", "An edge of a river."),
+            ]
+        );
+        assert_eq!(parsed.relations.len(), 1);
+        assert_eq!(parsed.relations[0].target, "depository");
+        assert_eq!(parsed.relations[0].part_of_speech.as_deref(), Some("Noun"));
+        assert!(parsed.anomalies.is_empty());
+    }
+
+    #[test]
+    fn spaces_in_headings() {
+        let parsed = parse("== English == \n=== Noun ===\n# A thing.\n");
+        assert_eq!(meanings(&parsed), [("English", "Noun", "A thing.")]);
+        assert!(parsed.anomalies.is_empty());
+    }
+
+    #[test]
+    fn unbalanced_headings() {
+        let parsed = parse("==English==\n===Noun==\n# A thing.\n====Verb=====\n# To do.\n");
+        assert_eq!(
+            meanings(&parsed),
+            [
+                ("English", "Noun", "A thing."),
+                ("English", "Verb", "To do.")
+            ]
+        );
+        assert_eq!(
+            anomalies(&parsed),
+            ["unbalanced heading", "unbalanced heading"]
+        );
+    }
+
+    #[test]
+    fn comment_after_heading() {
+        let parsed = parse("==English==\n===Noun=== <!-- or a verb? -->\n# A thing.\n");
+        assert_eq!(meanings(&parsed), [("English", "Noun", "A thing.")]);
+        assert_eq!(anomalies(&parsed), ["comment after heading"]);
+    }
+
+    #[test]
+    fn wrong_levels() {
+        let parsed = parse("=French=\n==Noun==\n# chat\n==Verb==\n# chatter\n");
+        assert_eq!(
+            meanings(&parsed),
+            [("French", "Noun", "chat"), ("French", "Verb", "chatter")]
+        );
+        assert_eq!(
+            anomalies(&parsed),
+            [
+                "level 1 heading",
+                "section at language level",
+                "section at language level"
+            ]
+        );
+        assert!(parsed.skipped_languages.is_empty());
+    }
+
+    #[test]
+    fn skipped_language_with_wrong_levels() {
+        let parsed = parse("==Dutch==\n==Noun==\n# kat\n==English==\n===Noun===\n# cat\n");
+        assert_eq!(meanings(&parsed), [("English", "Noun", "cat")]);
+        assert_eq!(parsed.skipped_languages, ["Dutch"]);
+    }
+
+    #[test]
+    fn definition_lines() {
+        let parsed = parse(
+            "==English==\n\
+             ===Noun===\n\
+             #A thing.\n\
+             #: An example.\n\
+             #* A quotation.\n\
+             ## A subsense.\n\
+             #  Another thing.\n",
+        );
+        assert_eq!(
+            meanings(&parsed),
+            [
+                ("English", "Noun", "A thing."),
+                ("English", "Noun", "Another thing.")
+            ]
+        );
+        assert_eq!(anomalies(&parsed), ["definition without a space"]);
+    }
+
+    #[test]
+    fn corrected_headers() {
+        let parts_of_speech: HashSet<&str> = ["Noun", "Verb", "Adjective"].into();
+        let corrector = HeaderCorrector::new(&parts_of_speech, ["Nouns".to_owned()].into());
+        let parsed = parse_wikitext_with(
+            "==English==\n\
+             ===Etymolgy 2===\n\
+             ====Adjectiv====\n\
+             # Feline.\n\
+             ====noun====\n\
+             # A cat.\n\
+             ====Nouns====\n\
+             ====Verv====\n\
+             # To cat.\n"
+                .to_owned(),
+            &["English"].into(),
+            &parts_of_speech,
+            Some(&corrector),
+        );
+        assert_eq!(
+            meanings(&parsed),
+            [
+                ("English", "Adjective", "Feline."),
+                ("English", "Noun", "A cat.")
+            ]
+        );
+        assert_eq!(
+            parsed.corrected_headers,
+            [
+                ("Etymolgy 2".to_owned(), "Etymology 2".to_owned()),
+                ("Adjectiv".to_owned(), "Adjective".to_owned()),
+                ("noun".to_owned(), "Noun".to_owned()),
+            ]
+        );
+        // Whitelisted, and too short to correct for more than case.
+        assert_eq!(parsed.unknown_headers, ["Nouns", "Verv"]);
+    }
+
+    #[test]
+    fn etymologies() {
+        let parsed = parse(
+            "==English==\n\
+             ===Etymology 1===\n\
+             [[File:Cat.jpg|thumb]]\n\
+             From Old English ''catt''.\n\
+             \n\
+             Compare German ''Katze''.\n\
+             ====Noun====\n\
+             # A cat.\n\
+             ====Verb====\n\
+             # To cat.\n\
+             ===Etymology 2===\n\
+             ===Etymology 3===\n\
+             Short for ''catamaran''.\n\
+             ====Noun====\n\
+             # A catamaran.\n\
+             ==German==\n\
+             ===Etymology===\n\
+             Not imported.\n\
+             ==French==\n\
+             ===Noun===\n\
+             # chat\n",
+        );
+        assert_eq!(
+            parsed.etymologies,
+            [
+                Etymology {
+                    language: "English".to_owned(),
+                    number: 1,
+                    text: "From Old English ''catt''.\nCompare German ''Katze''.".to_owned(),
+                    parts_of_speech: vec!["Noun".to_owned(), "Verb".to_owned()],
+                },
+                Etymology {
+                    language: "English".to_owned(),
+                    number: 3,
+                    text: "Short for ''catamaran''.".to_owned(),
+                    parts_of_speech: vec!["Noun".to_owned()],
+                },
+            ]
+        );
+        assert_eq!(meanings(&parsed).len(), 4);
+    }
+
+    #[test]
+    fn pronunciations() {
+        let parsed = parse(
+            "==English==\n\
+             ===Etymology 1===\n\
+             From somewhere.\n\
+             ====Pronunciation====\n\
+             * {{a|US}} {{IPA|en|/kæt/|[kʰæt]}}\n\
+             * {{audio|en|En-us-cat.ogg|Audio (US)}}\n\
+             * {{hyphenation|en|cat}}\n\
+             ====Noun====\n\
+             # A cat.\n\
+             ===Etymology 2===\n\
+             ====Pronunciation====\n\
+             * {{IPA|/kat/}}\n\
+             ==French==\n\
+             ===Pronunciation===\n\
+             * {{hyph|fr|chat|on||cha|ton}}\n\
+             ===Noun===\n\
+             # chat\n",
+        );
+        assert_eq!(
+            parsed.pronunciations,
+            [
+                Pronunciation {
+                    language: "English".to_owned(),
+                    ipa: vec!["/kæt/".to_owned(), "[kʰæt]".to_owned(), "/kat/".to_owned()],
+                    audio: vec!["En-us-cat.ogg".to_owned()],
+                    hyphenation: vec!["cat".to_owned()],
+                },
+                Pronunciation {
+                    language: "French".to_owned(),
+                    ipa: Vec::new(),
+                    audio: Vec::new(),
+                    hyphenation: vec!["chat‧on".to_owned(), "cha‧ton".to_owned()],
+                },
+            ]
+        );
+        assert_eq!(meanings(&parsed).len(), 2);
+    }
+
+    #[test]
+    fn relation_senses() {
+        let parsed = parse(
+            "==English==\n\
+             ===Noun===\n\
+             # A small carnivore.\n\
+             #: {{syn|en|moggy|kitty<q:informal>|Thesaurus:cat}}\n\
+             #: {{ant|en|dog}}\n\
+             # A man.\n\
+             ====Synonyms====\n\
+             * {{sense|man}} {{l|en|guy}}\n\
+             * [[feline]]\n",
+        );
+        let relations: Vec<(&str, &str, Option<&str>)> = parsed
+            .relations
+            .iter()
+            .map(|r| (r.kind.as_str(), r.target.as_str(), r.sense.as_deref()))
+            .collect();
+        assert_eq!(
+            relations,
+            [
+                ("synonym", "moggy", Some("A small carnivore.")),
+                ("synonym", "kitty", Some("A small carnivore.")),
+                ("antonym", "dog", Some("A small carnivore.")),
+                ("synonym", "guy", Some("man")),
+                ("synonym", "feline", None),
+            ]
+        );
+    }
+
+    #[test]
+    fn other_scripts() {
+        let languages: HashSet<&str> = ["Serbo-Croatian", "French"].into();
+        let parts_of_speech: HashSet<&str> = ["Noun"].into();
+        let parsed = parse_wikitext(
+            "==Serbo-Croatian==\n\
+             ===Alternative forms===\n\
+             * {{l|sh|мачка}}\n\
+             ===Noun===\n\
+             # {{sh-see|mačka}}\n\
+             ==French==\n\
+             ===Alternative forms===\n\
+             * {{alter|fr|chatte}}\n"
+                .to_owned(),
+            &languages,
+            &parts_of_speech,
+        );
+        let relations: Vec<(&str, &str, &str)> = parsed
+            .relations
+            .iter()
+            .map(|r| (r.language.as_str(), r.kind.as_str(), r.target.as_str()))
+            .collect();
+        assert_eq!(
+            relations,
+            [
+                ("Serbo-Croatian", "alternative form", "мачка"),
+                ("Serbo-Croatian", "alternative form", "mačka"),
+            ]
+        );
+        assert_eq!(
+            script_pointer(" {{sh-see|mačka}}").as_deref(),
+            Some("mačka")
+        );
+        assert_eq!(script_pointer("{{sh-see|mačka}} or a cat"), None);
+    }
+
+    #[test]
+    fn examples() {
+        let parsed = parse(
+            "==French==\n\
+             ===Noun===\n\
+             # [[cat]]\n\
+             #: {{ux|fr|Le '''chat''' dort.|The cat is sleeping.}}\n\
+             #: {{uxi|fr|un chat {{w|fr|noir}}|t=a black cat}}\n\
+             # [[pussy]]\n\
+             #* {{quote-book|fr|year=1900|author=Someone|passage=Un chat.|translation=A cat.}}\n\
+             #* {{quote-book|fr|year=1901|title=No passage}}\n",
+        );
+        let examples: Vec<(&str, &str, Option<&str>)> = parsed
+            .examples
+            .iter()
+            .map(|e| {
+                (
+                    e.definition.as_str(),
+                    e.text.as_str(),
+                    e.translation.as_deref(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            examples,
+            [
+                (
+                    "[[cat]]",
+                    "Le '''chat''' dort.",
+                    Some("The cat is sleeping.")
+                ),
+                ("[[cat]]", "un chat {{w|fr|noir}}", Some("a black cat")),
+                ("[[pussy]]", "Un chat.", Some("A cat.")),
+            ]
+        );
+    }
+
+    #[test]
+    fn headword_templates() {
+        let parsed = parse(
+            "==English==\n\
+             ===Noun===\n\
+             {{wikipedia}}\n\
+             {{en-noun|geese}}\n\
+             \n\
+             # A waterfowl.\n\
+             #: {{en-noun}}\n\
+             ===Verb===\n\
+             {{en-verb}}\n\
+             # To poke.\n\
+             ====Synonyms====\n\
+             * {{en-noun}}\n",
+        );
+        let grammar: Vec<(&str, Vec<(String, String)>)> = parsed
+            .headword_templates
+            .iter()
+            .map(|(_, pos, template)| (pos.as_str(), grammar(template, "goose")))
+            .collect();
+        let forms = |forms: &[(&str, &str)]| -> Vec<(String, String)> {
+            forms
+                .iter()
+                .map(|&(label, form)| (label.to_owned(), form.to_owned()))
+                .collect()
+        };
+        assert_eq!(
+            grammar,
+            [
+                ("Noun", forms(&[("plural", "geese")])),
+                (
+                    "Verb",
+                    forms(&[
+                        ("third-person singular simple present", "gooses"),
+                        ("present participle", "goosing"),
+                        ("simple past and past participle", "goosed"),
+                    ])
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn unparseable_headings() {
+        let parsed = parse("==English==\n=== \n====\n===Noun\n===Noun===\n# A thing.\n");
+        assert_eq!(meanings(&parsed), [("English", "Noun", "A thing.")]);
+        assert_eq!(parsed.unparseable_lines, ["=== ", "====", "===Noun"]);
+    }
 }
@@ -1,8 +1,13 @@
 extern crate quick_xml;
 use parse_xml::quick_xml::{events::Event, Reader};
 
-use std::io::BufRead;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
 use std::path::Path;
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread;
 
 use Page;
 
@@ -13,12 +18,9 @@ fn parse_revision<B: BufRead>(reader: &mut Reader<B>) -> Option<String> {
         match reader.read_event_into(&mut buf) {
             Ok(Event::Start(ref e)) if e.name().as_ref() == b"text" => {
                 let mut buf = Vec::new();
-                match reader.read_event_into(&mut buf) {
-                    Ok(Event::Text(e)) => {
-                        let text = e.unescape().unwrap().to_string();
-                        result = Some(text);
-                    }
-                    _ => (),
+                if let Ok(Event::Text(e)) = reader.read_event_into(&mut buf) {
+                    let text = e.unescape().unwrap().to_string();
+                    result = Some(text);
                 }
             }
             Ok(Event::End(ref e)) if e.name().as_ref() == b"revision" => break,
@@ -28,7 +30,7 @@ fn parse_revision<B: BufRead>(reader: &mut Reader<B>) -> Option<String> {
     result
 }
 
-pub fn parse_page<B: BufRead>(mut reader: &mut Reader<B>) -> Option<Page> {
+pub fn parse_page<B: BufRead>(reader: &mut Reader<B>) -> Option<Page> {
     let mut buf = Vec::new();
     let mut title = None;
     let mut content = None;
@@ -37,12 +39,13 @@ pub fn parse_page<B: BufRead>(mut reader: &mut Reader<B>) -> Option<Page> {
             Ok(Event::Start(ref e)) => {
                 let mut buf = Vec::new();
                 match e.name().as_ref() {
-                    b"title" => match reader.read_event_into(&mut buf) {
-                        Ok(Event::Text(e)) => title = Some(e.unescape().unwrap().to_string()),
-                        _ => (),
-                    },
+                    b"title" => {
+                        if let Ok(Event::Text(e)) = reader.read_event_into(&mut buf) {
+                            title = Some(e.unescape().unwrap().to_string())
+                        }
+                    }
                     b"revision" => {
-                        content = parse_revision(&mut reader);
+                        content = parse_revision(reader);
                     }
                     _ => (),
                 }
@@ -55,25 +58,99 @@ pub fn parse_page<B: BufRead>(mut reader: &mut Reader<B>) -> Option<Page> {
     title.and_then(|title| content.map(|content| Page { title, content }))
 }
 
-pub fn for_pages<F>(filename: &str, mut f: F)
+/// How far `for_pages_with_progress` has got through a dump, for progress bars.
+#[derive(Clone, Copy, Debug)]
+pub struct Progress {
+    /// Of the file itself, so compressed bytes for a compressed dump.
+    pub bytes_read: u64,
+    pub total_bytes: u64,
+    pub pages: u64,
+}
+
+// Counts the bytes read through it, for another thread to look at.
+struct CountingReader<R> {
+    inner: R,
+    count: Arc<AtomicU64>,
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.count.fetch_add(n as u64, Ordering::Relaxed);
+        Ok(n)
+    }
+}
+
+// The dump's XML, decompressing it if it's a .bz2 (as downloaded from dumps.wikimedia.org). That
+// goes through the system's bzip2, which is much faster than decompressing in-process would be
+// with only one of our threads to spare for it.
+pub(crate) fn open_dump(
+    filename: &str,
+    count: &Arc<AtomicU64>,
+) -> (Box<dyn BufRead>, Option<Child>) {
+    let file = CountingReader {
+        inner: File::open(Path::new(filename)).unwrap(),
+        count: Arc::clone(count),
+    };
+    if !filename.ends_with(".bz2") {
+        return (Box::new(BufReader::new(file)), None);
+    }
+    let mut child = Command::new("bzip2")
+        .arg("-dc")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap_or_else(|e| panic!("Could not run bzip2 to decompress {}: {}", filename, e));
+    let mut stdin = child.stdin.take().unwrap();
+    let mut file = file;
+    // bzip2 stops reading (and this thread with it) if we stop reading its output early.
+    thread::spawn(move || io::copy(&mut file, &mut stdin));
+    let stdout = child.stdout.take().unwrap();
+    (Box::new(BufReader::new(stdout)), Some(child))
+}
+
+pub fn for_pages<F>(filename: &str, f: F)
+where
+    F: FnMut(Page),
+{
+    for_pages_with_progress(filename, f, |_| ());
+}
+
+/// Like `for_pages`, calling `progress` after each page.
+pub fn for_pages_with_progress<F, P>(filename: &str, mut f: F, mut progress: P)
 where
-    F: FnMut(Page) -> (),
+    F: FnMut(Page),
+    P: FnMut(&Progress),
 {
+    let total_bytes = std::fs::metadata(filename).map_or(0, |metadata| metadata.len());
+    let count = Arc::new(AtomicU64::new(0));
+    let (input, mut child) = open_dump(filename, &count);
+    let mut pages = 0;
     let mut buf = Vec::new();
-    let mut reader = Reader::from_file(Path::new(filename)).unwrap();
+    let mut reader = Reader::from_reader(input);
     'read_words: loop {
         match reader.read_event_into(&mut buf) {
-            Ok(Event::Start(ref e)) => match e.name().as_ref() {
-                b"page" => {
-                    let page = parse_page(&mut reader);
-                    page.map(|page| f(page));
+            Ok(Event::Start(ref e)) if e.name().as_ref() == b"page" => {
+                if let Some(page) = parse_page(&mut reader) {
+                    f(page);
                 }
-                _ => (),
-            },
+                pages += 1;
+                progress(&Progress {
+                    bytes_read: count.load(Ordering::Relaxed),
+                    total_bytes,
+                    pages,
+                });
+            }
             Ok(Event::Eof) => break 'read_words,
             Err(e) => panic!("Error at position {}: {:?}", reader.buffer_position(), e),
             _ => (),
         }
         buf.clear();
     }
+    if let Some(ref mut child) = child {
+        let status = child.wait().unwrap();
+        if !status.success() {
+            panic!("bzip2 failed to decompress {}: {}", filename, status);
+        }
+    }
 }
@@ -0,0 +1,101 @@
+/// Percent-encodes a word for use as one segment of a URL path, or as a fragment.
+pub fn encode_path_segment(s: &str) -> String {
+    let mut encoded = String::with_capacity(s.len());
+    for byte in s.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+// MediaWiki titles and section anchors use underscores for spaces.
+fn encode_wiki_name(s: &str) -> String {
+    encode_path_segment(&s.replace(' ', "_"))
+}
+
+/// The fragment for `language`'s section of an entry, both on Wiktionary and in define3's HTML.
+pub fn language_anchor(language: &str) -> String {
+    encode_wiki_name(language)
+}
+
+/// The fragment for a part of speech in `language`'s section of an entry in define3's HTML and
+/// browser, e.g. "English-Verb".
+pub fn part_of_speech_anchor(language: &str, part_of_speech: &str) -> String {
+    format!(
+        "{}-{}",
+        language_anchor(language),
+        encode_wiki_name(part_of_speech)
+    )
+}
+
+/// The fragment for the `n`th sense (from 1) of a part of speech, e.g. "English-Verb-3". Senses are
+/// numbered across the whole part of speech, as `define` numbers them.
+pub fn sense_anchor(language: &str, part_of_speech: &str, n: usize) -> String {
+    format!("{}-{}", part_of_speech_anchor(language, part_of_speech), n)
+}
+
+/// Splits "set#English-Verb-3" into the word and the fragment. Titles can't have a `#` in them,
+/// so anything after one is a fragment.
+pub fn split_fragment(s: &str) -> (&str, Option<&str>) {
+    match s.split_once('#') {
+        Some((word, fragment)) => (word, Some(fragment)),
+        None => (s, None),
+    }
+}
+
+/// Whether two fragments name the same place, whether they're percent-encoded or not and with
+/// spaces or underscores.
+pub fn same_anchor(a: &str, b: &str) -> bool {
+    let normalize = |s: &str| percent_decode(s, false).replace(' ', "_");
+    normalize(a) == normalize(b)
+}
+
+/// The section for `language` on a word's page in a Wiktionary edition (e.g. "en").
+pub fn wiktionary_url(edition: &str, word: &str, language: &str) -> String {
+    format!(
+        "https://{}.wiktionary.org/wiki/{}#{}",
+        edition,
+        encode_wiki_name(word),
+        encode_wiki_name(language)
+    )
+}
+
+/// The same section on the server at `base_url`.
+pub fn local_url(base_url: &str, word: &str, language: &str) -> String {
+    format!(
+        "{}/define/{}#{}",
+        base_url.trim_end_matches('/'),
+        encode_path_segment(word),
+        language_anchor(language)
+    )
+}
+
+/// The inverse of percent-encoding, for paths and query strings as received. Query strings also
+/// encode spaces as `+`, so `plus_is_space` turns those back. Escapes that don't make valid UTF-8
+/// are replaced with U+FFFD.
+pub fn percent_decode(s: &str, plus_is_space: bool) -> String {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = s
+            .get(i + 1..i + 3)
+            .filter(|_| bytes[i] == b'%')
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (escaped, bytes[i]) {
+            (Some(byte), _) => {
+                decoded.push(byte);
+                i += 3;
+                continue;
+            }
+            (None, b'+') if plus_is_space => decoded.push(b' '),
+            (None, byte) => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
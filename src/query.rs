@@ -0,0 +1,317 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use rusqlite::ffi;
+
+use dictionary::Dictionary;
+use pagination::{Cursor, ResultPage};
+use {Meaning, Relation};
+
+/// Something to look up. Every frontend (the CLI, and the servers) goes through these, so a
+/// query means the same thing whichever way it arrives.
+#[derive(Clone, Debug)]
+pub enum Query {
+    /// All meanings of a word.
+    Lookup(String),
+    /// Only a word's best-ranked meaning; see `Dictionary::top_meaning`.
+    First(String),
+    /// Headwords matching a glob pattern, a page at a time.
+    Search {
+        pattern: String,
+        after: Option<Cursor>,
+        limit: usize,
+    },
+    /// Meanings passing every filter, with their headwords, a page at a time; see
+    /// `Dictionary::matching`.
+    Matching {
+        filter: MeaningFilter,
+        after: Option<Cursor>,
+        limit: usize,
+    },
+    /// The relation graph around a word; see `Dictionary::related`.
+    Related {
+        word: String,
+        kinds: Vec<String>,
+        depth: usize,
+    },
+}
+
+/// What `Query::Matching` looks for. Unset filters match everything.
+#[derive(Clone, Debug, Default)]
+pub struct MeaningFilter {
+    /// Headwords starting with this.
+    pub name_prefix: Option<String>,
+    /// Headwords matching this glob pattern.
+    pub name_pattern: Option<String>,
+    /// Definitions, as written in the wikitext, containing this. ASCII letters match either case.
+    pub definition_contains: Option<String>,
+    pub part_of_speech: Option<String>,
+}
+
+/// A query with the filters that apply to it. Searches only look at headwords, so they aren't
+/// filtered.
+#[derive(Clone, Debug)]
+pub struct QueryRequest {
+    pub query: Query,
+    /// Only this language (a normalized name), if set.
+    pub language: Option<String>,
+    /// Only this Wiktionary edition, if set. Relations aren't tagged with one.
+    pub edition: Option<String>,
+}
+
+impl QueryRequest {
+    pub fn new(query: Query) -> QueryRequest {
+        QueryRequest {
+            query,
+            language: None,
+            edition: None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum QueryResponse {
+    Meanings(Vec<Meaning>),
+    Headwords(ResultPage<String>),
+    /// (headword, meaning) pairs.
+    Entries(ResultPage<(String, Meaning)>),
+    /// (from, relation) edges.
+    Related(Vec<(String, Relation)>),
+}
+
+#[derive(Debug)]
+pub enum QueryError {
+    /// The request's `CancelToken` was cancelled before it finished.
+    Cancelled,
+    Sqlite(rusqlite::Error),
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            QueryError::Cancelled => write!(f, "query cancelled"),
+            QueryError::Sqlite(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<rusqlite::Error> for QueryError {
+    fn from(e: rusqlite::Error) -> QueryError {
+        match e.sqlite_error_code() {
+            Some(ffi::ErrorCode::OperationInterrupted) => QueryError::Cancelled,
+            _ => QueryError::Sqlite(e),
+        }
+    }
+}
+
+/// Lets another thread stop a query, e.g. when the client that asked for it has gone away.
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> CancelToken {
+        CancelToken::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+// How many SQLite virtual machine instructions run between checks for cancellation.
+const CANCEL_CHECK_INTERVAL: i32 = 10_000;
+
+impl Dictionary {
+    /// Runs a query. SQLite checks `cancel` as it goes, so even a full-table scan stops soon after
+    /// it's cancelled.
+    pub fn run(
+        &self,
+        request: &QueryRequest,
+        cancel: &CancelToken,
+    ) -> Result<QueryResponse, QueryError> {
+        let token = cancel.clone();
+        self.connection()
+            .progress_handler(CANCEL_CHECK_INTERVAL, Some(move || token.is_cancelled()))?;
+        let response = self.run_query(request);
+        self.connection()
+            .progress_handler(0, None::<fn() -> bool>)?;
+        if cancel.is_cancelled() {
+            return Err(QueryError::Cancelled);
+        }
+        response
+    }
+
+    fn run_query(&self, request: &QueryRequest) -> Result<QueryResponse, QueryError> {
+        let language = request.language.as_deref();
+        Ok(match request.query {
+            Query::Lookup(ref word) => {
+                let mut meanings = self.lookup(word)?;
+                meanings.retain(|meaning| {
+                    language.is_none_or(|language| meaning.language == language)
+                        && (request.edition.is_none() || meaning.edition == request.edition)
+                });
+                QueryResponse::Meanings(meanings)
+            }
+            Query::First(ref word) => QueryResponse::Meanings(
+                self.top_meaning(word, language, request.edition.as_deref())?
+                    .into_iter()
+                    .collect(),
+            ),
+            Query::Search {
+                ref pattern,
+                ref after,
+                limit,
+            } => QueryResponse::Headwords(self.search(pattern, after.as_ref(), limit)?),
+            Query::Matching {
+                ref filter,
+                ref after,
+                limit,
+            } => QueryResponse::Entries(self.matching(
+                filter,
+                language,
+                request.edition.as_deref(),
+                after.as_ref(),
+                limit,
+            )?),
+            Query::Related {
+                ref word,
+                ref kinds,
+                depth,
+            } => QueryResponse::Related(self.related(word, language, kinds, depth)?),
+        })
+    }
+}
+
+/// Words so common that they say next to nothing: most multi-word headwords contain them, so
+/// searching for them matches nearly everything.
+pub const DEFAULT_STOPWORDS: &[&str] = &[
+    "a", "an", "and", "as", "at", "be", "by", "for", "in", "is", "it", "of", "on", "or", "the",
+    "to",
+];
+
+/// Partial searches scan every headword, so these stop the ones that would be slow and match
+/// nearly everything anyway.
+pub struct PartialSearchLimits {
+    /// In characters. Short enough not to rule out two-character CJK words.
+    pub min_length: usize,
+    pub stopwords: HashSet<String>,
+}
+
+impl Default for PartialSearchLimits {
+    fn default() -> Self {
+        PartialSearchLimits {
+            min_length: 2,
+            stopwords: DEFAULT_STOPWORDS
+                .iter()
+                .map(|&word| word.to_owned())
+                .collect(),
+        }
+    }
+}
+
+impl PartialSearchLimits {
+    /// Why a partial search for `s` isn't allowed, if it isn't.
+    pub fn check(&self, s: &str) -> Result<(), String> {
+        let s = s.trim();
+        if s.chars().count() < self.min_length {
+            return Err(format!(
+                "{:?} is shorter than {} characters",
+                s, self.min_length
+            ));
+        }
+        if self.stopwords.contains(&s.to_lowercase()) {
+            return Err(format!("{:?} is a stopword", s));
+        }
+        Ok(())
+    }
+}
+
+/// `s` as a glob pattern that matches only itself, for building patterns for `Query::Search`.
+pub fn escape_glob(s: &str) -> String {
+    let mut pattern = String::new();
+    for c in s.chars() {
+        match c {
+            '*' | '?' | '[' => {
+                pattern.push('[');
+                pattern.push(c);
+                pattern.push(']');
+            }
+            _ => pattern.push(c),
+        }
+    }
+    pattern
+}
+
+/// A glob pattern for `Query::Search` matching headwords that contain `s`.
+pub fn contains_pattern(s: &str) -> String {
+    format!("*{}*", escape_glob(s))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_db::TestDb;
+
+    // Enough words that scanning them all takes many progress handler intervals.
+    fn many_words() -> TestDb {
+        TestDb::baseline(&[("cat", "English", "Noun", "A feline.")]).with(
+            "WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 20000)
+             INSERT INTO words SELECT 'word' || i, 'English', 'Noun', 'Definition ' || i FROM n",
+        )
+    }
+
+    fn scan() -> QueryRequest {
+        QueryRequest::new(Query::Matching {
+            filter: MeaningFilter {
+                definition_contains: Some("nowhere".to_owned()),
+                ..MeaningFilter::default()
+            },
+            after: None,
+            limit: 10,
+        })
+    }
+
+    #[test]
+    fn cancelling_interrupts_the_query() {
+        let db = many_words();
+        let dictionary = db.open();
+        // What SQLite returns when the progress handler stops it.
+        dictionary
+            .connection()
+            .progress_handler(CANCEL_CHECK_INTERVAL, Some(|| true))
+            .unwrap();
+        match dictionary.run_query(&scan()) {
+            Err(QueryError::Cancelled) => (),
+            other => panic!("expected the query to be interrupted, got {:?}", other),
+        }
+        dictionary
+            .connection()
+            .progress_handler(0, None::<fn() -> bool>)
+            .unwrap();
+
+        let cancel = CancelToken::new();
+        cancel.cancel();
+        match dictionary.run(&scan(), &cancel) {
+            Err(QueryError::Cancelled) => (),
+            other => panic!("expected the query to be cancelled, got {:?}", other),
+        }
+        assert_eq!(QueryError::Cancelled.to_string(), "query cancelled");
+
+        // The next query isn't affected.
+        let request = QueryRequest::new(Query::Lookup("cat".to_owned()));
+        match dictionary.run(&request, &CancelToken::new()).unwrap() {
+            QueryResponse::Meanings(meanings) => assert_eq!(meanings.len(), 1),
+            other => panic!("expected meanings, got {:?}", other),
+        }
+        match dictionary.run(&scan(), &CancelToken::new()).unwrap() {
+            QueryResponse::Entries(page) => assert!(page.items.is_empty()),
+            other => panic!("expected entries, got {:?}", other),
+        }
+    }
+}
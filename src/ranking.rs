@@ -0,0 +1,46 @@
+use std::sync::OnceLock;
+
+use regex::Regex;
+
+use Meaning;
+
+// How far down a sense with each label should go. Senses can have several labels, and the
+// strongest one wins.
+fn label_penalty(label: &str) -> u32 {
+    match label.trim() {
+        "obsolete" | "archaic" => 3,
+        "dated" | "historical" | "rare" | "very rare" => 2,
+        "nonstandard" | "uncommon" | "poetic" | "literary" => 1,
+        _ => 0,
+    }
+}
+
+fn label_template_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| {
+        Regex::new(r"\{\{(?:lb|lbl|label|q|qual|qualifier|i)\|(?P<labels>[^{}]*)\}\}").unwrap()
+    })
+}
+
+/// How uncommon a sense looks from the usage labels on its raw definition; 0 for unlabelled
+/// senses.
+pub fn commonness_penalty(definition: &str) -> u32 {
+    label_template_regex()
+        .captures_iter(definition)
+        .flat_map(|caps| {
+            caps.name("labels")
+                .unwrap()
+                .as_str()
+                .split('|')
+                .map(label_penalty)
+                .collect::<Vec<u32>>()
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+/// Sorts meanings so that everyday senses come before obsolete, archaic and rare ones, otherwise
+/// keeping Wiktionary's order.
+pub fn sort_common_first(meanings: &mut [Meaning]) {
+    meanings.sort_by_key(|meaning| commonness_penalty(&meaning.definition));
+}
@@ -0,0 +1,255 @@
+use std::sync::OnceLock;
+
+use regex::Regex;
+
+use languages::find_language;
+use parse_wikitext::plain_text;
+use templates::{parse_templates, Node, Template};
+
+/// A reference from a definition to another entry, through a link or a form-of template.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Reference {
+    /// The headword being referred to.
+    pub target: String,
+    /// The language section being referred to, when known.
+    pub language: Option<String>,
+    /// Anchor of that section on the target's page, e.g. `Old_English` for `cat#Old_English`.
+    pub anchor: Option<String>,
+}
+
+impl Reference {
+    fn new(target: &str, language: Option<String>) -> Reference {
+        let anchor = language.as_ref().map(|language| language.replace(' ', "_"));
+        Reference {
+            target: target.trim().to_owned(),
+            language,
+            anchor,
+        }
+    }
+}
+
+// Templates that refer to another entry, with the positions of their language code and target
+// arguments. Position 0 is the template name.
+fn reference_template(name: &str) -> Option<(Option<usize>, usize)> {
+    match name {
+        "l" | "ll" | "m" | "l-self" | "m-self" => Some((Some(1), 2)),
+        "ja-romanization of" => Some((None, 1)),
+        _ if name.ends_with(" of") || name == "alt form" || name == "alt sp" => Some((Some(1), 2)),
+        _ => None,
+    }
+}
+
+fn link_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"\[\[(?P<target>[^\[\]|]*)(?:\|[^\[\]]*)?\]\]").unwrap())
+}
+
+// Every template in `nodes`, outer ones before those in their arguments.
+fn templates<'n, 'a>(nodes: &'n [Node<'a>], found: &mut Vec<&'n Template<'a>>) {
+    for node in nodes {
+        if let Node::Template(template) = node {
+            found.push(template);
+            for argument in &template.arguments {
+                templates(&argument.value, found);
+            }
+        }
+    }
+}
+
+// Where `template` starts in `definition`, which it was parsed from.
+fn offset(definition: &str, template: &Template) -> usize {
+    template.raw.as_ptr() as usize - definition.as_ptr() as usize
+}
+
+fn template_reference(template: &Template) -> Option<Reference> {
+    let name = plain_text(&template.name);
+    let (language_arg, target_arg) = reference_template(&name)?;
+    // Named arguments don't count towards positions.
+    let args: Vec<String> = template.positional().map(plain_text).collect();
+    // Positions count from the name, as 0.
+    let arg = |i: usize| args.get(i - 1).cloned();
+    // Older form-of templates give the language as `lang=` and have the target in its place.
+    let lang = template
+        .named()
+        .find(|&(key, _)| key == "lang")
+        .map(|(_, value)| plain_text(value));
+    let (code, target) = match (language_arg, lang) {
+        (Some(_), Some(lang)) => (Some(lang), arg(1)),
+        (Some(i), None) if args.len() >= target_arg => (arg(i), arg(target_arg)),
+        (Some(_), None) => (None, arg(1)),
+        (None, _) => (None, arg(target_arg)),
+    };
+    let language = match name.as_str() {
+        "ja-romanization of" => Some("Japanese".to_owned()),
+        _ => code.map(|code| {
+            find_language(&code).map_or_else(|| code.clone(), |language| language.name.to_owned())
+        }),
+    };
+    target
+        .filter(|target| !target.is_empty())
+        .map(|target| Reference::new(&target, language))
+}
+
+// The entries `[[...]]` links in `definition` lead to, with where each link is.
+fn links(definition: &str) -> Vec<(usize, Reference)> {
+    let mut links = Vec::new();
+    for caps in link_regex().captures_iter(definition) {
+        let target = caps.name("target").unwrap();
+        let (page, anchor) = match target.as_str().find('#') {
+            None => (target.as_str(), None),
+            Some(i) => (&target.as_str()[..i], Some(&target.as_str()[i + 1..])),
+        };
+        // Links into other namespaces (w:, Category:, ...) aren't entries.
+        if page.trim().is_empty() || page.contains(':') {
+            continue;
+        }
+        let language = anchor.map(|anchor| anchor.replace('_', " "));
+        links.push((target.start(), Reference::new(page, language)));
+    }
+    links
+}
+
+/// The entries the `[[...]]` links in a definition lead to, in the order they appear; unlike
+/// `find_references`, not those of templates.
+pub fn find_links(definition: &str) -> Vec<Reference> {
+    links(definition)
+        .into_iter()
+        .map(|(_, reference)| reference)
+        .collect()
+}
+
+/// Finds the entries a raw definition refers to, in the order they appear.
+pub fn find_references(definition: &str) -> Vec<Reference> {
+    let mut references = links(definition);
+    let nodes = parse_templates(definition);
+    let mut found = Vec::new();
+    templates(&nodes, &mut found);
+    for template in found {
+        if let Some(reference) = template_reference(template) {
+            references.push((offset(definition, template), reference));
+        }
+    }
+
+    references.sort_by_key(|&(start, _)| start);
+    references
+        .into_iter()
+        .map(|(_, reference)| reference)
+        .collect()
+}
+
+// A definition that is only a form of another word, as in "Plural of [[goose]]" or, once the
+// importer has taken the link out, "Plural of goose", maybe after labels.
+fn form_of_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| {
+        Regex::new(
+            r"(?i)^\s*(?:[a-z-]+ )*(?:form|spelling|plural|tense|participle|comparative|superlative|abbreviation|misspelling) of (?:\[\[)?(?P<target>[^\[\]{}|#.,;:()]+)(?:[#|][^\[\]]*)?(?:\]\])?\.?\s*$",
+        )
+        .unwrap()
+    })
+}
+
+/// The entry a raw definition says its word is a form of, as in `{{plural of|en|goose}}` or
+/// "Plural of [[goose]]". None if it's a definition in its own right, or says more than that.
+pub fn form_of(definition: &str) -> Option<Reference> {
+    let nodes = parse_templates(definition);
+    let is_form_of = |node: &Node| match *node {
+        Node::Template(ref template) => {
+            let name = plain_text(&template.name);
+            name.ends_with(" of") || name == "alt form" || name == "alt sp"
+        }
+        _ => false,
+    };
+    if let Some(i) = nodes.iter().position(is_form_of) {
+        // Only labels can come before the template, and nothing but a full stop after it.
+        let labels = nodes[..i].iter().all(|node| match *node {
+            Node::Template(_) => true,
+            Node::Text(text) => text.trim().is_empty(),
+            Node::Parameter(_) => false,
+        });
+        let rest = nodes[i + 1..].iter().all(|node| match *node {
+            Node::Text(text) => text.trim().trim_end_matches('.').is_empty(),
+            _ => false,
+        });
+        return match nodes[i] {
+            Node::Template(ref template) if labels && rest => template_reference(template),
+            _ => None,
+        };
+    }
+    // Labels, like `{{lb|en|archaic}}`, can come first.
+    let start = nodes
+        .iter()
+        .find_map(|node| match *node {
+            Node::Template(_) => None,
+            Node::Text(text) if text.trim().is_empty() => None,
+            Node::Text(text) => Some(text.as_ptr() as usize - definition.as_ptr() as usize),
+            Node::Parameter(ref parameter) => {
+                Some(parameter.raw.as_ptr() as usize - definition.as_ptr() as usize)
+            }
+        })
+        .unwrap_or(definition.len());
+    let caps = form_of_regex().captures(&definition[start..])?;
+    Some(Reference::new(&caps["target"], None))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn targets(definition: &str) -> Vec<(String, Option<String>)> {
+        find_references(definition)
+            .into_iter()
+            .map(|reference| (reference.target, reference.language))
+            .collect()
+    }
+
+    #[test]
+    fn links_and_templates_in_order() {
+        assert_eq!(
+            targets("{{m|fr|chat|t=cat}}, like [[cat#English|a cat]] or {{l|en|kitty}}"),
+            [
+                ("chat".to_owned(), Some("French".to_owned())),
+                ("cat".to_owned(), Some("English".to_owned())),
+                ("kitty".to_owned(), Some("English".to_owned())),
+            ]
+        );
+    }
+
+    #[test]
+    fn templates_inside_templates() {
+        assert_eq!(
+            targets("{{gloss|{{l|en|cat}}}}"),
+            [("cat".to_owned(), Some("English".to_owned()))]
+        );
+    }
+
+    #[test]
+    fn alternative_form_of_in_either_order() {
+        let reference = form_of("{{alternative form of|en|color}}").unwrap();
+        assert_eq!(reference.target, "color");
+        assert_eq!(reference.language.as_deref(), Some("English"));
+        let reference = form_of("{{alternative form of|colour|lang=en}}").unwrap();
+        assert_eq!(reference.target, "colour");
+        assert_eq!(reference.language.as_deref(), Some("English"));
+    }
+
+    #[test]
+    fn form_of_only_when_thats_all_it_says() {
+        assert_eq!(
+            form_of("{{plural of|en|goose}}; also, a gaggle of them."),
+            None
+        );
+        assert_eq!(form_of("A bird, especially {{plural of|en|goose}}"), None);
+        assert!(form_of("{{plural of|en|goose}}.").is_some());
+    }
+
+    #[test]
+    fn form_of_after_labels() {
+        let reference = form_of("{{lb|en|archaic}} {{plural of|en|goose}}").unwrap();
+        assert_eq!(reference.target, "goose");
+        assert_eq!(reference.language.as_deref(), Some("English"));
+        let reference = form_of("{{lb|en|informal}} Plural of [[goose]].").unwrap();
+        assert_eq!(reference.target, "goose");
+        assert_eq!(form_of("{{lb|en|informal}} A goose."), None);
+    }
+}
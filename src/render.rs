@@ -0,0 +1,1069 @@
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use regex::{Captures, Regex};
+
+use abbreviations::Abbreviations;
+use conlang::{LojbanFormatter, TokiPonaFormatter};
+use normalize::fold_length_marks;
+use Meaning;
+
+fn superscript(c: char) -> Option<char> {
+    Some(match c {
+        '0' => '⁰',
+        '1' => '¹',
+        '2' => '²',
+        '3' => '³',
+        '4' => '⁴',
+        '5' => '⁵',
+        '6' => '⁶',
+        '7' => '⁷',
+        '8' => '⁸',
+        '9' => '⁹',
+        '+' => '⁺',
+        '-' | '−' => '⁻',
+        '=' => '⁼',
+        '(' => '⁽',
+        ')' => '⁾',
+        'a' => 'ᵃ',
+        'b' => 'ᵇ',
+        'c' => 'ᶜ',
+        'd' => 'ᵈ',
+        'e' => 'ᵉ',
+        'f' => 'ᶠ',
+        'g' => 'ᵍ',
+        'h' => 'ʰ',
+        'i' => 'ⁱ',
+        'j' => 'ʲ',
+        'k' => 'ᵏ',
+        'l' => 'ˡ',
+        'm' => 'ᵐ',
+        'n' => 'ⁿ',
+        'o' => 'ᵒ',
+        'p' => 'ᵖ',
+        'r' => 'ʳ',
+        's' => 'ˢ',
+        't' => 'ᵗ',
+        'u' => 'ᵘ',
+        'v' => 'ᵛ',
+        'w' => 'ʷ',
+        'x' => 'ˣ',
+        'y' => 'ʸ',
+        'z' => 'ᶻ',
+        _ => return None,
+    })
+}
+
+fn subscript(c: char) -> Option<char> {
+    Some(match c {
+        '0' => '₀',
+        '1' => '₁',
+        '2' => '₂',
+        '3' => '₃',
+        '4' => '₄',
+        '5' => '₅',
+        '6' => '₆',
+        '7' => '₇',
+        '8' => '₈',
+        '9' => '₉',
+        '+' => '₊',
+        '-' | '−' => '₋',
+        '=' => '₌',
+        '(' => '₍',
+        ')' => '₎',
+        'a' => 'ₐ',
+        'e' => 'ₑ',
+        'h' => 'ₕ',
+        'i' => 'ᵢ',
+        'j' => 'ⱼ',
+        'k' => 'ₖ',
+        'l' => 'ₗ',
+        'm' => 'ₘ',
+        'n' => 'ₙ',
+        'o' => 'ₒ',
+        'p' => 'ₚ',
+        'r' => 'ᵣ',
+        's' => 'ₛ',
+        't' => 'ₜ',
+        'u' => 'ᵤ',
+        'v' => 'ᵥ',
+        'x' => 'ₓ',
+        _ => return None,
+    })
+}
+
+// Maps every character of `s`, or gives up if any of them has no mapping.
+fn map_all(s: &str, f: fn(char) -> Option<char>) -> Option<String> {
+    s.chars().map(f).collect()
+}
+
+fn tag_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    // Innermost tags only; nested ones are handled by going over the text again.
+    RE.get_or_init(|| {
+        Regex::new(
+            r"(?i)<(?P<open>sub|sup|small|nowiki|span|b|i)(?:\s[^<>]*)?>(?P<text>[^<]*)</(?P<close>[a-z]+)>",
+        )
+        .unwrap()
+    })
+}
+
+// Line breaks, and the break between two paragraphs.
+fn br_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"(?i)<br\s*/?>|</p>\s*<p(?:\s[^<>]*)?>").unwrap())
+}
+
+// Footnotes, scripts, self-closing tags like `<nowiki/>` or `<ref name="x"/>`, and what's left
+// of paragraphs once the breaks between them are out have nothing to show.
+fn dropped_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| {
+        Regex::new(
+            r"(?is)<ref(?:\s[^<>]*)?>.*?</ref>|<script(?:\s[^<>]*)?>.*?</script>|<style(?:\s[^<>]*)?>.*?</style>|<[a-z]+(?:\s[^<>]*)?/>|</?p(?:\s[^<>]*)?>",
+        )
+        .unwrap()
+    })
+}
+
+fn entity_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"&(?P<entity>#[0-9]+|#x[0-9a-fA-F]+|[a-zA-Z]+);").unwrap())
+}
+
+fn decode_entity(entity: &str) -> Option<char> {
+    if let Some(hex) = entity.strip_prefix("#x") {
+        return u32::from_str_radix(hex, 16).ok().and_then(char::from_u32);
+    }
+    if let Some(decimal) = entity.strip_prefix('#') {
+        return decimal.parse().ok().and_then(char::from_u32);
+    }
+    Some(match entity {
+        "amp" => '&',
+        "lt" => '<',
+        "gt" => '>',
+        "quot" => '"',
+        "apos" => '\'',
+        "nbsp" => '\u{a0}',
+        "ndash" => '–',
+        "mdash" => '—',
+        "hellip" => '…',
+        "minus" => '−',
+        "times" => '×',
+        "middot" => '·',
+        _ => return None,
+    })
+}
+
+fn latex_symbol(command: &str) -> Option<&'static str> {
+    Some(match command {
+        "alpha" => "α",
+        "beta" => "β",
+        "gamma" => "γ",
+        "delta" => "δ",
+        "epsilon" | "varepsilon" => "ε",
+        "zeta" => "ζ",
+        "eta" => "η",
+        "theta" | "vartheta" => "θ",
+        "iota" => "ι",
+        "kappa" => "κ",
+        "lambda" => "λ",
+        "mu" => "μ",
+        "nu" => "ν",
+        "xi" => "ξ",
+        "pi" => "π",
+        "rho" | "varrho" => "ρ",
+        "sigma" => "σ",
+        "tau" => "τ",
+        "upsilon" => "υ",
+        "phi" | "varphi" => "φ",
+        "chi" => "χ",
+        "psi" => "ψ",
+        "omega" => "ω",
+        "Gamma" => "Γ",
+        "Delta" => "Δ",
+        "Theta" => "Θ",
+        "Lambda" => "Λ",
+        "Xi" => "Ξ",
+        "Pi" => "Π",
+        "Sigma" => "Σ",
+        "Phi" => "Φ",
+        "Psi" => "Ψ",
+        "Omega" => "Ω",
+        "times" => "×",
+        "cdot" => "·",
+        "div" => "÷",
+        "pm" => "±",
+        "mp" => "∓",
+        "le" | "leq" => "≤",
+        "ge" | "geq" => "≥",
+        "ne" | "neq" => "≠",
+        "approx" => "≈",
+        "equiv" => "≡",
+        "sim" => "∼",
+        "propto" => "∝",
+        "infty" => "∞",
+        "sum" => "∑",
+        "prod" => "∏",
+        "int" => "∫",
+        "oint" => "∮",
+        "partial" => "∂",
+        "nabla" => "∇",
+        "in" => "∈",
+        "notin" => "∉",
+        "subset" => "⊂",
+        "subseteq" => "⊆",
+        "supset" => "⊃",
+        "cup" => "∪",
+        "cap" => "∩",
+        "emptyset" | "varnothing" => "∅",
+        "forall" => "∀",
+        "exists" => "∃",
+        "neg" | "lnot" => "¬",
+        "land" | "wedge" => "∧",
+        "lor" | "vee" => "∨",
+        "to" | "rightarrow" => "→",
+        "leftarrow" => "←",
+        "Rightarrow" | "implies" => "⇒",
+        "Leftrightarrow" | "iff" => "⇔",
+        "mapsto" => "↦",
+        "circ" => "∘",
+        "deg" => "°",
+        "ldots" | "dots" | "cdots" => "…",
+        "hbar" => "ℏ",
+        "ell" => "ℓ",
+        "mathbb{R}" => "ℝ",
+        "sqrt" => "√",
+        "," | ";" | ":" | " " | "quad" => " ",
+        "qquad" => "  ",
+        "!" => "",
+        "{" => "{",
+        "}" => "}",
+        "%" => "%",
+        "&" => "&",
+        "_" => "_",
+        "#" => "#",
+        "left" | "right" | "displaystyle" | "textstyle" | "big" | "Big" => "",
+        _ => return None,
+    })
+}
+
+// Commands whose argument is printed as-is.
+fn is_font_command(command: &str) -> bool {
+    matches!(
+        command,
+        "mathrm"
+            | "mathbf"
+            | "mathit"
+            | "mathsf"
+            | "mathtt"
+            | "mathcal"
+            | "text"
+            | "textrm"
+            | "textit"
+            | "textbf"
+            | "operatorname"
+            | "boldsymbol"
+    )
+}
+
+fn blackboard(c: char) -> Option<char> {
+    Some(match c {
+        'C' => 'ℂ',
+        'N' => 'ℕ',
+        'P' => 'ℙ',
+        'Q' => 'ℚ',
+        'R' => 'ℝ',
+        'Z' => 'ℤ',
+        _ => return None,
+    })
+}
+
+// Wraps a fraction part or radicand in parentheses if it has more than one term.
+fn parenthesize(s: String) -> String {
+    if s.contains(|c: char| " +-−×·/=".contains(c)) {
+        format!("({})", s)
+    } else {
+        s
+    }
+}
+
+struct Latex<'a> {
+    chars: &'a [char],
+    pos: usize,
+}
+
+impl<'a> Latex<'a> {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).cloned()
+    }
+
+    // A `{...}` group or a single character/command, as used for arguments and scripts.
+    fn argument(&mut self) -> String {
+        while self.peek() == Some(' ') {
+            self.pos += 1;
+        }
+        match self.peek() {
+            Some('{') => {
+                self.pos += 1;
+                self.until_close()
+            }
+            Some('\\') => {
+                self.pos += 1;
+                self.command()
+            }
+            Some(c) => {
+                self.pos += 1;
+                c.to_string()
+            }
+            None => String::new(),
+        }
+    }
+
+    fn command(&mut self) -> String {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c.is_ascii_alphabetic()) {
+            self.pos += 1;
+        }
+        if self.pos == start && self.peek().is_some() {
+            self.pos += 1;
+        }
+        let name: String = self.chars[start..self.pos].iter().collect();
+        match name.as_str() {
+            "frac" | "dfrac" | "tfrac" => {
+                let numerator = self.argument();
+                let denominator = self.argument();
+                format!("{}/{}", parenthesize(numerator), parenthesize(denominator))
+            }
+            "sqrt" => format!("√{}", parenthesize(self.argument())),
+            "mathbb" => {
+                let arg = self.argument();
+                map_all(&arg, blackboard).unwrap_or(arg)
+            }
+            _ if is_font_command(&name) => self.argument(),
+            _ => match latex_symbol(&name) {
+                Some(symbol) => symbol.to_owned(),
+                // Left as LaTeX, along with its arguments.
+                None => {
+                    let mut unknown = format!("\\{}", name);
+                    while self.peek() == Some('{') {
+                        self.pos += 1;
+                        unknown.push_str(&format!("{{{}}}", self.until_close()));
+                    }
+                    unknown
+                }
+            },
+        }
+    }
+
+    fn until_close(&mut self) -> String {
+        let mut result = String::new();
+        while let Some(c) = self.peek() {
+            self.pos += 1;
+            match c {
+                '}' => break,
+                '{' => result.push_str(&self.until_close()),
+                '\\' => result.push_str(&self.command()),
+                '^' => {
+                    let arg = self.argument();
+                    result.push_str(
+                        &map_all(&arg, superscript).unwrap_or_else(|| format!("^({})", arg)),
+                    );
+                }
+                '_' => {
+                    let arg = self.argument();
+                    result.push_str(
+                        &map_all(&arg, subscript).unwrap_or_else(|| format!("_({})", arg)),
+                    );
+                }
+                '~' => result.push(' '),
+                _ => result.push(c),
+            }
+        }
+        result
+    }
+}
+
+/// Best-effort conversion of a LaTeX formula into plain Unicode, e.g. `\frac{1}{2}\pi r^2` into
+/// "1/2π r²". Anything it doesn't understand is left as LaTeX.
+pub fn latex_to_unicode(latex: &str) -> String {
+    let chars: Vec<char> = latex.trim().chars().collect();
+    let mut parser = Latex {
+        chars: &chars,
+        pos: 0,
+    };
+    let mut result = String::new();
+    // until_close stops at a stray "}", so keep going until the end.
+    while parser.pos < chars.len() {
+        result.push_str(&parser.until_close());
+    }
+    result
+}
+
+fn math_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"(?is)<math(?:\s[^<>]*)?>(?P<latex>.*?)</math>").unwrap())
+}
+
+/// The LaTeX of each `<math>` formula in a definition, in order.
+pub fn math_formulas(s: &str) -> Vec<String> {
+    math_regex()
+        .captures_iter(s)
+        .map(|caps| caps["latex"].trim().to_owned())
+        .collect()
+}
+
+/// Converts the HTML tags that show up in definitions into something a terminal can show:
+/// Unicode sub- and superscripts where possible, real line breaks, and plain text otherwise.
+/// Formulas in `<math>` are converted to Unicode as far as possible.
+pub fn html_to_text(s: &str) -> String {
+    let s = math_regex().replace_all(s, |caps: &Captures| latex_to_unicode(&caps["latex"]));
+    let s = br_regex().replace_all(&s, "\n");
+    let s = dropped_regex().replace_all(&s, "");
+    let mut result = s.into_owned();
+    loop {
+        let result_ = tag_regex()
+            .replace_all(&result, |caps: &Captures| {
+                let tag = caps["open"].to_lowercase();
+                let text = &caps["text"];
+                if !caps["close"].eq_ignore_ascii_case(&tag) {
+                    return caps[0].to_owned();
+                }
+                match tag.as_str() {
+                    "sup" => map_all(text, superscript).unwrap_or_else(|| format!("^{}", text)),
+                    "sub" => map_all(text, subscript).unwrap_or_else(|| format!("_{}", text)),
+                    _ => text.to_owned(),
+                }
+            })
+            .into_owned();
+        if result_ == result {
+            break;
+        }
+        result = result_;
+    }
+    entity_regex()
+        .replace_all(&result, |caps: &Captures| {
+            decode_entity(&caps["entity"]).map_or_else(|| caps[0].to_owned(), String::from)
+        })
+        .into_owned()
+}
+
+fn allowed_tag_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| {
+        Regex::new(r"(?i)&lt;(?P<close>/?)(?P<tag>sub|sup|small|b|i)(?:\s[^<>]*?)?&gt;").unwrap()
+    })
+}
+
+/// Escapes a definition for HTML output, keeping the formatting tags that are safe to pass
+/// through (without their attributes) and any entities that were already there. Formulas are
+/// shown converted to Unicode, with the original LaTeX kept in a `data-latex` attribute.
+pub fn html_to_html(s: &str) -> String {
+    let s = br_regex().replace_all(s, "\u{0}");
+    let s = dropped_regex().replace_all(&s, "");
+    let mut escaped = String::with_capacity(s.len());
+    let mut last = 0;
+    for m in math_regex().captures_iter(&s) {
+        let whole = m.get(0).unwrap();
+        escaped.push_str(&escape_html_keeping_entities(&s[last..whole.start()]));
+        escaped.push_str(&format!(
+            "<span class=\"math\" data-latex=\"{}\">{}</span>",
+            escape_html(m["latex"].trim()),
+            escape_html(&latex_to_unicode(&m["latex"]))
+        ));
+        last = whole.end();
+    }
+    escaped.push_str(&escape_html_keeping_entities(&s[last..]));
+    let result = allowed_tag_regex().replace_all(&escaped, "<$close$tag>");
+    result
+        .replace("&lt;nowiki&gt;", "")
+        .replace("&lt;/nowiki&gt;", "")
+        .replace('\u{0}', "<br>")
+}
+
+fn escape_html_keeping_entities(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    let mut last = 0;
+    for entity in entity_regex().find_iter(s) {
+        escaped.push_str(&escape_html(&s[last..entity.start()]));
+        escaped.push_str(entity.as_str());
+        last = entity.end();
+    }
+    escaped.push_str(&escape_html(&s[last..]));
+    escaped
+}
+
+/// Escapes text for use in HTML.
+pub fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// How quotes, dashes and spaces are written in output. Wiktionary mixes straight and curly
+/// quotes, and hyphens where dashes belong.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Typography {
+    /// As the definitions have them.
+    AsIs,
+    /// Curly quotes and apostrophes, and en and em dashes for `--` and `---`, for publishing.
+    Smart,
+    /// Only ASCII quotes, dashes and spaces, for plain-text pipelines.
+    Ascii,
+}
+
+// Whether a quote after `prev` opens rather than closes.
+fn opens_quote(prev: Option<char>) -> bool {
+    match prev {
+        None => true,
+        Some(c) => c.is_whitespace() || "([{<\u{2014}\u{2013}-/\u{201c}\u{2018}".contains(c),
+    }
+}
+
+// `prev` is the last character shown before `s`.
+fn smart_quotes(s: &str, mut prev: Option<char>) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        let next = chars.peek().copied();
+        let replacement = match c {
+            '"' if opens_quote(prev) => '\u{201c}',
+            '"' => '\u{201d}',
+            // An apostrophe at the start of a word for what's left out, as in '90s.
+            '\'' if opens_quote(prev) && !next.is_some_and(|c| c.is_ascii_digit()) => '\u{2018}',
+            '\'' => '\u{2019}',
+            '-' if next == Some('-') => {
+                chars.next();
+                if chars.peek() == Some(&'-') {
+                    chars.next();
+                    '\u{2014}'
+                } else {
+                    '\u{2013}'
+                }
+            }
+            // A hyphen with spaces around it is a dash.
+            '-' if prev == Some(' ') && next == Some(' ') => '\u{2013}',
+            c => c,
+        };
+        out.push(replacement);
+        prev = Some(replacement);
+    }
+    out
+}
+
+fn ascii_punctuation(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\u{2018}' | '\u{2019}' | '\u{201a}' | '\u{201b}' | '\u{2032}' => out.push('\''),
+            '\u{201c}' | '\u{201d}' | '\u{201e}' | '\u{201f}' | '\u{2033}' => out.push('"'),
+            '\u{2010}' | '\u{2011}' | '\u{2012}' | '\u{2013}' | '\u{2212}' => out.push('-'),
+            '\u{2014}' | '\u{2015}' => out.push_str("--"),
+            '\u{2026}' => out.push_str("..."),
+            '\u{a0}' | '\u{2007}' | '\u{2009}' | '\u{200a}' | '\u{202f}' => out.push(' '),
+            // Soft hyphens and zero-width spaces only say where a line may break.
+            '\u{ad}' | '\u{200b}' => (),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+// Text that must stay as it is: code and formulas, tags with their attributes, and colors.
+fn verbatim_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| {
+        Regex::new(
+            r"(?is)<(?:code|pre|kbd|samp|math)(?:\s[^<>]*)?>.*?</(?:code|pre|kbd|samp|math)>|</?[a-z][^<>]*>|\x1b\[[0-9;]*m",
+        )
+        .unwrap()
+    })
+}
+
+impl Typography {
+    /// `s` with its quotes, dashes and spaces written this way. Code, formulas and markup are
+    /// left alone.
+    pub fn apply(self, s: &str) -> String {
+        if self == Typography::AsIs {
+            return s.to_owned();
+        }
+        let mut out = String::with_capacity(s.len());
+        let mut last = 0;
+        // The last character shown, so a quote just after a tag opens or closes as it should.
+        let mut prev = None;
+        for verbatim in verbatim_regex().find_iter(s) {
+            let text = &s[last..verbatim.start()];
+            out.push_str(&self.apply_to_text(text, prev));
+            prev = text.chars().last().or(prev);
+            let whole = verbatim.as_str();
+            // After code or a formula, what counts is the last character in it.
+            if let Some(close) = whole.rfind("</").filter(|&close| close > 0) {
+                let content = &whole[..close];
+                let start = content.rfind('>').map_or(0, |end| end + 1);
+                prev = content[start..].chars().last().or(prev);
+            }
+            out.push_str(whole);
+            last = verbatim.end();
+        }
+        out.push_str(&self.apply_to_text(&s[last..], prev));
+        out
+    }
+
+    fn apply_to_text(self, s: &str, prev: Option<char>) -> String {
+        match self {
+            Typography::AsIs => s.to_owned(),
+            Typography::Smart => smart_quotes(s, prev),
+            Typography::Ascii => ascii_punctuation(s),
+        }
+    }
+}
+
+fn ansi_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"\x1b\[[0-9;]*m").unwrap())
+}
+
+// What goes at the end of truncated output.
+const ELLIPSIS: &str = " …";
+
+/// Keeps output within a number of characters and/or lines, for status bars, notifications and
+/// bots with strict limits. Output goes through it a block at a time (a heading, or a sense, which
+/// can wrap onto several lines), and only whole blocks are let through, so nothing is cut off
+/// mid-sentence, except a first sense that wouldn't fit at all. Once a block doesn't fit, nothing
+/// after it does either, and the last block that did ends in "…". Colors don't count.
+pub struct Truncator {
+    max_chars: Option<usize>,
+    max_lines: Option<usize>,
+    chars: usize,
+    lines: usize,
+    // The last block, held back until it's known whether it needs the "…".
+    pending: Option<String>,
+    shown_sense: bool,
+    full: bool,
+}
+
+impl Truncator {
+    pub fn new(max_chars: Option<usize>, max_lines: Option<usize>) -> Truncator {
+        Truncator {
+            max_chars,
+            max_lines,
+            chars: 0,
+            lines: 0,
+            pending: None,
+            shown_sense: false,
+            full: false,
+        }
+    }
+
+    /// Whether nothing more will fit, so there's no point rendering any more.
+    pub fn is_full(&self) -> bool {
+        self.full
+    }
+
+    fn is_limited(&self) -> bool {
+        self.max_chars.is_some() || self.max_lines.is_some()
+    }
+
+    /// Adds the next block, which is a sense if `sense`. Returns the block before it, which can
+    /// now be printed, or the block itself when there are no limits.
+    pub fn push(&mut self, block: String, sense: bool) -> Option<String> {
+        if !self.is_limited() {
+            return Some(block);
+        }
+        if self.full {
+            return None;
+        }
+        let visible = ansi_regex().replace_all(&block, "");
+        // A line break to separate it from the block before, and room for the "…".
+        let chars = visible.chars().count() + self.lines.min(1) + ELLIPSIS.chars().count();
+        let lines = visible.lines().count().max(1);
+        let chars_left = self.max_chars.map(|max| max.saturating_sub(self.chars));
+        let lines_left = self.max_lines.map(|max| max.saturating_sub(self.lines));
+        let fits = chars_left.is_none_or(|left| chars <= left)
+            && lines_left.is_none_or(|left| lines <= left);
+        if fits {
+            self.chars += chars - ELLIPSIS.chars().count();
+            self.lines += lines;
+            self.shown_sense |= sense;
+            return self.pending.replace(block);
+        }
+        self.full = true;
+        if sense && !self.shown_sense && lines_left != Some(0) {
+            // Better part of the first sense than none of it: as many words as fit on the lines
+            // that are left.
+            let mut room = chars_left.map_or(usize::MAX, |left| {
+                left.saturating_sub(self.lines.min(1) + ELLIPSIS.chars().count())
+            });
+            let mut cut = String::new();
+            for (i, line) in visible.lines().enumerate() {
+                if lines_left.is_some_and(|left| i >= left) {
+                    break;
+                }
+                let text = line.trim_start();
+                let indent = &line[..line.len() - text.len()];
+                let mut kept = String::new();
+                for word in text.split(' ') {
+                    let separator = if kept.is_empty() { indent.len() } else { 1 };
+                    let needed = word.chars().count() + separator;
+                    if needed > room {
+                        break;
+                    }
+                    kept.push_str(if kept.is_empty() { indent } else { " " });
+                    kept.push_str(word);
+                    room -= needed;
+                }
+                if kept.trim().is_empty() {
+                    break;
+                }
+                if !cut.is_empty() {
+                    cut.push('\n');
+                }
+                cut.push_str(&kept);
+                room = room.saturating_sub(1);
+            }
+            if !cut.trim().is_empty() {
+                return self.pending.replace(cut);
+            }
+        }
+        None
+    }
+
+    /// The last block, ending in "…" if anything after it was left out.
+    pub fn finish(self) -> Option<String> {
+        let full = self.full;
+        self.pending
+            .map(|block| if full { block + ELLIPSIS } else { block })
+    }
+}
+
+/// Presentation that only makes sense for one language: extra lines about the headword, or
+/// touch-ups to its definitions. Each one is registered in `Formatters` under the language's
+/// name.
+pub trait LanguageFormatter: Send + Sync {
+    /// Lines to show under the language's heading, before its definitions.
+    fn headword_lines(&self, _word: &str, _meanings: &[&Meaning]) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// A definition as plain text, after its templates have been expanded.
+    fn definition(&self, definition: String) -> String {
+        definition
+    }
+}
+
+/// The language formatters that apply to an entry, by (normalized) language name.
+pub struct Formatters {
+    by_language: HashMap<String, Box<dyn LanguageFormatter>>,
+    abbreviations: Option<Abbreviations>,
+}
+
+impl Default for Formatters {
+    /// The built-in formatters.
+    fn default() -> Self {
+        let mut formatters = Formatters::empty();
+        formatters.register("Latin", LatinFormatter);
+        formatters.register("Lojban", LojbanFormatter);
+        formatters.register("Toki Pona", TokiPonaFormatter);
+        formatters
+    }
+}
+
+impl Formatters {
+    pub fn empty() -> Formatters {
+        Formatters {
+            by_language: HashMap::new(),
+            abbreviations: None,
+        }
+    }
+
+    /// Registers `formatter` for `language`, replacing any it already had.
+    pub fn register<F>(&mut self, language: &str, formatter: F)
+    where
+        F: LanguageFormatter + 'static,
+    {
+        self.by_language
+            .insert(language.to_owned(), Box::new(formatter));
+    }
+
+    /// Spells out `abbreviations` in definitions, after the language's own formatter. They're
+    /// left alone otherwise.
+    pub fn expand_abbreviations(&mut self, abbreviations: Abbreviations) {
+        self.abbreviations = Some(abbreviations);
+    }
+
+    pub fn get(&self, language: &str) -> Option<&dyn LanguageFormatter> {
+        self.by_language.get(language).map(|formatter| &**formatter)
+    }
+
+    pub fn headword_lines(&self, language: &str, word: &str, meanings: &[&Meaning]) -> Vec<String> {
+        self.get(language).map_or_else(Vec::new, |formatter| {
+            formatter.headword_lines(word, meanings)
+        })
+    }
+
+    pub fn definition(&self, language: &str, definition: String) -> String {
+        let definition = match self.get(language) {
+            Some(formatter) => formatter.definition(definition),
+            None => definition,
+        };
+        match self.abbreviations {
+            Some(ref abbreviations) => abbreviations.expand(language, &definition),
+            None => definition,
+        }
+    }
+}
+
+// Points out the unmarked spelling of headwords with length marks, which is the one that's easy to
+// type.
+struct LatinFormatter;
+
+impl LanguageFormatter for LatinFormatter {
+    fn headword_lines(&self, word: &str, _meanings: &[&Meaning]) -> Vec<String> {
+        let folded = fold_length_marks(word);
+        if folded == word {
+            Vec::new()
+        } else {
+            vec![format!("without length marks: {}", folded)]
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn html_to_text_nested_tags() {
+        assert_eq!(html_to_text("H<sub>2</sub>O"), "H₂O");
+        assert_eq!(html_to_text("x<sup><i>2</i></sup>"), "x²");
+        assert_eq!(html_to_text("<b><i>very</i> bold</b>"), "very bold");
+        assert_eq!(html_to_text("e<sup>iπ</sup>"), "e^iπ");
+        assert_eq!(html_to_text("<span class=\"gloss\">word</span>"), "word");
+    }
+
+    #[test]
+    fn html_to_text_entities() {
+        assert_eq!(
+            html_to_text("fish &amp; chips &ndash; caf&#233; caf&#xe9;"),
+            "fish & chips – café café"
+        );
+        assert_eq!(html_to_text("&lt;b&gt;"), "<b>");
+        assert_eq!(html_to_text("&bogus; &#xzz;"), "&bogus; &#xzz;");
+    }
+
+    #[test]
+    fn html_to_text_line_breaks() {
+        assert_eq!(html_to_text("one<br>two<BR />three"), "one\ntwo\nthree");
+        assert_eq!(
+            html_to_text("<p>one</p>\n<p class=\"x\">two</p>"),
+            "one\ntwo"
+        );
+    }
+
+    #[test]
+    fn html_to_text_drops() {
+        assert_eq!(
+            html_to_text("cat<ref name=\"a\">Source</ref><ref name=\"b\"/>"),
+            "cat"
+        );
+        assert_eq!(html_to_text("a<script>alert(1)</script>b"), "ab");
+        assert_eq!(html_to_text("<nowiki/>[[x]]"), "[[x]]");
+    }
+
+    #[test]
+    fn html_to_html_keeps_safe_tags() {
+        assert_eq!(
+            html_to_html("H<sub>2</sub>O is <b onclick=\"steal()\">wet</b>"),
+            "H<sub>2</sub>O is <b>wet</b>"
+        );
+        assert_eq!(html_to_html("<i><b>both</b></i>"), "<i><b>both</b></i>");
+        assert_eq!(html_to_html("one<br/>two"), "one<br>two");
+        assert_eq!(html_to_html("<p>one</p><p>two</p>"), "one<br>two");
+    }
+
+    #[test]
+    fn html_to_html_escapes_the_rest() {
+        assert_eq!(html_to_html("a<script>alert(1)</script>b"), "ab");
+        assert_eq!(
+            html_to_html("<a href=\"javascript:x\">link</a>"),
+            "&lt;a href=&quot;javascript:x&quot;&gt;link&lt;/a&gt;"
+        );
+        assert_eq!(
+            html_to_html("<img src=x onerror=alert(1)>"),
+            "&lt;img src=x onerror=alert(1)&gt;"
+        );
+        assert_eq!(
+            html_to_html("fish &amp; chips & <3"),
+            "fish &amp; chips &amp; &lt;3"
+        );
+        assert_eq!(html_to_html("<nowiki>[[x]]</nowiki>"), "[[x]]");
+    }
+
+    #[test]
+    fn latex_scripts() {
+        assert_eq!(latex_to_unicode("x^2"), "x²");
+        assert_eq!(latex_to_unicode("x^{n+1}"), "xⁿ⁺¹");
+        assert_eq!(latex_to_unicode("a_{12} + x_{ij}"), "a₁₂ + xᵢⱼ");
+        // Not every letter has a superscript.
+        assert_eq!(latex_to_unicode("x^{q}"), "x^(q)");
+        assert_eq!(latex_to_unicode("e^{i\\pi}"), "e^(iπ)");
+    }
+
+    #[test]
+    fn latex_symbols() {
+        assert_eq!(latex_to_unicode("\\alpha + \\beta = \\Gamma"), "α + β = Γ");
+        assert_eq!(latex_to_unicode("x \\le y \\to \\infty"), "x ≤ y → ∞");
+        assert_eq!(latex_to_unicode("\\mathbb{R}^n"), "ℝⁿ");
+        assert_eq!(latex_to_unicode("\\mathrm{d}x"), "dx");
+    }
+
+    #[test]
+    fn latex_fractions_and_roots() {
+        assert_eq!(latex_to_unicode("\\frac{1}{2}\\pi r^2"), "1/2π r²");
+        assert_eq!(latex_to_unicode("\\frac{a+b}{2}"), "(a+b)/2");
+        assert_eq!(latex_to_unicode("\\dfrac12"), "1/2");
+        assert_eq!(latex_to_unicode("\\sqrt{x+1}"), "√(x+1)");
+        assert_eq!(latex_to_unicode("\\sqrt2"), "√2");
+    }
+
+    #[test]
+    fn latex_unknown_commands() {
+        assert_eq!(latex_to_unicode("\\foo"), "\\foo");
+        assert_eq!(latex_to_unicode("\\foo x"), "\\foo x");
+        assert_eq!(latex_to_unicode("\\overline{AB}"), "\\overline{AB}");
+        assert_eq!(latex_to_unicode("\\binom{n}{k}"), "\\binom{n}{k}");
+        assert_eq!(latex_to_unicode("\\hat{\\theta}"), "\\hat{θ}");
+        assert_eq!(latex_to_unicode("a}b"), "ab");
+    }
+
+    // Everything a truncator lets through, as it would be printed.
+    fn truncate(
+        max_chars: Option<usize>,
+        max_lines: Option<usize>,
+        blocks: &[(&str, bool)],
+    ) -> String {
+        let mut truncator = Truncator::new(max_chars, max_lines);
+        let mut shown: Vec<String> = blocks
+            .iter()
+            .filter_map(|&(block, sense)| truncator.push(block.to_owned(), sense))
+            .collect();
+        shown.extend(truncator.finish());
+        shown.join("\n")
+    }
+
+    #[test]
+    fn truncator_keeps_short_output() {
+        let blocks = [("cat", false), ("1. A small feline.", true)];
+        assert_eq!(truncate(None, None, &blocks), "cat\n1. A small feline.");
+        assert_eq!(
+            truncate(Some(100), Some(5), &blocks),
+            "cat\n1. A small feline."
+        );
+    }
+
+    #[test]
+    fn truncator_cuts_at_word_boundaries() {
+        let blocks = [
+            ("cat", false),
+            ("1. A small feline animal.", true),
+            ("2. A spiteful woman.", true),
+        ];
+        assert_eq!(truncate(Some(20), None, &blocks), "cat\n1. A small …");
+        assert_eq!(
+            truncate(Some(40), None, &blocks),
+            "cat\n1. A small feline animal. …"
+        );
+        assert_eq!(
+            truncate(None, Some(2), &blocks),
+            "cat\n1. A small feline animal. …"
+        );
+    }
+
+    #[test]
+    fn truncator_counts_characters() {
+        let blocks = [
+            ("кошка", false),
+            ("1. Домашнее животное семейства кошачьих.", true),
+        ];
+        let shown = truncate(Some(30), None, &blocks);
+        assert_eq!(shown, "кошка\n1. Домашнее животное …");
+        assert!(shown.chars().count() <= 30);
+        // A word with no room to cut it at.
+        let blocks = [("日本", false), ("1. 日本語日本語日本語日本語", true)];
+        assert_eq!(truncate(Some(12), None, &blocks), "日本\n1. …");
+        assert_eq!(truncate(Some(6), None, &blocks), "日本 …");
+    }
+
+    #[test]
+    fn truncator_ignores_colors() {
+        // Room for the "…" is kept until it's known that nothing more is coming.
+        let blocks = [("\x1b[1mcat\x1b[0m", false), ("1. A feline.", true)];
+        assert_eq!(
+            truncate(Some(18), None, &blocks),
+            "\x1b[1mcat\x1b[0m\n1. A feline."
+        );
+    }
+
+    #[test]
+    fn smart_quotes_curl() {
+        let smart = |s| Typography::Smart.apply(s);
+        assert_eq!(
+            smart("\"cat\" and 'dog'"),
+            "\u{201c}cat\u{201d} and \u{2018}dog\u{2019}"
+        );
+        assert_eq!(smart("the cat's toy"), "the cat\u{2019}s toy");
+        assert_eq!(smart("in the '90s"), "in the \u{2019}90s");
+        assert_eq!(smart("(\"quoted\")"), "(\u{201c}quoted\u{201d})");
+    }
+
+    #[test]
+    fn smart_dashes() {
+        let smart = |s| Typography::Smart.apply(s);
+        assert_eq!(smart("1914--1918"), "1914\u{2013}1918");
+        assert_eq!(smart("wait---what"), "wait\u{2014}what");
+        assert_eq!(smart("cat - a feline"), "cat \u{2013} a feline");
+        assert_eq!(smart("well-known"), "well-known");
+    }
+
+    #[test]
+    fn ascii_punctuation_replaces() {
+        let ascii = |s| Typography::Ascii.apply(s);
+        assert_eq!(
+            ascii("\u{201c}cat\u{201d} \u{2018}n\u{2019} dog"),
+            "\"cat\" 'n' dog"
+        );
+        assert_eq!(
+            ascii("1914\u{2013}1918\u{2014}ish\u{2026}"),
+            "1914-1918--ish..."
+        );
+        assert_eq!(ascii("100\u{a0}km so\u{ad}ft"), "100 km soft");
+        assert_eq!(Typography::AsIs.apply("\"--\""), "\"--\"");
+    }
+
+    #[test]
+    fn typography_leaves_code_and_markup() {
+        let smart = |s| Typography::Smart.apply(s);
+        assert_eq!(
+            smart("run <code>ls --all \"x\"</code> -- done"),
+            "run <code>ls --all \"x\"</code> \u{2013} done"
+        );
+        assert_eq!(
+            smart("<span class=\"gloss\">\"cat\"</span>"),
+            "<span class=\"gloss\">\u{201c}cat\u{201d}</span>"
+        );
+        assert_eq!(smart("\"<i>cat</i>\""), "\u{201c}<i>cat</i>\u{201d}");
+        assert_eq!(
+            smart("\"<code>ls</code>\""),
+            "\u{201c}<code>ls</code>\u{201d}"
+        );
+        assert_eq!(
+            smart("\x1b[1m\"cat\"\x1b[0m"),
+            "\x1b[1m\u{201c}cat\u{201d}\x1b[0m"
+        );
+        assert_eq!(
+            Typography::Ascii.apply("<math>a\u{2013}b</math> \u{2013}"),
+            "<math>a\u{2013}b</math> -"
+        );
+    }
+}
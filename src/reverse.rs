@@ -0,0 +1,237 @@
+//! Reverse lookups: finding words by what they mean, e.g. "fear of spiders" for
+//! "arachnophobia". The importer indexes every definition's text with SQLite's FTS5.
+
+use rusqlite::{Connection, Result};
+
+use compression::{Decompressor, DICTIONARY_TABLE};
+use dictionary::{text_column, Dictionary};
+use expand::{expand, TemplatePolicies, TemplatePolicy};
+use pagination::{Cursor, ResultPage};
+use query::DEFAULT_STOPWORDS;
+use Meaning;
+
+/// The FTS5 table. Its rowids are those of the words table; it keeps no text of its own.
+pub const SEARCH_TABLE: &str = "definitions_fts";
+
+/// (Re)builds the index over every definition in the database. Rows are renumbered whenever an
+/// edition is reimported, so it's rebuilt from scratch rather than kept up to date.
+pub fn build_search_index(conn: &Connection) -> Result<()> {
+    let decompressor: Option<Decompressor> = conn
+        .query_row(
+            &format!("SELECT content FROM {}", DICTIONARY_TABLE),
+            [],
+            |row| row.get::<_, Vec<u8>>(0),
+        )
+        .ok()
+        .map(|dictionary| Decompressor::new(&dictionary));
+    conn.execute_batch(&format!(
+        "DROP TABLE IF EXISTS {0};
+         CREATE VIRTUAL TABLE {0} USING fts5(
+             definition, content='', tokenize='porter unicode61 remove_diacritics 2'
+         );",
+        SEARCH_TABLE
+    ))?;
+    // Template names would only add noise.
+    let policies = TemplatePolicies {
+        default: TemplatePolicy::Strip,
+        ..TemplatePolicies::default()
+    };
+    let mut insert = conn.prepare(&format!(
+        "INSERT INTO {} (rowid, definition) VALUES (?1, ?2)",
+        SEARCH_TABLE
+    ))?;
+    let mut select = conn.prepare("SELECT rowid, definition FROM words")?;
+    let mut rows = select.query([])?;
+    while let Some(row) = rows.next()? {
+        let rowid: i64 = row.get("rowid")?;
+        let definition = text_column(row, "definition", decompressor.as_ref())?.unwrap_or_default();
+        let (text, _) = expand(&definition, &policies);
+        insert.execute(rusqlite::params![rowid, text])?;
+    }
+    Ok(())
+}
+
+// An FTS5 query matching any of the words in `text`, each quoted so that nothing in it is taken
+// for query syntax. Stopwords are left out unless there's nothing else.
+fn fts_query(text: &str) -> Option<String> {
+    let words: Vec<&str> = text
+        .split(|c: char| !c.is_alphanumeric() && c != '\'' && c != '-')
+        .filter(|word| !word.is_empty())
+        .collect();
+    let content: Vec<&str> = words
+        .iter()
+        .copied()
+        .filter(|word| !DEFAULT_STOPWORDS.contains(&word.to_lowercase().as_str()))
+        .collect();
+    let words = if content.is_empty() { words } else { content };
+    if words.is_empty() {
+        return None;
+    }
+    let terms: Vec<String> = words
+        .iter()
+        .map(|word| format!("\"{}\"", word.replace('"', "\"\"")))
+        .collect();
+    Some(terms.join(" OR "))
+}
+
+// FTS5's rank, a float, as a cursor's rank: its bits, with those of negative numbers flipped so
+// that the integers order the way the floats do. Flipping them again undoes it.
+fn flip_negative(bits: i64) -> i64 {
+    if bits < 0 {
+        bits ^ i64::MAX
+    } else {
+        bits
+    }
+}
+
+fn rank_to_cursor(rank: f64) -> i64 {
+    flip_negative(rank.to_bits() as i64)
+}
+
+fn rank_from_cursor(rank: i64) -> f64 {
+    f64::from_bits(flip_negative(rank) as u64)
+}
+
+impl Dictionary {
+    /// Words with definitions matching `text`, best match first, as (word, meaning) pairs, a page
+    /// of `limit` at a time starting after `after`. Definitions containing more of its words, and
+    /// rarer ones, match better. The cursors' ranks are the match scores and their keys the
+    /// rowids of the definitions.
+    pub fn reverse_search(
+        &self,
+        text: &str,
+        language: Option<&str>,
+        after: Option<&Cursor>,
+        limit: usize,
+    ) -> Result<ResultPage<(String, Meaning)>> {
+        let empty = ResultPage {
+            items: Vec::new(),
+            next: None,
+        };
+        let query = match fts_query(text) {
+            Some(query) => query,
+            None => return Ok(empty),
+        };
+        let after = match after {
+            Some(cursor) => match cursor.key.parse::<i64>() {
+                Ok(rowid) => Some((rank_from_cursor(cursor.rank), rowid)),
+                Err(_) => return Ok(empty),
+            },
+            None => None,
+        };
+        let mut stmt = self.connection().prepare_cached(&format!(
+            "SELECT rowid, rank FROM (SELECT rowid, rank FROM {0} WHERE {0} MATCH ?1)
+             WHERE ?2 IS NULL OR (rank, rowid) > (?2, ?3)
+             ORDER BY rank, rowid",
+            SEARCH_TABLE
+        ))?;
+        let (after_rank, after_rowid) =
+            after.map_or((None, None), |(rank, rowid)| (Some(rank), Some(rowid)));
+        let mut rows = stmt.query(rusqlite::params![query, after_rank, after_rowid])?;
+        let mut items = Vec::new();
+        let mut last = None;
+        while let Some(row) = rows.next()? {
+            let (rowid, rank): (i64, f64) = (row.get(0)?, row.get(1)?);
+            let (name, meaning) = self.meaning_by_rowid(rowid)?;
+            if language.is_some_and(|language| meaning.language != language) {
+                continue;
+            }
+            if items.len() == limit {
+                return Ok(ResultPage { items, next: last });
+            }
+            items.push((name, meaning));
+            last = Some(Cursor {
+                rank: rank_to_cursor(rank),
+                key: rowid.to_string(),
+            });
+        }
+        Ok(ResultPage { items, next: None })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_db::TestDb;
+
+    fn indexed() -> TestDb {
+        let db = TestDb::baseline(&[
+            ("arachnophobia", "English", "Noun", "Fear of spiders."),
+            (
+                "fear",
+                "English",
+                "Noun",
+                "A feeling of danger, or the fear of it.",
+            ),
+            ("phobia", "English", "Noun", "An irrational fear."),
+            ("peur", "French", "Noun", "fear"),
+            ("spider", "English", "Noun", "An eight-legged animal."),
+        ]);
+        build_search_index(&db.connection()).unwrap();
+        db
+    }
+
+    fn words(page: &ResultPage<(String, Meaning)>) -> Vec<&str> {
+        page.items.iter().map(|(word, _)| word.as_str()).collect()
+    }
+
+    #[test]
+    fn ranks_order_like_scores() {
+        let scores = [-3.5, -1.0, -0.0, 0.0, 0.25, 2.0];
+        for pair in scores.windows(2) {
+            assert!(
+                rank_to_cursor(pair[0]) <= rank_to_cursor(pair[1]),
+                "{:?}",
+                pair
+            );
+        }
+        for &score in &scores {
+            assert_eq!(rank_from_cursor(rank_to_cursor(score)), score);
+        }
+    }
+
+    #[test]
+    fn pages_through_matches() {
+        let db = indexed();
+        let dictionary = db.open();
+        let all = dictionary
+            .reverse_search("fear of spiders", None, None, 10)
+            .unwrap();
+        assert_eq!(words(&all)[0], "arachnophobia");
+        assert_eq!(all.items.len(), 4);
+        assert!(all.next.is_none());
+
+        let mut paged = Vec::new();
+        let mut after: Option<Cursor> = None;
+        loop {
+            let page = dictionary
+                .reverse_search("fear of spiders", None, after.as_ref(), 1)
+                .unwrap();
+            paged.extend(words(&page).into_iter().map(str::to_owned));
+            match page.next {
+                Some(next) => after = Some(next),
+                None => break,
+            }
+        }
+        assert_eq!(paged, words(&all));
+    }
+
+    #[test]
+    fn pages_within_a_language() {
+        let db = indexed();
+        let dictionary = db.open();
+        let first = dictionary
+            .reverse_search("fear", Some("English"), None, 2)
+            .unwrap();
+        assert_eq!(first.items.len(), 2);
+        let rest = dictionary
+            .reverse_search("fear", Some("English"), first.next.as_ref(), 2)
+            .unwrap();
+        assert_eq!(rest.items.len(), 1);
+        assert!(rest.next.is_none());
+        let french = dictionary
+            .reverse_search("fear", Some("French"), None, 2)
+            .unwrap();
+        assert_eq!(words(&french), ["peur"]);
+    }
+}
@@ -0,0 +1,618 @@
+//! Converting romanized input into the scripts that headwords are written in, for people without
+//! an input method for them.
+
+use std::sync::OnceLock;
+
+use regex::Regex;
+
+use normalize::InputConverter;
+use Meaning;
+
+fn pinyin_syllable_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"([a-zü]+)([0-5]?)").unwrap())
+}
+
+fn pinyin_input_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"^(?:[a-zü]+[0-5]?)+$").unwrap())
+}
+
+fn tone_mark(vowel: char, tone: usize) -> char {
+    let marks = match vowel {
+        'a' => ['ā', 'á', 'ǎ', 'à'],
+        'e' => ['ē', 'é', 'ě', 'è'],
+        'i' => ['ī', 'í', 'ǐ', 'ì'],
+        'o' => ['ō', 'ó', 'ǒ', 'ò'],
+        'u' => ['ū', 'ú', 'ǔ', 'ù'],
+        'ü' => ['ǖ', 'ǘ', 'ǚ', 'ǜ'],
+        _ => return vowel,
+    };
+    marks[tone - 1]
+}
+
+// Puts the tone mark where pinyin does: on "a" or "e" if there is one, on the "o" of "ou", and
+// otherwise on the last vowel.
+fn mark_syllable(syllable: &str, tone: usize) -> String {
+    if !(1..=4).contains(&tone) {
+        return syllable.to_owned();
+    }
+    let marked = if let Some(i) = syllable.find(['a', 'e']) {
+        Some(i)
+    } else if let Some(i) = syllable.find("ou") {
+        Some(i)
+    } else {
+        syllable.rfind(['i', 'o', 'u', 'ü'])
+    };
+    match marked {
+        None => syllable.to_owned(),
+        Some(i) => {
+            let vowel = syllable[i..].chars().next().unwrap();
+            format!(
+                "{}{}{}",
+                &syllable[..i],
+                tone_mark(vowel, tone),
+                &syllable[i + vowel.len_utf8()..]
+            )
+        }
+    }
+}
+
+/// Converts pinyin with tone numbers ("ni3hao3", with "v" or "u:" for "ü" and 5 or 0 for the
+/// neutral tone) into its syllables with tone marks ("nǐ", "hǎo"). Returns `None` for anything
+/// that doesn't look like numbered pinyin.
+pub fn numbered_pinyin_syllables(input: &str) -> Option<Vec<String>> {
+    let input = input.to_lowercase().replace("u:", "ü").replace('v', "ü");
+    if !pinyin_input_regex().is_match(&input) || !input.contains(|c: char| c.is_ascii_digit()) {
+        return None;
+    }
+    Some(
+        pinyin_syllable_regex()
+            .captures_iter(&input)
+            .map(|caps| mark_syllable(&caps[1], caps[2].parse().unwrap_or(0)))
+            .collect(),
+    )
+}
+
+/// Accepts Mandarin typed as pinyin with tone numbers, trying the toned pinyin entry, written
+/// solid the way Wiktionary titles words and then with its syllables spaced. Those entries list
+/// the hanzi with that reading.
+pub struct NumberedPinyin;
+
+impl InputConverter for NumberedPinyin {
+    fn name(&self) -> &'static str {
+        "pinyin"
+    }
+
+    fn language(&self) -> &'static str {
+        "Chinese"
+    }
+
+    fn convert(&self, input: &str) -> Vec<String> {
+        match numbered_pinyin_syllables(input) {
+            None => Vec::new(),
+            Some(syllables) => {
+                let mut alternatives = vec![syllables.concat()];
+                if syllables.len() > 1 {
+                    alternatives.push(syllables.join(" "));
+                }
+                alternatives
+            }
+        }
+    }
+}
+
+// Hepburn romaji, plus the Kunrei-shiki spellings people also type. Callers try the longest match
+// first.
+fn kana_syllable(romaji: &str) -> Option<&'static str> {
+    Some(match romaji {
+        "kya" => "きゃ",
+        "kyu" => "きゅ",
+        "kyo" => "きょ",
+        "sha" | "sya" => "しゃ",
+        "shi" => "し",
+        "shu" | "syu" => "しゅ",
+        "sho" | "syo" => "しょ",
+        "cha" | "tya" => "ちゃ",
+        "chi" => "ち",
+        "chu" | "tyu" => "ちゅ",
+        "cho" | "tyo" => "ちょ",
+        "tsu" => "つ",
+        "nya" => "にゃ",
+        "nyu" => "にゅ",
+        "nyo" => "にょ",
+        "hya" => "ひゃ",
+        "hyu" => "ひゅ",
+        "hyo" => "ひょ",
+        "mya" => "みゃ",
+        "myu" => "みゅ",
+        "myo" => "みょ",
+        "rya" => "りゃ",
+        "ryu" => "りゅ",
+        "ryo" => "りょ",
+        "gya" => "ぎゃ",
+        "gyu" => "ぎゅ",
+        "gyo" => "ぎょ",
+        "zya" => "じゃ",
+        "zyu" => "じゅ",
+        "zyo" => "じょ",
+        "bya" => "びゃ",
+        "byu" => "びゅ",
+        "byo" => "びょ",
+        "pya" => "ぴゃ",
+        "pyu" => "ぴゅ",
+        "pyo" => "ぴょ",
+        "ka" => "か",
+        "ki" => "き",
+        "ku" => "く",
+        "ke" => "け",
+        "ko" => "こ",
+        "sa" => "さ",
+        "si" => "し",
+        "su" => "す",
+        "se" => "せ",
+        "so" => "そ",
+        "ta" => "た",
+        "ti" => "ち",
+        "tu" => "つ",
+        "te" => "て",
+        "to" => "と",
+        "na" => "な",
+        "ni" => "に",
+        "nu" => "ぬ",
+        "ne" => "ね",
+        "no" => "の",
+        "ha" => "は",
+        "hi" => "ひ",
+        "fu" | "hu" => "ふ",
+        "he" => "へ",
+        "ho" => "ほ",
+        "ma" => "ま",
+        "mi" => "み",
+        "mu" => "む",
+        "me" => "め",
+        "mo" => "も",
+        "ya" => "や",
+        "yu" => "ゆ",
+        "yo" => "よ",
+        "ra" => "ら",
+        "ri" => "り",
+        "ru" => "る",
+        "re" => "れ",
+        "ro" => "ろ",
+        "wa" => "わ",
+        "wo" => "を",
+        "ga" => "が",
+        "gi" => "ぎ",
+        "gu" => "ぐ",
+        "ge" => "げ",
+        "go" => "ご",
+        "za" => "ざ",
+        "ji" | "zi" => "じ",
+        "zu" => "ず",
+        "ze" => "ぜ",
+        "zo" => "ぞ",
+        "ja" => "じゃ",
+        "ju" => "じゅ",
+        "jo" => "じょ",
+        "da" => "だ",
+        "di" => "ぢ",
+        "du" => "づ",
+        "de" => "で",
+        "do" => "ど",
+        "ba" => "ば",
+        "bi" => "び",
+        "bu" => "ぶ",
+        "be" => "べ",
+        "bo" => "ぼ",
+        "pa" => "ぱ",
+        "pi" => "ぴ",
+        "pu" => "ぷ",
+        "pe" => "ぺ",
+        "po" => "ぽ",
+        "a" => "あ",
+        "i" => "い",
+        "u" => "う",
+        "e" => "え",
+        "o" => "お",
+        "-" => "ー",
+        _ => return None,
+    })
+}
+
+fn is_romaji_vowel(c: u8) -> bool {
+    b"aiueo".contains(&c)
+}
+
+fn romaji_to_kana(romaji: &str) -> Option<String> {
+    let bytes = romaji.as_bytes();
+    let mut kana = String::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let c = bytes[i];
+        let next = bytes.get(i + 1).cloned();
+        if c == b'n' && next.is_none_or(|next| !is_romaji_vowel(next) && next != b'y') {
+            // "n'" separates ん from a following vowel, as in "kan'i".
+            kana.push('ん');
+            i += if next == Some(b'\'') { 2 } else { 1 };
+            continue;
+        }
+        // A doubled consonant ("kk", or "tch") is a small っ before the syllable.
+        if next == Some(c) && !is_romaji_vowel(c) || (c == b't' && next == Some(b'c')) {
+            kana.push('っ');
+            i += 1;
+            continue;
+        }
+        let syllable = (1..=3)
+            .rev()
+            .filter(|&len| i + len <= bytes.len())
+            .find_map(|len| {
+                romaji
+                    .get(i..i + len)
+                    .and_then(kana_syllable)
+                    .map(|s| (len, s))
+            });
+        let (len, syllable) = syllable?;
+        kana.push_str(syllable);
+        i += len;
+    }
+    Some(kana)
+}
+
+/// Converts romaji ("neko", "kon'nichiwa", "tōkyō") to hiragana and katakana, or `None` if it
+/// isn't romaji. Long vowels are spelled the way each script usually does: "ō" is おう in
+/// hiragana and オー in katakana.
+pub fn romaji_to_hiragana_and_katakana(input: &str) -> Option<(String, String)> {
+    let input = input.to_lowercase();
+    let long_vowels = |katakana: bool| -> String {
+        input
+            .chars()
+            .map(|c| match (c, katakana) {
+                ('ā', false) | ('â', false) => "aa".to_owned(),
+                ('ī', false) | ('î', false) => "ii".to_owned(),
+                ('ū', false) | ('û', false) => "uu".to_owned(),
+                ('ē', false) | ('ê', false) => "ee".to_owned(),
+                ('ō', false) | ('ô', false) => "ou".to_owned(),
+                ('ā', true) | ('â', true) => "a-".to_owned(),
+                ('ī', true) | ('î', true) => "i-".to_owned(),
+                ('ū', true) | ('û', true) => "u-".to_owned(),
+                ('ē', true) | ('ê', true) => "e-".to_owned(),
+                ('ō', true) | ('ô', true) => "o-".to_owned(),
+                _ => c.to_string(),
+            })
+            .collect()
+    };
+    let hiragana = romaji_to_kana(&long_vowels(false))?;
+    let katakana = hiragana_to_katakana(&romaji_to_kana(&long_vowels(true))?);
+    Some((hiragana, katakana))
+}
+
+/// Katakana for the hiragana in `s`, which the Unicode blocks line up one to one.
+pub fn hiragana_to_katakana(s: &str) -> String {
+    s.chars()
+        .map(|c| match c {
+            '\u{3041}'..='\u{3096}' => std::char::from_u32(c as u32 + 0x60).unwrap(),
+            _ => c,
+        })
+        .collect()
+}
+
+fn han_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"\p{Han}[\p{Han}\p{Hiragana}]*").unwrap())
+}
+
+/// The kanji spellings that a kana entry's Japanese definitions point to, e.g. 猫 for ねこ, in
+/// the order they first appear.
+pub fn kanji_spellings(meanings: &[Meaning]) -> Vec<String> {
+    let mut spellings: Vec<String> = Vec::new();
+    for meaning in meanings
+        .iter()
+        .filter(|meaning| meaning.language == "Japanese")
+    {
+        for m in han_regex().find_iter(&meaning.definition) {
+            if !spellings.iter().any(|spelling| spelling == m.as_str()) {
+                spellings.push(m.as_str().to_owned());
+            }
+        }
+    }
+    spellings
+}
+
+/// Accepts Japanese typed in romaji, trying it in hiragana and then in katakana.
+pub struct Romaji;
+
+impl InputConverter for Romaji {
+    fn name(&self) -> &'static str {
+        "romaji"
+    }
+
+    fn language(&self) -> &'static str {
+        "Japanese"
+    }
+
+    fn convert(&self, input: &str) -> Vec<String> {
+        match romaji_to_hiragana_and_katakana(input) {
+            None => Vec::new(),
+            Some((hiragana, katakana)) => vec![hiragana, katakana],
+        }
+    }
+}
+
+// Revised Romanization of the Hangul jamo, by their index in the syllable block formula.
+const HANGUL_INITIALS: &[(&str, u32)] = &[
+    ("g", 0),
+    ("kk", 1),
+    ("n", 2),
+    ("d", 3),
+    ("tt", 4),
+    ("r", 5),
+    ("l", 5),
+    ("m", 6),
+    ("b", 7),
+    ("pp", 8),
+    ("s", 9),
+    ("ss", 10),
+    ("", 11),
+    ("j", 12),
+    ("jj", 13),
+    ("ch", 14),
+    ("k", 15),
+    ("t", 16),
+    ("p", 17),
+    ("h", 18),
+];
+const HANGUL_VOWELS: &[(&str, u32)] = &[
+    ("a", 0),
+    ("ae", 1),
+    ("ya", 2),
+    ("yae", 3),
+    ("eo", 4),
+    ("e", 5),
+    ("yeo", 6),
+    ("ye", 7),
+    ("o", 8),
+    ("wa", 9),
+    ("wae", 10),
+    ("oe", 11),
+    ("yo", 12),
+    ("u", 13),
+    ("wo", 14),
+    ("we", 15),
+    ("wi", 16),
+    ("yu", 17),
+    ("eu", 18),
+    ("ui", 19),
+    ("i", 20),
+];
+const HANGUL_FINALS: &[(&str, u32)] = &[
+    ("", 0),
+    ("k", 1),
+    ("g", 1),
+    ("kk", 2),
+    ("n", 4),
+    ("t", 7),
+    ("d", 7),
+    ("l", 8),
+    ("m", 16),
+    ("p", 17),
+    ("b", 17),
+    ("s", 19),
+    ("ss", 20),
+    ("ng", 21),
+    ("j", 22),
+    ("ch", 23),
+    ("h", 27),
+];
+
+// How many readings of a romanized word are worth trying.
+const MAX_HANGUL_CANDIDATES: usize = 5;
+
+// Longer input isn't taken for a word; Korean headwords are much shorter romanized.
+const MAX_HANGUL_INPUT: usize = 30;
+
+// How likely a reading is, lower first: the syllables written without an initial consonant, then
+// the number of syllables.
+type HangulCost = (usize, usize);
+
+// The most likely readings of each suffix of `romaji`, by where it starts, worked out from the end
+// so each suffix is only read once. A reading of a suffix is a syllable followed by one of the
+// best readings of what's after it, so keeping the best few of each is enough. Ties stay in the
+// order the jamo tables list them.
+fn hangul_readings(romaji: &str) -> Vec<(HangulCost, String)> {
+    let mut best: Vec<Vec<(HangulCost, String)>> = vec![Vec::new(); romaji.len() + 1];
+    best[romaji.len()].push(((0, 0), String::new()));
+    for start in (0..romaji.len()).rev() {
+        let mut readings: Vec<(HangulCost, String)> = Vec::new();
+        for &(initial, i) in HANGUL_INITIALS {
+            let rest = match romaji[start..].strip_prefix(initial) {
+                Some(rest) => rest,
+                None => continue,
+            };
+            for &(vowel, v) in HANGUL_VOWELS {
+                let rest = match rest.strip_prefix(vowel) {
+                    Some(rest) => rest,
+                    None => continue,
+                };
+                for &(last, f) in HANGUL_FINALS {
+                    let rest = match rest.strip_prefix(last) {
+                        Some(rest) => rest,
+                        None => continue,
+                    };
+                    let block = std::char::from_u32(0xac00 + (i * 21 + v) * 28 + f).unwrap();
+                    let without_initial = usize::from(initial.is_empty());
+                    for ((empty, syllables), after) in &best[romaji.len() - rest.len()] {
+                        readings.push((
+                            (empty + without_initial, syllables + 1),
+                            format!("{}{}", block, after),
+                        ));
+                    }
+                }
+            }
+        }
+        readings.sort_by_key(|&(cost, _)| cost);
+        let mut kept: Vec<(HangulCost, String)> = Vec::new();
+        for reading in readings {
+            if !kept.iter().any(|(_, hangul)| *hangul == reading.1) {
+                kept.push(reading);
+            }
+            if kept.len() == MAX_HANGUL_CANDIDATES {
+                break;
+            }
+        }
+        best[start] = kept;
+    }
+    best.swap_remove(0)
+}
+
+/// Hangul spellings of a word in Revised Romanization ("annyeong" is 안녕), most likely first.
+/// The romanization doesn't mark syllable boundaries, so there can be several; readings where
+/// consonants start syllables rather than end them come first, the way they're usually
+/// pronounced.
+pub fn revised_romanization_to_hangul(input: &str) -> Vec<String> {
+    let input = input.to_lowercase();
+    if input.is_empty()
+        || input.len() > MAX_HANGUL_INPUT
+        || !input.bytes().all(|c| c.is_ascii_lowercase())
+    {
+        return Vec::new();
+    }
+    hangul_readings(&input)
+        .into_iter()
+        .map(|(_, hangul)| hangul)
+        .collect()
+}
+
+/// Accepts Korean typed in Revised Romanization.
+pub struct RevisedRomanization;
+
+impl InputConverter for RevisedRomanization {
+    fn name(&self) -> &'static str {
+        "revised-romanization"
+    }
+
+    fn language(&self) -> &'static str {
+        "Korean"
+    }
+
+    fn convert(&self, input: &str) -> Vec<String> {
+        revised_romanization_to_hangul(input)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::*;
+
+    fn pinyin(input: &str) -> Option<String> {
+        numbered_pinyin_syllables(input).map(|syllables| syllables.join(" "))
+    }
+
+    #[test]
+    fn pinyin_tone_placement() {
+        assert_eq!(pinyin("ni3hao3").unwrap(), "nǐ hǎo");
+        // "a" and "e" always take the mark, then the "o" of "ou", then the last vowel.
+        assert_eq!(pinyin("zhuang1").unwrap(), "zhuāng");
+        assert_eq!(pinyin("xue2").unwrap(), "xué");
+        assert_eq!(pinyin("dou4").unwrap(), "dòu");
+        assert_eq!(pinyin("xiong2").unwrap(), "xióng");
+        assert_eq!(pinyin("liu2").unwrap(), "liú");
+        assert_eq!(pinyin("gui4").unwrap(), "guì");
+        assert_eq!(pinyin("Bei3jing1").unwrap(), "běi jīng");
+    }
+
+    #[test]
+    fn pinyin_umlaut_and_neutral_tone() {
+        assert_eq!(pinyin("nv3").unwrap(), "nǚ");
+        assert_eq!(pinyin("lu:4").unwrap(), "lǜ");
+        assert_eq!(pinyin("lve4").unwrap(), "lüè");
+        assert_eq!(pinyin("ma1ma5").unwrap(), "mā ma");
+        assert_eq!(pinyin("ma1ma0").unwrap(), "mā ma");
+        assert_eq!(pinyin("ni3hao").unwrap(), "nǐ hao");
+    }
+
+    #[test]
+    fn pinyin_needs_tone_numbers() {
+        assert_eq!(pinyin("nihao"), None);
+        assert_eq!(pinyin("ni6"), None);
+        assert_eq!(pinyin("你好"), None);
+        assert_eq!(pinyin(""), None);
+        assert_eq!(
+            NumberedPinyin.convert("ni3hao3"),
+            vec!["nǐhǎo".to_owned(), "nǐ hǎo".to_owned()]
+        );
+    }
+
+    fn kana(input: &str) -> Option<(String, String)> {
+        romaji_to_hiragana_and_katakana(input)
+    }
+
+    fn pair(hiragana: &str, katakana: &str) -> Option<(String, String)> {
+        Some((hiragana.to_owned(), katakana.to_owned()))
+    }
+
+    #[test]
+    fn romaji_syllables() {
+        assert_eq!(kana("neko"), pair("ねこ", "ネコ"));
+        assert_eq!(kana("konnichiwa"), pair("こんにちわ", "コンニチワ"));
+        assert_eq!(kana("Shashin"), pair("しゃしん", "シャシン"));
+        assert_eq!(kana("sya"), pair("しゃ", "シャ"));
+    }
+
+    #[test]
+    fn romaji_long_vowels() {
+        assert_eq!(kana("tōkyō"), pair("とうきょう", "トーキョー"));
+        assert_eq!(kana("obāsan"), pair("おばあさん", "オバーサン"));
+        assert_eq!(kana("ra-men"), pair("らーめん", "ラーメン"));
+    }
+
+    #[test]
+    fn romaji_small_tsu_and_n() {
+        assert_eq!(kana("kitte"), pair("きって", "キッテ"));
+        assert_eq!(kana("matcha"), pair("まっちゃ", "マッチャ"));
+        assert_eq!(kana("kan'i"), pair("かんい", "カンイ"));
+        assert_eq!(kana("kani"), pair("かに", "カニ"));
+        assert_eq!(kana("n'a"), pair("んあ", "ンア"));
+        assert_eq!(kana("hon"), pair("ほん", "ホン"));
+    }
+
+    #[test]
+    fn romaji_rejects_other_input() {
+        assert_eq!(kana("xyz"), None);
+        assert_eq!(kana("l"), None);
+        assert_eq!(kana("ねこ"), None);
+    }
+
+    #[test]
+    fn hangul_readings() {
+        let annyeong = revised_romanization_to_hangul("annyeong");
+        assert_eq!(annyeong[0], "안녕");
+        assert!(annyeong.len() <= MAX_HANGUL_CANDIDATES);
+        assert_eq!(revised_romanization_to_hangul("Saram")[0], "사람");
+        // The romanization doesn't say where the g goes.
+        let hangugeo = revised_romanization_to_hangul("hangugeo");
+        assert_eq!(hangugeo[0], "한구거");
+        assert!(hangugeo.contains(&"한국어".to_owned()), "{:?}", hangugeo);
+        assert!(revised_romanization_to_hangul("xyz").is_empty());
+        assert!(revised_romanization_to_hangul("").is_empty());
+        assert!(revised_romanization_to_hangul("안녕").is_empty());
+    }
+
+    // Every way of splitting the input used to be tried, which took seconds for a few dozen
+    // letters.
+    #[test]
+    fn long_input_is_quick() {
+        let started = Instant::now();
+        let readings = revised_romanization_to_hangul(&"gan".repeat(MAX_HANGUL_INPUT / 3));
+        assert_eq!(readings.len(), MAX_HANGUL_CANDIDATES);
+        assert_eq!(readings[0], "간".repeat(MAX_HANGUL_INPUT / 3));
+        assert!(revised_romanization_to_hangul(&"gan".repeat(21)).is_empty());
+        assert!(
+            started.elapsed() < Duration::from_secs(1),
+            "{:?}",
+            started.elapsed()
+        );
+    }
+}
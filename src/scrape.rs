@@ -0,0 +1,236 @@
+//! Fetches a few pages at a time from the Wiktionary API, for building a small database from a
+//! word list without downloading a whole dump. Requests go through `curl`, one batch at a time
+//! with a pause in between, as the API's etiquette asks, and every page fetched is cached so that
+//! running it again only fetches what's new.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde_json::Value;
+
+use Page;
+
+/// How many titles the API takes in one request.
+pub const BATCH_SIZE: usize = 50;
+
+/// The pause between requests unless asked for another.
+pub const DEFAULT_DELAY: Duration = Duration::from_secs(1);
+
+// How many times a failed or throttled request is tried again, waiting longer each time.
+const RETRIES: u32 = 3;
+
+const USER_AGENT: &str = concat!(
+    "define3/",
+    env!("CARGO_PKG_VERSION"),
+    " (offline dictionary built from Wiktionary)"
+);
+
+pub struct Scraper {
+    /// The Wiktionary edition, e.g. "en".
+    edition: String,
+    cache_dir: PathBuf,
+    delay: Duration,
+    last_request: Option<Instant>,
+}
+
+// A title as a file name: everything but ASCII letters, digits, - and _ percent-encoded.
+fn cache_name(title: &str) -> String {
+    let mut name = String::new();
+    for byte in title.bytes() {
+        if byte.is_ascii_alphanumeric() || byte == b'-' || byte == b'_' {
+            name.push(byte as char);
+        } else {
+            name.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    name
+}
+
+impl Scraper {
+    /// Fetches from `edition`, caching pages in `cache_dir` and waiting `delay` between requests.
+    pub fn new(edition: &str, cache_dir: &Path, delay: Duration) -> Scraper {
+        Scraper {
+            edition: edition.to_owned(),
+            cache_dir: cache_dir.join(edition),
+            delay,
+            last_request: None,
+        }
+    }
+
+    // The cached wikitext of `title`: Some(None) if it's known not to exist, None if it hasn't
+    // been fetched.
+    fn cached(&self, title: &str) -> Option<Option<String>> {
+        let name = cache_name(title);
+        if self.cache_dir.join(format!("{}.missing", name)).exists() {
+            return Some(None);
+        }
+        fs::read_to_string(self.cache_dir.join(format!("{}.wikitext", name)))
+            .ok()
+            .map(Some)
+    }
+
+    fn cache(&self, title: &str, content: Option<&str>) -> io::Result<()> {
+        fs::create_dir_all(&self.cache_dir)?;
+        let name = cache_name(title);
+        match content {
+            Some(content) => fs::write(self.cache_dir.join(format!("{}.wikitext", name)), content),
+            None => fs::write(self.cache_dir.join(format!("{}.missing", name)), ""),
+        }
+    }
+
+    /// The pages for `titles` that exist, from the cache where possible (unless `refresh`), with
+    /// `progress` called with how many titles are done after each request.
+    pub fn fetch<P>(
+        &mut self,
+        titles: &[String],
+        refresh: bool,
+        mut progress: P,
+    ) -> Result<Vec<Page>, String>
+    where
+        P: FnMut(usize, usize),
+    {
+        let mut contents: HashMap<&str, Option<String>> = HashMap::new();
+        let mut to_fetch: Vec<&str> = Vec::new();
+        for title in titles {
+            match self.cached(title).filter(|_| !refresh) {
+                Some(content) => {
+                    contents.insert(title, content);
+                }
+                None => to_fetch.push(title),
+            }
+        }
+        to_fetch.sort_unstable();
+        to_fetch.dedup();
+        let mut done = titles.len() - to_fetch.len();
+        for batch in to_fetch.chunks(BATCH_SIZE) {
+            let fetched = self.request(batch)?;
+            for &title in batch {
+                let content = fetched.get(title).cloned().flatten();
+                self.cache(title, content.as_deref())
+                    .map_err(|e| format!("Could not cache {}: {}", title, e))?;
+                contents.insert(title, content);
+            }
+            done += batch.len();
+            progress(done, titles.len());
+        }
+        Ok(titles
+            .iter()
+            .filter_map(|title| {
+                let content = contents.get(title.as_str())?.clone()?;
+                Some(Page {
+                    title: title.clone(),
+                    content,
+                })
+            })
+            .collect())
+    }
+
+    /// The wikitext of `title` as it is now, without the cache; None if there's no such page.
+    pub fn live(&mut self, title: &str) -> Result<Option<String>, String> {
+        let mut pages = self.request(&[title])?;
+        Ok(pages.remove(title).flatten())
+    }
+
+    // One request for up to `BATCH_SIZE` pages, by the title they were asked for; None for the
+    // ones that don't exist.
+    fn request(&mut self, titles: &[&str]) -> Result<HashMap<String, Option<String>>, String> {
+        let mut wait = self.delay.max(Duration::from_secs(5));
+        for attempt in 0..=RETRIES {
+            if let Some(last) = self.last_request {
+                if let Some(remaining) = self.delay.checked_sub(last.elapsed()) {
+                    thread::sleep(remaining);
+                }
+            }
+            self.last_request = Some(Instant::now());
+            match self.try_request(titles) {
+                Ok(pages) => return Ok(pages),
+                Err(e) if attempt == RETRIES => return Err(e),
+                Err(_) => {
+                    thread::sleep(wait);
+                    wait *= 2;
+                }
+            }
+        }
+        unreachable!()
+    }
+
+    fn try_request(&self, titles: &[&str]) -> Result<HashMap<String, Option<String>>, String> {
+        let url = format!("https://{}.wiktionary.org/w/api.php", self.edition);
+        let output = Command::new("curl")
+            .args(["--silent", "--show-error", "--fail", "--get"])
+            .args(["--user-agent", USER_AGENT])
+            .args([
+                "--data",
+                "action=query&prop=revisions&rvprop=content&rvslots=main&format=json\
+                 &formatversion=2&maxlag=5",
+            ])
+            .arg("--data-urlencode")
+            .arg(format!("titles={}", titles.join("|")))
+            .arg(&url)
+            .output()
+            .map_err(|e| format!("Could not run curl: {}", e))?;
+        if !output.status.success() {
+            return Err(format!(
+                "Could not fetch from {}: {}",
+                url,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+        let response: Value = serde_json::from_slice(&output.stdout)
+            .map_err(|e| format!("Could not understand the response from {}: {}", url, e))?;
+        // Including "maxlag", when the servers are busy.
+        if let Some(error) = response["error"]["info"].as_str() {
+            return Err(format!("{} said: {}", url, error));
+        }
+        // Pages come back under their normalized titles, e.g. with spaces for underscores.
+        let mut asked_as: HashMap<&str, &str> = HashMap::new();
+        for normalized in response["query"]["normalized"]
+            .as_array()
+            .into_iter()
+            .flatten()
+        {
+            if let (Some(from), Some(to)) = (normalized["from"].as_str(), normalized["to"].as_str())
+            {
+                asked_as.insert(to, from);
+            }
+        }
+        let mut pages = HashMap::new();
+        for page in response["query"]["pages"].as_array().into_iter().flatten() {
+            let title = match page["title"].as_str() {
+                Some(title) => title,
+                None => continue,
+            };
+            let content = page["revisions"][0]["slots"]["main"]["content"]
+                .as_str()
+                .map(str::to_owned);
+            let title = asked_as.get(title).cloned().unwrap_or(title);
+            pages.insert(title.to_owned(), content);
+        }
+        Ok(pages)
+    }
+}
+
+fn escape_xml(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Writes pages as a dump that `build_definitions_db` can read.
+pub fn write_dump<W: Write>(pages: &[Page], out: &mut W) -> io::Result<()> {
+    writeln!(out, "<mediawiki>")?;
+    for page in pages {
+        writeln!(
+            out,
+            "<page><title>{}</title><revision><text>{}</text></revision></page>",
+            escape_xml(&page.title),
+            escape_xml(&page.content)
+        )?;
+    }
+    writeln!(out, "</mediawiki>")
+}
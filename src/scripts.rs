@@ -0,0 +1,178 @@
+//! Which writing system a word is written in, for grouping search results so that e.g. Cyrillic
+//! words aren't interleaved with Latin ones by code point. Only the common scripts are told apart.
+
+use feruca::Collator;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Script {
+    Latin,
+    Greek,
+    Cyrillic,
+    Armenian,
+    Georgian,
+    Hebrew,
+    Arabic,
+    Devanagari,
+    Bengali,
+    Thai,
+    Hangul,
+    Kana,
+    Han,
+    Other,
+}
+
+impl Script {
+    /// The script of a letter, or None for characters without one, like digits, punctuation and
+    /// combining marks.
+    pub fn of_char(c: char) -> Option<Script> {
+        if !c.is_alphabetic() {
+            return None;
+        }
+        Some(match c {
+            'A'..='Z'
+            | 'a'..='z'
+            | '\u{c0}'..='\u{24f}'
+            | '\u{1e00}'..='\u{1eff}'
+            | '\u{2c60}'..='\u{2c7f}'
+            | '\u{a720}'..='\u{a7ff}'
+            | '\u{ff21}'..='\u{ff5a}' => Script::Latin,
+            '\u{370}'..='\u{3ff}' | '\u{1f00}'..='\u{1fff}' => Script::Greek,
+            '\u{400}'..='\u{52f}'
+            | '\u{1c80}'..='\u{1c8f}'
+            | '\u{2de0}'..='\u{2dff}'
+            | '\u{a640}'..='\u{a69f}' => Script::Cyrillic,
+            '\u{530}'..='\u{58f}' => Script::Armenian,
+            '\u{10a0}'..='\u{10ff}' | '\u{1c90}'..='\u{1cbf}' => Script::Georgian,
+            '\u{590}'..='\u{5ff}' => Script::Hebrew,
+            '\u{600}'..='\u{6ff}'
+            | '\u{750}'..='\u{77f}'
+            | '\u{8a0}'..='\u{8ff}'
+            | '\u{fb50}'..='\u{fdff}'
+            | '\u{fe70}'..='\u{feff}' => Script::Arabic,
+            '\u{900}'..='\u{97f}' => Script::Devanagari,
+            '\u{980}'..='\u{9ff}' => Script::Bengali,
+            '\u{e00}'..='\u{e7f}' => Script::Thai,
+            '\u{1100}'..='\u{11ff}' | '\u{3130}'..='\u{318f}' | '\u{ac00}'..='\u{d7af}' => {
+                Script::Hangul
+            }
+            '\u{3040}'..='\u{30ff}' | '\u{31f0}'..='\u{31ff}' | '\u{ff66}'..='\u{ff9f}' => {
+                Script::Kana
+            }
+            '\u{2e80}'..='\u{2fdf}'
+            | '\u{3005}'
+            | '\u{3007}'
+            | '\u{3400}'..='\u{4dbf}'
+            | '\u{4e00}'..='\u{9fff}'
+            | '\u{f900}'..='\u{faff}'
+            | '\u{20000}'..='\u{3134f}' => Script::Han,
+            _ => Script::Other,
+        })
+    }
+
+    /// The script of a word's first letter. Words without letters are Other.
+    pub fn of(word: &str) -> Script {
+        word.chars()
+            .find_map(Script::of_char)
+            .unwrap_or(Script::Other)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Script::Latin => "Latin",
+            Script::Greek => "Greek",
+            Script::Cyrillic => "Cyrillic",
+            Script::Armenian => "Armenian",
+            Script::Georgian => "Georgian",
+            Script::Hebrew => "Hebrew",
+            Script::Arabic => "Arabic",
+            Script::Devanagari => "Devanagari",
+            Script::Bengali => "Bengali",
+            Script::Thai => "Thai",
+            Script::Hangul => "Hangul",
+            Script::Kana => "Kana",
+            Script::Han => "Han",
+            Script::Other => "Other",
+        }
+    }
+}
+
+/// `words` grouped by script, Latin first, and sorted within each group with the CLDR root
+/// collation, like the database's `unicode` collation.
+pub fn group_by_script(words: Vec<String>) -> Vec<(Script, Vec<String>)> {
+    let mut groups: Vec<(Script, Vec<String>)> = Vec::new();
+    for word in words {
+        let script = Script::of(&word);
+        match groups.iter_mut().find(|(s, _)| *s == script) {
+            Some((_, group)) => group.push(word),
+            None => groups.push((script, vec![word])),
+        }
+    }
+    groups.sort_by_key(|&(script, _)| script);
+    let mut collator = Collator::default();
+    for (_, group) in &mut groups {
+        group.sort_by(|a, b| collator.collate(a, b));
+    }
+    groups
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classifies_words() {
+        assert_eq!(Script::of("cat"), Script::Latin);
+        assert_eq!(Script::of("Ærøskøbing"), Script::Latin);
+        assert_eq!(Script::of("кошка"), Script::Cyrillic);
+        assert_eq!(Script::of("γάτα"), Script::Greek);
+        assert_eq!(Script::of("חתול"), Script::Hebrew);
+        assert_eq!(Script::of("قطة"), Script::Arabic);
+        assert_eq!(Script::of("बिल्ली"), Script::Devanagari);
+        assert_eq!(Script::of("แมว"), Script::Thai);
+    }
+
+    #[test]
+    fn classifies_han_kana_and_hangul() {
+        assert_eq!(Script::of("猫"), Script::Han);
+        assert_eq!(Script::of("々"), Script::Han);
+        assert_eq!(Script::of("ねこ"), Script::Kana);
+        assert_eq!(Script::of("ネコ"), Script::Kana);
+        assert_eq!(Script::of("ｶﾀｶﾅ"), Script::Kana);
+        assert_eq!(Script::of("고양이"), Script::Hangul);
+        assert_eq!(Script::of("ㄱ"), Script::Hangul);
+    }
+
+    // A word is in the script of its first letter, whatever comes before or after it.
+    #[test]
+    fn classifies_mixed_words() {
+        assert_eq!(Script::of("食べる"), Script::Han);
+        assert_eq!(Script::of("たべ物"), Script::Kana);
+        assert_eq!(Script::of("Tシャツ"), Script::Latin);
+        assert_eq!(Script::of("3D"), Script::Latin);
+        assert_eq!(Script::of("-ский"), Script::Cyrillic);
+        assert_eq!(Script::of("e\u{301}"), Script::Latin);
+        assert_eq!(Script::of("123"), Script::Other);
+        assert_eq!(Script::of(""), Script::Other);
+        assert_eq!(Script::of_char('\u{301}'), None);
+        assert_eq!(Script::of_char('ー'), Some(Script::Kana));
+    }
+
+    #[test]
+    fn groups_by_script() {
+        let words = ["ねこ", "кошка", "zebra", "猫", "abeja", "ábaco", "гусь"];
+        let groups = group_by_script(words.iter().map(|&word| word.to_owned()).collect());
+        let groups: Vec<(&str, Vec<&str>)> = groups
+            .iter()
+            .map(|(script, words)| (script.name(), words.iter().map(String::as_str).collect()))
+            .collect();
+        assert_eq!(
+            groups,
+            [
+                ("Latin", vec!["ábaco", "abeja", "zebra"]),
+                ("Cyrillic", vec!["гусь", "кошка"]),
+                ("Kana", vec!["ねこ"]),
+                ("Han", vec!["猫"]),
+            ]
+        );
+    }
+}
@@ -0,0 +1,147 @@
+use std::sync::Mutex;
+
+use feruca::Collator;
+use rusqlite::functions::FunctionFlags;
+use rusqlite::types::ValueRef;
+use rusqlite::{Connection, Error, Result};
+
+use compression::Decompressor;
+
+/// Name of the Unicode-aware collation, for use as `ORDER BY x COLLATE unicode`. It orders every
+/// language the same way, by the CLDR root table, not by each language's own alphabet.
+pub const UNICODE_COLLATION: &str = "unicode";
+
+/// Registers define3's custom collations and functions on a connection. SQLite only knows about
+/// these for the lifetime of the connection, so this needs to be called right after opening.
+pub fn register(conn: &Connection) -> Result<()> {
+    // Collation with the CLDR root table, so that e.g. "ä" sorts with "a" and "ñ" right after "n"
+    // instead of after "z" like they do in byte order. feruca has no tailorings for particular
+    // languages (only for Arabic script), so Swedish "ä" still sorts with "a" rather than after
+    // "z".
+    let collator = Mutex::new(Collator::default());
+    conn.create_collation(UNICODE_COLLATION, move |a, b| {
+        collator.lock().unwrap().collate(a, b)
+    })?;
+
+    // editdist(a, b): Levenshtein distance in characters, or NULL if either side is NULL. This is
+    // a full scan if used on its own, so callers should pre-filter candidates by length or prefix.
+    conn.create_scalar_function(
+        "editdist",
+        2,
+        FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+        |ctx| {
+            let a: Option<String> = ctx.get(0)?;
+            let b: Option<String> = ctx.get(1)?;
+            Ok(a.and_then(|a| b.map(|b| levenshtein(&a, &b) as i64)))
+        },
+    )?;
+
+    // rarray(?1), for binding a whole list of values as one parameter.
+    rusqlite::vtab::array::load_module(conn)?;
+    Ok(())
+}
+
+/// Registers `decompress(x)`, the text of a definition or template compressed with `dictionary` by
+/// `build_definitions_db --compress`, for filtering on it in SQL. Text (from rows merged in
+/// uncompressed) and NULL are returned as they are.
+pub fn register_decompress(conn: &Connection, dictionary: &[u8]) -> Result<()> {
+    let decompressor = Decompressor::new(dictionary);
+    conn.create_scalar_function(
+        "decompress",
+        1,
+        FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+        move |ctx| match ctx.get_raw(0) {
+            ValueRef::Blob(data) => decompressor
+                .decompress(data)
+                .map(Some)
+                .map_err(|e| Error::UserFunctionError(Box::new(e))),
+            _ => ctx.get::<Option<String>>(0),
+        },
+    )
+}
+
+/// Number of single-character insertions, deletions and substitutions needed to turn `a` into `b`.
+pub fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    let mut cur = vec![0; b.len() + 1];
+    for (i, ca) in a.chars().enumerate() {
+        cur[0] = i + 1;
+        for (j, &cb) in b.iter().enumerate() {
+            let substitution = prev[j] + if ca == cb { 0 } else { 1 };
+            cur[j + 1] = substitution.min(prev[j + 1] + 1).min(cur[j] + 1);
+        }
+        std::mem::swap(&mut prev, &mut cur);
+    }
+    prev[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_db::TestDb;
+
+    #[test]
+    fn levenshtein_distances() {
+        assert_eq!(levenshtein("", ""), 0);
+        assert_eq!(levenshtein("", "cat"), 3);
+        assert_eq!(levenshtein("cat", ""), 3);
+        assert_eq!(levenshtein("cat", "cat"), 0);
+        assert_eq!(levenshtein("kitten", "sitting"), 3);
+        assert_eq!(levenshtein("cat", "act"), 2);
+    }
+
+    #[test]
+    fn levenshtein_counts_characters() {
+        assert_eq!(levenshtein("façade", "facade"), 1);
+        assert_eq!(levenshtein("日本語", "日本"), 1);
+        assert_eq!(levenshtein("", "ñ"), 1);
+        assert_eq!(levenshtein("안녕", "안녕하세요"), 3);
+    }
+
+    #[test]
+    fn editdist_in_sql() {
+        let db = TestDb::baseline(&[]);
+        let conn = db.connection();
+        register(&conn).unwrap();
+        let distance: i64 = conn
+            .query_row("SELECT editdist('façade', 'facades')", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(distance, 2);
+        let null: Option<i64> = conn
+            .query_row("SELECT editdist(NULL, 'cat')", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(null, None);
+    }
+
+    #[test]
+    fn unicode_collation() {
+        let words = [
+            ("zebra", "English", "Noun", "An animal."),
+            ("façade", "English", "Noun", "A front."),
+            ("Facade", "English", "Noun", "A front."),
+            ("facade", "English", "Noun", "A front."),
+            ("éclair", "English", "Noun", "A pastry."),
+        ];
+        let db = TestDb::baseline(&words);
+        let conn = db.connection();
+        register(&conn).unwrap();
+        let ordered = |collation: &str| -> Vec<String> {
+            let sql = format!("SELECT name FROM words ORDER BY name COLLATE {}", collation);
+            let mut stmt = conn.prepare(&sql).unwrap();
+            let names = stmt.query_map([], |row| row.get(0)).unwrap();
+            names.collect::<Result<_>>().unwrap()
+        };
+        // Accents matter less than letters, and case less than accents.
+        assert_eq!(
+            ordered(UNICODE_COLLATION),
+            ["éclair", "facade", "Facade", "façade", "zebra"]
+        );
+        assert_eq!(
+            ordered("BINARY"),
+            ["Facade", "facade", "façade", "zebra", "éclair"]
+        );
+    }
+}
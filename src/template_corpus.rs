@@ -0,0 +1,121 @@
+//! Regression cases for the template expander, kept in a file rather than in Rust tests so that a
+//! fix to how a template expands can come with a case for it: wikitext, and the text it should
+//! expand to. `define test-templates` runs them.
+//!
+//! A corpus is TOML, with one `[[case]]` table per case:
+//!
+//! ```toml
+//! [[case]]
+//! name = "defdate with a century"  # optional; the wikitext stands in for it
+//! wikitext = '{{defdate|from 15th c.}}'
+//! expected = "[from 15th c.]"
+//! ```
+//!
+//! Literal strings, in single quotes, save escaping the wikitext. Values are read the way the
+//! config file's are, so strings are on one line, with `\n` for a line break.
+
+use config::parse_value;
+use expand::{expand_with, TemplatePolicies, TemplateSource};
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Case {
+    pub name: Option<String>,
+    pub wikitext: String,
+    pub expected: String,
+    /// The line of the corpus the case starts on.
+    pub line: usize,
+}
+
+impl Case {
+    /// What to call the case in a report: its name, or else its wikitext.
+    pub fn label(&self) -> &str {
+        self.name.as_deref().unwrap_or(&self.wikitext)
+    }
+}
+
+/// A case whose wikitext didn't expand to what it should have.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Failure {
+    pub case: Case,
+    pub actual: String,
+}
+
+/// Reads the cases in a corpus.
+pub fn parse_corpus(text: &str) -> Result<Vec<Case>, String> {
+    let mut cases: Vec<Case> = Vec::new();
+    // Which keys the case being read has set.
+    let mut wikitext = false;
+    let mut expected = false;
+    let check = |case: Option<&Case>, wikitext: bool, expected: bool| match case {
+        Some(case) if !wikitext || !expected => Err(format!(
+            "line {}: a case needs both wikitext and expected",
+            case.line
+        )),
+        _ => Ok(()),
+    };
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if line.starts_with('[') {
+            if line.split('#').next().unwrap_or("").trim() != "[[case]]" {
+                return Err(format!("line {}: expected [[case]]", i + 1));
+            }
+            check(cases.last(), wikitext, expected)?;
+            cases.push(Case {
+                line: i + 1,
+                ..Case::default()
+            });
+            wikitext = false;
+            expected = false;
+            continue;
+        }
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| format!("line {}: expected key = value", i + 1))?;
+        let key = key.trim();
+        let value = parse_value(value.trim())
+            .and_then(|value| value.into_string(key))
+            .map_err(|e| format!("line {}: {}", i + 1, e))?;
+        let case = cases
+            .last_mut()
+            .ok_or_else(|| format!("line {}: {} is outside a [[case]]", i + 1, key))?;
+        match key {
+            "name" => case.name = Some(value),
+            "wikitext" => {
+                case.wikitext = value;
+                wikitext = true;
+            }
+            "expected" => {
+                case.expected = value;
+                expected = true;
+            }
+            _ => return Err(format!("line {}: unknown key {:?}", i + 1, key)),
+        }
+    }
+    check(cases.last(), wikitext, expected)?;
+    Ok(cases)
+}
+
+/// Expands each case's wikitext, with template bodies from `templates`, and returns the ones that
+/// didn't come out as expected.
+pub fn run_corpus(cases: &[Case], templates: &dyn TemplateSource) -> Vec<Failure> {
+    let policies = TemplatePolicies::default();
+    cases
+        .iter()
+        .filter_map(|case| {
+            let (actual, _) = expand_with(&case.wikitext, &policies, templates);
+            Some(Failure {
+                case: case.clone(),
+                actual,
+            })
+            .filter(|failure| failure.actual != case.expected)
+        })
+        .collect()
+}
+
+/// How many characters into `a` and `b` they first differ, for pointing at it.
+pub fn first_difference(a: &str, b: &str) -> usize {
+    a.chars().zip(b.chars()).take_while(|(a, b)| a == b).count()
+}
@@ -0,0 +1,286 @@
+//! Parses the templates in wikitext (`{{name|positional|key=named}}`) into a tree, so that
+//! arguments can contain links with pipes and other templates. Template bodies also have
+//! parameters (`{{{1|default}}}`), which parse the same way.
+
+use nom::branch::alt;
+use nom::bytes::complete::{is_not, tag, take_until};
+use nom::combinator::{map, not, opt, recognize};
+use nom::multi::{many0, many1};
+use nom::sequence::{preceded, terminated, tuple};
+use nom::IResult;
+
+/// A piece of wikitext.
+#[derive(Debug, PartialEq)]
+pub enum Node<'a> {
+    Text(&'a str),
+    Template(Template<'a>),
+    Parameter(Parameter<'a>),
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Template<'a> {
+    /// The template as written, from `{{` to `}}`.
+    pub raw: &'a str,
+    /// Usually plain text, but can itself contain templates.
+    pub name: Vec<Node<'a>>,
+    /// In the order given, positional and named mixed, since parser functions like `#switch` care.
+    pub arguments: Vec<Argument<'a>>,
+}
+
+impl<'a> Template<'a> {
+    /// The arguments without a key, numbered from 1 in wikitext.
+    pub fn positional<'t>(&'t self) -> impl Iterator<Item = &'t [Node<'a>]> + 't {
+        self.arguments
+            .iter()
+            .filter(|argument| argument.key.is_none())
+            .map(|argument| argument.value.as_slice())
+    }
+
+    /// (key, value) pairs in the order given.
+    pub fn named<'t>(&'t self) -> impl Iterator<Item = (&'a str, &'t [Node<'a>])> + 't {
+        self.arguments
+            .iter()
+            .filter_map(|argument| argument.key.map(|key| (key, argument.value.as_slice())))
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Argument<'a> {
+    /// The argument as written, including any `key=`.
+    pub raw: &'a str,
+    /// Trimmed, like MediaWiki does. Positional arguments don't have one.
+    pub key: Option<&'a str>,
+    pub value: Vec<Node<'a>>,
+}
+
+/// A parameter in a template body, replaced by the argument of that name (or number) when the
+/// template is used.
+#[derive(Debug, PartialEq)]
+pub struct Parameter<'a> {
+    /// The parameter as written, from `{{{` to `}}}`.
+    pub raw: &'a str,
+    pub name: Vec<Node<'a>>,
+    /// What to use if there's no such argument.
+    pub default: Option<Vec<Node<'a>>>,
+}
+
+// A link is kept as text, pipes and all.
+fn link(input: &str) -> IResult<&str, &str> {
+    recognize(tuple((tag("[["), take_until("]]"), tag("]]"))))(input)
+}
+
+// Text within a template argument: anything up to the next `|`, `}}`, `{{` or `[[`. Single braces
+// and brackets are text too.
+fn argument_text(input: &str) -> IResult<&str, &str> {
+    recognize(many1(alt((
+        is_not("|{}["),
+        terminated(tag("{"), not(tag("{"))),
+        terminated(tag("}"), not(tag("}"))),
+        terminated(tag("["), not(tag("["))),
+    ))))(input)
+}
+
+fn argument(input: &str) -> IResult<&str, Vec<Node<'_>>> {
+    many0(alt((
+        map(parameter, Node::Parameter),
+        map(template, Node::Template),
+        map(link, Node::Text),
+        map(argument_text, Node::Text),
+    )))(input)
+}
+
+// An argument along with the text it was parsed from.
+fn raw_argument(input: &str) -> IResult<&str, (&str, Vec<Node<'_>>)> {
+    let (rest, argument) = argument(input)?;
+    Ok((rest, (&input[..input.len() - rest.len()], argument)))
+}
+
+// Splits `key=value` off an argument whose text starts with one.
+fn split_key<'a>(raw: &'a str, mut value: Vec<Node<'a>>) -> Argument<'a> {
+    let split = match value.first() {
+        // `=` inside a link, as in `[[a|b=c]]`, is part of the link.
+        Some(Node::Text(text)) if !text.starts_with("[[") => text
+            .find('=')
+            .map(|i| (text[..i].trim(), &text[i + 1..]))
+            .filter(|(key, _)| !key.is_empty() && !key.contains('\n')),
+        _ => None,
+    };
+    let key = split.map(|(key, rest)| {
+        value[0] = Node::Text(rest);
+        key
+    });
+    Argument { raw, key, value }
+}
+
+/// Parses one template parameter at the start of `input`. Anything after a second `|` is ignored,
+/// like MediaWiki does.
+pub fn parameter(input: &str) -> IResult<&str, Parameter<'_>> {
+    let (rest, (_, name, default, _, _)) = tuple((
+        tag("{{{"),
+        argument,
+        opt(preceded(tag("|"), argument)),
+        many0(preceded(tag("|"), argument)),
+        tag("}}}"),
+    ))(input)?;
+    let raw = &input[..input.len() - rest.len()];
+    Ok((rest, Parameter { raw, name, default }))
+}
+
+/// Parses one template at the start of `input`.
+pub fn template(input: &str) -> IResult<&str, Template<'_>> {
+    let (rest, (_, name, arguments, _)) = tuple((
+        tag("{{"),
+        argument,
+        many0(preceded(tag("|"), raw_argument)),
+        tag("}}"),
+    ))(input)?;
+    let raw = &input[..input.len() - rest.len()];
+    let arguments = arguments
+        .into_iter()
+        .map(|(raw, value)| split_key(raw, value))
+        .collect();
+    Ok((
+        rest,
+        Template {
+            raw,
+            name,
+            arguments,
+        },
+    ))
+}
+
+/// Parses wikitext into text, templates and parameters. Anything that doesn't parse as either,
+/// like an unclosed `{{`, is left as text.
+pub fn parse_templates(input: &str) -> Vec<Node<'_>> {
+    let mut nodes = Vec::new();
+    let mut rest = input;
+    let mut text_start = 0;
+    while let Some(i) = rest.find("{{") {
+        let offset = input.len() - rest.len();
+        let node = alt((
+            map(parameter, Node::Parameter),
+            map(template, Node::Template),
+        ))(&rest[i..]);
+        match node {
+            Ok((after, node)) => {
+                if text_start < offset + i {
+                    nodes.push(Node::Text(&input[text_start..offset + i]));
+                }
+                nodes.push(node);
+                rest = after;
+                text_start = input.len() - rest.len();
+            }
+            Err(_) => rest = &rest[i + 1..],
+        }
+    }
+    if text_start < input.len() {
+        nodes.push(Node::Text(&input[text_start..]));
+    }
+    nodes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text<'a>(nodes: &[Node<'a>]) -> Vec<&'a str> {
+        nodes
+            .iter()
+            .map(|node| match *node {
+                Node::Text(text) => text,
+                Node::Template(ref template) => template.raw,
+                Node::Parameter(ref parameter) => parameter.raw,
+            })
+            .collect()
+    }
+
+    fn only_template<'a>(nodes: &'a [Node<'a>]) -> &'a Template<'a> {
+        match nodes {
+            [Node::Template(template)] => template,
+            _ => panic!("expected one template, got {:?}", nodes),
+        }
+    }
+
+    #[test]
+    fn positional_and_named_arguments() {
+        let nodes = parse_templates("{{m|en|cat| t = a feline |pos=noun}}");
+        let template = only_template(&nodes);
+        assert_eq!(text(&template.name), ["m"]);
+        let positional: Vec<Vec<&str>> = template.positional().map(text).collect();
+        assert_eq!(positional, [vec!["en"], vec!["cat"]]);
+        let named: Vec<(&str, Vec<&str>)> = template
+            .named()
+            .map(|(key, value)| (key, text(value)))
+            .collect();
+        assert_eq!(named, [("t", vec![" a feline "]), ("pos", vec!["noun"])]);
+    }
+
+    #[test]
+    fn nested_templates() {
+        let nodes = parse_templates("A {{l|en|{{w|cat}}s}} here");
+        assert_eq!(text(&nodes), ["A ", "{{l|en|{{w|cat}}s}}", " here"]);
+        let template = match nodes[1] {
+            Node::Template(ref template) => template,
+            _ => panic!("expected a template"),
+        };
+        let target = template.positional().nth(1).unwrap();
+        assert_eq!(text(target), ["{{w|cat}}", "s"]);
+        let inner = only_template(&target[..1]);
+        assert_eq!(
+            inner.positional().map(text).collect::<Vec<_>>(),
+            [vec!["cat"]]
+        );
+    }
+
+    #[test]
+    fn equals_inside_nested_values_isnt_a_key() {
+        let nodes = parse_templates("{{gloss|{{m|en|x|t=y}}|[[a|b=c]]}}");
+        let template = only_template(&nodes);
+        assert_eq!(template.named().count(), 0);
+        let positional: Vec<Vec<&str>> = template.positional().map(text).collect();
+        assert_eq!(positional, [vec!["{{m|en|x|t=y}}"], vec!["[[a|b=c]]"]]);
+    }
+
+    #[test]
+    fn links_keep_their_pipes() {
+        let nodes = parse_templates("{{gloss|[[cat|cats]] and dogs}}");
+        let template = only_template(&nodes);
+        let positional: Vec<Vec<&str>> = template.positional().map(text).collect();
+        assert_eq!(positional, [vec!["[[cat|cats]]", " and dogs"]]);
+    }
+
+    #[test]
+    fn unbalanced_braces_are_text() {
+        assert_eq!(text(&parse_templates("{{l|en|cat")), ["{{l|en|cat"]);
+        assert_eq!(
+            text(&parse_templates("cat}} and {dog}")),
+            ["cat}} and {dog}"]
+        );
+        let nodes = parse_templates("{{l|en|{{m|fr|chat}} {{x");
+        assert_eq!(text(&nodes), ["{{l|en|", "{{m|fr|chat}}", " {{x"]);
+    }
+
+    #[test]
+    fn parameter_with_default() {
+        let nodes = parse_templates("{{{1|{{{lang|en}}}}}}");
+        let parameter = match nodes.as_slice() {
+            [Node::Parameter(parameter)] => parameter,
+            _ => panic!("expected one parameter, got {:?}", nodes),
+        };
+        assert_eq!(text(&parameter.name), ["1"]);
+        let default = parameter.default.as_ref().unwrap();
+        assert_eq!(text(default), ["{{{lang|en}}}"]);
+        match default[0] {
+            Node::Parameter(ref inner) => {
+                assert_eq!(text(&inner.name), ["lang"]);
+                assert_eq!(text(inner.default.as_ref().unwrap()), ["en"]);
+            }
+            _ => panic!("expected a parameter"),
+        }
+        let nodes = parse_templates("{{{2}}}");
+        match nodes.as_slice() {
+            [Node::Parameter(parameter)] => assert_eq!(parameter.default, None),
+            _ => panic!("expected one parameter, got {:?}", nodes),
+        }
+    }
+}
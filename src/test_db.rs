@@ -0,0 +1,76 @@
+//! Throwaway databases for tests, in the temporary directory, removed again when dropped.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use rusqlite::Connection;
+
+use dictionary::Dictionary;
+
+// The tables `build_definitions_db` made before anything else was imported.
+const BASELINE_SCHEMA: &str = "
+    CREATE TABLE templates (name text not null, content text not null);
+    CREATE TABLE modules (name text not null, content text not null);
+    CREATE TABLE words (
+        name           text not null,
+        language       text not null,
+        part_of_speech text not null,
+        definition     text not null
+    );";
+
+pub struct TestDb {
+    path: PathBuf,
+}
+
+impl TestDb {
+    /// A database with only the baseline tables, and `words` as (name, language, part of speech,
+    /// definition).
+    pub fn baseline(words: &[(&str, &str, &str, &str)]) -> TestDb {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let path = env::temp_dir().join(format!(
+            "define3-test-{}-{}.sqlite3",
+            process::id(),
+            COUNT.fetch_add(1, Ordering::SeqCst)
+        ));
+        let _ = fs::remove_file(&path);
+        let db = TestDb { path };
+        let conn = db.connection();
+        conn.execute_batch(BASELINE_SCHEMA).unwrap();
+        for word in words {
+            conn.execute(
+                "INSERT INTO words (name, language, part_of_speech, definition)
+                 VALUES (?1, ?2, ?3, ?4)",
+                [word.0, word.1, word.2, word.3],
+            )
+            .unwrap();
+        }
+        db
+    }
+
+    /// Runs `sql` on the database, e.g. to add the tables a test needs.
+    pub fn with(self, sql: &str) -> TestDb {
+        self.connection().execute_batch(sql).unwrap();
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn connection(&self) -> Connection {
+        Connection::open(&self.path).unwrap()
+    }
+
+    pub fn open(&self) -> Dictionary {
+        Dictionary::open(&self.path).unwrap()
+    }
+}
+
+impl Drop for TestDb {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
@@ -0,0 +1,550 @@
+//! A full-screen browser for entries too long to read as they scroll by, like "set" or "run": a
+//! search box, a sidebar of the entry's languages and parts of speech, and a pane of definitions
+//! that scrolls. It draws with ANSI escapes on a raw terminal, so it needs nothing beyond libc.
+//!
+//! Looking up "set#English-Verb-3" goes straight to that sense, by the same anchors as define3's
+//! HTML, and `a` shows the address of what's at the top of the pane.
+
+use std::io::{self, IsTerminal, Read, Write};
+use std::mem;
+
+use dictionary::Dictionary;
+use expand::{expand_with, TemplatePolicies};
+use lookup::{group_by_language, SearchOptions};
+use permalink::{
+    language_anchor, part_of_speech_anchor, same_anchor, sense_anchor, split_fragment,
+};
+use render::Formatters;
+
+// How many similar words to suggest when a search finds nothing.
+const SUGGESTIONS: usize = 5;
+
+// The widest the sidebar gets, in columns. It never takes more than a quarter of the screen.
+const SIDEBAR_WIDTH: usize = 24;
+
+const KEYS_HELP: &str =
+    "Enter look up · Tab next pane · ↑↓ PgUp PgDn Home End scroll · a address · / search · Esc quit";
+
+// Keeps the terminal raw and on the alternate screen for as long as it lives, and puts it back
+// however the browser exits.
+struct Terminal {
+    original: libc::termios,
+}
+
+impl Terminal {
+    fn open() -> io::Result<Terminal> {
+        // Safe: tcgetattr and tcsetattr only read and write the termios given.
+        let original = unsafe {
+            let mut original: libc::termios = mem::zeroed();
+            if libc::tcgetattr(libc::STDIN_FILENO, &mut original) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let mut raw = original;
+            raw.c_iflag &= !(libc::ICRNL | libc::IXON);
+            raw.c_lflag &= !(libc::ICANON | libc::ECHO | libc::ISIG | libc::IEXTEN);
+            // Reads give up after a tenth of a second without input, so resizes get noticed.
+            raw.c_cc[libc::VMIN] = 0;
+            raw.c_cc[libc::VTIME] = 1;
+            if libc::tcsetattr(libc::STDIN_FILENO, libc::TCSAFLUSH, &raw) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            original
+        };
+        let mut stdout = io::stdout();
+        stdout.write_all(b"\x1b[?1049h\x1b[?25l")?;
+        stdout.flush()?;
+        Ok(Terminal { original })
+    }
+
+    // (columns, rows)
+    fn size() -> (usize, usize) {
+        // Safe: TIOCGWINSZ only writes the winsize given.
+        unsafe {
+            let mut size: libc::winsize = mem::zeroed();
+            if libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) == 0 && size.ws_col > 0
+            {
+                return (size.ws_col as usize, size.ws_row as usize);
+            }
+        }
+        (80, 24)
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        let mut stdout = io::stdout();
+        let _ = stdout.write_all(b"\x1b[?25h\x1b[?1049l");
+        let _ = stdout.flush();
+        // Safe: as in `Terminal::open`.
+        unsafe {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSAFLUSH, &self.original);
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Key {
+    Char(char),
+    Enter,
+    Backspace,
+    Tab,
+    Up,
+    Down,
+    PageUp,
+    PageDown,
+    Home,
+    End,
+    Escape,
+}
+
+// The keys in what one read returned. Escape sequences split across reads come out as Escape
+// and some characters; that's rare enough not to bother with.
+fn parse_keys(bytes: &[u8]) -> Vec<Key> {
+    let mut keys = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let byte = bytes[i];
+        i += 1;
+        let key = match byte {
+            0x1b if i < bytes.len() && (bytes[i] == b'[' || bytes[i] == b'O') => {
+                let start = i + 1;
+                let end = bytes[start..]
+                    .iter()
+                    .position(|b| (0x40..=0x7e).contains(b))
+                    .map_or(bytes.len(), |end| start + end + 1);
+                i = end;
+                match &bytes[start..end] {
+                    b"A" => Key::Up,
+                    b"B" => Key::Down,
+                    b"H" | b"1~" | b"7~" => Key::Home,
+                    b"F" | b"4~" | b"8~" => Key::End,
+                    b"5~" => Key::PageUp,
+                    b"6~" => Key::PageDown,
+                    _ => continue,
+                }
+            }
+            // Ctrl-C too, since raw mode doesn't turn it into a signal.
+            0x1b | 0x03 => Key::Escape,
+            b'\r' | b'\n' => Key::Enter,
+            0x7f | 0x08 => Key::Backspace,
+            b'\t' => Key::Tab,
+            0..=0x1f => continue,
+            _ => {
+                let len = match byte {
+                    0xf0..=0xff => 4,
+                    0xe0..=0xef => 3,
+                    0xc0..=0xdf => 2,
+                    _ => 1,
+                };
+                let end = (i - 1 + len).min(bytes.len());
+                let c = std::str::from_utf8(&bytes[i - 1..end])
+                    .ok()
+                    .and_then(|s| s.chars().next());
+                i = end;
+                match c {
+                    Some(c) => Key::Char(c),
+                    None => continue,
+                }
+            }
+        };
+        keys.push(key);
+    }
+    keys
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Style {
+    Language,
+    PartOfSpeech,
+    Headword,
+    Definition,
+}
+
+impl Style {
+    // The same colors as `define` prints with.
+    fn escape(self) -> &'static str {
+        match self {
+            Style::Language => "\x1b[1;32m",
+            Style::PartOfSpeech => "\x1b[37m",
+            Style::Headword => "\x1b[2m",
+            Style::Definition => "",
+        }
+    }
+}
+
+// A paragraph of the entry, before it's wrapped to the pane's width.
+struct Block {
+    style: Style,
+    text: String,
+    /// What the sidebar lists it as, if it starts a section.
+    section: Option<String>,
+    /// Of the first line, and of the lines it wraps onto.
+    indent: (String, String),
+    /// The fragment that goes to it, for languages, parts of speech and senses.
+    anchor: Option<String>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Focus {
+    Search,
+    Sidebar,
+    Definitions,
+}
+
+// The entry wrapped to a width: its lines, and where each section and anchor starts.
+struct Layout {
+    lines: Vec<(Style, String)>,
+    sections: Vec<(String, usize)>,
+    anchors: Vec<(String, usize)>,
+}
+
+struct Browser<'a> {
+    dictionary: &'a Dictionary,
+    options: &'a SearchOptions,
+    policies: &'a TemplatePolicies,
+    formatters: &'a Formatters,
+    query: String,
+    focus: Focus,
+    /// The word the entry on screen is for.
+    headword: String,
+    blocks: Vec<Block>,
+    /// Where to go in the entry once it's laid out, from a search for "word#fragment".
+    fragment: Option<String>,
+    /// What's shown in the status line instead of the keys, until the next key.
+    message: Option<String>,
+    /// The first line of the entry on screen.
+    scroll: usize,
+    /// The section highlighted in the sidebar.
+    selected: usize,
+}
+
+// `s` cut to at most `width` columns, and padded out to exactly that.
+fn fit(s: &str, width: usize) -> String {
+    let mut fitted = String::new();
+    let mut used = 0;
+    for c in s.chars() {
+        let w = textwrap::core::display_width(c.encode_utf8(&mut [0; 4]));
+        if used + w > width {
+            break;
+        }
+        fitted.push(c);
+        used += w;
+    }
+    fitted.push_str(&" ".repeat(width - used));
+    fitted
+}
+
+impl<'a> Browser<'a> {
+    fn look_up(&mut self) {
+        let (word, fragment) = split_fragment(self.query.trim());
+        let (word, fragment) = (word.to_owned(), fragment.map(str::to_owned));
+        if word.is_empty() {
+            return;
+        }
+        let found = match self.dictionary.lookup_with(&word, self.options) {
+            Ok(found) => found,
+            Err(e) => {
+                self.message = Some(format!("Lookup failed: {:?}", e));
+                return;
+            }
+        };
+        self.message = if found.meanings.is_empty() {
+            let suggestions = self
+                .dictionary
+                .suggestions(&word, SUGGESTIONS)
+                .unwrap_or_default();
+            Some(if suggestions.is_empty() {
+                format!("No results found for {}.", word)
+            } else {
+                format!(
+                    "No results found for {}. Did you mean: {}",
+                    word,
+                    suggestions.join(", ")
+                )
+            })
+        } else if found.headword != word {
+            Some(format!("(showing results for {})", found.headword))
+        } else {
+            None
+        };
+        // What was on screen stays there when nothing's found.
+        if found.meanings.is_empty() {
+            return;
+        }
+        self.blocks.clear();
+        self.scroll = 0;
+        self.selected = 0;
+        self.fragment = fragment;
+        self.headword = found.headword;
+        let headword = &self.headword;
+        for (language, poses) in group_by_language(found.meanings) {
+            self.blocks.push(Block {
+                style: Style::Language,
+                text: language.clone(),
+                section: Some(language.clone()),
+                indent: (String::new(), String::new()),
+                anchor: Some(language_anchor(&language)),
+            });
+            let meanings: Vec<_> = poses.values().flatten().collect();
+            for line in self
+                .formatters
+                .headword_lines(&language, headword, &meanings)
+            {
+                self.blocks.push(Block {
+                    style: Style::Headword,
+                    text: line,
+                    section: None,
+                    indent: ("  ".to_owned(), "  ".to_owned()),
+                    anchor: None,
+                });
+            }
+            for (pos, meanings) in &poses {
+                self.blocks.push(Block {
+                    style: Style::PartOfSpeech,
+                    text: pos.clone(),
+                    section: Some(format!("  {}", pos)),
+                    indent: ("  ".to_owned(), "  ".to_owned()),
+                    anchor: Some(part_of_speech_anchor(&language, pos)),
+                });
+                for (i, meaning) in meanings.iter().enumerate() {
+                    let (text, _) =
+                        expand_with(&meaning.definition, self.policies, self.dictionary);
+                    let marker = format!("    {}. ", i + 1);
+                    let indent = " ".repeat(marker.len());
+                    self.blocks.push(Block {
+                        style: Style::Definition,
+                        text: self.formatters.definition(&language, text),
+                        section: None,
+                        indent: (marker, indent),
+                        anchor: Some(sense_anchor(&language, pos, i + 1)),
+                    });
+                }
+            }
+        }
+    }
+
+    fn layout(&self, width: usize) -> Layout {
+        let mut layout = Layout {
+            lines: Vec::new(),
+            sections: Vec::new(),
+            anchors: Vec::new(),
+        };
+        for block in &self.blocks {
+            if let Some(ref section) = block.section {
+                layout.sections.push((section.clone(), layout.lines.len()));
+            }
+            if let Some(ref anchor) = block.anchor {
+                layout.anchors.push((anchor.clone(), layout.lines.len()));
+            }
+            let options = textwrap::Options::new(width.max(1))
+                .initial_indent(&block.indent.0)
+                .subsequent_indent(&block.indent.1);
+            for line in textwrap::wrap(&block.text, &options) {
+                layout.lines.push((block.style, line.into_owned()));
+            }
+        }
+        layout
+    }
+
+    // (sidebar width, pane width, rows for the two of them)
+    fn panes(columns: usize, rows: usize) -> (usize, usize, usize) {
+        let sidebar = SIDEBAR_WIDTH.min(columns / 4);
+        let pane = columns.saturating_sub(sidebar + 3);
+        (sidebar, pane, rows.saturating_sub(3))
+    }
+
+    // `clear` wipes what was there first, which only matters once the size changes.
+    fn draw(&self, (columns, rows): (usize, usize), clear: bool) -> io::Result<()> {
+        let (sidebar, pane, body) = Browser::panes(columns, rows);
+        let layout = self.layout(pane);
+        let mut frame = String::from(if clear { "\x1b[2J\x1b[H" } else { "\x1b[H" });
+
+        let search = format!(" Search: {}", self.query);
+        let cursor = if self.focus == Focus::Search {
+            "█"
+        } else {
+            ""
+        };
+        frame.push_str(&format!(
+            "\x1b[1m{}\x1b[0m\x1b[K\r\n",
+            fit(&format!("{}{}", search, cursor), columns)
+        ));
+        frame.push_str(&format!("\x1b[2m{}\x1b[0m\x1b[K\r\n", "─".repeat(columns)));
+
+        // Keep the selected section in view.
+        let sidebar_scroll = (self.selected + 1).saturating_sub(body);
+        for row in 0..body {
+            match layout.sections.get(sidebar_scroll + row) {
+                Some((label, _)) if sidebar_scroll + row == self.selected => {
+                    let style = if self.focus == Focus::Sidebar {
+                        "\x1b[7m"
+                    } else {
+                        "\x1b[1m"
+                    };
+                    frame.push_str(&format!("{}{}\x1b[0m", style, fit(label, sidebar)));
+                }
+                Some((label, _)) => frame.push_str(&fit(label, sidebar)),
+                None => frame.push_str(&" ".repeat(sidebar)),
+            }
+            frame.push_str(" \x1b[2m│\x1b[0m ");
+            if let Some((style, line)) = layout.lines.get(self.scroll + row) {
+                frame.push_str(&format!("{}{}\x1b[0m", style.escape(), fit(line, pane)));
+            }
+            frame.push_str("\x1b[K\r\n");
+        }
+
+        let status = match self.message {
+            Some(ref message) => message.as_str(),
+            None => KEYS_HELP,
+        };
+        frame.push_str(&format!("\x1b[2m{}\x1b[0m\x1b[K", fit(status, columns)));
+        let mut stdout = io::stdout();
+        stdout.write_all(frame.as_bytes())?;
+        stdout.flush()
+    }
+
+    // Moves the definitions to `scroll`, and the sidebar to the section that's then at the top.
+    fn scroll_to(&mut self, scroll: usize, layout: &Layout, body: usize) {
+        let max = layout.lines.len().saturating_sub(body);
+        self.scroll = scroll.min(max);
+        self.selected = layout
+            .sections
+            .iter()
+            .rposition(|&(_, start)| start <= self.scroll)
+            .unwrap_or(0);
+    }
+
+    // Scrolls to the fragment searched for, if there was one.
+    fn go_to_fragment(&mut self, size: (usize, usize)) {
+        let fragment = match self.fragment.take() {
+            Some(fragment) => fragment,
+            None => return,
+        };
+        let (_, pane, body) = Browser::panes(size.0, size.1);
+        let layout = self.layout(pane);
+        match layout
+            .anchors
+            .iter()
+            .find(|(anchor, _)| same_anchor(anchor, &fragment))
+        {
+            Some(&(_, start)) => self.scroll_to(start, &layout, body),
+            None => self.message = Some(format!("{} has no {}", self.headword, fragment)),
+        }
+    }
+
+    // False once it's time to quit.
+    fn handle(&mut self, key: Key, size: (usize, usize)) -> bool {
+        let (_, pane, body) = Browser::panes(size.0, size.1);
+        let layout = self.layout(pane);
+        self.message = None;
+        match (self.focus, key) {
+            (_, Key::Escape) => return false,
+            (Focus::Search, Key::Char(c)) => self.query.push(c),
+            (Focus::Search, Key::Backspace) => {
+                self.query.pop();
+            }
+            (Focus::Search, Key::Enter) => {
+                self.look_up();
+                if !self.blocks.is_empty() {
+                    self.focus = Focus::Definitions;
+                }
+            }
+            (_, Key::Char('q')) => return false,
+            (_, Key::Char('/')) => self.focus = Focus::Search,
+            (_, Key::Char('a')) if !self.blocks.is_empty() => {
+                // The innermost anchor at or above the top line.
+                let anchor = layout
+                    .anchors
+                    .iter()
+                    .rev()
+                    .find(|&&(_, start)| start <= self.scroll)
+                    .map(|(anchor, _)| anchor.as_str());
+                self.message = Some(match anchor {
+                    Some(anchor) => format!("{}#{}", self.headword, anchor),
+                    None => self.headword.clone(),
+                });
+            }
+            (Focus::Search, Key::Tab) => self.focus = Focus::Sidebar,
+            (Focus::Sidebar, Key::Tab) | (Focus::Sidebar, Key::Enter) => {
+                self.focus = Focus::Definitions
+            }
+            (Focus::Definitions, Key::Tab) => self.focus = Focus::Search,
+            (Focus::Sidebar, Key::Up) | (Focus::Sidebar, Key::Down) => {
+                let selected = if key == Key::Up {
+                    self.selected.saturating_sub(1)
+                } else {
+                    (self.selected + 1).min(layout.sections.len().saturating_sub(1))
+                };
+                if let Some(&(_, start)) = layout.sections.get(selected) {
+                    self.scroll_to(start, &layout, body);
+                    // The last sections can start too far down to scroll to the top.
+                    self.selected = selected;
+                }
+            }
+            (_, Key::Up) => self.scroll_to(self.scroll.saturating_sub(1), &layout, body),
+            (_, Key::Down) => self.scroll_to(self.scroll + 1, &layout, body),
+            (_, Key::PageUp) => self.scroll_to(self.scroll.saturating_sub(body), &layout, body),
+            (_, Key::PageDown) => self.scroll_to(self.scroll + body, &layout, body),
+            (_, Key::Home) => self.scroll_to(0, &layout, body),
+            (_, Key::End) => self.scroll_to(layout.lines.len(), &layout, body),
+            _ => {}
+        }
+        true
+    }
+}
+
+/// Browses entries until the user quits, starting with `word` if given. Words are looked up with
+/// `options` and expanded with `policies`.
+pub fn browse(
+    dictionary: &Dictionary,
+    options: &SearchOptions,
+    policies: &TemplatePolicies,
+    formatters: &Formatters,
+    word: Option<&str>,
+) -> io::Result<()> {
+    if !io::stdin().is_terminal() || !io::stdout().is_terminal() {
+        return Err(io::Error::other("the browser needs a terminal"));
+    }
+    let mut browser = Browser {
+        dictionary,
+        options,
+        policies,
+        formatters,
+        query: word.unwrap_or_default().to_owned(),
+        focus: Focus::Search,
+        headword: String::new(),
+        blocks: Vec::new(),
+        fragment: None,
+        message: None,
+        scroll: 0,
+        selected: 0,
+    };
+    if word.is_some() {
+        browser.look_up();
+        if !browser.blocks.is_empty() {
+            browser.focus = Focus::Definitions;
+        }
+    }
+
+    let _terminal = Terminal::open()?;
+    let mut stdin = io::stdin();
+    let mut buffer = [0; 64];
+    let mut drawn = None;
+    let mut changed = true;
+    loop {
+        let size = Terminal::size();
+        browser.go_to_fragment(size);
+        if changed || drawn != Some(size) {
+            browser.draw(size, drawn != Some(size))?;
+            drawn = Some(size);
+            changed = false;
+        }
+        let read = stdin.read(&mut buffer)?;
+        for key in parse_keys(&buffer[..read]) {
+            if !browser.handle(key, size) {
+                return Ok(());
+            }
+            changed = true;
+        }
+    }
+}
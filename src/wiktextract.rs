@@ -0,0 +1,353 @@
+//! Imports wiktextract's JSONL (as published on kaikki.org) instead of a Wiktionary dump. Each
+//! line is one part of speech of one word in one language, already parsed.
+
+use std::collections::HashSet;
+use std::io::BufRead;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use serde_json::Value;
+
+use parse_wikitext::ParsedWikitext;
+use parse_xml::{open_dump, Progress};
+use {Etymology, Example, Headword, Meaning, Pronunciation, Relation, Word};
+
+// The section header Wiktionary uses for each of wiktextract's parts of speech. "character" is
+// under a header named after the script, so it depends on the language.
+fn part_of_speech(pos: &str, language: &str) -> String {
+    let header = match (pos, language) {
+        ("character", "Japanese") => "Kanji",
+        ("character", "Korean") => "Hanja",
+        ("character", "Chinese") => "Hanzi",
+        ("noun", _) => "Noun",
+        ("verb", _) => "Verb",
+        ("adj", _) => "Adjective",
+        ("adv", _) => "Adverb",
+        ("name", _) => "Proper noun",
+        ("pron", _) => "Pronoun",
+        ("prep", _) => "Preposition",
+        ("postp", _) => "Postposition",
+        ("conj", _) => "Conjunction",
+        ("intj", _) => "Interjection",
+        ("det", _) => "Determiner",
+        ("article", _) => "Article",
+        ("num", _) => "Numeral",
+        ("particle", _) => "Particle",
+        ("phrase", _) => "Phrase",
+        ("prep_phrase", _) => "Prepositional phrase",
+        ("proverb", _) => "Proverb",
+        ("prefix", _) => "Prefix",
+        ("suffix", _) => "Suffix",
+        ("infix", _) => "Infix",
+        ("affix", _) => "Affix",
+        ("abbrev", _) => "Abbreviation",
+        ("symbol", _) => "Symbol",
+        ("punct", _) => "Punctuation mark",
+        ("romanization", _) => "Romanization",
+        ("contraction", _) => "Contraction",
+        ("classifier", _) => "Classifier",
+        ("counter", _) => "Counter",
+        _ => {
+            let mut chars = pos.chars();
+            return match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars).collect(),
+                None => String::new(),
+            };
+        }
+    };
+    header.to_owned()
+}
+
+// wiktextract's relation lists and the kinds we store them as.
+const RELATIONS: &[(&str, &str)] = &[
+    ("synonyms", "synonym"),
+    ("antonyms", "antonym"),
+    ("hypernyms", "hypernym"),
+    ("hyponyms", "hyponym"),
+    ("meronyms", "meronym"),
+    ("holonyms", "holonym"),
+    ("troponyms", "troponym"),
+    ("coordinate_terms", "coordinate term"),
+];
+
+fn strings(value: &Value) -> Vec<&str> {
+    value
+        .as_array()
+        .map(|values| values.iter().filter_map(Value::as_str).collect())
+        .unwrap_or_default()
+}
+
+// Tags that the templates they come from print as a label; see `expand::ANNOTATED_FORMS`.
+// wiktextract keeps them in a sense's tags rather than its raw glosses.
+const LABELED_TAGS: &[&str] = &["inclusive", "gender-neutral", "reformed"];
+
+// (tags, label) for the forms a headword template gives, as wiktextract tags them, in the order
+// `headword::grammar` gives them. Forms from inflection tables have a "source" and aren't these.
+const HEADWORD_FORMS: &[(&[&str], &str)] = &[
+    (&["plural"], "plural"),
+    (&["comparative"], "comparative"),
+    (&["superlative"], "superlative"),
+    (
+        &["present", "singular", "third-person"],
+        "third-person singular simple present",
+    ),
+    (&["participle", "present"], "present participle"),
+    (&["past"], "simple past"),
+    (&["participle", "past"], "past participle"),
+    (&["feminine"], "feminine"),
+    (&["masculine"], "masculine"),
+];
+
+// The relations listed in `value`, either an entry or one of its senses (whose gloss is `sense`).
+fn relations(
+    value: &Value,
+    language: &str,
+    part_of_speech: &str,
+    sense: Option<&str>,
+) -> Vec<Relation> {
+    let mut relations = Vec::new();
+    for &(key, kind) in RELATIONS {
+        for related in value[key].as_array().into_iter().flatten() {
+            if let Some(target) = related["word"].as_str() {
+                relations.push(Relation {
+                    language: language.to_owned(),
+                    part_of_speech: Some(part_of_speech.to_owned()),
+                    kind: kind.to_owned(),
+                    target: target.to_owned(),
+                    sense: related["sense"].as_str().or(sense).map(str::to_owned),
+                });
+            }
+        }
+    }
+    relations
+}
+
+/// Turns one line of wiktextract output into a word, with what there is to report about it in
+/// the same form `parse_wikitext` gives it. Lines in languages or parts of speech that aren't
+/// imported give a word without meanings.
+pub fn parse_entry(
+    line: &str,
+    languages: &HashSet<&str>,
+    parts_of_speech: &HashSet<&str>,
+) -> Result<(Word, ParsedWikitext), serde_json::Error> {
+    let entry: Value = serde_json::from_str(line)?;
+    let name = entry["word"].as_str().unwrap_or_default().to_owned();
+    let language = entry["lang"].as_str().unwrap_or_default();
+    let pos = part_of_speech(entry["pos"].as_str().unwrap_or_default(), language);
+    let mut parsed = ParsedWikitext::default();
+    let mut word = Word {
+        name,
+        meanings: Vec::new(),
+        relations: Vec::new(),
+        etymologies: Vec::new(),
+        pronunciations: Vec::new(),
+        examples: Vec::new(),
+        headwords: Vec::new(),
+    };
+    if !languages.contains(language) {
+        parsed.skipped_languages.push(language.to_owned());
+        return Ok((word, parsed));
+    }
+    if !parts_of_speech.contains(pos.as_str()) {
+        parsed.unknown_headers.push(pos);
+        return Ok((word, parsed));
+    }
+    word.relations = relations(&entry, language, &pos, None);
+    // An entry is one part of speech of one etymology, so the importer gets the same etymology
+    // from each of the others.
+    if let Some(text) = entry["etymology_text"]
+        .as_str()
+        .filter(|text| !text.is_empty())
+    {
+        word.etymologies.push(Etymology {
+            language: language.to_owned(),
+            number: entry["etymology_number"].as_u64().unwrap_or(0) as u32,
+            text: text.to_owned(),
+            parts_of_speech: vec![pos.clone()],
+        });
+    }
+    let mut pronunciation = Pronunciation {
+        language: language.to_owned(),
+        ..Pronunciation::default()
+    };
+    for sound in entry["sounds"].as_array().into_iter().flatten() {
+        if let Some(ipa) = sound["ipa"].as_str() {
+            pronunciation.ipa.push(ipa.to_owned());
+        }
+        if let Some(audio) = sound["audio"].as_str() {
+            pronunciation.audio.push(audio.to_owned());
+        }
+    }
+    // Older dumps have a list of strings, newer ones a list of parts for each.
+    pronunciation.hyphenation = strings(&entry["hyphenation"])
+        .into_iter()
+        .map(str::to_owned)
+        .collect();
+    for hyphenation in entry["hyphenations"].as_array().into_iter().flatten() {
+        pronunciation
+            .hyphenation
+            .push(strings(&hyphenation["parts"]).join("‧"));
+    }
+    if !(pronunciation.ipa.is_empty()
+        && pronunciation.audio.is_empty()
+        && pronunciation.hyphenation.is_empty())
+    {
+        word.pronunciations.push(pronunciation);
+    }
+    let mut forms: Vec<(String, String)> = Vec::new();
+    for form in entry["forms"].as_array().into_iter().flatten() {
+        let (value, mut tags) = match form["form"].as_str() {
+            Some(value) if form["source"].is_null() => (value, strings(&form["tags"])),
+            _ => continue,
+        };
+        tags.sort_unstable();
+        if let Some(&(_, label)) = HEADWORD_FORMS.iter().find(|(t, _)| **t == *tags) {
+            forms.push((label.to_owned(), value.to_owned()));
+        }
+    }
+    if !forms.is_empty() {
+        word.headwords.push(Headword {
+            language: language.to_owned(),
+            part_of_speech: pos.clone(),
+            forms,
+        });
+    }
+    for sense in entry["senses"].as_array().into_iter().flatten() {
+        // Raw glosses keep the qualifiers, e.g. "(zoology)". A subsense's glosses start with its
+        // parent's, so the last one is its own.
+        let raw_glosses = strings(&sense["raw_glosses"]);
+        let glosses = strings(&sense["glosses"]);
+        let gloss = match raw_glosses.last().or_else(|| glosses.last()) {
+            Some(gloss) => gloss,
+            None => continue,
+        };
+        let labels: Vec<&str> = strings(&sense["tags"])
+            .into_iter()
+            .filter(|tag| LABELED_TAGS.contains(tag) && !gloss.contains(tag))
+            .collect();
+        let definition = if labels.is_empty() {
+            gloss.to_string()
+        } else {
+            format!("({}) {}", labels.join(", "), gloss)
+        };
+        // Older dumps call the translation "english".
+        for example in sense["examples"].as_array().into_iter().flatten() {
+            if let Some(text) = example["text"].as_str().filter(|text| !text.is_empty()) {
+                word.examples.push(Example {
+                    language: language.to_owned(),
+                    part_of_speech: pos.clone(),
+                    definition: definition.clone(),
+                    text: text.to_owned(),
+                    translation: example["translation"]
+                        .as_str()
+                        .or(example["english"].as_str())
+                        .map(str::to_owned),
+                });
+            }
+        }
+        word.meanings.push(Meaning {
+            language: language.to_owned(),
+            part_of_speech: pos.clone(),
+            definition,
+            expanded: None,
+            edition: None,
+        });
+        word.relations
+            .extend(relations(sense, language, &pos, Some(gloss)));
+    }
+    Ok((word, parsed))
+}
+
+/// Calls `f` with each entry in a wiktextract JSONL file (optionally .bz2), and `progress` after
+/// each line. Lines that aren't valid JSON are skipped, with an error saying which line for `f`.
+pub fn for_entries<F, P>(
+    filename: &str,
+    languages: &HashSet<&str>,
+    parts_of_speech: &HashSet<&str>,
+    mut f: F,
+    mut progress: P,
+) where
+    F: FnMut(Result<(Word, ParsedWikitext), String>),
+    P: FnMut(&Progress),
+{
+    let total_bytes = std::fs::metadata(filename).map_or(0, |metadata| metadata.len());
+    let count = Arc::new(AtomicU64::new(0));
+    let (input, mut child) = open_dump(filename, &count);
+    for (i, line) in input.lines().enumerate() {
+        let line = line.unwrap();
+        if line.trim().is_empty() {
+            continue;
+        }
+        f(parse_entry(&line, languages, parts_of_speech)
+            .map_err(|e| format!("line {}: {}", i + 1, e)));
+        progress(&Progress {
+            bytes_read: count.load(Ordering::Relaxed),
+            total_bytes,
+            pages: i as u64 + 1,
+        });
+    }
+    if let Some(ref mut child) = child {
+        let status = child.wait().unwrap();
+        if !status.success() {
+            panic!("bzip2 failed to decompress {}: {}", filename, status);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    use super::*;
+
+    #[test]
+    fn parses_entries() {
+        let line = r#"{"word": "cat", "lang": "English", "pos": "noun",
+            "senses": [{"glosses": ["A feline."]}]}"#;
+        let languages = HashSet::from(["English"]);
+        let parts_of_speech = HashSet::from(["Noun"]);
+        let (word, _) = parse_entry(line, &languages, &parts_of_speech).unwrap();
+        assert_eq!(word.name, "cat");
+        assert_eq!(word.meanings.len(), 1);
+        assert_eq!(word.meanings[0].part_of_speech, "Noun");
+        assert_eq!(word.meanings[0].definition, "A feline.");
+        let german = HashSet::from(["German"]);
+        let (word, _) = parse_entry(line, &german, &parts_of_speech).unwrap();
+        assert!(word.meanings.is_empty());
+    }
+
+    #[test]
+    fn skips_lines_that_are_not_json() {
+        let path = env::temp_dir().join(format!("define3-test-{}.jsonl", process::id()));
+        fs::write(
+            &path,
+            concat!(
+                r#"{"word": "cat", "lang": "English", "pos": "noun", "senses": [{"glosses": ["A feline."]}]}"#,
+                "\n\n",
+                r#"{"word": "dog", "lang": "#,
+                "\n",
+            ),
+        )
+        .unwrap();
+        let languages = HashSet::from(["English"]);
+        let parts_of_speech = HashSet::from(["Noun"]);
+        let mut words = Vec::new();
+        let mut errors = Vec::new();
+        for_entries(
+            path.to_str().unwrap(),
+            &languages,
+            &parts_of_speech,
+            |entry| match entry {
+                Ok((word, _)) => words.push(word.name),
+                Err(e) => errors.push(e),
+            },
+            |_| (),
+        );
+        fs::remove_file(&path).unwrap();
+        assert_eq!(words, ["cat"]);
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].starts_with("line 3: "), "{}", errors[0]);
+    }
+}
@@ -0,0 +1,82 @@
+//! What `define` exits with, run against databases built with only the tables the first
+//! importer made, which is where lookups that assume newer tables fail.
+
+extern crate rusqlite;
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command};
+
+const BASELINE_SCHEMA: &str = "
+    CREATE TABLE templates (name text not null, content text not null);
+    CREATE TABLE modules (name text not null, content text not null);
+    CREATE TABLE words (
+        name           text not null,
+        language       text not null,
+        part_of_speech text not null,
+        definition     text not null
+    );
+    INSERT INTO words VALUES ('cat', 'English', 'Noun', 'A {{l|en|feline}}.');";
+
+// A directory of its own for each test, so the config and history of whoever runs the tests
+// don't come into it.
+fn scratch(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("define3-exit-codes-{}-{}", process::id(), name));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn baseline_db(dir: &Path) -> PathBuf {
+    let path = dir.join("define3.sqlite3");
+    let conn = rusqlite::Connection::open(&path).unwrap();
+    conn.execute_batch(BASELINE_SCHEMA).unwrap();
+    path
+}
+
+fn define(dir: &Path, args: &[&str]) -> i32 {
+    let output = Command::new(env!("CARGO_BIN_EXE_define"))
+        .args(args)
+        .env("XDG_CONFIG_HOME", dir.join("config"))
+        .env("XDG_DATA_HOME", dir.join("data"))
+        .env("HOME", dir)
+        .output()
+        .unwrap();
+    output.status.code().unwrap()
+}
+
+#[test]
+fn found_and_not_found() {
+    let dir = scratch("found");
+    let db = baseline_db(&dir);
+    let db = db.to_str().unwrap();
+    assert_eq!(define(&dir, &["-d", db, "cat"]), 0);
+    assert_eq!(define(&dir, &["-d", db, "--ignore-case", "CAT"]), 0);
+    assert_eq!(define(&dir, &["-d", db, "--json", "cat"]), 0);
+    assert_eq!(define(&dir, &["-d", db, "--follow", "--ipa", "cat"]), 0);
+    assert_eq!(define(&dir, &["-d", db, "dog"]), 1);
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn errors() {
+    let dir = scratch("errors");
+    let db = baseline_db(&dir);
+    assert_eq!(define(&dir, &["--no-such-option", "cat"]), 2);
+    // There's no published manifest to fall back on.
+    assert_eq!(define(&dir, &["fetch"]), 2);
+    let missing = dir.join("missing.sqlite3");
+    assert_eq!(define(&dir, &["-d", missing.to_str().unwrap(), "cat"]), 3);
+    let not_a_db = dir.join("not-a-db.sqlite3");
+    fs::write(
+        &not_a_db,
+        "not SQLite at all, and long enough for a header".repeat(10),
+    )
+    .unwrap();
+    assert_eq!(define(&dir, &["-d", not_a_db.to_str().unwrap(), "cat"]), 4);
+    let conn = rusqlite::Connection::open(&db).unwrap();
+    conn.execute_batch("DROP TABLE words").unwrap();
+    assert_eq!(define(&dir, &["-d", db.to_str().unwrap(), "cat"]), 5);
+    let _ = fs::remove_dir_all(&dir);
+}